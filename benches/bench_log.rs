@@ -14,11 +14,9 @@ use std::io;
 use std::num::FromPrimitive;
 use test::Bencher;
 
-use serde::de;
 use serde::json::ser::escape_str;
 use serde::json;
 use serde::ser::Serialize;
-use serde::ser;
 
 use rustc_serialize::Encodable;
 
@@ -38,6 +36,9 @@ struct Http {
 }
 
 #[derive(Copy, Show, PartialEq, FromPrimitive)]
+#[derive_serialize]
+#[derive_deserialize]
+#[serde(discriminant)]
 enum HttpProtocol {
     HTTP_PROTOCOL_UNKNOWN,
     HTTP10,
@@ -59,21 +60,10 @@ impl rustc_serialize::Decodable for HttpProtocol {
     }
 }
 
-impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for HttpProtocol {
-    #[inline]
-    fn serialize(&self, s: &mut S) -> Result<(), E> {
-        s.serialize_uint(*self as uint)
-    }
-}
-
-impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for HttpProtocol {
-    #[inline]
-    fn deserialize_token(d: &mut D, token: de::Token) -> Result<HttpProtocol, E> {
-        d.expect_from_primitive(token)
-    }
-}
-
 #[derive(Copy, Show, PartialEq, FromPrimitive)]
+#[derive_serialize]
+#[derive_deserialize]
+#[serde(discriminant)]
 enum HttpMethod {
     METHOD_UNKNOWN,
     GET,
@@ -103,21 +93,10 @@ impl rustc_serialize::Decodable for HttpMethod {
     }
 }
 
-impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for HttpMethod {
-    #[inline]
-    fn serialize(&self, s: &mut S) -> Result<(), E> {
-        s.serialize_uint(*self as uint)
-    }
-}
-
-impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for HttpMethod {
-    #[inline]
-    fn deserialize_token(d: &mut D, token: de::Token) -> Result<HttpMethod, E> {
-        d.expect_from_primitive(token)
-    }
-}
-
 #[derive(Copy, Show, PartialEq, FromPrimitive)]
+#[derive_serialize]
+#[derive_deserialize]
+#[serde(discriminant)]
 enum CacheStatus {
     CACHESTATUS_UNKNOWN,
     Miss,
@@ -140,20 +119,6 @@ impl rustc_serialize::Decodable for CacheStatus {
     }
 }
 
-impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for CacheStatus {
-    #[inline]
-    fn serialize(&self, s: &mut S) -> Result<(), E> {
-        s.serialize_uint(*self as uint)
-    }
-}
-
-impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for CacheStatus {
-    #[inline]
-    fn deserialize_token(d: &mut D, token: de::Token) -> Result<CacheStatus, E> {
-        d.expect_from_primitive(token)
-    }
-}
-
 #[derive(Show, PartialEq, RustcEncodable, RustcDecodable)]
 #[derive_serialize]
 #[derive_deserialize]
@@ -165,6 +130,9 @@ struct Origin {
 }
 
 #[derive(Copy, Show, PartialEq, FromPrimitive)]
+#[derive_serialize]
+#[derive_deserialize]
+#[serde(discriminant)]
 enum OriginProtocol {
     ORIGIN_PROTOCOL_UNKNOWN,
     HTTP,
@@ -186,21 +154,10 @@ impl rustc_serialize::Decodable for OriginProtocol {
     }
 }
 
-impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for OriginProtocol {
-    #[inline]
-    fn serialize(&self, s: &mut S) -> Result<(), E> {
-        s.serialize_uint(*self as uint)
-    }
-}
-
-impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for OriginProtocol {
-    #[inline]
-    fn deserialize_token(d: &mut D, token: de::Token) -> Result<OriginProtocol, E> {
-        d.expect_from_primitive(token)
-    }
-}
-
 #[derive(Copy, Show, PartialEq, FromPrimitive)]
+#[derive_serialize]
+#[derive_deserialize]
+#[serde(discriminant)]
 enum ZonePlan {
     ZONEPLAN_UNKNOWN,
     FREE,
@@ -224,21 +181,10 @@ impl rustc_serialize::Decodable for ZonePlan {
     }
 }
 
-impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for ZonePlan {
-    #[inline]
-    fn serialize(&self, s: &mut S) -> Result<(), E> {
-        s.serialize_uint(*self as uint)
-    }
-}
-
-impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for ZonePlan {
-    #[inline]
-    fn deserialize_token(d: &mut D, token: de::Token) -> Result<ZonePlan, E> {
-        d.expect_from_primitive(token)
-    }
-}
-
 #[derive(Copy, Show, PartialEq, FromPrimitive)]
+#[derive_serialize]
+#[derive_deserialize]
+#[serde(discriminant)]
 enum Country {
 	UNKNOWN,
 	A1,
@@ -513,20 +459,6 @@ impl rustc_serialize::Decodable for Country {
     }
 }
 
-impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for Country {
-    #[inline]
-    fn serialize(&self, s: &mut S) -> Result<(), E> {
-        s.serialize_uint(*self as uint)
-    }
-}
-
-impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for Country {
-    #[inline]
-    fn deserialize_token(d: &mut D, token: de::Token) -> Result<Country, E> {
-        d.expect_from_primitive(token)
-    }
-}
-
 #[derive(Show, PartialEq, RustcEncodable, RustcDecodable)]
 #[derive_serialize]
 #[derive_deserialize]
@@ -4,14 +4,20 @@ extern crate syntax;
 extern crate rustc;
 
 use syntax::ast::{
+    Attribute,
     Ident,
     MetaItem,
+    MetaList,
+    MetaNameValue,
+    MetaWord,
     Item,
+    ItemStruct,
     Expr,
     MutMutable,
     //LitNil,
 };
 use syntax::ast;
+use syntax::attr;
 use syntax::codemap::Span;
 use syntax::ext::base::{ExtCtxt, Decorator, ItemDecorator};
 use syntax::ext::build::AstBuilder;
@@ -122,7 +128,7 @@ fn expand_derive_serialize<>(cx: &mut ExtCtxt,
                 ),
                 attributes: attrs,
                 combine_substructure: combine_substructure(Box::new(|a, b, c| {
-                    serialize_substructure(a, b, c)
+                    serialize_substructure(a, b, c, item)
                 })),
             }
         ]
@@ -131,20 +137,33 @@ fn expand_derive_serialize<>(cx: &mut ExtCtxt,
     trait_def.expand(cx, mitem, item, |item| push.call_mut((item,)))
 }
 
-fn serialize_substructure(cx: &ExtCtxt, span: Span, substr: &Substructure) -> P<Expr> {
+fn serialize_substructure(cx: &ExtCtxt,
+                          span: Span,
+                          substr: &Substructure,
+                          item: &Item) -> P<Expr> {
     let state = substr.nonself_args[0].clone();
     let visitor = substr.nonself_args[1].clone();
 
-    match *substr.fields {
-        Struct(ref fields) => {
+    match (&item.node, substr.fields) {
+        (&ItemStruct(ref definition, _), &Struct(ref fields)) => {
             if fields.is_empty() {
                 serialize_tuple_struct(cx)
             } else {
-                serialize_struct(cx, span, state, visitor, substr.type_ident, fields)
+                // `default` and `deny_unknown_fields` only affect
+                // deserialization, but still need to be validated here.
+                container_attrs(cx, item.attrs.as_slice());
+
+                let fields: Vec<&FieldInfo> = definition.fields.iter()
+                    .zip(fields.iter())
+                    .filter(|&(def, _)| !field_attrs(cx, def.node.attrs.as_slice()).skip)
+                    .map(|(_, field)| field)
+                    .collect();
+
+                serialize_struct(cx, span, state, visitor, substr.type_ident, fields.as_slice())
             }
         }
 
-        EnumMatching(_idx, variant, ref fields) => {
+        (_, &EnumMatching(_idx, variant, ref fields)) => {
             serialize_enum(cx, span, state, visitor, substr.type_ident, variant, fields)
         }
 
@@ -162,7 +181,7 @@ fn serialize_struct(cx: &ExtCtxt,
                     state: P<Expr>,
                     visitor: P<Expr>,
                     type_ident: Ident,
-                    fields: &Vec<FieldInfo>) -> P<Expr> {
+                    fields: &[&FieldInfo]) -> P<Expr> {
 
     let type_name = cx.expr_str(
         span,
@@ -171,7 +190,7 @@ fn serialize_struct(cx: &ExtCtxt,
 
     let arms: Vec<ast::Arm> = fields.iter()
         .enumerate()
-        .map(|(i, &FieldInfo { name, span, .. })| {
+        .map(|(i, &&FieldInfo { name, span, .. })| {
             let first = if i == 0 {
                 quote_expr!(cx, true)
             } else {
@@ -593,3 +612,63 @@ fn deserialize_static_fields(
     }
 }
 */
+
+/// Field attributes parsed from `#[serde(...)]`.
+struct FieldAttrs {
+    skip: bool,
+}
+
+/// Iterate over the items of every `#[serde(...)]` attribute, marking the
+/// attributes as used.
+fn serde_meta_items<'a, F>(attrs: &'a [Attribute], mut f: F) where
+    F: FnMut(&'a MetaItem)
+{
+    for at in attrs.iter() {
+        match at.node.value.node {
+            MetaList(ref name, ref items) if name.get() == "serde" => {
+                attr::mark_used(at);
+                for item in items.iter() {
+                    f(&**item);
+                }
+            }
+            _ => ()
+        }
+    }
+}
+
+fn field_attrs(cx: &ExtCtxt, attrs: &[Attribute]) -> FieldAttrs {
+    let mut field_attrs = FieldAttrs {
+        skip: false,
+    };
+
+    serde_meta_items(attrs, |item| {
+        match item.node {
+            MetaWord(ref name) if name.get() == "skip" => {
+                field_attrs.skip = true;
+            }
+            // These only affect deserialization, which serde2 can't derive
+            // yet.
+            MetaWord(ref name) | MetaNameValue(ref name, _) if name.get() == "default" => {
+                cx.span_err(item.span, "`default` is not supported by serde2 yet");
+            }
+            _ => {
+                cx.span_err(item.span, "unknown serde field attribute");
+            }
+        }
+    });
+
+    field_attrs
+}
+
+fn container_attrs(cx: &ExtCtxt, attrs: &[Attribute]) {
+    serde_meta_items(attrs, |item| {
+        match item.node {
+            MetaWord(ref name) if name.get() == "deny_unknown_fields" => {
+                cx.span_err(item.span, "`deny_unknown_fields` is not supported by serde2 yet");
+            }
+            _ => {
+                cx.span_err(item.span, "unknown serde container attribute");
+            }
+        }
+    });
+}
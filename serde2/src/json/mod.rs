@@ -1,9 +1,12 @@
-pub use self::ser::Writer;
+pub use self::ser::{Writer, PrettyWriter};
 pub use self::ser::{to_vec, to_string};
+pub use self::ser::{to_pretty_vec, to_pretty_string};
 pub use self::ser::escape_str;
 
 pub use self::de::from_str;
 
+pub use self::value::{Value, to_value, from_value};
+
 pub mod builder;
 pub mod de;
 pub mod error;
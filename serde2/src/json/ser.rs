@@ -1,5 +1,5 @@
 use std::f64;
-use std::io::{self, ByRefWriter, IoError};
+use std::io::{self, ByRefWriter, IoError, IoResult};
 use std::num::{Float, FpCategory};
 use std::string::FromUtf8Error;
 
@@ -177,6 +177,300 @@ impl<W: io::Writer> ser::Visitor<W, (), IoError> for Visitor {
     }
 }
 
+/// A structure for implementing serialization to pretty-printed JSON.
+pub struct PrettyWriter<W> {
+    writer: W,
+    indent: Vec<u8>,
+    level: uint,
+    trailing_newline: bool,
+    compact_scalar_arrays: bool,
+    // Elements of an array are rendered into these buffers when
+    // `compact_scalar_arrays` is set, until we know how to lay the array out.
+    buffers: Vec<Vec<u8>>,
+    wrote_container: bool,
+}
+
+impl<W: io::Writer> PrettyWriter<W> {
+    /// Creates a new pretty-printing JSON visitor whose output will be written
+    /// to the writer specified. It indents with two spaces.
+    #[inline]
+    pub fn new(writer: W) -> PrettyWriter<W> {
+        PrettyWriter {
+            writer: writer,
+            indent: b"  ".to_vec(),
+            level: 0,
+            trailing_newline: false,
+            compact_scalar_arrays: false,
+            buffers: Vec::new(),
+            wrote_container: false,
+        }
+    }
+
+    /// Sets the string written once per level of nesting, such as `"    "` or
+    /// `"\t"`.
+    #[inline]
+    pub fn indent(mut self, indent: &str) -> PrettyWriter<W> {
+        self.indent = indent.as_bytes().to_vec();
+        self
+    }
+
+    /// Sets whether a newline is written after the value.
+    #[inline]
+    pub fn trailing_newline(mut self, enabled: bool) -> PrettyWriter<W> {
+        self.trailing_newline = enabled;
+        self
+    }
+
+    /// Sets whether arrays that only hold scalars are written on one line,
+    /// like `[1, 2, 3]`.
+    #[inline]
+    pub fn compact_scalar_arrays(mut self, enabled: bool) -> PrettyWriter<W> {
+        self.compact_scalar_arrays = enabled;
+        self
+    }
+
+    /// Unwrap the Writer from the Serializer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    #[inline]
+    fn write_bytes(&mut self, buf: &[u8]) -> IoResult<()> {
+        match self.buffers.last_mut() {
+            Some(buffer) => {
+                buffer.push_all(buf);
+                Ok(())
+            }
+            None => self.writer.write(buf),
+        }
+    }
+
+    fn write_newline_and_indent(&mut self) -> IoResult<()> {
+        try!(self.write_bytes(b"\n"));
+        for _ in range(0, self.level) {
+            match self.buffers.last_mut() {
+                Some(buffer) => { buffer.push_all(self.indent.as_slice()); }
+                None => { try!(self.writer.write(self.indent.as_slice())); }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The scalars are written by the compact `Visitor`, so the pretty writer can
+// stand in for any `io::Writer`.
+impl<W: io::Writer> io::Writer for PrettyWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.write_bytes(buf)
+    }
+}
+
+impl<W: io::Writer> ser::Serializer<PrettyWriter<W>, (), IoError> for PrettyWriter<W> {
+    #[inline]
+    fn visit<
+        T: ser::Serialize,
+    >(&mut self, value: &T) -> Result<(), IoError> {
+        try!(value.visit(self, PrettyVisitor));
+
+        if self.trailing_newline {
+            try!(self.write_bytes(b"\n"));
+        }
+
+        Ok(())
+    }
+}
+
+struct PrettyVisitor;
+
+impl<W: io::Writer> ser::Visitor<PrettyWriter<W>, (), IoError> for PrettyVisitor {
+    #[inline]
+    fn visit_null(&self, writer: &mut PrettyWriter<W>) -> Result<(), IoError> {
+        ser::Visitor::visit_null(&Visitor, writer)
+    }
+
+    #[inline]
+    fn visit_bool(&self, writer: &mut PrettyWriter<W>, value: bool) -> Result<(), IoError> {
+        ser::Visitor::visit_bool(&Visitor, writer, value)
+    }
+
+    #[inline]
+    fn visit_i64(&self, writer: &mut PrettyWriter<W>, value: i64) -> Result<(), IoError> {
+        ser::Visitor::visit_i64(&Visitor, writer, value)
+    }
+
+    #[inline]
+    fn visit_u64(&self, writer: &mut PrettyWriter<W>, value: u64) -> Result<(), IoError> {
+        ser::Visitor::visit_u64(&Visitor, writer, value)
+    }
+
+    #[inline]
+    fn visit_f64(&self, writer: &mut PrettyWriter<W>, value: f64) -> Result<(), IoError> {
+        ser::Visitor::visit_f64(&Visitor, writer, value)
+    }
+
+    #[inline]
+    fn visit_char(&self, writer: &mut PrettyWriter<W>, value: char) -> Result<(), IoError> {
+        ser::Visitor::visit_char(&Visitor, writer, value)
+    }
+
+    #[inline]
+    fn visit_str(&self, writer: &mut PrettyWriter<W>, value: &str) -> Result<(), IoError> {
+        ser::Visitor::visit_str(&Visitor, writer, value)
+    }
+
+    #[inline]
+    fn visit_seq<
+        V: ser::SeqVisitor<PrettyWriter<W>, (), IoError>
+    >(&self, writer: &mut PrettyWriter<W>, mut visitor: V) -> Result<(), IoError> {
+        if writer.compact_scalar_arrays {
+            return visit_compact_seq(writer, visitor);
+        }
+
+        writer.wrote_container = true;
+        try!(writer.write_bytes(b"["));
+        writer.level += 1;
+
+        let mut empty = true;
+        loop {
+            match visitor.visit(writer, PrettyVisitor) {
+                Ok(Some(())) => { empty = false; }
+                Ok(None) => { break; }
+                Err(err) => {
+                    writer.level -= 1;
+                    return Err(err);
+                }
+            }
+        }
+
+        writer.level -= 1;
+        if !empty {
+            try!(writer.write_newline_and_indent());
+        }
+        writer.write_bytes(b"]")
+    }
+
+    #[inline]
+    fn visit_seq_elt<
+        T: ser::Serialize,
+    >(&self, writer: &mut PrettyWriter<W>, first: bool, value: T) -> Result<(), IoError> {
+        // Compact arrays are laid out by `visit_compact_seq` once all the
+        // elements have been written.
+        if !writer.compact_scalar_arrays {
+            if !first {
+                try!(writer.write_bytes(b","));
+            }
+            try!(writer.write_newline_and_indent());
+        }
+
+        value.visit(writer, PrettyVisitor)
+    }
+
+    #[inline]
+    fn visit_map<
+        V: ser::MapVisitor<PrettyWriter<W>, (), IoError>
+    >(&self, writer: &mut PrettyWriter<W>, mut visitor: V) -> Result<(), IoError> {
+        writer.wrote_container = true;
+        try!(writer.write_bytes(b"{"));
+        writer.level += 1;
+
+        let mut empty = true;
+        loop {
+            match visitor.visit(writer, PrettyVisitor) {
+                Ok(Some(())) => { empty = false; }
+                Ok(None) => { break; }
+                Err(err) => {
+                    writer.level -= 1;
+                    return Err(err);
+                }
+            }
+        }
+
+        writer.level -= 1;
+        if !empty {
+            try!(writer.write_newline_and_indent());
+        }
+        writer.write_bytes(b"}")
+    }
+
+    #[inline]
+    fn visit_map_elt<
+        K: ser::Serialize,
+        V: ser::Serialize,
+    >(&self, writer: &mut PrettyWriter<W>, first: bool, key: K, value: V) -> Result<(), IoError> {
+        if !first {
+            try!(writer.write_bytes(b","));
+        }
+        try!(writer.write_newline_and_indent());
+
+        try!(key.visit(writer, PrettyVisitor));
+        try!(writer.write_bytes(b": "));
+        value.visit(writer, PrettyVisitor)
+    }
+}
+
+/// Writes an array that only holds scalars on one line, and any other array
+/// one element per line.
+fn visit_compact_seq<
+    W: io::Writer,
+    V: ser::SeqVisitor<PrettyWriter<W>, (), IoError>,
+>(writer: &mut PrettyWriter<W>, mut visitor: V) -> Result<(), IoError> {
+    // The elements are rendered one level deeper, so nested containers are
+    // indented correctly whichever layout we pick.
+    writer.level += 1;
+
+    let mut elements = Vec::new();
+    let mut scalars = true;
+
+    loop {
+        writer.buffers.push(Vec::new());
+        writer.wrote_container = false;
+
+        let result = visitor.visit(writer, PrettyVisitor);
+        let buffer = writer.buffers.pop().unwrap();
+
+        match result {
+            Ok(Some(())) => {
+                scalars = scalars && !writer.wrote_container;
+                elements.push(buffer);
+            }
+            Ok(None) => { break; }
+            Err(err) => {
+                writer.level -= 1;
+                return Err(err);
+            }
+        }
+    }
+
+    writer.level -= 1;
+    writer.wrote_container = true;
+
+    try!(writer.write_bytes(b"["));
+
+    if scalars {
+        for (i, element) in elements.iter().enumerate() {
+            if i != 0 {
+                try!(writer.write_bytes(b", "));
+            }
+            try!(writer.write_bytes(element.as_slice()));
+        }
+    } else {
+        writer.level += 1;
+        for (i, element) in elements.iter().enumerate() {
+            if i != 0 {
+                try!(writer.write_bytes(b","));
+            }
+            try!(writer.write_newline_and_indent());
+            try!(writer.write_bytes(element.as_slice()));
+        }
+        writer.level -= 1;
+        try!(writer.write_newline_and_indent());
+    }
+
+    writer.write_bytes(b"]")
+}
+
 #[inline]
 pub fn escape_bytes<W: io::Writer>(wr: &mut W, bytes: &[u8]) -> Result<(), IoError> {
     try!(wr.write_str("\""));
@@ -256,3 +550,105 @@ pub fn to_string<
     let vec = try!(to_vec(value));
     Ok(String::from_utf8(vec))
 }
+
+#[inline]
+pub fn to_pretty_writer<
+    W: io::Writer,
+    T: ser::Serialize,
+>(wr: &mut W, value: &T) -> Result<(), IoError> {
+    let mut wr = PrettyWriter::new(wr.by_ref());
+    try!(wr.visit(value));
+    Ok(())
+}
+
+#[inline]
+pub fn to_pretty_vec<
+    T: ser::Serialize,
+>(value: &T) -> Result<Vec<u8>, IoError> {
+    let mut wr = Vec::with_capacity(128);
+    to_pretty_writer(&mut wr, value).unwrap();
+    Ok(wr)
+}
+
+#[inline]
+pub fn to_pretty_string<
+    T: ser::Serialize,
+>(value: &T) -> Result<Result<String, FromUtf8Error>, IoError> {
+    let vec = try!(to_pretty_vec(value));
+    Ok(String::from_utf8(vec))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ser::Serializer;
+    use super::{PrettyWriter, to_pretty_string};
+
+    macro_rules! treemap {
+        ($($k:expr => $v:expr),*) => ({
+            let mut _m = BTreeMap::new();
+            $(_m.insert($k, $v);)*
+            _m
+        })
+    }
+
+    fn to_pretty<F>(value: &BTreeMap<String, Vec<Vec<int>>>, f: F) -> String where
+        F: FnOnce(PrettyWriter<Vec<u8>>) -> PrettyWriter<Vec<u8>>
+    {
+        let mut writer = f(PrettyWriter::new(Vec::new()));
+        writer.visit(value).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_write_pretty() {
+        let value: BTreeMap<String, Vec<Vec<int>>> = treemap!(
+            "a".to_string() => vec![vec![1, 2], vec![]],
+            "b".to_string() => vec![]
+        );
+
+        assert_eq!(to_pretty_string(&value).unwrap().unwrap(), concat!(
+            "{\n",
+            "  \"a\": [\n",
+            "    [\n",
+            "      1,\n",
+            "      2\n",
+            "    ],\n",
+            "    []\n",
+            "  ],\n",
+            "  \"b\": []\n",
+            "}"));
+
+        assert_eq!(to_pretty_string(&treemap!("a".to_string() => 1i)).unwrap().unwrap(),
+                   "{\n  \"a\": 1\n}");
+        assert_eq!(to_pretty_string(&Vec::<int>::new()).unwrap().unwrap(), "[]");
+        assert_eq!(to_pretty_string(&1i).unwrap().unwrap(), "1");
+    }
+
+    #[test]
+    fn test_write_pretty_options() {
+        let value: BTreeMap<String, Vec<Vec<int>>> = treemap!(
+            "a".to_string() => vec![vec![1, 2], vec![]]
+        );
+
+        assert_eq!(to_pretty(&value, |w| w.indent("\t").trailing_newline(true)), concat!(
+            "{\n",
+            "\t\"a\": [\n",
+            "\t\t[\n",
+            "\t\t\t1,\n",
+            "\t\t\t2\n",
+            "\t\t],\n",
+            "\t\t[]\n",
+            "\t]\n",
+            "}\n"));
+
+        assert_eq!(to_pretty(&value, |w| w.indent("    ").compact_scalar_arrays(true)), concat!(
+            "{\n",
+            "    \"a\": [\n",
+            "        [1, 2],\n",
+            "        []\n",
+            "    ]\n",
+            "}"));
+    }
+}
@@ -1,10 +1,15 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, btree_map};
 use std::fmt;
+use std::i64;
 use std::io;
 use std::str;
+use std::vec;
 
+use de;
 use ser::{self, Serializer};
 
+use super::error::Error;
+
 #[derive(PartialEq)]
 pub enum Value {
     Null,
@@ -50,6 +55,103 @@ impl ser::Serialize for Value {
     }
 }
 
+impl<S: de::Deserializer<E>, E: de::Error> de::Deserialize<S, E> for Value {
+    #[inline]
+    fn deserialize(state: &mut S) -> Result<Value, E> {
+        struct Visitor;
+
+        impl<
+            S: de::Deserializer<E>,
+            E: de::Error,
+        > de::Visitor<S, Value, E> for Visitor {
+            #[inline]
+            fn visit_null(&mut self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            #[inline]
+            fn visit_bool(&mut self, value: bool) -> Result<Value, E> {
+                Ok(Value::Bool(value))
+            }
+
+            #[inline]
+            fn visit_i64(&mut self, value: i64) -> Result<Value, E> {
+                Ok(Value::I64(value))
+            }
+
+            #[inline]
+            fn visit_u64(&mut self, value: u64) -> Result<Value, E> {
+                // Values that don't fit in an `i64` would wrap around.
+                if value > i64::MAX as u64 {
+                    Ok(Value::F64(value as f64))
+                } else {
+                    Ok(Value::I64(value as i64))
+                }
+            }
+
+            #[inline]
+            fn visit_f64(&mut self, value: f64) -> Result<Value, E> {
+                Ok(Value::F64(value))
+            }
+
+            #[inline]
+            fn visit_str<'a>(&'a mut self, value: &'a str) -> Result<Value, E> {
+                Ok(Value::String(value.to_string()))
+            }
+
+            #[inline]
+            fn visit_string(&mut self, value: String) -> Result<Value, E> {
+                Ok(Value::String(value))
+            }
+
+            #[inline]
+            fn visit_option<
+                V: de::OptionVisitor<S, E>,
+            >(&mut self, mut visitor: V) -> Result<Value, E> {
+                match try!(visitor.visit()) {
+                    Some(value) => Ok(value),
+                    None => Ok(Value::Null),
+                }
+            }
+
+            #[inline]
+            fn visit_seq<
+                V: de::SeqVisitor<S, E>,
+            >(&mut self, mut visitor: V) -> Result<Value, E> {
+                let (len, _) = visitor.size_hint();
+                let mut values = Vec::with_capacity(len);
+
+                loop {
+                    match try!(visitor.visit()) {
+                        Some(value) => { values.push(value); }
+                        None => { break; }
+                    }
+                }
+
+                Ok(Value::Array(values))
+            }
+
+            #[inline]
+            fn visit_map<
+                V: de::MapVisitor<S, E>,
+            >(&mut self, mut visitor: V) -> Result<Value, E> {
+                let mut values = BTreeMap::new();
+
+                loop {
+                    match try!(visitor.visit()) {
+                        Some((key, value)) => { values.insert(key, value); }
+                        None => { break; }
+                    }
+                }
+
+                Ok(Value::Object(values))
+            }
+        }
+
+        state.visit(&mut Visitor)
+    }
+}
+
 struct WriterFormatter<'a, 'b: 'a> {
     inner: &'a mut fmt::Formatter<'b>,
 }
@@ -133,7 +235,12 @@ impl ser::Visitor<Writer, (), ()> for Visitor {
 
     #[inline]
     fn visit_u64(&self, state: &mut Writer, value: u64) -> Result<(), ()> {
-        state.state.push(State::Value(Value::I64(value as i64)));
+        let value = if value > i64::MAX as u64 {
+            Value::F64(value as f64)
+        } else {
+            Value::I64(value as i64)
+        };
+        state.state.push(State::Value(value));
         Ok(())
     }
 
@@ -250,3 +357,275 @@ impl ser::Visitor<Writer, (), ()> for Visitor {
         Ok(())
     }
 }
+
+/// Walks a `Value`, so it can be deserialized into any `Deserialize` type.
+pub struct Deserializer {
+    value: Option<Value>,
+}
+
+impl Deserializer {
+    /// Creates a new deserializer instance for deserializing the specified JSON value.
+    pub fn new(value: Value) -> Deserializer {
+        Deserializer {
+            value: Some(value),
+        }
+    }
+}
+
+impl de::Deserializer<Error> for Deserializer {
+    #[inline]
+    fn visit<
+        R,
+        V: de::Visitor<Deserializer, R, Error>,
+    >(&mut self, visitor: &mut V) -> Result<R, Error> {
+        let value = match self.value.take() {
+            Some(value) => value,
+            None => { return Err(de::Error::end_of_stream_error()); }
+        };
+
+        match value {
+            Value::Null => visitor.visit_null(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::I64(v) => visitor.visit_i64(v),
+            Value::F64(v) => visitor.visit_f64(v),
+            Value::String(v) => visitor.visit_string(v),
+            Value::Array(v) => {
+                let len = v.len();
+                visitor.visit_seq(SeqDeserializer {
+                    de: self,
+                    iter: v.into_iter(),
+                    len: len,
+                })
+            }
+            Value::Object(v) => {
+                let len = v.len();
+                visitor.visit_map(MapDeserializer {
+                    de: self,
+                    iter: v.into_iter(),
+                    value: None,
+                    len: len,
+                })
+            }
+        }
+    }
+
+    #[inline]
+    fn visit_option<
+        R,
+        V: de::Visitor<Deserializer, R, Error>,
+    >(&mut self, visitor: &mut V) -> Result<R, Error> {
+        match self.value {
+            Some(_) => visitor.visit_option(OptionDeserializer { de: self }),
+            None => Err(de::Error::end_of_stream_error()),
+        }
+    }
+}
+
+struct OptionDeserializer<'a> {
+    de: &'a mut Deserializer,
+}
+
+impl<'a> de::OptionVisitor<Deserializer, Error> for OptionDeserializer<'a> {
+    fn visit<
+        T: de::Deserialize<Deserializer, Error>,
+    >(&mut self) -> Result<Option<T>, Error> {
+        match self.de.value {
+            Some(Value::Null) => {
+                self.de.value = None;
+                Ok(None)
+            }
+            _ => {
+                let value = try!(de::Deserialize::deserialize(self.de));
+                Ok(Some(value))
+            }
+        }
+    }
+}
+
+struct SeqDeserializer<'a> {
+    de: &'a mut Deserializer,
+    iter: vec::IntoIter<Value>,
+    len: uint,
+}
+
+impl<'a> de::SeqVisitor<Deserializer, Error> for SeqDeserializer<'a> {
+    fn visit<
+        T: de::Deserialize<Deserializer, Error>,
+    >(&mut self) -> Result<Option<T>, Error> {
+        match self.iter.next() {
+            Some(value) => {
+                self.len -= 1;
+                self.de.value = Some(value);
+                Ok(Some(try!(de::Deserialize::deserialize(self.de))))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        if self.len == 0 {
+            Ok(())
+        } else {
+            Err(de::Error::syntax_error())
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        (self.len, Some(self.len))
+    }
+}
+
+struct MapDeserializer<'a> {
+    de: &'a mut Deserializer,
+    iter: btree_map::IntoIter<String, Value>,
+    value: Option<Value>,
+    len: uint,
+}
+
+impl<'a> de::MapVisitor<Deserializer, Error> for MapDeserializer<'a> {
+    fn visit_key<
+        K: de::Deserialize<Deserializer, Error>,
+    >(&mut self) -> Result<Option<K>, Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.len -= 1;
+                self.value = Some(value);
+                self.de.value = Some(Value::String(key));
+                Ok(Some(try!(de::Deserialize::deserialize(self.de))))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn visit_value<
+        V: de::Deserialize<Deserializer, Error>,
+    >(&mut self) -> Result<V, Error> {
+        match self.value.take() {
+            Some(value) => {
+                self.de.value = Some(value);
+                de::Deserialize::deserialize(self.de)
+            }
+            None => Err(de::Error::end_of_stream_error()),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        if self.len == 0 {
+            Ok(())
+        } else {
+            Err(de::Error::syntax_error())
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        (self.len, Some(self.len))
+    }
+}
+
+/// Decodes a json value from a `Value`.
+pub fn from_value<
+    T: de::Deserialize<Deserializer, Error>
+>(value: Value) -> Result<T, Error> {
+    let mut d = Deserializer::new(value);
+    de::Deserialize::deserialize(&mut d)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::u64;
+
+    use de;
+    use super::{Value, from_value, to_value};
+    use super::super::de::from_str;
+    use super::super::error::Error;
+
+    macro_rules! treemap {
+        ($($k:expr => $v:expr),*) => ({
+            let mut _m = BTreeMap::new();
+            $(_m.insert($k, $v);)*
+            _m
+        })
+    }
+
+    #[test]
+    fn test_parse_value() {
+        let v: Value = from_str("null").unwrap();
+        assert_eq!(v, Value::Null);
+
+        let v: Value = from_str("[true, 1, -2.5, \"a\"]").unwrap();
+        assert_eq!(v, Value::Array(vec![
+            Value::Bool(true),
+            Value::I64(1),
+            Value::F64(-2.5),
+            Value::String("a".to_string()),
+        ]));
+
+        let v: Value = from_str("{\"a\": {\"b\": []}, \"c\": null}").unwrap();
+        assert_eq!(v, Value::Object(treemap!(
+            "a".to_string() => Value::Object(treemap!(
+                "b".to_string() => Value::Array(vec![])
+            )),
+            "c".to_string() => Value::Null
+        )));
+    }
+
+    #[test]
+    fn test_from_value() {
+        let v: Vec<int> = from_value(Value::Array(vec![Value::I64(1), Value::I64(2)])).unwrap();
+        assert_eq!(v, vec![1, 2]);
+
+        let v: (bool, String) = from_value(Value::Array(vec![
+            Value::Bool(true),
+            Value::String("a".to_string()),
+        ])).unwrap();
+        assert_eq!(v, (true, "a".to_string()));
+
+        let v: Option<int> = from_value(Value::Null).unwrap();
+        assert_eq!(v, None);
+
+        let v: Option<int> = from_value(Value::I64(5)).unwrap();
+        assert_eq!(v, Some(5));
+
+        let value: Value = from_str("{\"a\": [1, 2], \"b\": []}").unwrap();
+        let v: BTreeMap<String, Vec<uint>> = from_value(value).unwrap();
+        assert_eq!(v, treemap!("a".to_string() => vec![1, 2], "b".to_string() => vec![]));
+
+        // A value round trips through the deserializer.
+        let value: Value = from_str("{\"a\": [1, {\"b\": null}], \"c\": 1.5}").unwrap();
+        let v: Value = from_value(from_str("{\"a\": [1, {\"b\": null}], \"c\": 1.5}").unwrap())
+            .unwrap();
+        assert_eq!(v, value);
+
+        let v: Result<(uint,), _> = from_value(Value::Array(vec![Value::I64(1), Value::I64(2)]));
+        assert!(v.is_err());
+
+        let v: Result<bool, _> = from_value(Value::I64(1));
+        assert!(v.is_err());
+    }
+
+    struct U64Deserializer(u64);
+
+    impl de::Deserializer<Error> for U64Deserializer {
+        fn visit<
+            R,
+            V: de::Visitor<U64Deserializer, R, Error>,
+        >(&mut self, visitor: &mut V) -> Result<R, Error> {
+            visitor.visit_u64(self.0)
+        }
+    }
+
+    #[test]
+    fn test_u64_out_of_i64_range() {
+        let v: Value = de::Deserialize::deserialize(&mut U64Deserializer(5)).unwrap();
+        assert_eq!(v, Value::I64(5));
+
+        let v: Value = de::Deserialize::deserialize(&mut U64Deserializer(u64::MAX)).unwrap();
+        assert_eq!(v, Value::F64(u64::MAX as f64));
+
+        assert_eq!(to_value(&5u64), Value::I64(5));
+        assert_eq!(to_value(&u64::MAX), Value::F64(u64::MAX as f64));
+    }
+}
@@ -10,7 +10,9 @@ use syntax::ast::{
     Attribute,
     Ident,
     MetaItem,
+    MetaList,
     MetaNameValue,
+    MetaWord,
     Item,
     ItemEnum,
     ItemStruct,
@@ -71,6 +73,8 @@ fn expand_derive_serialize(cx: &mut ExtCtxt,
                              mitem: &MetaItem,
                              item: &Item,
                              mut push: Box<FnMut(P<ast::Item>)>) {
+    let item_attrs = item_attrs(cx, sp, item);
+
     let inline = cx.meta_word(sp, token::InternedString::new("inline"));
     let attrs = vec!(cx.attribute(sp, inline));
 
@@ -108,7 +112,7 @@ fn expand_derive_serialize(cx: &mut ExtCtxt,
                 ),
                 attributes: attrs,
                 combine_substructure: combine_substructure(Box::new( |a, b, c| {
-                    serialize_substructure(a, b, c, item)
+                    serialize_substructure(a, b, c, item, &item_attrs)
                 })),
             })
     };
@@ -119,50 +123,51 @@ fn expand_derive_serialize(cx: &mut ExtCtxt,
 fn serialize_substructure(cx: &ExtCtxt,
                           span: Span,
                           substr: &Substructure,
-                          item: &Item) -> P<Expr> {
+                          item: &Item,
+                          attrs: &ItemAttrs) -> P<Expr> {
     let serializer = substr.nonself_args[0].clone();
 
     match (&item.node, substr.fields) {
         (&ItemStruct(ref definition, _), &Struct(ref fields)) => {
             if fields.is_empty() {
-                // unit structs have no fields and need to return `Ok()`
-                quote_expr!(cx, Ok(()))
-            } else {
-                let type_name = cx.expr_str(
-                    span,
-                    token::get_ident(substr.type_ident)
-                );
+                // unit structs have no fields, so write them as `null`
+                quote_expr!(cx, $serializer.serialize_null())
+            } else if fields.len() == 1 && fields[0].name.is_none() {
+                // newtype structs are written as their inner value
+                let self_ = fields[0].self_.clone();
+                quote_expr!(cx, ::serde::ser::Serialize::serialize(&$self_, $serializer))
+            } else if fields[0].name.is_none() {
                 let len = fields.len();
 
-                let stmts: Vec<P<ast::Stmt>> = definition.fields.iter()
-                    .zip(fields.iter())
-                    .enumerate()
-                    .map(|(i, (def, &FieldInfo { name, ref self_, span, .. }))| {
-                        let serial_name = find_serial_name(def.node.attrs.iter());
-                        let name = match (serial_name, name) {
-                            (Some(serial), _) => serial.clone(),
-                            (None, Some(id)) => token::get_ident(id),
-                            (None, None) => token::intern_and_get_ident(format!("_field{}", i).as_slice()),
-                        };
-
-                        let name = cx.expr_str(span, name);
-
-                        quote_stmt!(
-                            cx,
-                            try!($serializer.serialize_struct_elt($name, &$self_))
-                        )
+                let stmts: Vec<P<ast::Stmt>> = fields.iter()
+                    .map(|&FieldInfo { ref self_, .. }| {
+                        quote_stmt!(cx, try!($serializer.serialize_tuple_elt(&$self_)))
                     })
                     .collect();
 
                 quote_expr!(cx, {
-                    try!($serializer.serialize_struct_start($type_name, $len));
+                    try!($serializer.serialize_tuple_start($len));
                     $stmts
-                    $serializer.serialize_struct_end()
+                    $serializer.serialize_tuple_end()
                 })
+            } else {
+                let type_name = cx.expr_str(
+                    span,
+                    token::get_ident(substr.type_ident)
+                );
+
+                let fields = serialized_fields(
+                    cx,
+                    &attrs.container,
+                    definition.fields.as_slice(),
+                    attrs.fields[0].as_slice(),
+                    fields.as_slice());
+
+                serialize_struct_elts(cx, serializer, type_name, Vec::new(), fields)
             }
         }
 
-        (&ItemEnum(ref definition, _), &EnumMatching(_idx, variant, ref fields)) => {
+        (&ItemEnum(..), &EnumMatching(idx, variant, ref fields)) => {
             let type_name = cx.expr_str(
                 span,
                 token::get_ident(substr.type_ident)
@@ -171,35 +176,292 @@ fn serialize_substructure(cx: &ExtCtxt,
                 span,
                 token::get_ident(variant.node.name)
             );
-            let len = fields.len();
 
-            let stmts: Vec<P<ast::Stmt>> = definition.variants.iter()
-                .zip(fields.iter())
-                .map(|(def, &FieldInfo { ref self_, .. })| {
-                    let _serial_name = find_serial_name(def.node.attrs.iter());
-                    quote_stmt!(
-                        cx,
-                        try!($serializer.serialize_enum_elt(&$self_))
-                    )
-                })
-                .collect();
+            let container_attrs = &attrs.container;
+            let field_attrs = attrs.fields[idx].as_slice();
 
-            quote_expr!(cx, {
-                try!($serializer.serialize_enum_start($type_name, $variant_name, $len));
-                $stmts
-                $serializer.serialize_enum_end()
-            })
+            match attrs.repr {
+                EnumRepr::External => {
+                    // The fields of a struct variant are written as a single
+                    // struct element, so they keep their names.
+                    let stmts: Vec<P<ast::Stmt>> = if fields.iter().any(|f| f.name.is_some()) {
+                        let (prelude, value) = variant_content_expr(
+                            cx, span, container_attrs, variant, field_attrs,
+                            variant_name.clone(), fields.as_slice());
+                        vec![quote_stmt!(cx, {
+                            $prelude
+                            try!($serializer.serialize_enum_elt($value));
+                        })]
+                    } else {
+                        fields.iter()
+                            .map(|&FieldInfo { ref self_, .. }| {
+                                quote_stmt!(
+                                    cx,
+                                    try!($serializer.serialize_enum_elt(&$self_))
+                                )
+                            })
+                            .collect()
+                    };
+                    let len = stmts.len();
+
+                    quote_expr!(cx, {
+                        try!($serializer.serialize_enum_variant_start(
+                            $type_name, $idx, $variant_name, $len));
+                        $stmts
+                        $serializer.serialize_enum_end()
+                    })
+                }
+                EnumRepr::Internal(ref tag) => {
+                    let tag = cx.expr_str(span, tag.clone());
+
+                    let fields = serialized_fields(
+                        cx, container_attrs, variant_fields(variant), field_attrs,
+                        fields.as_slice());
+                    let tag_stmt = quote_stmt!(cx,
+                        try!($serializer.serialize_struct_elt($tag, &$variant_name))
+                    );
+
+                    serialize_struct_elts(cx, serializer, type_name, vec![tag_stmt], fields)
+                }
+                EnumRepr::Adjacent(ref tag, ref content) => {
+                    let tag = cx.expr_str(span, tag.clone());
+                    let content = cx.expr_str(span, content.clone());
+
+                    let stmts: Vec<P<ast::Stmt>> = if fields.is_empty() {
+                        vec![]
+                    } else {
+                        let (prelude, value) = variant_content_expr(
+                            cx, span, container_attrs, variant, field_attrs,
+                            variant_name.clone(), fields.as_slice());
+                        vec![quote_stmt!(cx, {
+                            $prelude
+                            try!($serializer.serialize_struct_elt($content, $value));
+                        })]
+                    };
+                    let len = stmts.len() + 1;
+
+                    quote_expr!(cx, {
+                        try!($serializer.serialize_struct_start($type_name, $len));
+                        try!($serializer.serialize_struct_elt($tag, &$variant_name));
+                        $stmts
+                        $serializer.serialize_struct_end()
+                    })
+                }
+                EnumRepr::Untagged => {
+                    serialize_untagged_variant(
+                        cx, serializer, container_attrs, variant, field_attrs, variant_name,
+                        fields.as_slice())
+                }
+                EnumRepr::Discriminant => {
+                    let path = cx.expr_path(
+                        cx.path(span, vec![substr.type_ident, variant.node.name]));
+
+                    quote_expr!(cx, $serializer.serialize_i64($path as i64))
+                }
+            }
         }
 
         _ => cx.bug("expected Struct or EnumMatching in derive_serialize")
     }
 }
 
+/// A named field to serialize, with the name it is written under and the
+/// expression deciding whether to leave it out at runtime, if any.
+struct SerializedField {
+    name: P<Expr>,
+    self_: P<Expr>,
+    skip: Option<P<Expr>>,
+}
+
+/// Apply the field attributes to the named fields of a struct or struct
+/// variant. Fields marked `#[serde(skip)]` are left out altogether.
+fn serialized_fields(cx: &ExtCtxt,
+                     container_attrs: &ContainerAttrs,
+                     definitions: &[StructField],
+                     field_attrs: &[FieldAttrs],
+                     fields: &[FieldInfo]) -> Vec<SerializedField> {
+    definitions.iter()
+        .zip(field_attrs.iter())
+        .zip(fields.iter())
+        .enumerate()
+        .filter(|&(_, ((_, attrs), _))| !attrs.skip)
+        .map(|(i, ((def, attrs), &FieldInfo { name, ref self_, span, .. }))| {
+            let serial_name = find_serial_name(def.node.attrs.iter());
+            let name = match (serial_name, name) {
+                (Some(serial), _) => serial.clone(),
+                (None, Some(id)) => token::get_ident(id),
+                (None, None) => token::intern_and_get_ident(format!("_field{}", i).as_slice()),
+            };
+
+            SerializedField {
+                name: cx.expr_str(span, name),
+                self_: self_.clone(),
+                skip: skip_serializing_expr(cx, span, container_attrs, attrs, def, self_),
+            }
+        })
+        .collect()
+}
+
+/// Serialize named fields as a struct called `type_name`, writing the
+/// `extra` struct elements before them.
+fn serialize_struct_elts(cx: &ExtCtxt,
+                         serializer: P<Expr>,
+                         type_name: P<Expr>,
+                         extra: Vec<P<ast::Stmt>>,
+                         fields: Vec<SerializedField>) -> P<Expr> {
+    let len = extra.len() + fields.len();
+
+    // Fields that may be skipped at runtime are only counted towards the
+    // length when they are actually serialized. The predicate is evaluated
+    // once, so the length always agrees with the fields that get written.
+    let mut len_stmts = Vec::new();
+
+    let stmts: Vec<P<ast::Stmt>> = fields.into_iter()
+        .enumerate()
+        .map(|(i, SerializedField { name, self_, skip })| {
+            let stmt = quote_stmt!(
+                cx,
+                try!($serializer.serialize_struct_elt($name, &$self_))
+            );
+
+            match skip {
+                Some(skip) => {
+                    let skip_ident = cx.ident_of(format!("__skip{}", i).as_slice());
+                    len_stmts.push(quote_stmt!(cx, let $skip_ident: bool = $skip;));
+                    len_stmts.push(quote_stmt!(cx, if $skip_ident { len -= 1; }));
+                    quote_stmt!(cx, if $skip_ident {
+                        try!($serializer.serialize_struct_skipped_elt($name));
+                    } else {
+                        $stmt
+                    })
+                }
+                None => stmt,
+            }
+        })
+        .collect();
+
+    let let_len = if len_stmts.is_empty() {
+        quote_stmt!(cx, let len: uint = $len;)
+    } else {
+        quote_stmt!(cx, let mut len: uint = $len;)
+    };
+
+    quote_expr!(cx, {
+        $let_len
+        $len_stmts
+        try!($serializer.serialize_struct_start($type_name, len));
+        $extra
+        $stmts
+        $serializer.serialize_struct_end()
+    })
+}
+
+/// A reference to a `Serialize` value holding the fields of a variant: the
+/// value itself for a newtype variant, a tuple for a tuple variant, and a
+/// struct for a struct variant. Struct variants also need the returned
+/// statements to gather the fields that aren't skipped.
+fn variant_content_expr(cx: &ExtCtxt,
+                        span: Span,
+                        container_attrs: &ContainerAttrs,
+                        variant: &Variant,
+                        field_attrs: &[FieldAttrs],
+                        variant_name: P<Expr>,
+                        fields: &[FieldInfo]) -> (Vec<P<ast::Stmt>>, P<Expr>) {
+    if fields.len() == 1 && fields[0].name.is_none() {
+        let self_ = fields[0].self_.clone();
+        (Vec::new(), quote_expr!(cx, &$self_))
+    } else if fields.iter().all(|field| field.name.is_none()) {
+        let elts = fields.iter()
+            .map(|&FieldInfo { ref self_, .. }| quote_expr!(cx, &$self_))
+            .collect();
+        (Vec::new(), cx.expr_addr_of(span, cx.expr_tuple(span, elts)))
+    } else {
+        let fields = serialized_fields(
+            cx, container_attrs, variant_fields(variant), field_attrs, fields);
+
+        let mut stmts = vec![quote_stmt!(cx,
+            let mut __fields: Vec<(&'static str, Option<&::serde::ser::Serialize<__S, __E>>)> =
+                Vec::new();
+        )];
+
+        for SerializedField { name, self_, skip } in fields.into_iter() {
+            let push = quote_stmt!(cx,
+                __fields.push(($name, Some(&$self_ as &::serde::ser::Serialize<__S, __E>)))
+            );
+
+            stmts.push(match skip {
+                Some(skip) => quote_stmt!(cx, if $skip {
+                    __fields.push(($name, None));
+                } else {
+                    $push
+                }),
+                None => push,
+            });
+        }
+
+        (stmts, quote_expr!(cx,
+            &::serde::ser::StructFields::new($variant_name, __fields.as_slice())
+        ))
+    }
+}
+
+/// Serialize a variant without any tag, as `null` for a unit variant or as
+/// the content of the variant otherwise.
+fn serialize_untagged_variant(cx: &ExtCtxt,
+                              serializer: P<Expr>,
+                              container_attrs: &ContainerAttrs,
+                              variant: &Variant,
+                              field_attrs: &[FieldAttrs],
+                              variant_name: P<Expr>,
+                              fields: &[FieldInfo]) -> P<Expr> {
+    if fields.is_empty() {
+        quote_expr!(cx, $serializer.serialize_null())
+    } else if fields.len() == 1 && fields[0].name.is_none() {
+        let self_ = fields[0].self_.clone();
+        quote_expr!(cx, ::serde::ser::Serialize::serialize(&$self_, $serializer))
+    } else if fields.iter().all(|field| field.name.is_none()) {
+        let len = fields.len();
+        let stmts: Vec<P<ast::Stmt>> = fields.iter()
+            .map(|&FieldInfo { ref self_, .. }| {
+                quote_stmt!(cx, try!($serializer.serialize_tuple_elt(&$self_)))
+            })
+            .collect();
+
+        quote_expr!(cx, {
+            try!($serializer.serialize_tuple_start($len));
+            $stmts
+            $serializer.serialize_tuple_end()
+        })
+    } else {
+        let fields = serialized_fields(
+            cx, container_attrs, variant_fields(variant), field_attrs, fields);
+        serialize_struct_elts(cx, serializer, variant_name, Vec::new(), fields)
+    }
+}
+
 pub fn expand_derive_deserialize(cx: &mut ExtCtxt,
                                    span: Span,
                                    mitem: &MetaItem,
                                    item: &Item,
                                    mut push: Box<FnMut(P<Item>)>) {
+    let item_attrs = item_attrs(cx, span, item);
+
+    // Fields like `&'a str` borrow from the input, so the deserializer has to
+    // be able to lend it out for each of the lifetimes they borrow for.
+    let lifetimes: Vec<token::InternedString> = borrowed_lifetimes(item).into_iter()
+        .map(token::get_name)
+        .collect();
+
+    let mut deserializer_bounds = vec!(Path::new_(
+        vec!("serde", "de", "Deserializer"), None,
+        vec!(Box::new(Literal(Path::new_local("__E")))), true));
+
+    for lifetime in lifetimes.iter() {
+        deserializer_bounds.push(Path::new_(
+            vec!("serde", "de", "BorrowDeserializer"), Some(lifetime.get()),
+            vec!(Box::new(Literal(Path::new_local("__E")))), true));
+    }
+
     let trait_def = TraitDef {
         span: span,
         attributes: Vec::new(),
@@ -209,12 +471,36 @@ pub fn expand_derive_deserialize(cx: &mut ExtCtxt,
         additional_bounds: Vec::new(),
         generics: LifetimeBounds {
             lifetimes: Vec::new(),
-            bounds: vec!(("__D", vec!(Path::new_(
-                            vec!("serde", "de", "Deserializer"), None,
-                            vec!(Box::new(Literal(Path::new_local("__E")))), true))),
+            bounds: vec!(("__D", deserializer_bounds),
                          ("__E", vec!()))
         },
         methods: vec!(
+            MethodDef {
+                name: "deserialize",
+                generics: LifetimeBounds::empty(),
+                explicit_self: None,
+                args: vec!(
+                    Ptr(
+                        Box::new(Literal(Path::new_local("__D"))),
+                        Borrowed(None, MutMutable)
+                    ),
+                ),
+                ret_ty: Literal(
+                    Path::new_(
+                        vec!("std", "result", "Result"),
+                        None,
+                        vec!(
+                            Box::new(Self),
+                            Box::new(Literal(Path::new_local("__E")))
+                        ),
+                        true
+                    )
+                ),
+                attributes: Vec::new(),
+                combine_substructure: combine_substructure(Box::new(|a, b, c| {
+                    deserialize_substructure(a, b, c, &item_attrs)
+                })),
+            },
             MethodDef {
                 name: "deserialize_token",
                 generics: LifetimeBounds::empty(),
@@ -239,7 +525,7 @@ pub fn expand_derive_deserialize(cx: &mut ExtCtxt,
                 ),
                 attributes: Vec::new(),
                 combine_substructure: combine_substructure(Box::new(|a, b, c| {
-                    deserialize_substructure(a, b, c)
+                    deserialize_substructure(a, b, c, &item_attrs)
                 })),
             })
     };
@@ -247,11 +533,103 @@ pub fn expand_derive_deserialize(cx: &mut ExtCtxt,
     trait_def.expand(cx, mitem, item, |item| push.call_mut((item,)))
 }
 
+/// The lifetimes strings are borrowed for by the fields of a struct or enum,
+/// like the `'a` of `&'a str` or `CowString<'a>`.
+fn borrowed_lifetimes(item: &Item) -> Vec<ast::Name> {
+    let mut lifetimes = Vec::new();
+
+    match item.node {
+        ItemStruct(ref definition, _) => {
+            for field in definition.fields.iter() {
+                find_borrowed_lifetimes(&*field.node.ty, &mut lifetimes);
+            }
+        }
+        ItemEnum(ref definition, _) => {
+            for variant in definition.variants.iter() {
+                match variant.node.kind {
+                    ast::TupleVariantKind(ref args) => {
+                        for arg in args.iter() {
+                            find_borrowed_lifetimes(&*arg.ty, &mut lifetimes);
+                        }
+                    }
+                    ast::StructVariantKind(ref definition) => {
+                        for field in definition.fields.iter() {
+                            find_borrowed_lifetimes(&*field.node.ty, &mut lifetimes);
+                        }
+                    }
+                }
+            }
+        }
+        _ => { }
+    }
+
+    lifetimes
+}
+
+fn find_borrowed_lifetimes(ty: &ast::Ty, lifetimes: &mut Vec<ast::Name>) {
+    fn push(lifetimes: &mut Vec<ast::Name>, name: ast::Name) {
+        if !lifetimes.contains(&name) {
+            lifetimes.push(name);
+        }
+    }
+
+    match ty.node {
+        ast::TyRptr(ref lifetime, ref mt) => {
+            let is_str = match mt.ty.node {
+                ast::TyPath(ref path, _) => {
+                    path.segments.len() == 1 &&
+                        token::get_ident(path.segments[0].identifier).get() == "str"
+                }
+                _ => false,
+            };
+
+            match *lifetime {
+                Some(ref lifetime) if is_str => push(lifetimes, lifetime.name),
+                _ => { }
+            }
+
+            find_borrowed_lifetimes(&*mt.ty, lifetimes);
+        }
+        ast::TyPath(ref path, _) => {
+            for segment in path.segments.iter() {
+                match segment.parameters {
+                    ast::AngleBracketedParameters(ref data) => {
+                        let name = token::get_ident(segment.identifier);
+                        if name.get() == "CowString" || name.get() == "Cow" {
+                            for lifetime in data.lifetimes.iter() {
+                                push(lifetimes, lifetime.name);
+                            }
+                        }
+
+                        for ty in data.types.iter() {
+                            find_borrowed_lifetimes(&**ty, lifetimes);
+                        }
+                    }
+                    ast::ParenthesizedParameters(_) => { }
+                }
+            }
+        }
+        ast::TyVec(ref ty) | ast::TyFixedLengthVec(ref ty, _) | ast::TyParen(ref ty) => {
+            find_borrowed_lifetimes(&**ty, lifetimes);
+        }
+        ast::TyTup(ref tys) => {
+            for ty in tys.iter() {
+                find_borrowed_lifetimes(&**ty, lifetimes);
+            }
+        }
+        _ => { }
+    }
+}
+
 fn deserialize_substructure(cx: &mut ExtCtxt,
                             span: Span,
-                            substr: &Substructure) -> P<Expr> {
+                            substr: &Substructure,
+                            attrs: &ItemAttrs) -> P<Expr> {
     let deserializer = substr.nonself_args[0].clone();
-    let token = substr.nonself_args[1].clone();
+
+    // `deserialize` reads the value's first token itself, while
+    // `deserialize_token` is handed it.
+    let token = substr.nonself_args.get(1).map(|token| token.clone());
 
     match *substr.fields {
         StaticStruct(ref definition, ref fields) => {
@@ -259,7 +637,9 @@ fn deserialize_substructure(cx: &mut ExtCtxt,
                 cx,
                 span,
                 substr.type_ident,
+                &attrs.container,
                 definition.fields.as_slice(),
+                attrs.fields[0].as_slice(),
                 fields,
                 deserializer.clone(),
                 token)
@@ -269,6 +649,7 @@ fn deserialize_substructure(cx: &mut ExtCtxt,
                 cx,
                 span,
                 substr.type_ident,
+                attrs,
                 definition.variants.as_slice(),
                 fields.as_slice(),
                 deserializer,
@@ -282,17 +663,96 @@ fn deserialize_struct(
     cx: &ExtCtxt,
     span: Span,
     type_ident: Ident,
+    container_attrs: &ContainerAttrs,
     definitions: &[StructField],
+    field_attrs: &[FieldAttrs],
     fields: &StaticFields,
     deserializer: P<ast::Expr>,
-    token: P<ast::Expr>
+    token: Option<P<ast::Expr>>
 ) -> P<ast::Expr> {
     let type_name_str = cx.expr_str(span, token::get_ident(type_ident));
+    let path = cx.path_ident(span, type_ident);
 
-    let fields = match *fields {
-        Unnamed(_) => panic!(),
-        Named(ref fields) => fields.as_slice(),
-    };
+    match *fields {
+        Unnamed(ref fields) if fields.is_empty() => {
+            // Unit structs are written as `null`.
+            let path = cx.expr_path(path);
+            let start = match token {
+                Some(token) => quote_expr!(cx, $deserializer.expect_null($token)),
+                None => quote_expr!(cx, $deserializer.expect_next_null()),
+            };
+            quote_expr!(cx, {
+                try!($start);
+                Ok($path)
+            })
+        }
+        Unnamed(ref fields) if fields.len() == 1 => {
+            // Newtype structs are written as their inner value.
+            let value = deserialize_value(cx, deserializer, token);
+            let arg = quote_expr!(cx, try!($value));
+            let result = cx.expr_call(span, cx.expr_path(path), vec![arg]);
+            quote_expr!(cx, Ok($result))
+        }
+        Unnamed(ref fields) => {
+            let args = fields.iter()
+                .map(|_| quote_expr!(cx, try!($deserializer.expect_tuple_elt())))
+                .collect();
+            let result = cx.expr_call(span, cx.expr_path(path), args);
+            let start = match token {
+                Some(token) => quote_expr!(cx, $deserializer.expect_tuple_start($token)),
+                None => quote_expr!(cx, $deserializer.expect_next_tuple_start()),
+            };
+
+            quote_expr!(cx, {
+                try!($start);
+                let result = $result;
+                try!($deserializer.expect_tuple_end());
+                Ok(result)
+            })
+        }
+        Named(ref fields) => {
+            let result = deserialize_struct_fields(
+                cx,
+                span,
+                path,
+                container_attrs,
+                definitions,
+                field_attrs,
+                fields.as_slice(),
+                deserializer.clone());
+            let start = match token {
+                Some(token) => quote_expr!(cx,
+                    $deserializer.expect_struct_start($token, $type_name_str)),
+                None => quote_expr!(cx, $deserializer.expect_next_struct_start($type_name_str)),
+            };
+
+            quote_expr!(cx, {
+                try!($start);
+                $result
+            })
+        }
+    }
+}
+
+/// Create a deserializer for the fields of a struct or struct variant,
+/// starting right after the struct start token:
+/// - `path` is the name of the struct or variant to construct.
+fn deserialize_struct_fields(
+    cx: &ExtCtxt,
+    span: Span,
+    path: ast::Path,
+    container_attrs: &ContainerAttrs,
+    definitions: &[StructField],
+    attrs: &[FieldAttrs],
+    fields: &[(Ident, Span)],
+    deserializer: P<ast::Expr>
+) -> P<ast::Expr> {
+    // The name reported for missing fields, like `Foo` or `Enum::Variant`.
+    let struct_name = path.segments.iter()
+        .map(|segment| token::get_ident(segment.identifier).get().to_string())
+        .collect::<Vec<String>>()
+        .connect("::");
+    let struct_name = cx.expr_str(span, token::intern_and_get_ident(struct_name.as_slice()));
 
     // Convert each field into a unique ident.
     let field_idents: Vec<ast::Ident> = fields.iter()
@@ -313,18 +773,32 @@ fn deserialize_struct(
         })
         .collect();
 
+    // Skipped fields are never read from the stream, so they don't get a
+    // name in `FIELDS` nor a key arm.
+    let deserialized: Vec<uint> = attrs.iter()
+        .enumerate()
+        .filter(|&(_, attr)| !attr.skip)
+        .map(|(i, _)| i)
+        .collect();
+
     // Declare the static vec slice of field names.
-    let static_fields = cx.expr_vec_slice(span, field_strs.clone());
+    let static_fields = cx.expr_vec_slice(
+        span,
+        deserialized.iter().map(|&i| field_strs[i].clone()).collect());
 
     // Declare each field.
-    let let_fields: Vec<P<ast::Stmt>> = field_idents.iter()
-        .map(|ident| quote_stmt!(cx, let mut $ident = None))
+    let let_fields: Vec<P<ast::Stmt>> = deserialized.iter()
+        .map(|&i| {
+            let ident = field_idents[i];
+            quote_stmt!(cx, let mut $ident = None)
+        })
         .collect();
 
     // Declare key arms.
-    let idx_arms: Vec<ast::Arm> = field_idents.iter()
+    let idx_arms: Vec<ast::Arm> = deserialized.iter()
         .enumerate()
-        .map(|(idx, ident)| {
+        .map(|(idx, &i)| {
+            let ident = field_idents[i];
             quote_arm!(cx,
                 Some($idx) => { $ident = Some(try!($deserializer.expect_struct_value())); }
             )
@@ -333,19 +807,55 @@ fn deserialize_struct(
 
     let extract_fields: Vec<P<ast::Stmt>> = field_idents.iter()
         .zip(field_strs.iter())
-        .map(|(ident, field_str)| {
-            quote_stmt!(cx,
-                let $ident = match $ident {
-                    Some($ident) => $ident,
-                    None => try!($deserializer.missing_field($field_str)),
+        .zip(attrs.iter())
+        .map(|((ident, field_str), attr)| {
+            if attr.skip {
+                let default = match attr.default {
+                    Some(ref default) => default_expr(cx, span, default),
+                    None => default_expr(cx, span, &FieldDefault::Trait),
                 };
-            )
+
+                quote_stmt!(cx, let $ident = $default;)
+            } else {
+                let missing = match attr.default {
+                    Some(ref default) => default_expr(cx, span, default),
+                    None => quote_expr!(cx,
+                        try!(::serde::de::Deserialize::deserialize_missing(
+                            $deserializer, $struct_name, $field_str))
+                    ),
+                };
+
+                quote_stmt!(cx,
+                    let $ident = match $ident {
+                        Some($ident) => $ident,
+                        None => $missing,
+                    };
+                )
+            }
         })
         .collect();
 
-    let result = cx.expr_struct_ident(
+    // Structs that deny unknown fields ask the deserializer to report any
+    // key that isn't in `FIELDS`, so the `None` arm below is never hit.
+    let next_field = if container_attrs.deny_unknown_fields {
+        quote_expr!(cx,
+            match try!($deserializer.expect_known_struct_field_or_end(FIELDS)) {
+                Some(idx) => Some(idx),
+                None => { break; }
+            }
+        )
+    } else {
+        quote_expr!(cx,
+            match try!($deserializer.expect_struct_field_or_end(FIELDS)) {
+                Some(idx) => idx,
+                None => { break; }
+            }
+        )
+    };
+
+    let result = cx.expr_struct(
         span,
-        type_ident,
+        path,
         fields.iter()
             .zip(field_idents.iter())
             .map(|(&(name, _), ident)| {
@@ -355,16 +865,11 @@ fn deserialize_struct(
     );
 
     quote_expr!(cx, {
-        try!($deserializer.expect_struct_start($token, $type_name_str));
-
         static FIELDS: &'static [&'static str] = $static_fields;
         $let_fields
 
         loop {
-            let idx = match try!($deserializer.expect_struct_field_or_end(FIELDS)) {
-                Some(idx) => idx,
-                None => { break; }
-            };
+            let idx = $next_field;
 
             match idx {
                 $idx_arms
@@ -386,16 +891,52 @@ fn deserialize_enum(
     cx: &ExtCtxt,
     span: Span,
     type_ident: Ident,
+    attrs: &ItemAttrs,
     definitions: &[P<Variant>],
     fields: &[(Ident, Span, StaticFields)],
     deserializer: P<ast::Expr>,
-    token: P<ast::Expr>
+    token: Option<P<ast::Expr>>
 ) -> P<ast::Expr> {
-    let type_name = cx.expr_str(span, token::get_ident(type_ident));
+    match attrs.repr {
+        EnumRepr::External => {
+            deserialize_externally_tagged_enum(
+                cx, span, type_ident, attrs, definitions, fields,
+                deserializer, token)
+        }
+        EnumRepr::Internal(ref tag) => {
+            deserialize_internally_tagged_enum(
+                cx, span, type_ident, attrs, definitions, fields,
+                tag, deserializer, token)
+        }
+        EnumRepr::Adjacent(ref tag, ref content) => {
+            deserialize_adjacently_tagged_enum(
+                cx, span, type_ident, attrs, definitions, fields,
+                tag, content, deserializer, token)
+        }
+        EnumRepr::Untagged => {
+            deserialize_untagged_enum(
+                cx, span, type_ident, attrs, definitions, fields,
+                deserializer, token)
+        }
+        EnumRepr::Discriminant => {
+            deserialize_discriminant_enum(cx, span, type_ident, fields, deserializer, token)
+        }
+    }
+}
 
-    let serial_names = definitions.iter().map(|def|
-        find_serial_name(def.node.attrs.iter())
-    ).collect::<Vec<Option<token::InternedString>>>();
+/// Deserialize an enum as `{"Variant": [fields...]}`, or whatever the
+/// deserializer uses for `expect_enum_start`.
+fn deserialize_externally_tagged_enum(
+    cx: &ExtCtxt,
+    span: Span,
+    type_ident: Ident,
+    attrs: &ItemAttrs,
+    definitions: &[P<Variant>],
+    fields: &[(Ident, Span, StaticFields)],
+    deserializer: P<ast::Expr>,
+    token: Option<P<ast::Expr>>
+) -> P<ast::Expr> {
+    let type_name = cx.expr_str(span, token::get_ident(type_ident));
 
     let variants = fields.iter()
         .map(|&(name, span, _)| {
@@ -405,27 +946,56 @@ fn deserialize_enum(
 
     let variants = cx.expr_vec(span, variants);
 
-    let arms: Vec<ast::Arm> = fields.iter()
+    let arms: Vec<ast::Arm> = definitions.iter()
+        .zip(fields.iter())
         .enumerate()
-        .map(|(i, &(name, span, ref parts))| {
+        .map(|(i, (def, &(name, span, ref parts)))| {
             let path = cx.path(span, vec![type_ident, name]);
-            let call = deserialize_static_fields(
-                cx,
-                span,
-                path,
-                serial_names.as_slice(),
-                parts,
-                |&: cx, _, _| {
-                    quote_expr!(cx, try!($deserializer.expect_enum_elt()))
+
+            let call = match *parts {
+                Named(ref parts) => {
+                    // Struct variants are written as a single struct element.
+                    let variant_name = cx.expr_str(span, token::get_ident(name));
+                    let result = deserialize_struct_fields(
+                        cx,
+                        span,
+                        path,
+                        &attrs.container,
+                        variant_fields(&**def),
+                        attrs.fields[i].as_slice(),
+                        parts.as_slice(),
+                        deserializer.clone());
+
+                    quote_expr!(cx, {
+                        try!($deserializer.expect_enum_struct_start($variant_name));
+                        try!($result)
+                    })
                 }
-            );
+                Unnamed(_) => {
+                    deserialize_static_fields(
+                        cx,
+                        span,
+                        path,
+                        parts,
+                        |&: cx, _, _| {
+                            quote_expr!(cx, try!($deserializer.expect_enum_elt()))
+                        }
+                    )
+                }
+            };
 
             quote_arm!(cx, $i => $call,)
         })
         .collect();
 
+    let start = match token {
+        Some(token) => quote_expr!(cx,
+            $deserializer.expect_enum_start($token, $type_name, &$variants)),
+        None => quote_expr!(cx, $deserializer.expect_next_enum_start($type_name, &$variants)),
+    };
+
     quote_expr!(cx, {
-        let i = try!($deserializer.expect_enum_start($token, $type_name, &$variants));
+        let i = try!($start);
 
         let result = match i {
             $arms
@@ -438,6 +1008,366 @@ fn deserialize_enum(
     })
 }
 
+/// Gather the whole value and read the variant name from its `tag` entry.
+/// Evaluates to the remaining `GatherTokens` and the variant index.
+fn deserialize_enum_tag(
+    cx: &ExtCtxt,
+    span: Span,
+    type_ident: Ident,
+    fields: &[(Ident, Span, StaticFields)],
+    tag: &token::InternedString,
+    deserializer: P<ast::Expr>,
+    token: Option<P<ast::Expr>>
+) -> P<ast::Expr> {
+    let type_name = cx.expr_str(span, token::get_ident(type_ident));
+    let tag = cx.expr_str(span, tag.clone());
+
+    let variants = fields.iter()
+        .map(|&(name, span, _)| cx.expr_str(span, token::get_ident(name)))
+        .collect();
+    let variants = cx.expr_vec_slice(span, variants);
+    let tokens = deserialize_value(cx, deserializer.clone(), token);
+
+    quote_expr!(cx, {
+        static VARIANTS: &'static [&'static str] = $variants;
+
+        let mut tokens: ::serde::de::GatherTokens = try!($tokens);
+
+        let variant: ::std::string::String = match tokens.take_field($tag) {
+            Some(tag) => {
+                let mut tag = ::serde::de::ReplayDeserializer::new($deserializer, tag);
+                try!(::serde::de::Deserialize::deserialize(&mut tag))
+            }
+            None => try!($deserializer.missing_struct_field($type_name, $tag)),
+        };
+
+        match VARIANTS.iter().position(|v| *v == variant.as_slice()) {
+            Some(idx) => (tokens, idx),
+            None => {
+                return Err($deserializer.unexpected_name_error(
+                    ::serde::de::Token::String(variant)));
+            }
+        }
+    })
+}
+
+/// Dispatch on the variant index returned by `deserialize_enum_tag`. The
+/// remaining tokens are only bound when some variant has fields to read.
+fn deserialize_tagged_variant(
+    cx: &ExtCtxt,
+    fields: &[(Ident, Span, StaticFields)],
+    variant: P<ast::Expr>,
+    arms: Vec<ast::Arm>
+) -> P<ast::Expr> {
+    let has_fields = fields.iter().any(|&(_, _, ref parts)| {
+        match *parts {
+            Unnamed(ref parts) => !parts.is_empty(),
+            Named(_) => true,
+        }
+    });
+
+    let let_variant = if has_fields {
+        quote_stmt!(cx, let (tokens, idx) = $variant;)
+    } else {
+        quote_stmt!(cx, let (_, idx) = $variant;)
+    };
+
+    quote_expr!(cx, {
+        $let_variant
+
+        match idx {
+            $arms
+            _ => { unreachable!() }
+        }
+    })
+}
+
+/// Deserialize an enum as `{"<tag>": "Variant", fields...}`. Only unit and
+/// struct variants can be represented this way.
+fn deserialize_internally_tagged_enum(
+    cx: &ExtCtxt,
+    span: Span,
+    type_ident: Ident,
+    attrs: &ItemAttrs,
+    definitions: &[P<Variant>],
+    fields: &[(Ident, Span, StaticFields)],
+    tag: &token::InternedString,
+    deserializer: P<ast::Expr>,
+    token: Option<P<ast::Expr>>
+) -> P<ast::Expr> {
+    let variant = deserialize_enum_tag(
+        cx, span, type_ident, fields, tag, deserializer.clone(), token);
+
+    let arms: Vec<ast::Arm> = definitions.iter()
+        .zip(fields.iter())
+        .enumerate()
+        .map(|(i, (def, &(name, span, ref parts)))| {
+            let path = cx.path(span, vec![type_ident, name]);
+            let name = cx.expr_str(span, token::get_ident(name));
+
+            let body = match *parts {
+                Unnamed(ref parts) if parts.is_empty() => {
+                    let path = cx.expr_path(path);
+                    quote_expr!(cx, Ok($path))
+                }
+                // Rejected by `item_attrs`.
+                Unnamed(_) => quote_expr!(cx, unreachable!()),
+                Named(ref parts) => {
+                    let replay = quote_expr!(cx, replay);
+                    let result = deserialize_struct_fields(
+                        cx,
+                        span,
+                        path,
+                        &attrs.container,
+                        variant_fields(&**def),
+                        attrs.fields[i].as_slice(),
+                        parts.as_slice(),
+                        replay);
+
+                    quote_expr!(cx, {
+                        let replay = &mut ::serde::de::ReplayDeserializer::new(
+                            $deserializer, tokens.unwrap());
+                        try!(replay.expect_next_struct_start($name));
+                        $result
+                    })
+                }
+            };
+
+            quote_arm!(cx, $i => $body,)
+        })
+        .collect();
+
+    deserialize_tagged_variant(cx, fields, variant, arms)
+}
+
+/// Deserialize an enum as `{"<tag>": "Variant", "<content>": <fields>}`.
+fn deserialize_adjacently_tagged_enum(
+    cx: &ExtCtxt,
+    span: Span,
+    type_ident: Ident,
+    attrs: &ItemAttrs,
+    definitions: &[P<Variant>],
+    fields: &[(Ident, Span, StaticFields)],
+    tag: &token::InternedString,
+    content: &token::InternedString,
+    deserializer: P<ast::Expr>,
+    token: Option<P<ast::Expr>>
+) -> P<ast::Expr> {
+    let variant = deserialize_enum_tag(
+        cx, span, type_ident, fields, tag, deserializer.clone(), token);
+    let type_name = cx.expr_str(span, token::get_ident(type_ident));
+    let content = cx.expr_str(span, content.clone());
+
+    let arms: Vec<ast::Arm> = definitions.iter()
+        .zip(fields.iter())
+        .enumerate()
+        .map(|(i, (def, &(name, span, ref parts)))| {
+            let path = cx.path(span, vec![type_ident, name]);
+
+            let body = match *parts {
+                Unnamed(ref parts) if parts.is_empty() => {
+                    let path = cx.expr_path(path);
+                    quote_expr!(cx, Ok($path))
+                }
+                _ => {
+                    // A newtype variant is missing its content the way a
+                    // struct field of its type is, so an `Option` is `None`.
+                    let missing = match *parts {
+                        Unnamed(ref parts) if parts.len() == 1 => {
+                            let arg = quote_expr!(cx,
+                                try!(::serde::de::Deserialize::deserialize_missing(
+                                    $deserializer, $type_name, $content)));
+                            let result = cx.expr_call(span, cx.expr_path(path.clone()), vec![arg]);
+                            quote_expr!(cx, Ok($result))
+                        }
+                        _ => quote_expr!(cx,
+                            $deserializer.missing_struct_field($type_name, $content)),
+                    };
+
+                    let replay = quote_expr!(cx, replay);
+                    let result = deserialize_variant_content(
+                        cx, span, path, &attrs.container, &**def, attrs.fields[i].as_slice(),
+                        parts, replay);
+
+                    quote_expr!(cx, {
+                        let mut tokens = tokens;
+                        let content = match tokens.take_field($content) {
+                            Some(content) => content,
+                            None => { return $missing; }
+                        };
+                        let replay = &mut ::serde::de::ReplayDeserializer::new(
+                            $deserializer, content);
+                        $result
+                    })
+                }
+            };
+
+            quote_arm!(cx, $i => $body,)
+        })
+        .collect();
+
+    deserialize_tagged_variant(cx, fields, variant, arms)
+}
+
+/// Deserialize an enum without any tag, trying each variant in order until
+/// one of them matches the data.
+fn deserialize_untagged_enum(
+    cx: &ExtCtxt,
+    span: Span,
+    type_ident: Ident,
+    attrs: &ItemAttrs,
+    definitions: &[P<Variant>],
+    fields: &[(Ident, Span, StaticFields)],
+    deserializer: P<ast::Expr>,
+    token: Option<P<ast::Expr>>
+) -> P<ast::Expr> {
+    let attempts: Vec<P<ast::Stmt>> = definitions.iter()
+        .zip(fields.iter())
+        .enumerate()
+        .map(|(i, (def, &(name, span, ref parts)))| {
+            let path = cx.path(span, vec![type_ident, name]);
+
+            let body = match *parts {
+                Unnamed(ref parts) if parts.is_empty() => {
+                    let path = cx.expr_path(path);
+                    quote_expr!(cx, {
+                        try!(replay.expect_next_null());
+                        Ok($path)
+                    })
+                }
+                _ => {
+                    let replay = quote_expr!(cx, replay);
+                    deserialize_variant_content(
+                        cx, span, path, &attrs.container, &**def, attrs.fields[i].as_slice(),
+                        parts, replay)
+                }
+            };
+
+            quote_stmt!(cx, {
+                let replay = &mut ::serde::de::ReplayDeserializer::new(
+                    $deserializer, tokens.clone());
+                let result = (|| $body)();
+
+                if let Ok(value) = result {
+                    return Ok(value);
+                }
+            })
+        })
+        .collect();
+
+    let tokens = deserialize_value(cx, deserializer.clone(), token);
+
+    quote_expr!(cx, {
+        let tokens: ::serde::de::GatherTokens = try!($tokens);
+        let tokens = tokens.unwrap();
+
+        $attempts
+
+        Err($deserializer.conversion_error(tokens[0].clone()))
+    })
+}
+
+/// Deserialize a unit-only enum from its integer discriminant.
+fn deserialize_discriminant_enum(
+    cx: &ExtCtxt,
+    span: Span,
+    type_ident: Ident,
+    fields: &[(Ident, Span, StaticFields)],
+    deserializer: P<ast::Expr>,
+    token: Option<P<ast::Expr>>
+) -> P<ast::Expr> {
+    let arms: Vec<ast::Arm> = fields.iter()
+        .map(|&(name, span, _)| {
+            let path = cx.expr_path(cx.path(span, vec![type_ident, name]));
+
+            quote_arm!(cx, value if value == $path as i64 => Ok($path),)
+        })
+        .collect();
+
+    let value = deserialize_value(cx, deserializer.clone(), token);
+
+    quote_expr!(cx, {
+        let value: i64 = try!($value);
+
+        match value {
+            $arms
+            _ => Err($deserializer.conversion_error(::serde::de::Token::I64(value))),
+        }
+    })
+}
+
+/// Create a deserializer for the fields of a newtype, tuple or struct
+/// variant, written on their own as a value, a sequence or a struct.
+fn deserialize_variant_content(
+    cx: &ExtCtxt,
+    span: Span,
+    path: ast::Path,
+    container_attrs: &ContainerAttrs,
+    definition: &Variant,
+    field_attrs: &[FieldAttrs],
+    fields: &StaticFields,
+    deserializer: P<ast::Expr>
+) -> P<ast::Expr> {
+    match *fields {
+        Unnamed(ref fields) if fields.len() == 1 => {
+            let arg = quote_expr!(cx, try!(::serde::de::Deserialize::deserialize($deserializer)));
+            let result = cx.expr_call(span, cx.expr_path(path), vec![arg]);
+            quote_expr!(cx, Ok($result))
+        }
+        Unnamed(ref fields) => {
+            let args = fields.iter()
+                .map(|_| quote_expr!(cx, try!($deserializer.expect_tuple_elt())))
+                .collect();
+            let result = cx.expr_call(span, cx.expr_path(path), args);
+
+            quote_expr!(cx, {
+                try!($deserializer.expect_next_tuple_start());
+                let result = $result;
+                try!($deserializer.expect_tuple_end());
+                Ok(result)
+            })
+        }
+        Named(ref fields) => {
+            let name = cx.expr_str(span, token::get_ident(definition.node.name));
+            let result = deserialize_struct_fields(
+                cx,
+                span,
+                path,
+                container_attrs,
+                variant_fields(definition),
+                field_attrs,
+                fields.as_slice(),
+                deserializer.clone());
+
+            quote_expr!(cx, {
+                try!($deserializer.expect_next_struct_start($name));
+                $result
+            })
+        }
+    }
+}
+
+/// Deserialize a whole value, from `token` if the caller was handed one.
+fn deserialize_value(
+    cx: &ExtCtxt,
+    deserializer: P<ast::Expr>,
+    token: Option<P<ast::Expr>>
+) -> P<ast::Expr> {
+    match token {
+        Some(token) => quote_expr!(cx,
+            ::serde::de::Deserialize::deserialize_token($deserializer, $token)),
+        None => quote_expr!(cx, ::serde::de::Deserialize::deserialize($deserializer)),
+    }
+}
+
+/// The field definitions of a struct variant, or nothing for other variants.
+fn variant_fields(variant: &Variant) -> &[StructField] {
+    match variant.node.kind {
+        ast::StructVariantKind(ref definition) => definition.fields.as_slice(),
+        ast::TupleVariantKind(_) => &[],
+    }
+}
+
 /// Create a deserializer for a single enum variant/struct:
 /// - `outer_pat_ident` is the name of this enum variant/struct
 /// - `getarg` should retrieve the `uint`-th field with name `&str`.
@@ -445,7 +1375,6 @@ fn deserialize_static_fields<F>(
     cx: &ExtCtxt,
     span: Span,
     outer_pat_path: ast::Path,
-    serial_names: &[Option<token::InternedString>],
     fields: &StaticFields,
     getarg: F
 ) -> P<Expr> where F: Fn(&ExtCtxt, Span, token::InternedString) -> P<Expr> {
@@ -468,15 +1397,11 @@ fn deserialize_static_fields<F>(
         }
         Named(ref fields) => {
             // use the field's span to get nicer error messages.
-            let fields = serial_names.iter()
-                .zip(fields.iter()).map(|(serial_name, &(name, span))| {
-                let effective_name = serial_name.clone().unwrap_or(
-                    token::get_ident(name)
-                );
+            let fields = fields.iter().map(|&(name, span)| {
                 let arg = getarg(
                     cx,
                     span,
-                    effective_name
+                    token::get_ident(name)
                 );
                 cx.field_imm(span, name, arg)
             }).collect();
@@ -505,3 +1430,319 @@ fn find_serial_name<'a, I>(mut iterator: I) -> Option<token::InternedString> whe
     }
     None
 }
+
+/// The default value used for a field that is missing from the input, or
+/// that is skipped altogether.
+enum FieldDefault {
+    /// `#[serde(default)]`, uses `Default::default()`.
+    Trait,
+    /// `#[serde(default = "path::to::function")]`.
+    Path(token::InternedString),
+}
+
+/// Field attributes parsed from `#[serde(...)]`.
+struct FieldAttrs {
+    skip: bool,
+    skip_serializing_if: Option<token::InternedString>,
+    default: Option<FieldDefault>,
+}
+
+/// Container attributes parsed from `#[serde(...)]`.
+struct ContainerAttrs {
+    deny_unknown_fields: bool,
+    skip_serializing_none: bool,
+    tag: Option<token::InternedString>,
+    content: Option<token::InternedString>,
+    untagged: bool,
+    discriminant: bool,
+}
+
+/// How the variant of an enum is written.
+enum EnumRepr {
+    /// `{"Variant": [fields...]}`, the default.
+    External,
+    /// `#[serde(tag = "type")]`: `{"type": "Variant", fields...}`.
+    Internal(token::InternedString),
+    /// `#[serde(tag = "t", content = "c")]`: `{"t": "Variant", "c": fields}`.
+    Adjacent(token::InternedString, token::InternedString),
+    /// `#[serde(untagged)]`: just the fields.
+    Untagged,
+    /// `#[serde(discriminant)]`: the integer discriminant of a unit variant.
+    Discriminant,
+}
+
+/// The attributes of a struct or enum and of its fields, parsed once per
+/// expansion so that each mistake in them is only reported once.
+struct ItemAttrs {
+    container: ContainerAttrs,
+    /// Always `External` for structs.
+    repr: EnumRepr,
+    /// The attributes of the named fields of the struct, or of each variant
+    /// of the enum.
+    fields: Vec<Vec<FieldAttrs>>,
+}
+
+/// Iterate over the items of every `#[serde(...)]` attribute, marking the
+/// attributes as used.
+fn serde_meta_items<'a, F>(attrs: &'a [Attribute], mut f: F) where
+    F: FnMut(&'a MetaItem)
+{
+    for at in attrs.iter() {
+        match at.node.value.node {
+            MetaList(ref name, ref items) if name.get() == "serde" => {
+                attr::mark_used(at);
+                for item in items.iter() {
+                    f(&**item);
+                }
+            }
+            _ => ()
+        }
+    }
+}
+
+fn field_attrs(cx: &ExtCtxt, attrs: &[Attribute]) -> FieldAttrs {
+    let mut field_attrs = FieldAttrs {
+        skip: false,
+        skip_serializing_if: None,
+        default: None,
+    };
+
+    serde_meta_items(attrs, |item| {
+        match item.node {
+            MetaWord(ref name) if name.get() == "skip" => {
+                field_attrs.skip = true;
+            }
+            MetaWord(ref name) if name.get() == "default" => {
+                field_attrs.default = Some(FieldDefault::Trait);
+            }
+            MetaNameValue(ref name, ref value) if name.get() == "default" => {
+                match value.node {
+                    LitStr(ref path, _) => {
+                        field_attrs.default = Some(FieldDefault::Path(path.clone()));
+                    }
+                    _ => {
+                        cx.span_err(item.span, "expected a string literal for `default`");
+                    }
+                }
+            }
+            MetaNameValue(ref name, ref value) if name.get() == "skip_serializing_if" => {
+                match value.node {
+                    LitStr(ref path, _) => {
+                        field_attrs.skip_serializing_if = Some(path.clone());
+                    }
+                    _ => {
+                        cx.span_err(
+                            item.span,
+                            "expected a string literal for `skip_serializing_if`");
+                    }
+                }
+            }
+            _ => {
+                cx.span_err(item.span, "unknown serde field attribute");
+            }
+        }
+    });
+
+    field_attrs
+}
+
+fn fields_attrs(cx: &ExtCtxt, definitions: &[StructField]) -> Vec<FieldAttrs> {
+    definitions.iter()
+        .map(|def| field_attrs(cx, def.node.attrs.as_slice()))
+        .collect()
+}
+
+fn container_attrs(cx: &ExtCtxt, attrs: &[Attribute]) -> ContainerAttrs {
+    let mut container_attrs = ContainerAttrs {
+        deny_unknown_fields: false,
+        skip_serializing_none: false,
+        tag: None,
+        content: None,
+        untagged: false,
+        discriminant: false,
+    };
+
+    serde_meta_items(attrs, |item| {
+        match item.node {
+            MetaWord(ref name) if name.get() == "deny_unknown_fields" => {
+                container_attrs.deny_unknown_fields = true;
+            }
+            MetaWord(ref name) if name.get() == "skip_serializing_none" => {
+                container_attrs.skip_serializing_none = true;
+            }
+            MetaWord(ref name) if name.get() == "untagged" => {
+                container_attrs.untagged = true;
+            }
+            MetaWord(ref name) if name.get() == "discriminant" => {
+                container_attrs.discriminant = true;
+            }
+            MetaNameValue(ref name, ref value) if name.get() == "tag" => {
+                match value.node {
+                    LitStr(ref tag, _) => {
+                        container_attrs.tag = Some(tag.clone());
+                    }
+                    _ => {
+                        cx.span_err(item.span, "expected a string literal for `tag`");
+                    }
+                }
+            }
+            MetaNameValue(ref name, ref value) if name.get() == "content" => {
+                match value.node {
+                    LitStr(ref content, _) => {
+                        container_attrs.content = Some(content.clone());
+                    }
+                    _ => {
+                        cx.span_err(item.span, "expected a string literal for `content`");
+                    }
+                }
+            }
+            _ => {
+                cx.span_err(item.span, "unknown serde container attribute");
+            }
+        }
+    });
+
+    container_attrs
+}
+
+fn enum_repr(cx: &ExtCtxt, span: Span, container_attrs: &ContainerAttrs) -> EnumRepr {
+    if container_attrs.discriminant {
+        if container_attrs.tag.is_some()
+            || container_attrs.content.is_some()
+            || container_attrs.untagged {
+            cx.span_err(
+                span,
+                "`discriminant` can't be combined with `tag`, `content` or `untagged`");
+        }
+
+        return EnumRepr::Discriminant;
+    }
+
+    match (&container_attrs.tag, &container_attrs.content, container_attrs.untagged) {
+        (&None, &None, false) => EnumRepr::External,
+        (&Some(ref tag), &None, false) => EnumRepr::Internal(tag.clone()),
+        (&Some(ref tag), &Some(ref content), false) => {
+            EnumRepr::Adjacent(tag.clone(), content.clone())
+        }
+        (&None, &None, true) => EnumRepr::Untagged,
+        (&None, &Some(_), false) => {
+            cx.span_err(span, "`content` requires a `tag`");
+            EnumRepr::External
+        }
+        (_, _, true) => {
+            cx.span_err(span, "`untagged` can't be combined with `tag` or `content`");
+            EnumRepr::Untagged
+        }
+    }
+}
+
+fn item_attrs(cx: &ExtCtxt, span: Span, item: &Item) -> ItemAttrs {
+    let container = container_attrs(cx, item.attrs.as_slice());
+
+    match item.node {
+        ItemStruct(ref definition, _) => {
+            ItemAttrs {
+                container: container,
+                repr: EnumRepr::External,
+                fields: vec![fields_attrs(cx, definition.fields.as_slice())],
+            }
+        }
+        ItemEnum(ref definition, _) => {
+            let repr = enum_repr(cx, span, &container);
+
+            for variant in definition.variants.iter() {
+                match (&repr, &variant.node.kind) {
+                    (&EnumRepr::Internal(_), &ast::TupleVariantKind(ref args))
+                            if !args.is_empty() => {
+                        cx.span_err(
+                            variant.span,
+                            "internally tagged enums only support unit and struct variants");
+                    }
+                    (&EnumRepr::Discriminant, &ast::TupleVariantKind(ref args))
+                            if args.is_empty() => { }
+                    (&EnumRepr::Discriminant, _) => {
+                        cx.span_err(variant.span, "`discriminant` only supports unit variants");
+                    }
+                    _ => { }
+                }
+            }
+
+            let fields = definition.variants.iter()
+                .map(|variant| fields_attrs(cx, variant_fields(&**variant)))
+                .collect();
+
+            ItemAttrs {
+                container: container,
+                repr: repr,
+                fields: fields,
+            }
+        }
+        _ => cx.span_bug(span, "expected a struct or an enum"),
+    }
+}
+
+fn default_expr(cx: &ExtCtxt, span: Span, default: &FieldDefault) -> P<Expr> {
+    match *default {
+        FieldDefault::Trait => {
+            quote_expr!(cx, ::std::default::Default::default())
+        }
+        FieldDefault::Path(ref path) => {
+            cx.expr_call(span, cx.expr_path(parse_path(cx, span, path.get())), vec!())
+        }
+    }
+}
+
+/// Build the expression deciding whether a field should be left out when
+/// serializing, if any. An explicit `skip_serializing_if` predicate wins over
+/// leaving out an absent `Presence`, or a `None` with the container's
+/// `skip_serializing_none`.
+fn skip_serializing_expr(cx: &ExtCtxt,
+                         span: Span,
+                         container_attrs: &ContainerAttrs,
+                         field_attrs: &FieldAttrs,
+                         def: &StructField,
+                         self_: &P<Expr>) -> Option<P<Expr>> {
+    match field_attrs.skip_serializing_if {
+        Some(ref path) => {
+            let predicate = cx.expr_path(parse_path(cx, span, path.get()));
+            let arg = cx.expr_addr_of(span, self_.clone());
+            Some(cx.expr_call(span, predicate, vec!(arg)))
+        }
+        None if is_type(&*def.node.ty, "Presence") => {
+            Some(quote_expr!(cx, $self_.is_absent()))
+        }
+        None if container_attrs.skip_serializing_none && is_type(&*def.node.ty, "Option") => {
+            Some(quote_expr!(cx, $self_.is_none()))
+        }
+        None => None,
+    }
+}
+
+/// Whether the field's type is spelled as `name`, like `Option`.
+fn is_type(ty: &ast::Ty, name: &str) -> bool {
+    match ty.node {
+        ast::TyPath(ref path, _) => {
+            match path.segments.last() {
+                Some(segment) => token::get_ident(segment.identifier).get() == name,
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Turn a path like `"foo::bar"` or `"::foo::bar"` from an attribute into an
+/// `ast::Path`.
+fn parse_path(cx: &ExtCtxt, span: Span, path: &str) -> ast::Path {
+    let global = path.starts_with("::");
+    let idents = path.split_str("::")
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| cx.ident_of(segment))
+        .collect();
+
+    if global {
+        cx.path_global(span, idents)
+    } else {
+        cx.path(span, idents)
+    }
+}
@@ -0,0 +1,1245 @@
+//! A compact binary serialization format.
+//!
+//! Values are written without any names or type information, so they can
+//! only be read back as the type that wrote them:
+//!
+//! * `()` and unit structs take no space, and `bool`s take one byte.
+//! * Integers are either written at their full width or as varints, as set
+//!   by `Config::int_encoding`. Varints hold seven bits per byte, and signed
+//!   integers are zigzag encoded first so that small negative numbers stay
+//!   small. Single byte integers are always written as they are.
+//! * `int` and `uint` are as wide as on the platform that wrote them, so
+//!   they should be read back on a platform of the same width when they are
+//!   not varints.
+//! * Floats are written as their IEEE 754 bits, and `char`s as a `u32`.
+//! * Strings, sequences and maps start with their length as a `u64`.
+//! * Options start with a `0` or `1` byte.
+//! * Tuples and structs are their fields in order.
+//! * Enums start with the index of their variant as a `u32`.
+//!
+//! Since the input doesn't describe itself, types that need to look at it
+//! to know what they are can't be read, and fail with
+//! `Error::NotSelfDescribing`: `json::Value`, internally tagged, adjacently
+//! tagged and untagged enums. Structs that leave out fields with
+//! `skip_serializing_if` or `skip_serializing_none` can't be read back
+//! either.
+
+use std::borrow::Cow;
+use std::char;
+use std::cmp;
+use std::error;
+use std::io::{self, IoError, IoResult};
+use std::mem;
+use std::num;
+use std::string::CowString;
+
+use de::{self, Deserialize, Token, TokenKind};
+use ser::{self, Serialize};
+
+/// The order in which the bytes of fixed width numbers are written.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum ByteOrder {
+    BigEndian,
+    LittleEndian,
+}
+
+/// How integers are written.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum IntEncoding {
+    /// Every integer takes its full width.
+    Fixed,
+    /// Integers take as many bytes as their value needs.
+    Varint,
+}
+
+/// The options shared by the serializer and the deserializer. Data has to be
+/// read with the same options it was written with.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub struct Config {
+    byte_order: ByteOrder,
+    int_encoding: IntEncoding,
+    limit: Option<u64>,
+}
+
+impl Config {
+    /// Creates a configuration for little endian varints, with no size
+    /// limit.
+    #[inline]
+    pub fn new() -> Config {
+        Config {
+            byte_order: ByteOrder::LittleEndian,
+            int_encoding: IntEncoding::Varint,
+            limit: None,
+        }
+    }
+
+    /// Sets the order in which fixed width integers and floats are written.
+    #[inline]
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Config {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// Sets how integers are written.
+    #[inline]
+    pub fn int_encoding(mut self, int_encoding: IntEncoding) -> Config {
+        self.int_encoding = int_encoding;
+        self
+    }
+
+    /// Sets the most bytes that may be written or read for a single value.
+    /// Going over it fails with `Error::SizeLimit`, which protects readers
+    /// from lengths that would allocate more than the input could hold.
+    #[inline]
+    pub fn limit(mut self, limit: u64) -> Config {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, PartialEq, Show)]
+pub enum Error {
+    IoError(IoError),
+    /// The input ended in the middle of a value.
+    EndOfStream,
+    /// The value is larger than the configured limit.
+    SizeLimit,
+    /// A `bool` wasn't a `0` or a `1`.
+    InvalidBool(u8),
+    /// An `Option` didn't start with a `0` or a `1`.
+    InvalidOptionTag(u8),
+    /// A `char` wasn't a unicode scalar value.
+    InvalidChar(u32),
+    /// A string wasn't valid UTF-8.
+    InvalidUtf8,
+    /// A varint ran past 64 bits.
+    VarintOverflow,
+    /// An integer doesn't fit in the type being read.
+    IntegerOverflow,
+    /// An enum variant index that the enum doesn't have.
+    UnknownVariant(u32),
+    /// An enum was serialized without the index of its variant.
+    MissingVariantIndex,
+    /// A type asked for the next token of the input, which only formats
+    /// that describe their values have.
+    NotSelfDescribing,
+    SyntaxError(Token, &'static [TokenKind]),
+    UnexpectedName(Token),
+    ConversionError(Token),
+    MissingField(&'static str),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::IoError(ref error) => error.description(),
+            Error::EndOfStream => "end of stream",
+            Error::SizeLimit => "size limit exceeded",
+            Error::InvalidBool(_) => "invalid bool",
+            Error::InvalidOptionTag(_) => "invalid option tag",
+            Error::InvalidChar(_) => "invalid char",
+            Error::InvalidUtf8 => "string is not utf-8",
+            Error::VarintOverflow => "varint overflow",
+            Error::IntegerOverflow => "integer overflow",
+            Error::UnknownVariant(_) => "unknown variant",
+            Error::MissingVariantIndex => "enum written without a variant index",
+            Error::NotSelfDescribing => "type can only be read from a self-describing format",
+            Error::SyntaxError(..) => "syntax error",
+            Error::UnexpectedName(_) => "unexpected name",
+            Error::ConversionError(_) => "conversion error",
+            Error::MissingField(_) => "missing field",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match *self {
+            Error::IoError(ref error) => error.detail(),
+            Error::InvalidBool(v) => Some(format!("expected 0 or 1, found {}", v)),
+            Error::InvalidOptionTag(v) => Some(format!("expected 0 or 1, found {}", v)),
+            Error::InvalidChar(v) => Some(format!("{:x} is not a char", v)),
+            Error::UnknownVariant(v) => Some(format!("unknown variant {}", v)),
+            Error::SyntaxError(ref token, tokens) => {
+                Some(format!("expected {:?}, found {:?}", tokens, token))
+            }
+            Error::UnexpectedName(ref token) => Some(format!("unexpected name {:?}", token)),
+            Error::ConversionError(ref token) => Some(format!("failed to convert {:?}", token)),
+            Error::MissingField(field) => Some(format!("missing field {:?}", field)),
+            _ => None,
+        }
+    }
+}
+
+impl error::FromError<IoError> for Error {
+    fn from_error(error: IoError) -> Error {
+        if error.kind == io::EndOfFile {
+            Error::EndOfStream
+        } else {
+            Error::IoError(error)
+        }
+    }
+}
+
+#[inline]
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+#[inline]
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A structure for serializing values into the binary format.
+pub struct Serializer<W> {
+    wr: W,
+    config: Config,
+    written: u64,
+    // Sequences and maps whose length isn't known up front are written here
+    // first, so that their length can go before them.
+    buffers: Vec<Vec<u8>>,
+}
+
+impl<W: Writer> Serializer<W> {
+    /// Creates a new serializer with the default `Config`.
+    #[inline]
+    pub fn new(wr: W) -> Serializer<W> {
+        Serializer::with_config(wr, Config::new())
+    }
+
+    #[inline]
+    pub fn with_config(wr: W, config: Config) -> Serializer<W> {
+        Serializer {
+            wr: wr,
+            config: config,
+            written: 0,
+            buffers: Vec::new(),
+        }
+    }
+
+    /// Unwrap the Writer from the Serializer.
+    #[inline]
+    pub fn unwrap(self) -> W {
+        self.wr
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        match self.buffers.last_mut() {
+            Some(buf) => {
+                buf.push_all(bytes);
+                Ok(())
+            }
+            None => {
+                try!(self.wr.write(bytes));
+                Ok(())
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.written += bytes.len() as u64;
+
+        match self.config.limit {
+            Some(limit) if self.written > limit => Err(Error::SizeLimit),
+            _ => self.write_raw(bytes),
+        }
+    }
+
+    fn write_fixed(&mut self, v: u64, size: uint) -> Result<(), Error> {
+        let mut buf = [0u8; 8];
+
+        for i in range(0, size) {
+            let shift = match self.config.byte_order {
+                ByteOrder::BigEndian => (size - i - 1) * 8,
+                ByteOrder::LittleEndian => i * 8,
+            };
+            buf[i] = (v >> shift) as u8;
+        }
+
+        self.write_bytes(buf.slice_to(size))
+    }
+
+    fn write_varint(&mut self, mut v: u64) -> Result<(), Error> {
+        let mut buf = [0u8; 10];
+        let mut len = 0;
+
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+
+            if v == 0 {
+                buf[len] = byte;
+                len += 1;
+                break;
+            }
+
+            buf[len] = byte | 0x80;
+            len += 1;
+        }
+
+        self.write_bytes(buf.slice_to(len))
+    }
+
+    #[inline]
+    fn write_unsigned(&mut self, v: u64, size: uint) -> Result<(), Error> {
+        match self.config.int_encoding {
+            IntEncoding::Varint if size > 1 => self.write_varint(v),
+            _ => self.write_fixed(v, size),
+        }
+    }
+
+    #[inline]
+    fn write_signed(&mut self, v: i64, size: uint) -> Result<(), Error> {
+        match self.config.int_encoding {
+            IntEncoding::Varint if size > 1 => self.write_varint(zigzag(v)),
+            _ => self.write_fixed(v as u64, size),
+        }
+    }
+
+    #[inline]
+    fn write_len(&mut self, len: uint) -> Result<(), Error> {
+        self.write_unsigned(len as u64, 8)
+    }
+}
+
+impl<W: Writer> ser::Serializer<Error> for Serializer<W> {
+    #[inline]
+    fn serialize_null(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> Result<(), Error> {
+        self.write_bytes(&[v as u8])
+    }
+
+    #[inline]
+    fn serialize_int(&mut self, v: int) -> Result<(), Error> {
+        self.write_signed(v as i64, mem::size_of::<int>())
+    }
+
+    #[inline]
+    fn serialize_i8(&mut self, v: i8) -> Result<(), Error> {
+        self.write_signed(v as i64, 1)
+    }
+
+    #[inline]
+    fn serialize_i16(&mut self, v: i16) -> Result<(), Error> {
+        self.write_signed(v as i64, 2)
+    }
+
+    #[inline]
+    fn serialize_i32(&mut self, v: i32) -> Result<(), Error> {
+        self.write_signed(v as i64, 4)
+    }
+
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> Result<(), Error> {
+        self.write_signed(v, 8)
+    }
+
+    #[inline]
+    fn serialize_uint(&mut self, v: uint) -> Result<(), Error> {
+        self.write_unsigned(v as u64, mem::size_of::<uint>())
+    }
+
+    #[inline]
+    fn serialize_u8(&mut self, v: u8) -> Result<(), Error> {
+        self.write_unsigned(v as u64, 1)
+    }
+
+    #[inline]
+    fn serialize_u16(&mut self, v: u16) -> Result<(), Error> {
+        self.write_unsigned(v as u64, 2)
+    }
+
+    #[inline]
+    fn serialize_u32(&mut self, v: u32) -> Result<(), Error> {
+        self.write_unsigned(v as u64, 4)
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> Result<(), Error> {
+        self.write_unsigned(v, 8)
+    }
+
+    #[inline]
+    fn serialize_f32(&mut self, v: f32) -> Result<(), Error> {
+        let bits: u32 = unsafe { mem::transmute(v) };
+        self.write_fixed(bits as u64, 4)
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> Result<(), Error> {
+        let bits: u64 = unsafe { mem::transmute(v) };
+        self.write_fixed(bits, 8)
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> Result<(), Error> {
+        self.write_unsigned(v as u64, 4)
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> Result<(), Error> {
+        try!(self.write_len(v.len()));
+        self.write_bytes(v.as_bytes())
+    }
+
+    // The same as a sequence of `u8`s, just without writing them one by one.
+    #[inline]
+    fn serialize_bytes(&mut self, v: &[u8]) -> Result<(), Error> {
+        try!(self.write_len(v.len()));
+        self.write_bytes(v)
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, _len: uint) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: Serialize<Serializer<W>, Error>
+    >(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, _len: uint) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: Serialize<Serializer<W>, Error>
+    >(&mut self, _name: &str, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // Variants are only written by their index.
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, _variant: &str, _len: uint) -> Result<(), Error> {
+        Err(Error::MissingVariantIndex)
+    }
+
+    #[inline]
+    fn serialize_enum_variant_start(&mut self,
+                                    _name: &str,
+                                    variant_index: uint,
+                                    _variant: &str,
+                                    _len: uint) -> Result<(), Error> {
+        self.write_unsigned(variant_index as u64, 4)
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: Serialize<Serializer<W>, Error>
+    >(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: Serialize<Serializer<W>, Error>
+    >(&mut self, v: &Option<T>) -> Result<(), Error> {
+        match *v {
+            Some(ref v) => {
+                try!(self.write_bytes(&[1]));
+                v.serialize(self)
+            }
+            None => {
+                self.write_bytes(&[0])
+            }
+        }
+    }
+
+    #[inline]
+    fn serialize_seq<
+        T: Serialize<Serializer<W>, Error>,
+        Iter: Iterator<Item=T>
+    >(&mut self, mut iter: Iter) -> Result<(), Error> {
+        match iter.size_hint() {
+            (lo, Some(hi)) if lo == hi => {
+                try!(self.write_len(lo));
+                for elt in iter {
+                    try!(elt.serialize(self));
+                }
+                Ok(())
+            }
+            _ => {
+                self.buffers.push(Vec::new());
+                let mut len = 0;
+                for elt in iter {
+                    try!(elt.serialize(self));
+                    len += 1;
+                }
+                let buf = self.buffers.pop().unwrap();
+
+                // The elements were already counted against the limit.
+                try!(self.write_len(len));
+                self.write_raw(buf.as_slice())
+            }
+        }
+    }
+
+    #[inline]
+    fn serialize_map<
+        K: Serialize<Serializer<W>, Error>,
+        V: Serialize<Serializer<W>, Error>,
+        Iter: Iterator<Item=(K, V)>
+    >(&mut self, mut iter: Iter) -> Result<(), Error> {
+        match iter.size_hint() {
+            (lo, Some(hi)) if lo == hi => {
+                try!(self.write_len(lo));
+                for (key, value) in iter {
+                    try!(key.serialize(self));
+                    try!(value.serialize(self));
+                }
+                Ok(())
+            }
+            _ => {
+                self.buffers.push(Vec::new());
+                let mut len = 0;
+                for (key, value) in iter {
+                    try!(key.serialize(self));
+                    try!(value.serialize(self));
+                    len += 1;
+                }
+                let buf = self.buffers.pop().unwrap();
+
+                // The entries were already counted against the limit.
+                try!(self.write_len(len));
+                self.write_raw(buf.as_slice())
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// The most elements that are allocated up front for a sequence or map, so a
+/// corrupt length can't make the reader run out of memory before it runs out
+/// of input.
+static MAX_PREALLOCATION: uint = 4096;
+
+/// A structure for deserializing values from the binary format.
+pub struct Deserializer<R> {
+    rdr: R,
+    config: Config,
+    read: u64,
+    // The index of the next field of each struct being read.
+    fields: Vec<uint>,
+    // The number of elements left in each sequence and map being read.
+    remaining: Vec<uint>,
+}
+
+impl<R: Reader> Deserializer<R> {
+    /// Creates a new deserializer with the default `Config`.
+    #[inline]
+    pub fn new(rdr: R) -> Deserializer<R> {
+        Deserializer::with_config(rdr, Config::new())
+    }
+
+    #[inline]
+    pub fn with_config(rdr: R, config: Config) -> Deserializer<R> {
+        Deserializer {
+            rdr: rdr,
+            config: config,
+            read: 0,
+            fields: Vec::new(),
+            remaining: Vec::new(),
+        }
+    }
+
+    /// Unwrap the Reader from the Deserializer.
+    #[inline]
+    pub fn unwrap(self) -> R {
+        self.rdr
+    }
+
+    /// Counts `len` bytes against the limit before they are read.
+    fn consume(&mut self, len: u64) -> Result<(), Error> {
+        match self.config.limit {
+            Some(limit) if len > limit - self.read => {
+                return Err(Error::SizeLimit);
+            }
+            _ => { }
+        }
+
+        self.read += len;
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        try!(self.consume(1));
+        Ok(try!(self.rdr.read_u8()))
+    }
+
+    fn read_bytes(&mut self, len: uint) -> Result<Vec<u8>, Error> {
+        try!(self.consume(len as u64));
+
+        // Read in chunks, so a corrupt length fails at the end of the input
+        // rather than by allocating all of it.
+        let mut buf = Vec::with_capacity(cmp::min(len, MAX_PREALLOCATION));
+        while buf.len() < len {
+            let chunk = cmp::min(len - buf.len(), MAX_PREALLOCATION);
+            try!(self.rdr.push_at_least(chunk, chunk, &mut buf));
+        }
+
+        Ok(buf)
+    }
+
+    fn read_fixed(&mut self, size: uint) -> Result<u64, Error> {
+        let mut v = 0;
+
+        for i in range(0, size) {
+            let byte = try!(self.read_byte()) as u64;
+            let shift = match self.config.byte_order {
+                ByteOrder::BigEndian => (size - i - 1) * 8,
+                ByteOrder::LittleEndian => i * 8,
+            };
+            v |= byte << shift;
+        }
+
+        Ok(v)
+    }
+
+    fn read_varint(&mut self) -> Result<u64, Error> {
+        let mut v = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = try!(self.read_byte());
+
+            // The tenth byte only has room for the top bit.
+            if shift == 63 && byte > 1 {
+                return Err(Error::VarintOverflow);
+            }
+
+            v |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+
+            shift += 7;
+        }
+    }
+
+    fn read_unsigned(&mut self, size: uint) -> Result<u64, Error> {
+        let v = match self.config.int_encoding {
+            IntEncoding::Varint if size > 1 => try!(self.read_varint()),
+            _ => try!(self.read_fixed(size)),
+        };
+
+        if size < 8 && v >> (size * 8) != 0 {
+            Err(Error::IntegerOverflow)
+        } else {
+            Ok(v)
+        }
+    }
+
+    fn read_signed(&mut self, size: uint) -> Result<i64, Error> {
+        match self.config.int_encoding {
+            IntEncoding::Varint if size > 1 => {
+                let v = unzigzag(try!(self.read_varint()));
+                let bits = size * 8;
+
+                if size < 8 && (v < -(1 << (bits - 1)) || v >= 1 << (bits - 1)) {
+                    Err(Error::IntegerOverflow)
+                } else {
+                    Ok(v)
+                }
+            }
+            _ => {
+                // Sign extend from the top bit that was read.
+                let shift = 64 - size * 8;
+                let v = try!(self.read_fixed(size));
+                Ok(((v << shift) as i64) >> shift)
+            }
+        }
+    }
+
+    fn read_len(&mut self) -> Result<uint, Error> {
+        let len = try!(self.read_unsigned(8));
+        match num::cast(len) {
+            Some(len) => Ok(len),
+            None => Err(Error::IntegerOverflow),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, Error> {
+        let len = try!(self.read_len());
+        let bytes = try!(self.read_bytes(len));
+        match String::from_utf8(bytes) {
+            Ok(v) => Ok(v),
+            Err(_) => Err(Error::InvalidUtf8),
+        }
+    }
+}
+
+impl<R: Reader> Iterator for Deserializer<R> {
+    type Item = Result<Token, Error>;
+
+    // The input has no tokens to read. Values are read straight from it by
+    // the `expect_next_*` methods below, as the type being read asks for.
+    #[inline]
+    fn next(&mut self) -> Option<Result<Token, Error>> {
+        Some(Err(Error::NotSelfDescribing))
+    }
+}
+
+impl<R: Reader> de::Deserializer<Error> for Deserializer<R> {
+    fn end_of_stream_error(&mut self) -> Error {
+        Error::EndOfStream
+    }
+
+    fn syntax_error(&mut self, token: Token, expected: &'static [TokenKind]) -> Error {
+        Error::SyntaxError(token, expected)
+    }
+
+    fn unexpected_name_error(&mut self, token: Token) -> Error {
+        Error::UnexpectedName(token)
+    }
+
+    fn conversion_error(&mut self, token: Token) -> Error {
+        Error::ConversionError(token)
+    }
+
+    #[inline]
+    fn missing_field<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self, field: &'static str) -> Result<T, Error> {
+        Err(Error::MissingField(field))
+    }
+
+    #[inline]
+    fn expect_next_null(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn expect_next_bool(&mut self) -> Result<bool, Error> {
+        match try!(self.read_byte()) {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(Error::InvalidBool(v)),
+        }
+    }
+
+    #[inline]
+    fn expect_next_int(&mut self) -> Result<int, Error> {
+        Ok(try!(self.read_signed(mem::size_of::<int>())) as int)
+    }
+
+    #[inline]
+    fn expect_next_i8(&mut self) -> Result<i8, Error> {
+        Ok(try!(self.read_signed(1)) as i8)
+    }
+
+    #[inline]
+    fn expect_next_i16(&mut self) -> Result<i16, Error> {
+        Ok(try!(self.read_signed(2)) as i16)
+    }
+
+    #[inline]
+    fn expect_next_i32(&mut self) -> Result<i32, Error> {
+        Ok(try!(self.read_signed(4)) as i32)
+    }
+
+    #[inline]
+    fn expect_next_i64(&mut self) -> Result<i64, Error> {
+        self.read_signed(8)
+    }
+
+    #[inline]
+    fn expect_next_uint(&mut self) -> Result<uint, Error> {
+        Ok(try!(self.read_unsigned(mem::size_of::<uint>())) as uint)
+    }
+
+    #[inline]
+    fn expect_next_u8(&mut self) -> Result<u8, Error> {
+        Ok(try!(self.read_unsigned(1)) as u8)
+    }
+
+    #[inline]
+    fn expect_next_u16(&mut self) -> Result<u16, Error> {
+        Ok(try!(self.read_unsigned(2)) as u16)
+    }
+
+    #[inline]
+    fn expect_next_u32(&mut self) -> Result<u32, Error> {
+        Ok(try!(self.read_unsigned(4)) as u32)
+    }
+
+    #[inline]
+    fn expect_next_u64(&mut self) -> Result<u64, Error> {
+        self.read_unsigned(8)
+    }
+
+    #[inline]
+    fn expect_next_f32(&mut self) -> Result<f32, Error> {
+        let bits = try!(self.read_fixed(4)) as u32;
+        Ok(unsafe { mem::transmute::<u32, f32>(bits) })
+    }
+
+    #[inline]
+    fn expect_next_f64(&mut self) -> Result<f64, Error> {
+        let bits = try!(self.read_fixed(8));
+        Ok(unsafe { mem::transmute::<u64, f64>(bits) })
+    }
+
+    #[inline]
+    fn expect_next_char(&mut self) -> Result<char, Error> {
+        let v = try!(self.read_unsigned(4)) as u32;
+        match char::from_u32(v) {
+            Some(v) => Ok(v),
+            None => Err(Error::InvalidChar(v)),
+        }
+    }
+
+    #[inline]
+    fn expect_next_string(&mut self) -> Result<String, Error> {
+        self.read_string()
+    }
+
+    #[inline]
+    fn expect_next_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let len = try!(self.read_len());
+        self.read_bytes(len)
+    }
+
+    #[inline]
+    fn expect_next_option<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self) -> Result<Option<T>, Error> {
+        match try!(self.read_byte()) {
+            0 => Ok(None),
+            1 => Ok(Some(try!(Deserialize::deserialize(self)))),
+            v => Err(Error::InvalidOptionTag(v)),
+        }
+    }
+
+    #[inline]
+    fn expect_next_tuple_start(&mut self) -> Result<uint, Error> {
+        Ok(0)
+    }
+
+    #[inline]
+    fn expect_tuple_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn expect_next_struct_start(&mut self, _name: &str) -> Result<(), Error> {
+        self.fields.push(0);
+        Ok(())
+    }
+
+    // Fields are always written, in order, so they are read back the same
+    // way without looking at their names.
+    #[inline]
+    fn expect_struct_field_or_end(&mut self,
+                                  fields: &'static [&'static str]
+                                 ) -> Result<Option<Option<uint>>, Error> {
+        Ok(try!(self.expect_known_struct_field_or_end(fields)).map(Some))
+    }
+
+    #[inline]
+    fn expect_known_struct_field_or_end(&mut self,
+                                        fields: &'static [&'static str]
+                                       ) -> Result<Option<uint>, Error> {
+        match self.fields.pop() {
+            Some(idx) if idx < fields.len() => {
+                self.fields.push(idx + 1);
+                Ok(Some(idx))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn expect_struct_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn expect_next_enum_start(&mut self, _name: &str, variants: &[&str]) -> Result<uint, Error> {
+        let idx = try!(self.read_unsigned(4));
+        if idx < variants.len() as u64 {
+            Ok(idx as uint)
+        } else {
+            Err(Error::UnknownVariant(idx as u32))
+        }
+    }
+
+    #[inline]
+    fn expect_enum_struct_start(&mut self, name: &str) -> Result<(), Error> {
+        self.expect_next_struct_start(name)
+    }
+
+    #[inline]
+    fn expect_enum_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn expect_next_seq_start(&mut self) -> Result<uint, Error> {
+        let len = try!(self.read_len());
+        self.remaining.push(len);
+        Ok(cmp::min(len, MAX_PREALLOCATION))
+    }
+
+    #[inline]
+    fn expect_seq_elt_or_end<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self) -> Result<Option<T>, Error> {
+        match self.remaining.pop() {
+            Some(0) | None => Ok(None),
+            Some(len) => {
+                self.remaining.push(len - 1);
+                Ok(Some(try!(Deserialize::deserialize(self))))
+            }
+        }
+    }
+
+    #[inline]
+    fn expect_next_map_start(&mut self) -> Result<uint, Error> {
+        let len = try!(self.read_len());
+        self.remaining.push(len);
+        Ok(cmp::min(len, MAX_PREALLOCATION))
+    }
+
+    #[inline]
+    fn expect_map_elt_or_end<
+        K: Deserialize<Deserializer<R>, Error>,
+        V: Deserialize<Deserializer<R>, Error>
+    >(&mut self) -> Result<Option<(K, V)>, Error> {
+        match self.remaining.pop() {
+            Some(0) | None => Ok(None),
+            Some(len) => {
+                self.remaining.push(len - 1);
+                let key = try!(Deserialize::deserialize(self));
+                let value = try!(Deserialize::deserialize(self));
+                Ok(Some((key, value)))
+            }
+        }
+    }
+}
+
+// Strings are always copied out of the reader.
+impl<'a, R: Reader> de::BorrowDeserializer<'a, Error> for Deserializer<R> {
+    #[inline]
+    fn expect_borrowed_str(&mut self) -> Result<CowString<'a>, Error> {
+        Ok(Cow::Owned(try!(self.read_string())))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A `Writer` that only counts the bytes written to it.
+pub struct SizeCounter {
+    size: u64,
+}
+
+impl Writer for SizeCounter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// Encode the specified value into a binary `[u8]` writer.
+#[inline]
+pub fn to_writer<
+    W: Writer,
+    T: Serialize<Serializer<W>, Error>
+>(writer: W, value: &T, config: Config) -> Result<W, Error> {
+    let mut serializer = Serializer::with_config(writer, config);
+    try!(value.serialize(&mut serializer));
+    Ok(serializer.unwrap())
+}
+
+/// Encode the specified value into a binary `[u8]` buffer.
+#[inline]
+pub fn to_vec<
+    T: Serialize<Serializer<Vec<u8>>, Error>
+>(value: &T, config: Config) -> Result<Vec<u8>, Error> {
+    to_writer(Vec::with_capacity(128), value, config)
+}
+
+/// Returns the number of bytes the value is encoded in, without keeping
+/// them.
+#[inline]
+pub fn serialized_size<
+    T: Serialize<Serializer<SizeCounter>, Error>
+>(value: &T, config: Config) -> Result<u64, Error> {
+    let counter = try!(to_writer(SizeCounter { size: 0 }, value, config));
+    Ok(counter.size)
+}
+
+/// Decodes a binary value from a `Reader`.
+#[inline]
+pub fn from_reader<
+    R: Reader,
+    T: Deserialize<Deserializer<R>, Error>
+>(rdr: R, config: Config) -> Result<T, Error> {
+    let mut deserializer = Deserializer::with_config(rdr, config);
+    Deserialize::deserialize(&mut deserializer)
+}
+
+/// Decodes a binary value from a `[u8]` buffer.
+#[inline]
+pub fn from_slice<
+    'a,
+    T: Deserialize<Deserializer<io::BufReader<'a>>, Error>
+>(v: &'a [u8], config: Config) -> Result<T, Error> {
+    from_reader(io::BufReader::new(v), config)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::fmt::Show;
+    use std::io::MemReader;
+    use std::{i64, u64};
+
+    use de::{Deserialize, Presence};
+    use json;
+    use ser::{self, Serialize};
+
+    use super::{ByteOrder, Config, Deserializer, Error, IntEncoding, Serializer};
+    use super::{from_reader, from_slice, serialized_size, to_vec};
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Inner {
+        a: (),
+        b: uint,
+        c: HashMap<String, Option<char>>,
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Outer {
+        inner: Vec<Inner>,
+        point: (i8, i16, f32, f64),
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    enum Animal {
+        Dog,
+        Frog(String, int),
+        Cat { age: uint, name: String },
+    }
+
+    fn test_round_trip<
+        T: PartialEq + Show + Serialize<Serializer<Vec<u8>>, Error>
+                     + Deserialize<Deserializer<MemReader>, Error>
+    >(value: &T, config: Config) {
+        let bytes = to_vec(value, config).unwrap();
+        let v: T = from_reader(MemReader::new(bytes), config).unwrap();
+        assert_eq!(v, *value);
+    }
+
+    #[test]
+    fn test_write_primitives() {
+        let value = (1u16, -1i32, "ab", true, 'a');
+
+        let bytes = to_vec(&value, Config::new()).unwrap();
+        assert_eq!(bytes, vec![1, 1, 2, b'a', b'b', 1, 97]);
+
+        let config = Config::new()
+            .int_encoding(IntEncoding::Fixed)
+            .byte_order(ByteOrder::BigEndian);
+        let bytes = to_vec(&value, config).unwrap();
+        assert_eq!(bytes, vec![
+            0, 1,
+            0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0, 0, 0, 0, 2, b'a', b'b',
+            1,
+            0, 0, 0, 97,
+        ]);
+
+        let config = Config::new().int_encoding(IntEncoding::Fixed);
+        let bytes = to_vec(&(300u16, 1.0f32), config).unwrap();
+        assert_eq!(bytes, vec![0x2c, 0x01, 0, 0, 0x80, 0x3f]);
+    }
+
+    #[test]
+    fn test_write_varint() {
+        assert_eq!(to_vec(&300u32, Config::new()).unwrap(), vec![0xac, 0x02]);
+        assert_eq!(to_vec(&-2i64, Config::new()).unwrap(), vec![3]);
+        assert_eq!(
+            to_vec(&u64::MAX, Config::new()).unwrap(),
+            vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+
+        // A single byte is never a varint.
+        assert_eq!(to_vec(&200u8, Config::new()).unwrap(), vec![200]);
+    }
+
+    #[test]
+    fn test_write_enum() {
+        assert_eq!(to_vec(&Animal::Dog, Config::new()).unwrap(), vec![0]);
+        assert_eq!(
+            to_vec(&Animal::Frog("Henry".to_string(), 349), Config::new()).unwrap(),
+            vec![1, 5, b'H', b'e', b'n', b'r', b'y', 0xba, 0x05]);
+    }
+
+    #[test]
+    fn test_round_trip_values() {
+        let mut map = HashMap::new();
+        map.insert("abc".to_string(), Some('c'));
+        map.insert("def".to_string(), None);
+
+        let outer = Outer {
+            inner: vec![
+                Inner { a: (), b: 5, c: map },
+                Inner { a: (), b: 0, c: HashMap::new() },
+            ],
+            point: (-128, 1000, 1.5, -0.25),
+        };
+
+        let mut tree = BTreeMap::new();
+        tree.insert(-3i64, vec![Animal::Dog]);
+        tree.insert(70000, vec![
+            Animal::Frog("Henry".to_string(), -349),
+            Animal::Cat { age: 3, name: "Tom".to_string() },
+        ]);
+
+        let configs = [
+            Config::new(),
+            Config::new().int_encoding(IntEncoding::Fixed),
+            Config::new().int_encoding(IntEncoding::Fixed).byte_order(ByteOrder::BigEndian),
+        ];
+
+        for config in configs.iter() {
+            test_round_trip(&outer, *config);
+            test_round_trip(&tree, *config);
+            test_round_trip(&(i64::MIN, u64::MAX, -1i16), *config);
+            test_round_trip(&Some("\u{1f600}".to_string()), *config);
+            test_round_trip(&vec![Presence::Value(5i), Presence::Null], *config);
+        }
+    }
+
+    #[test]
+    fn test_write_unsized_seq() {
+        let mut serializer = Serializer::new(Vec::new());
+        ser::Serializer::serialize_seq(
+            &mut serializer,
+            vec![vec![1u8, 2, 3], vec![4, 5]].into_iter()
+                .map(|v| v.into_iter().filter(|x| *x % 2 == 1).collect::<Vec<u8>>())
+                .filter(|v| !v.is_empty())).unwrap();
+
+        assert_eq!(serializer.unwrap(), vec![2, 2, 1, 3, 1, 5]);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let value = "hello".to_string();
+
+        assert_eq!(serialized_size(&value, Config::new()), Ok(6));
+        assert_eq!(
+            serialized_size(&value, Config::new().int_encoding(IntEncoding::Fixed)),
+            Ok(13));
+
+        assert_eq!(to_vec(&value, Config::new().limit(6)), Ok(b"\x05hello".to_vec()));
+        assert_eq!(to_vec(&value, Config::new().limit(5)), Err(Error::SizeLimit));
+        assert_eq!(serialized_size(&value, Config::new().limit(5)), Err(Error::SizeLimit));
+
+        let v: Result<String, Error> = from_slice(b"\x05hello", Config::new().limit(6));
+        assert_eq!(v, Ok(value));
+
+        // The length alone would have allocated a terabyte.
+        let v: Result<String, Error> = from_slice(
+            b"\x80\x80\x80\x80\x80\x20hello", Config::new().limit(1024));
+        assert_eq!(v, Err(Error::SizeLimit));
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let v: Result<String, Error> = from_slice(b"\x05hel", Config::new());
+        assert_eq!(v, Err(Error::EndOfStream));
+
+        let v: Result<bool, Error> = from_slice(b"\x02", Config::new());
+        assert_eq!(v, Err(Error::InvalidBool(2)));
+
+        let v: Result<Option<int>, Error> = from_slice(b"\x02", Config::new());
+        assert_eq!(v, Err(Error::InvalidOptionTag(2)));
+
+        let v: Result<u16, Error> = from_slice(b"\x80\x80\x04", Config::new());
+        assert_eq!(v, Err(Error::IntegerOverflow));
+
+        let v: Result<u64, Error> = from_slice(
+            b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02", Config::new());
+        assert_eq!(v, Err(Error::VarintOverflow));
+
+        let v: Result<Animal, Error> = from_slice(b"\x03", Config::new());
+        assert_eq!(v, Err(Error::UnknownVariant(3)));
+
+        let v: Result<String, Error> = from_slice(b"\x01\xff", Config::new());
+        assert_eq!(v, Err(Error::InvalidUtf8));
+    }
+
+    #[test]
+    fn test_read_not_self_describing() {
+        #[derive(PartialEq, Show)]
+        #[derive_deserialize]
+        #[serde(untagged)]
+        enum Untagged {
+            Int(int),
+            Text(String),
+        }
+
+        let v: Result<json::Value, Error> = from_slice(b"\x01", Config::new());
+        assert_eq!(v, Err(Error::NotSelfDescribing));
+
+        let v: Result<Untagged, Error> = from_slice(b"\x01", Config::new());
+        assert_eq!(v, Err(Error::NotSelfDescribing));
+
+        // Values that know their type still read the input as before.
+        let v: Result<(Vec<i8>, f64), Error> = from_slice(
+            b"\x02\xff\x7f\x00\x00\x00\x00\x00\x00\xf0\x3f", Config::new());
+        assert_eq!(v, Ok((vec![-1, 127], 1.0)));
+    }
+
+    #[test]
+    fn test_write_enum_without_index() {
+        struct Shape;
+
+        impl<S: ser::Serializer<E>, E> Serialize<S, E> for Shape {
+            fn serialize(&self, s: &mut S) -> Result<(), E> {
+                try!(s.serialize_enum_start("Shape", "Square", 0));
+                s.serialize_enum_end()
+            }
+        }
+
+        assert_eq!(to_vec(&Shape, Config::new()), Err(Error::MissingVariantIndex));
+    }
+}
@@ -0,0 +1,140 @@
+//! Helpers shared by the MessagePack and CBOR deserializers, which read from
+//! a `Reader` and write enums the same way: a unit variant as its name, and
+//! any other variant as a map from its name to an array of its fields.
+
+use std::cmp;
+use std::io::{self, IoResult};
+
+use de::{self, Deserialize, Token, TokenKind};
+
+/// The most elements that are allocated up front for an array, map or
+/// string, so a corrupt length can't make the reader run out of memory
+/// before it runs out of input.
+pub static MAX_PREALLOCATION: uint = 4096;
+
+static STR_TOKEN_KINDS: &'static [TokenKind] = &[
+    TokenKind::StrKind,
+    TokenKind::StringKind,
+];
+
+/// The errors a format reports for enums it can't read.
+pub trait EnumError {
+    fn unknown_variant(variant: String) -> Self;
+
+    /// A variant with fields was written as just its name.
+    fn missing_variant_fields() -> Self;
+}
+
+/// Reads `len` bytes in chunks, so a corrupt length fails at the end of the
+/// input rather than by allocating all of it.
+pub fn read_bytes<R: Reader>(rdr: &mut R, len: uint) -> IoResult<Vec<u8>> {
+    let mut buf = Vec::with_capacity(cmp::min(len, MAX_PREALLOCATION));
+    while buf.len() < len {
+        let chunk = cmp::min(len - buf.len(), MAX_PREALLOCATION);
+        try!(rdr.push_at_least(chunk, chunk, &mut buf));
+    }
+
+    Ok(buf)
+}
+
+/// Returns whether there is no input left in `rdr`.
+pub fn at_end<R: Reader>(rdr: &mut R) -> IoResult<bool> {
+    match rdr.read_u8() {
+        Ok(_) => Ok(false),
+        Err(ref err) if err.kind == io::EndOfFile => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+/// Reads the start of an enum, returning the variant's index and whether it
+/// was written as a bare name.
+pub fn expect_enum_start<
+    D: de::Deserializer<E>,
+    E: EnumError,
+>(d: &mut D, token: Token, variants: &[&str]) -> Result<(uint, bool), E> {
+    let (variant, unit_variant) = match token {
+        Token::String(variant) => (variant, true),
+        Token::MapStart(_) => {
+            // Enums only have one field in them, which is the variant name.
+            let variant = match try!(d.expect_token()) {
+                Token::String(variant) => variant,
+                token => { return Err(d.syntax_error(token, STR_TOKEN_KINDS)); }
+            };
+
+            // The variant's field is an array of the values.
+            match try!(d.expect_token()) {
+                Token::SeqStart(_) => { }
+                token => {
+                    static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                        TokenKind::SeqStartKind,
+                    ];
+                    return Err(d.syntax_error(token, EXPECTED_TOKENS));
+                }
+            }
+
+            (variant, false)
+        }
+        token => {
+            static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                TokenKind::StringKind,
+                TokenKind::MapStartKind,
+            ];
+            return Err(d.syntax_error(token, EXPECTED_TOKENS));
+        }
+    };
+
+    match variants.iter().position(|v| *v == variant.as_slice()) {
+        Some(idx) => Ok((idx, unit_variant)),
+        None => Err(EnumError::unknown_variant(variant)),
+    }
+}
+
+pub fn expect_enum_elt<
+    D: de::Deserializer<E>,
+    E: EnumError,
+    T: Deserialize<D, E>,
+>(d: &mut D, unit_variant: bool) -> Result<T, E> {
+    // A bare variant name can't carry any fields.
+    if unit_variant {
+        return Err(EnumError::missing_variant_fields());
+    }
+
+    Deserialize::deserialize(d)
+}
+
+pub fn expect_enum_struct_start<
+    D: de::Deserializer<E>,
+    E: EnumError,
+>(d: &mut D, unit_variant: bool, name: &str) -> Result<(), E> {
+    if unit_variant {
+        return Err(EnumError::missing_variant_fields());
+    }
+
+    let token = try!(d.expect_token());
+    d.expect_struct_start(token, name)
+}
+
+pub fn expect_enum_end<
+    D: de::Deserializer<E>,
+    E,
+>(d: &mut D, unit_variant: bool) -> Result<(), E> {
+    // Unit variants written as a string have nothing left to consume.
+    if unit_variant {
+        return Ok(());
+    }
+
+    // There will be one `End` for the array, and one for the map.
+    for _ in range(0u, 2) {
+        match try!(d.expect_token()) {
+            Token::End => { }
+            token => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::EndKind,
+                ];
+                return Err(d.syntax_error(token, EXPECTED_TOKENS));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Wrappers for byte buffers that serialize as binary data, rather than as a
+//! sequence of integers, in formats that can tell the two apart.
+
+use std::ops;
+
+use de::{Deserialize, Deserializer, Token};
+use ser::{Serialize, Serializer};
+
+/// A borrowed byte buffer.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl<'a, S: Serializer<E>, E> Serialize<S, E> for Bytes<'a> {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        s.serialize_bytes(self.0)
+    }
+}
+
+/// An owned byte buffer.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub struct ByteBuf(pub Vec<u8>);
+
+impl ByteBuf {
+    #[inline]
+    pub fn new() -> ByteBuf {
+        ByteBuf(Vec::new())
+    }
+
+    /// Unwrap the bytes from the ByteBuf.
+    #[inline]
+    pub fn unwrap(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl ops::Deref for ByteBuf {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+impl<S: Serializer<E>, E> Serialize<S, E> for ByteBuf {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        s.serialize_bytes(self.0.as_slice())
+    }
+}
+
+impl<D: Deserializer<E>, E> Deserialize<D, E> for ByteBuf {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(try!(d.expect_next_bytes())))
+    }
+
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(try!(d.expect_bytes(token))))
+    }
+}
@@ -0,0 +1,1264 @@
+//! CBOR serialization, following RFC 8949.
+//!
+//! Integers, strings, arrays and maps are written as the CBOR major type of
+//! the same name, byte buffers wrapped in `bytes::Bytes` or
+//! `bytes::ByteBuf` as byte strings, and floats at their own width. `None`
+//! is written as `null` and `Some(v)` as just `v`. Structs are written as
+//! maps from their field names to their values, and enums the same way as
+//! in JSON: a unit variant as its name, and any other variant as a map from
+//! its name to an array of its fields. Sequences and maps whose length
+//! isn't known up front are written with an indefinite length.
+//!
+//! A `Tagged` value is written with a semantic tag in front of it. Tags are
+//! otherwise skipped when reading, so a tagged value can be read as the
+//! plain value.
+//!
+//! The canonical serializer follows the core deterministic encoding
+//! requirements instead, so equal values always produce the same bytes:
+//! floats are written in the fewest bytes that hold them exactly, lengths
+//! are always definite, and map keys are sorted by their encoded bytes.
+
+use std::cmp;
+use std::error;
+use std::io::{self, IoError, IoResult};
+use std::mem;
+use std::num::{self, Float};
+use std::{u8, u16, u32, i64};
+
+use binary::{self, MAX_PREALLOCATION};
+use de::{self, Deserialize, Token, TokenKind};
+use ser::{self, Serialize};
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, PartialEq, Show)]
+pub enum Error {
+    IoError(IoError),
+    /// The input ended in the middle of a value.
+    EndOfStream,
+    /// A header with additional information that CBOR reserves.
+    InvalidHeader(u8),
+    /// A chunk of an indefinite length string that isn't a definite length
+    /// string of the same type.
+    InvalidChunk(u8),
+    /// A break outside of an indefinite length item.
+    UnexpectedBreak,
+    /// A simple value that has no Rust equivalent.
+    UnknownSimpleValue(u8),
+    /// A negative integer or a length that doesn't fit the type it's read
+    /// as.
+    IntegerOverflow,
+    /// A text string wasn't valid UTF-8.
+    InvalidUtf8,
+    /// There was more input after the value.
+    TrailingBytes,
+    /// A variant with fields was written as just its name.
+    MissingVariantFields,
+    SyntaxError(Token, &'static [TokenKind]),
+    UnexpectedName(Token),
+    ConversionError(Token),
+    /// field, struct
+    MissingField(&'static str, &'static str),
+    UnknownField(String),
+    UnknownVariant(String),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::IoError(ref error) => error.description(),
+            Error::EndOfStream => "end of stream",
+            Error::InvalidHeader(_) => "invalid header",
+            Error::InvalidChunk(_) => "invalid string chunk",
+            Error::UnexpectedBreak => "unexpected break",
+            Error::UnknownSimpleValue(_) => "unknown simple value",
+            Error::IntegerOverflow => "integer overflow",
+            Error::InvalidUtf8 => "string is not utf-8",
+            Error::TrailingBytes => "trailing bytes",
+            Error::MissingVariantFields => "missing variant fields",
+            Error::SyntaxError(..) => "syntax error",
+            Error::UnexpectedName(_) => "unexpected name",
+            Error::ConversionError(_) => "conversion error",
+            Error::MissingField(..) => "missing field",
+            Error::UnknownField(_) => "unknown field",
+            Error::UnknownVariant(_) => "unknown variant",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match *self {
+            Error::IoError(ref error) => error.detail(),
+            Error::InvalidHeader(v) => Some(format!("invalid header 0x{:x}", v)),
+            Error::InvalidChunk(v) => Some(format!("invalid chunk header 0x{:x}", v)),
+            Error::UnknownSimpleValue(v) => Some(format!("unknown simple value {}", v)),
+            Error::SyntaxError(ref token, tokens) => {
+                Some(format!("expected {:?}, found {:?}", tokens, token))
+            }
+            Error::UnexpectedName(ref token) => Some(format!("unexpected name {:?}", token)),
+            Error::ConversionError(ref token) => Some(format!("failed to convert {:?}", token)),
+            Error::MissingField(field, name) => {
+                Some(format!("missing field `{}` in struct `{}`", field, name))
+            }
+            Error::UnknownField(ref field) => Some(format!("unknown field {:?}", field)),
+            Error::UnknownVariant(ref variant) => Some(format!("unknown variant {:?}", variant)),
+            _ => None,
+        }
+    }
+}
+
+impl error::FromError<IoError> for Error {
+    fn from_error(error: IoError) -> Error {
+        if error.kind == io::EndOfFile {
+            Error::EndOfStream
+        } else {
+            Error::IoError(error)
+        }
+    }
+}
+
+impl binary::EnumError for Error {
+    fn unknown_variant(variant: String) -> Error {
+        Error::UnknownVariant(variant)
+    }
+
+    fn missing_variant_fields() -> Error {
+        Error::MissingVariantFields
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A value with a semantic tag, such as `1` for a value that is an epoch
+/// based date and time. Formats without tags leave them out, so the tag of
+/// a value read from one is `None`.
+#[derive(Clone, PartialEq, Show)]
+pub struct Tagged<T> {
+    pub tag: Option<u64>,
+    pub value: T,
+}
+
+impl<T> Tagged<T> {
+    #[inline]
+    pub fn new(tag: u64, value: T) -> Tagged<T> {
+        Tagged {
+            tag: Some(tag),
+            value: value,
+        }
+    }
+
+    /// Unwrap the value from the Tagged.
+    #[inline]
+    pub fn unwrap(self) -> T {
+        self.value
+    }
+}
+
+impl<S: ser::Serializer<E>, E, T: Serialize<S, E>> Serialize<S, E> for Tagged<T> {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        match self.tag {
+            Some(tag) => { try!(s.serialize_tag(tag)); }
+            None => { }
+        }
+        self.value.serialize(s)
+    }
+}
+
+impl<D: de::Deserializer<E>, E, T: Deserialize<D, E>> Deserialize<D, E> for Tagged<T> {
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Tagged<T>, E> {
+        let tag = try!(d.expect_tag());
+        let value = try!(Deserialize::deserialize_token(d, token));
+        Ok(Tagged {
+            tag: tag,
+            value: value,
+        })
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+static MAJOR_UNSIGNED: u8 = 0;
+static MAJOR_NEGATIVE: u8 = 1;
+static MAJOR_BYTES: u8 = 2;
+static MAJOR_TEXT: u8 = 3;
+static MAJOR_ARRAY: u8 = 4;
+static MAJOR_MAP: u8 = 5;
+static MAJOR_TAG: u8 = 6;
+
+static FALSE: u8 = 0xf4;
+static TRUE: u8 = 0xf5;
+static NULL: u8 = 0xf6;
+static FLOAT16: u8 = 0xf9;
+static FLOAT32: u8 = 0xfa;
+static FLOAT64: u8 = 0xfb;
+static BREAK: u8 = 0xff;
+
+/// The additional information of a header that marks an indefinite length.
+static INDEFINITE: u8 = 31;
+
+/// Returns the bits of the half precision float equal to `v`, if there is
+/// one. All NaNs become the same quiet NaN.
+fn f32_to_f16(v: f32) -> Option<u16> {
+    let bits: u32 = unsafe { mem::transmute(v) };
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        return if mantissa == 0 { Some(sign | 0x7c00) } else { Some(0x7e00) };
+    }
+
+    // Single precision subnormals are all too small for half precision.
+    if exp == 0 {
+        return if mantissa == 0 { Some(sign) } else { None };
+    }
+
+    let exp = exp - 127;
+    if exp >= -14 && exp <= 15 {
+        if mantissa & 0x1fff != 0 {
+            return None;
+        }
+        Some(sign | (((exp + 15) as u16) << 10) | (mantissa >> 13) as u16)
+    } else if exp >= -24 && exp < -14 {
+        // Half precision subnormals hold the significand, implicit bit and
+        // all, as a multiple of 2^-24.
+        let significand = mantissa | 0x80_0000;
+        let shift = (13 - 14 - exp) as uint;
+        if significand & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        Some(sign | (significand >> shift) as u16)
+    } else {
+        None
+    }
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let exp = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    let value = match exp {
+        0 => mantissa * 2.0f32.powi(-24),
+        31 => if mantissa == 0.0 { Float::infinity() } else { Float::nan() },
+        _ => (1024.0 + mantissa) * 2.0f32.powi(exp - 25),
+    };
+
+    if half & 0x8000 != 0 { -value } else { value }
+}
+
+/// A structure for serializing values into CBOR.
+pub struct Serializer<W> {
+    wr: W,
+    canonical: bool,
+    // Values that have to be written somewhere other than straight to the
+    // writer, such as the elements of a sequence that needs its length, are
+    // written here first.
+    buffers: Vec<Vec<u8>>,
+    // The encoded fields of each struct being written canonically, which
+    // are sorted before being written.
+    entries: Vec<Vec<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl<W: Writer> Serializer<W> {
+    /// Creates a new CBOR serializer whose output will be written to the
+    /// writer specified.
+    #[inline]
+    pub fn new(wr: W) -> Serializer<W> {
+        Serializer {
+            wr: wr,
+            canonical: false,
+            buffers: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Creates a new CBOR serializer that writes the deterministic encoding
+    /// of values.
+    #[inline]
+    pub fn canonical(wr: W) -> Serializer<W> {
+        Serializer {
+            wr: wr,
+            canonical: true,
+            buffers: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Unwrap the Writer from the Serializer.
+    #[inline]
+    pub fn unwrap(self) -> W {
+        self.wr
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> IoResult<()> {
+        match self.buffers.last_mut() {
+            Some(buf) => {
+                buf.push_all(bytes);
+                Ok(())
+            }
+            None => self.wr.write(bytes),
+        }
+    }
+
+    /// Writes a header of the major type `major` in the fewest bytes that
+    /// hold its argument `v`.
+    fn write_head(&mut self, major: u8, v: u64) -> IoResult<()> {
+        let (info, size) = if v < 24 {
+            (v as u8, 0)
+        } else if v <= u8::MAX as u64 {
+            (24, 1)
+        } else if v <= u16::MAX as u64 {
+            (25, 2)
+        } else if v <= u32::MAX as u64 {
+            (26, 4)
+        } else {
+            (27, 8)
+        };
+
+        let mut buf = [0u8; 9];
+        buf[0] = major << 5 | info;
+
+        for i in range(0, size) {
+            buf[i + 1] = (v >> ((size - i - 1) * 8)) as u8;
+        }
+
+        self.write_bytes(buf.slice_to(size + 1))
+    }
+
+    fn write_float(&mut self, marker: u8, bits: u64, size: uint) -> IoResult<()> {
+        let mut buf = [0u8; 9];
+        buf[0] = marker;
+
+        for i in range(0, size) {
+            buf[i + 1] = (bits >> ((size - i - 1) * 8)) as u8;
+        }
+
+        self.write_bytes(buf.slice_to(size + 1))
+    }
+
+    /// Serializes `value` on its own, rather than into the output.
+    fn encode<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, value: &T) -> IoResult<Vec<u8>> {
+        self.buffers.push(Vec::new());
+        let result = value.serialize(self);
+        let buf = self.buffers.pop().unwrap();
+        try!(result);
+        Ok(buf)
+    }
+
+    /// Writes a map of encoded entries, sorted by their keys.
+    fn write_entries(&mut self, mut entries: Vec<(Vec<u8>, Vec<u8>)>) -> IoResult<()> {
+        entries.sort_by(|&(ref a, _), &(ref b, _)| a.cmp(b));
+
+        try!(self.write_head(MAJOR_MAP, entries.len() as u64));
+        for &(ref key, ref value) in entries.iter() {
+            try!(self.write_bytes(key.as_slice()));
+            try!(self.write_bytes(value.as_slice()));
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Writer> ser::Serializer<IoError> for Serializer<W> {
+    #[inline]
+    fn serialize_null(&mut self) -> IoResult<()> {
+        self.write_bytes(&[NULL])
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> IoResult<()> {
+        self.write_bytes(&[if v { TRUE } else { FALSE }])
+    }
+
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> IoResult<()> {
+        if v >= 0 {
+            self.write_head(MAJOR_UNSIGNED, v as u64)
+        } else {
+            // Negative integers are written as `-1 - v`.
+            self.write_head(MAJOR_NEGATIVE, !v as u64)
+        }
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> IoResult<()> {
+        self.write_head(MAJOR_UNSIGNED, v)
+    }
+
+    #[inline]
+    fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
+        if self.canonical {
+            match f32_to_f16(v) {
+                Some(bits) => { return self.write_float(FLOAT16, bits as u64, 2); }
+                None => { }
+            }
+        }
+
+        let bits: u32 = unsafe { mem::transmute(v) };
+        self.write_float(FLOAT32, bits as u64, 4)
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
+        if self.canonical && (v.is_nan() || v as f32 as f64 == v) {
+            return ser::Serializer::serialize_f32(self, v as f32);
+        }
+
+        let bits: u64 = unsafe { mem::transmute(v) };
+        self.write_float(FLOAT64, bits, 8)
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> IoResult<()> {
+        self.serialize_str(v.to_string().as_slice())
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> IoResult<()> {
+        try!(self.write_head(MAJOR_TEXT, v.len() as u64));
+        self.write_bytes(v.as_bytes())
+    }
+
+    #[inline]
+    fn serialize_bytes(&mut self, v: &[u8]) -> IoResult<()> {
+        try!(self.write_head(MAJOR_BYTES, v.len() as u64));
+        self.write_bytes(v)
+    }
+
+    #[inline]
+    fn serialize_tag(&mut self, tag: u64) -> IoResult<()> {
+        self.write_head(MAJOR_TAG, tag)
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, len: uint) -> IoResult<()> {
+        self.write_head(MAJOR_ARRAY, len as u64)
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, len: uint) -> IoResult<()> {
+        if self.canonical {
+            self.entries.push(Vec::with_capacity(len));
+            Ok(())
+        } else {
+            self.write_head(MAJOR_MAP, len as u64)
+        }
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, name: &str, value: &T) -> IoResult<()> {
+        if self.canonical {
+            let key = try!(self.encode(&name));
+            let value = try!(self.encode(value));
+            self.entries.last_mut().unwrap().push((key, value));
+            Ok(())
+        } else {
+            try!(ser::Serializer::serialize_str(self, name));
+            value.serialize(self)
+        }
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> IoResult<()> {
+        if self.canonical {
+            let entries = self.entries.pop().unwrap();
+            self.write_entries(entries)
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, len: uint) -> IoResult<()> {
+        // Unit variants are written as just their name.
+        if len == 0 {
+            return ser::Serializer::serialize_str(self, variant);
+        }
+
+        try!(self.write_head(MAJOR_MAP, 1));
+        try!(ser::Serializer::serialize_str(self, variant));
+        self.write_head(MAJOR_ARRAY, len as u64)
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, v: &Option<T>) -> IoResult<()> {
+        match *v {
+            Some(ref v) => v.serialize(self),
+            None => ser::Serializer::serialize_null(self),
+        }
+    }
+
+    #[inline]
+    fn serialize_seq<
+        T: Serialize<Serializer<W>, IoError>,
+        Iter: Iterator<Item=T>
+    >(&mut self, mut iter: Iter) -> IoResult<()> {
+        match iter.size_hint() {
+            (lo, Some(hi)) if lo == hi => {
+                try!(self.write_head(MAJOR_ARRAY, lo as u64));
+                for elt in iter {
+                    try!(elt.serialize(self));
+                }
+                Ok(())
+            }
+            _ if self.canonical => {
+                self.buffers.push(Vec::new());
+                let mut len = 0u64;
+                for elt in iter {
+                    try!(elt.serialize(self));
+                    len += 1;
+                }
+                let buf = self.buffers.pop().unwrap();
+
+                try!(self.write_head(MAJOR_ARRAY, len));
+                self.write_bytes(buf.as_slice())
+            }
+            _ => {
+                try!(self.write_bytes(&[MAJOR_ARRAY << 5 | INDEFINITE]));
+                for elt in iter {
+                    try!(elt.serialize(self));
+                }
+                self.write_bytes(&[BREAK])
+            }
+        }
+    }
+
+    #[inline]
+    fn serialize_map<
+        K: Serialize<Serializer<W>, IoError>,
+        V: Serialize<Serializer<W>, IoError>,
+        Iter: Iterator<Item=(K, V)>
+    >(&mut self, mut iter: Iter) -> IoResult<()> {
+        if self.canonical {
+            let mut entries = Vec::with_capacity(iter.size_hint().0);
+            for (key, value) in iter {
+                let key = try!(self.encode(&key));
+                let value = try!(self.encode(&value));
+                entries.push((key, value));
+            }
+            return self.write_entries(entries);
+        }
+
+        match iter.size_hint() {
+            (lo, Some(hi)) if lo == hi => {
+                try!(self.write_head(MAJOR_MAP, lo as u64));
+                for (key, value) in iter {
+                    try!(key.serialize(self));
+                    try!(value.serialize(self));
+                }
+                Ok(())
+            }
+            _ => {
+                try!(self.write_bytes(&[MAJOR_MAP << 5 | INDEFINITE]));
+                for (key, value) in iter {
+                    try!(key.serialize(self));
+                    try!(value.serialize(self));
+                }
+                self.write_bytes(&[BREAK])
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A structure that deserializes CBOR into Rust values.
+pub struct Deserializer<R> {
+    rdr: R,
+    // The number of values left in each array and map being read, or `None`
+    // for ones of indefinite length, which end with a break. Map keys and
+    // values are counted separately.
+    remaining: Vec<Option<uint>>,
+    // Set once the top level value has been started.
+    started: bool,
+    // Set while reading a unit variant written as a bare string.
+    unit_variant: bool,
+    // The tags of the value last read that haven't been asked for, from the
+    // outermost in.
+    tags: Vec<u64>,
+}
+
+impl<R: Reader> Iterator for Deserializer<R> {
+    type Item = Result<Token, Error>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<Token, Error>> {
+        if self.remaining.is_empty() {
+            if self.started {
+                return None;
+            }
+            self.started = true;
+        }
+
+        Some(self.parse_next())
+    }
+}
+
+impl<R: Reader> Deserializer<R> {
+    /// Creates the CBOR deserializer.
+    #[inline]
+    pub fn new(rdr: R) -> Deserializer<R> {
+        Deserializer {
+            rdr: rdr,
+            remaining: Vec::new(),
+            started: false,
+            unit_variant: false,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Unwrap the Reader from the Deserializer.
+    #[inline]
+    pub fn unwrap(self) -> R {
+        self.rdr
+    }
+
+    /// Makes sure there is no input left after the value.
+    #[inline]
+    pub fn end(&mut self) -> Result<(), Error> {
+        if try!(binary::at_end(&mut self.rdr)) {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes)
+        }
+    }
+
+    fn parse_next(&mut self) -> Result<Token, Error> {
+        match self.remaining.last().map(|len| *len) {
+            Some(Some(0)) => {
+                self.remaining.pop();
+                return Ok(Token::End);
+            }
+            Some(Some(len)) => {
+                let i = self.remaining.len() - 1;
+                self.remaining[i] = Some(len - 1);
+            }
+            Some(None) => {
+                let byte = try!(self.rdr.read_u8());
+                if byte == BREAK {
+                    self.remaining.pop();
+                    return Ok(Token::End);
+                }
+                return self.parse_value(byte);
+            }
+            None => { }
+        }
+
+        let byte = try!(self.rdr.read_u8());
+        self.parse_value(byte)
+    }
+
+    fn parse_value(&mut self, mut byte: u8) -> Result<Token, Error> {
+        self.tags.clear();
+        while byte >> 5 == MAJOR_TAG {
+            let tag = try!(self.read_arg(byte));
+            self.tags.push(tag);
+            byte = try!(self.rdr.read_u8());
+        }
+
+        match byte >> 5 {
+            0 => Ok(Token::U64(try!(self.read_arg(byte)))),
+            1 => {
+                let v = try!(self.read_arg(byte));
+                if v > i64::MAX as u64 {
+                    Err(Error::IntegerOverflow)
+                } else {
+                    Ok(Token::I64(-1 - v as i64))
+                }
+            }
+            2 => Ok(Token::Bytes(try!(self.read_string(byte)))),
+            3 => {
+                let bytes = try!(self.read_string(byte));
+                match String::from_utf8(bytes) {
+                    Ok(v) => Ok(Token::String(v)),
+                    Err(_) => Err(Error::InvalidUtf8),
+                }
+            }
+            4 => {
+                let len = try!(self.read_len(byte));
+                self.remaining.push(len);
+                Ok(Token::SeqStart(capacity(len)))
+            }
+            5 => {
+                let len = try!(self.read_len(byte));
+                self.remaining.push(len.map(|len| len * 2));
+                Ok(Token::MapStart(capacity(len)))
+            }
+            _ => self.parse_simple(byte),
+        }
+    }
+
+    fn parse_simple(&mut self, byte: u8) -> Result<Token, Error> {
+        match byte & 0x1f {
+            20 => Ok(Token::Bool(false)),
+            21 => Ok(Token::Bool(true)),
+            // Undefined has no Rust equivalent of its own.
+            22 | 23 => Ok(Token::Null),
+            24 => Err(Error::UnknownSimpleValue(try!(self.rdr.read_u8()))),
+            25 => Ok(Token::F32(f16_to_f32(try!(self.rdr.read_be_u16())))),
+            26 => Ok(Token::F32(try!(self.rdr.read_be_f32()))),
+            27 => Ok(Token::F64(try!(self.rdr.read_be_f64()))),
+            31 => Err(Error::UnexpectedBreak),
+            info if info < 20 => Err(Error::UnknownSimpleValue(info)),
+            _ => Err(Error::InvalidHeader(byte)),
+        }
+    }
+
+    /// Reads the argument of the header `byte`.
+    fn read_arg(&mut self, byte: u8) -> Result<u64, Error> {
+        match byte & 0x1f {
+            info if info < 24 => Ok(info as u64),
+            24 => Ok(try!(self.rdr.read_u8()) as u64),
+            25 => Ok(try!(self.rdr.read_be_u16()) as u64),
+            26 => Ok(try!(self.rdr.read_be_u32()) as u64),
+            27 => Ok(try!(self.rdr.read_be_u64())),
+            _ => Err(Error::InvalidHeader(byte)),
+        }
+    }
+
+    /// Reads the length of the header `byte`, which is `None` if it is
+    /// indefinite.
+    fn read_len(&mut self, byte: u8) -> Result<Option<uint>, Error> {
+        if byte & 0x1f == INDEFINITE {
+            return Ok(None);
+        }
+
+        let len = try!(self.read_arg(byte));
+        match num::cast(len) {
+            Some(len) => Ok(Some(len)),
+            None => Err(Error::IntegerOverflow),
+        }
+    }
+
+    fn read_string(&mut self, byte: u8) -> Result<Vec<u8>, Error> {
+        match try!(self.read_len(byte)) {
+            Some(len) => Ok(try!(binary::read_bytes(&mut self.rdr, len))),
+            None => {
+                // Indefinite length strings are a series of definite length
+                // ones of the same type, ending with a break.
+                let mut buf = Vec::new();
+                loop {
+                    let chunk = try!(self.rdr.read_u8());
+                    if chunk == BREAK {
+                        return Ok(buf);
+                    }
+
+                    match try!(self.read_len(chunk)) {
+                        Some(len) if chunk >> 5 == byte >> 5 => {
+                            let bytes = try!(binary::read_bytes(&mut self.rdr, len));
+                            buf.push_all(bytes.as_slice());
+                        }
+                        _ => { return Err(Error::InvalidChunk(chunk)); }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The lengths in the tokens are only used to reserve space, so they are
+// capped like `read_bytes`.
+fn capacity(len: Option<uint>) -> uint {
+    match len {
+        Some(len) => cmp::min(len, MAX_PREALLOCATION),
+        None => 0,
+    }
+}
+
+impl<R: Reader> de::Deserializer<Error> for Deserializer<R> {
+    fn end_of_stream_error(&mut self) -> Error {
+        Error::EndOfStream
+    }
+
+    fn syntax_error(&mut self, token: Token, expected: &'static [TokenKind]) -> Error {
+        Error::SyntaxError(token, expected)
+    }
+
+    fn unexpected_name_error(&mut self, token: Token) -> Error {
+        Error::UnexpectedName(token)
+    }
+
+    fn conversion_error(&mut self, token: Token) -> Error {
+        Error::ConversionError(token)
+    }
+
+    fn unknown_field_error(&mut self, field: &str) -> Error {
+        Error::UnknownField(field.to_string())
+    }
+
+    #[inline]
+    fn missing_field<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self, _field: &'static str) -> Result<T, Error> {
+        // A missing value is read like a `null` one.
+        Deserialize::deserialize_token(self, Token::Null)
+    }
+
+    #[inline]
+    fn missing_struct_field<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self, name: &'static str, field: &'static str) -> Result<T, Error> {
+        Err(Error::MissingField(field, name))
+    }
+
+    #[inline]
+    fn expect_tag(&mut self) -> Result<Option<u64>, Error> {
+        if self.tags.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(self.tags.remove(0)))
+        }
+    }
+
+    #[inline]
+    fn expect_option<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self, token: Token) -> Result<Option<T>, Error> {
+        match token {
+            Token::Null => Ok(None),
+            token => {
+                let value = try!(Deserialize::deserialize_token(self, token));
+                Ok(Some(value))
+            }
+        }
+    }
+
+    #[inline]
+    fn expect_struct_start(&mut self, token: Token, _name: &str) -> Result<(), Error> {
+        match token {
+            Token::MapStart(_) => Ok(()),
+            _ => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::MapStartKind,
+                ];
+                Err(self.syntax_error(token, EXPECTED_TOKENS))
+            }
+        }
+    }
+
+    #[inline]
+    fn expect_enum_start(&mut self,
+                         token: Token,
+                         _name: &str,
+                         variants: &[&str]) -> Result<uint, Error> {
+        let (idx, unit_variant) = try!(binary::expect_enum_start(self, token, variants));
+        self.unit_variant = unit_variant;
+        Ok(idx)
+    }
+
+    #[inline]
+    fn expect_enum_elt<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self) -> Result<T, Error> {
+        let unit_variant = self.unit_variant;
+        binary::expect_enum_elt(self, unit_variant)
+    }
+
+    #[inline]
+    fn expect_enum_struct_start(&mut self, name: &str) -> Result<(), Error> {
+        let unit_variant = self.unit_variant;
+        binary::expect_enum_struct_start(self, unit_variant, name)
+    }
+
+    fn expect_enum_end(&mut self) -> Result<(), Error> {
+        let unit_variant = mem::replace(&mut self.unit_variant, false);
+        binary::expect_enum_end(self, unit_variant)
+    }
+}
+
+// Strings are always copied out of the reader.
+impl<'a, R: Reader> de::BorrowDeserializer<'a, Error> for Deserializer<R> { }
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// Encode the specified value into a CBOR `[u8]` writer.
+#[inline]
+pub fn to_writer<
+    W: Writer,
+    T: Serialize<Serializer<W>, IoError>
+>(writer: W, value: &T) -> IoResult<W> {
+    let mut serializer = Serializer::new(writer);
+    try!(value.serialize(&mut serializer));
+    Ok(serializer.unwrap())
+}
+
+/// Encode the specified value into a CBOR `[u8]` buffer.
+#[inline]
+pub fn to_vec<
+    T: Serialize<Serializer<Vec<u8>>, IoError>
+>(value: &T) -> Vec<u8> {
+    // We are writing to a Vec, which doesn't fail. So we can ignore
+    // the error.
+    to_writer(Vec::with_capacity(128), value).unwrap()
+}
+
+/// Encode the deterministic encoding of the specified value into a CBOR
+/// `[u8]` writer.
+#[inline]
+pub fn to_canonical_writer<
+    W: Writer,
+    T: Serialize<Serializer<W>, IoError>
+>(writer: W, value: &T) -> IoResult<W> {
+    let mut serializer = Serializer::canonical(writer);
+    try!(value.serialize(&mut serializer));
+    Ok(serializer.unwrap())
+}
+
+/// Encode the deterministic encoding of the specified value into a CBOR
+/// `[u8]` buffer.
+#[inline]
+pub fn to_canonical_vec<
+    T: Serialize<Serializer<Vec<u8>>, IoError>
+>(value: &T) -> Vec<u8> {
+    to_canonical_writer(Vec::with_capacity(128), value).unwrap()
+}
+
+/// Decodes a CBOR value from a `Reader`, which must hold nothing after it.
+#[inline]
+pub fn from_reader<
+    R: Reader,
+    T: Deserialize<Deserializer<R>, Error>
+>(rdr: R) -> Result<T, Error> {
+    let mut deserializer = Deserializer::new(rdr);
+    let value = try!(Deserialize::deserialize(&mut deserializer));
+    try!(deserializer.end());
+    Ok(value)
+}
+
+/// Decodes a CBOR value from a `[u8]` buffer.
+#[inline]
+pub fn from_slice<
+    'a,
+    T: Deserialize<Deserializer<io::BufReader<'a>>, Error>
+>(v: &'a [u8]) -> Result<T, Error> {
+    from_reader(io::BufReader::new(v))
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fmt::Show;
+    use std::io::{IoError, MemReader};
+    use std::num::Float;
+    use std::{f32, f64, i64};
+
+    use bytes::ByteBuf;
+    use de::Deserialize;
+    use json::{self, Value};
+    use ser::{self, Serialize};
+
+    use super::{Deserializer, Error, Serializer, Tagged};
+    use super::{from_reader, from_slice, to_canonical_vec, to_vec};
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Inner {
+        a: int,
+        b: Vec<int>,
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Unordered {
+        b: int,
+        aa: int,
+        a: int,
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    enum Animal {
+        Dog,
+        Frog(String, int),
+        Cat { age: uint, name: String },
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Reading {
+        time: Tagged<u64>,
+        values: BTreeMap<String, f64>,
+        raw: Option<ByteBuf>,
+        animal: Animal,
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        let digits: Vec<u8> = s.chars().map(|c| c.to_digit(16).unwrap() as u8).collect();
+        digits.chunks(2).map(|d| d[0] << 4 | d[1]).collect()
+    }
+
+    fn test_encode<
+        T: PartialEq + Show + Serialize<Serializer<Vec<u8>>, IoError>
+                     + Deserialize<Deserializer<MemReader>, Error>
+    >(value: T, expected: &str) {
+        let v = to_vec(&value);
+        assert_eq!(v, hex(expected));
+
+        let v: T = from_reader(MemReader::new(v)).unwrap();
+        assert_eq!(v, value);
+    }
+
+    fn test_encode_canonical<
+        T: PartialEq + Show + Serialize<Serializer<Vec<u8>>, IoError>
+                     + Deserialize<Deserializer<MemReader>, Error>
+    >(value: T, expected: &str) {
+        let v = to_canonical_vec(&value);
+        assert_eq!(v, hex(expected));
+
+        let v: T = from_reader(MemReader::new(v)).unwrap();
+        assert_eq!(v, value);
+    }
+
+    fn test_decode_json(input: &str, expected: &str) {
+        let v: Value = from_slice(hex(input).as_slice()).unwrap();
+        assert_eq!(json::to_string(&v).unwrap(), expected);
+    }
+
+    // The examples are from appendix A of RFC 8949.
+    #[test]
+    fn test_write_primitives() {
+        test_encode(0u, "00");
+        test_encode(23u, "17");
+        test_encode(24u, "1818");
+        test_encode(100u, "1864");
+        test_encode(1000u, "1903e8");
+        test_encode(1000000u, "1a000f4240");
+        test_encode(1000000000000u64, "1b000000e8d4a51000");
+        test_encode(18446744073709551615u64, "1bffffffffffffffff");
+        test_encode(-1i, "20");
+        test_encode(-10i, "29");
+        test_encode(-100i, "3863");
+        test_encode(-1000i, "3903e7");
+        test_encode(i64::MIN, "3b7fffffffffffffff");
+
+        test_encode(false, "f4");
+        test_encode(true, "f5");
+        test_encode((), "f6");
+        test_encode(None::<int>, "f6");
+        test_encode(Some(1i), "01");
+
+        test_encode("".to_string(), "60");
+        test_encode("a".to_string(), "6161");
+        test_encode("IETF".to_string(), "6449455446");
+        test_encode("\u{fc}".to_string(), "62c3bc");
+        test_encode('\u{6c34}', "63e6b0b4");
+
+        test_encode(ByteBuf(vec![1, 2, 3, 4]), "4401020304");
+
+        test_encode(1.5f32, "fa3fc00000");
+        test_encode(1.5f64, "fb3ff8000000000000");
+        test_encode(1.1f64, "fb3ff199999999999a");
+    }
+
+    #[test]
+    fn test_write_compound() {
+        test_encode(Vec::<int>::new(), "80");
+        test_encode(vec![1i, 2, 3], "83010203");
+        test_encode((1i, (2i, 3i), vec![4i, 5]), "8301820203820405");
+        test_encode(
+            range(1i, 26).collect::<Vec<int>>(),
+            "98190102030405060708090a0b0c0d0e0f101112131415161718181819");
+
+        test_encode(Inner { a: 1, b: vec![2, 3] }, "a26161016162820203");
+
+        test_encode(Animal::Dog, "63446f67");
+        test_encode(Animal::Frog("Henry".to_string(), 349),
+                    "a16446726f67826548656e727919015d");
+        test_encode(Animal::Cat { age: 3, name: "Tom".to_string() },
+                    "a16343617481a26361676503646e616d6563546f6d");
+    }
+
+    #[test]
+    fn test_write_indefinite() {
+        let v = vec![1i, 2, 3, 4];
+
+        let mut serializer = Serializer::new(Vec::new());
+        ser::Serializer::serialize_seq(&mut serializer, v.iter().filter(|x| **x % 2 == 0)).unwrap();
+        assert_eq!(serializer.unwrap(), hex("9f0204ff"));
+
+        let mut serializer = Serializer::canonical(Vec::new());
+        ser::Serializer::serialize_seq(&mut serializer, v.iter().filter(|x| **x % 2 == 0)).unwrap();
+        assert_eq!(serializer.unwrap(), hex("820204"));
+
+        let mut serializer = Serializer::new(Vec::new());
+        ser::Serializer::serialize_map(
+            &mut serializer,
+            v.iter().filter(|x| **x > 2).map(|x| (*x, *x))).unwrap();
+        assert_eq!(serializer.unwrap(), hex("bf03030404ff"));
+    }
+
+    #[test]
+    fn test_write_canonical() {
+        test_encode_canonical(0.0f64, "f90000");
+        test_encode_canonical(-0.0f64, "f98000");
+        test_encode_canonical(1.0f64, "f93c00");
+        test_encode_canonical(1.1f64, "fb3ff199999999999a");
+        test_encode_canonical(1.5f64, "f93e00");
+        test_encode_canonical(65504.0f64, "f97bff");
+        test_encode_canonical(100000.0f64, "fa47c35000");
+        test_encode_canonical(3.4028234663852886e+38f64, "fa7f7fffff");
+        test_encode_canonical(5.960464477539063e-8f64, "f90001");
+        test_encode_canonical(0.00006103515625f64, "f90400");
+        test_encode_canonical(-4.0f64, "f9c400");
+        test_encode_canonical(-4.1f64, "fbc010666666666666");
+        test_encode_canonical(f64::INFINITY, "f97c00");
+        test_encode_canonical(f64::NEG_INFINITY, "f9fc00");
+
+        assert_eq!(to_canonical_vec(&f64::NAN), hex("f97e00"));
+
+        // Keys are sorted by their encoding, so shorter keys go first.
+        test_encode_canonical(Unordered { b: 1, aa: 2, a: 3 }, "a361610361620162616102");
+
+        let mut map = BTreeMap::new();
+        map.insert("b".to_string(), 1i);
+        map.insert("aa".to_string(), 2i);
+        map.insert("a".to_string(), 3i);
+        test_encode_canonical(map, "a361610361620162616102");
+    }
+
+    #[test]
+    fn test_read_indefinite() {
+        test_decode_json("9f018202039f0405ffff", "[1,[2,3],[4,5]]");
+        test_decode_json("9f01820203820405ff", "[1,[2,3],[4,5]]");
+        test_decode_json("bf61610161629f0203ffff", "{\"a\":1,\"b\":[2,3]}");
+
+        let v: Inner = from_slice(hex("bf61610161629f0203ffff").as_slice()).unwrap();
+        assert_eq!(v, Inner { a: 1, b: vec![2, 3] });
+
+        let v: ByteBuf = from_slice(hex("5f42010243030405ff").as_slice()).unwrap();
+        assert_eq!(v, ByteBuf(vec![1, 2, 3, 4, 5]));
+
+        let v: String = from_slice(hex("7f657374726561646d696e67ff").as_slice()).unwrap();
+        assert_eq!(v, "streaming".to_string());
+    }
+
+    #[test]
+    fn test_read_half_floats() {
+        let tests = [
+            ("f90000", 0.0),
+            ("f93c00", 1.0),
+            ("f97bff", 65504.0),
+            ("f90001", 5.960464477539063e-8),
+            ("f90400", 0.00006103515625),
+            ("f9c400", -4.0),
+            ("f97c00", f32::INFINITY),
+        ];
+
+        for &(input, expected) in tests.iter() {
+            let v: f32 = from_slice(hex(input).as_slice()).unwrap();
+            assert_eq!(v, expected);
+        }
+
+        let v: f32 = from_slice(hex("f97e00").as_slice()).unwrap();
+        assert!(v.is_nan());
+    }
+
+    #[test]
+    fn test_tags() {
+        test_encode(Tagged::new(1, 1363896240u64), "c11a514b67b0");
+        test_encode(Tagged::new(0, "2013-03-21T20:04:00Z".to_string()),
+                    "c074323031332d30332d32315432303a30343a30305a");
+        test_encode(Tagged { tag: None, value: 1u }, "01");
+
+        // Tags can be nested, or skipped by reading the plain value.
+        test_encode(Tagged::new(55799, Tagged::new(1, 0u)), "d9d9f7c100");
+
+        let v: u64 = from_slice(hex("c11a514b67b0").as_slice()).unwrap();
+        assert_eq!(v, 1363896240);
+
+        // Formats without tags leave them out.
+        assert_eq!(json::to_string(&Tagged::new(1, 2u)).unwrap(), "2");
+        let v: Tagged<u64> = json::from_str("2").unwrap();
+        assert_eq!(v, Tagged { tag: None, value: 2 });
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut values = BTreeMap::new();
+        values.insert("temperature".to_string(), 21.5);
+        values.insert("humidity".to_string(), 0.4);
+
+        let reading = Reading {
+            time: Tagged::new(1, 1363896240),
+            values: values,
+            raw: Some(ByteBuf(range(0u, 300).map(|i| i as u8).collect())),
+            animal: Animal::Cat { age: 3, name: "Tom".to_string() },
+        };
+
+        let v: Reading = from_slice(to_vec(&reading).as_slice()).unwrap();
+        assert_eq!(v, reading);
+
+        let v: Reading = from_slice(to_canonical_vec(&reading).as_slice()).unwrap();
+        assert_eq!(v, reading);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let v: Result<String, Error> = from_slice(&[0x63, b'a']);
+        assert_eq!(v, Err(Error::EndOfStream));
+
+        let v: Result<int, Error> = from_slice(&[0xff]);
+        assert_eq!(v, Err(Error::UnexpectedBreak));
+
+        let v: Result<int, Error> = from_slice(&[0x1c]);
+        assert_eq!(v, Err(Error::InvalidHeader(0x1c)));
+
+        let v: Result<int, Error> = from_slice(&[0xf0]);
+        assert_eq!(v, Err(Error::UnknownSimpleValue(16)));
+
+        let v: Result<i64, Error> = from_slice(hex("3bffffffffffffffff").as_slice());
+        assert_eq!(v, Err(Error::IntegerOverflow));
+
+        let v: Result<int, Error> = from_slice(&[0x01, 0x02]);
+        assert_eq!(v, Err(Error::TrailingBytes));
+
+        let v: Result<String, Error> = from_slice(&[0x61, 0xff]);
+        assert_eq!(v, Err(Error::InvalidUtf8));
+
+        let v: Result<String, Error> = from_slice(hex("7f4161ff").as_slice());
+        assert_eq!(v, Err(Error::InvalidChunk(0x41)));
+
+        let v: Result<Inner, Error> = from_slice(hex("a1616101").as_slice());
+        assert_eq!(v, Err(Error::MissingField("b", "Inner")));
+
+        let v: Result<Animal, Error> = from_slice(hex("63436f77").as_slice());
+        assert_eq!(v, Err(Error::UnknownVariant("Cow".to_string())));
+    }
+}
@@ -12,11 +12,14 @@ use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet};
 use std::collections::hash_map::Hasher;
 use std::hash::Hash;
 use std::iter::FromIterator;
+use std::mem;
 use std::num::{self, FromPrimitive};
+use std::borrow::Cow;
 use std::option;
 use std::rc::Rc;
-use std::string;
+use std::string::{self, CowString};
 use std::sync::Arc;
+use std::vec;
 
 #[derive(Clone, PartialEq, Show)]
 pub enum Token {
@@ -37,6 +40,8 @@ pub enum Token {
     Char(char),
     Str(&'static str),
     String(String),
+    RawNumber(String),
+    Bytes(Vec<u8>),
     Option(bool),
 
     TupleStart(uint),
@@ -68,6 +73,8 @@ impl Token {
             Token::Char(_) => TokenKind::CharKind,
             Token::Str(_) => TokenKind::StrKind,
             Token::String(_) => TokenKind::StringKind,
+            Token::RawNumber(_) => TokenKind::RawNumberKind,
+            Token::Bytes(_) => TokenKind::BytesKind,
             Token::Option(_) => TokenKind::OptionKind,
             Token::TupleStart(_) => TokenKind::TupleStartKind,
             Token::StructStart(_, _) => TokenKind::StructStartKind,
@@ -98,6 +105,8 @@ pub enum TokenKind {
     CharKind,
     StrKind,
     StringKind,
+    RawNumberKind,
+    BytesKind,
     OptionKind,
 
     TupleStartKind,
@@ -122,6 +131,7 @@ static PRIMITIVE_TOKEN_KINDS: &'static [TokenKind] = &[
     TokenKind::U64Kind,
     TokenKind::F32Kind,
     TokenKind::F64Kind,
+    TokenKind::RawNumberKind,
 ];
 
 static STR_TOKEN_KINDS: &'static [TokenKind] = &[
@@ -129,6 +139,13 @@ static STR_TOKEN_KINDS: &'static [TokenKind] = &[
     TokenKind::StringKind,
 ];
 
+static BYTES_TOKEN_KINDS: &'static [TokenKind] = &[
+    TokenKind::BytesKind,
+    TokenKind::StrKind,
+    TokenKind::StringKind,
+    TokenKind::SeqStartKind,
+];
+
 static COMPOUND_TOKEN_KINDS: &'static [TokenKind] = &[
     TokenKind::OptionKind,
     TokenKind::EnumStartKind,
@@ -158,6 +175,8 @@ impl ::std::fmt::Show for TokenKind {
             TokenKind::CharKind => "Char".fmt(f),
             TokenKind::StrKind => "Str".fmt(f),
             TokenKind::StringKind => "String".fmt(f),
+            TokenKind::RawNumberKind => "RawNumber".fmt(f),
+            TokenKind::BytesKind => "Bytes".fmt(f),
             TokenKind::OptionKind => "Option".fmt(f),
             TokenKind::TupleStartKind => "TupleStart".fmt(f),
             TokenKind::StructStartKind => "StructStart".fmt(f),
@@ -178,6 +197,32 @@ macro_rules! to_result {
     }
 }
 
+/// Converts the digits of a `Token::RawNumber`, trying the integer types
+/// first so that large integers stay exact.
+fn raw_number_to_num<T: num::NumCast>(v: &str) -> Option<T> {
+    match v.parse::<i64>() {
+        Some(x) => num::cast(x),
+        None => {
+            match v.parse::<u64>() {
+                Some(x) => num::cast(x),
+                None => v.parse::<f64>().and_then(num::cast),
+            }
+        }
+    }
+}
+
+fn raw_number_from_primitive<T: FromPrimitive>(v: &str) -> Option<T> {
+    match v.parse::<i64>() {
+        Some(x) => num::from_i64(x),
+        None => {
+            match v.parse::<u64>() {
+                Some(x) => num::from_u64(x),
+                None => v.parse::<f64>().and_then(num::from_f64),
+            }
+        }
+    }
+}
+
 pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
     /// Called when a `Deserialize` expected more tokens, but the
     /// `Deserializer` was empty.
@@ -192,12 +237,31 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
     /// Called when a value was unable to be coerced into another value.
     fn conversion_error(&mut self, token: Token) -> E;
 
+    /// Called when a structure that denies unknown fields got a field named
+    /// `field` that it didn't expect.
+    #[inline]
+    fn unknown_field_error(&mut self, field: &str) -> E {
+        self.unexpected_name_error(Token::String(field.to_string()))
+    }
+
     /// Called when a `Deserialize` structure did not deserialize a field
     /// named `field`.
     fn missing_field<
         T: Deserialize<Self, E>
     >(&mut self, field: &'static str) -> Result<T, E>;
 
+    /// Called when the structure `name` did not deserialize a field named
+    /// `field`, and the field's type has no value for being missing. Derived
+    /// structures only get here for fields that can't be missing, so formats
+    /// that read a missing value as `null` in `missing_field` should fail
+    /// here instead. This defers to `missing_field` by default.
+    #[inline]
+    fn missing_struct_field<
+        T: Deserialize<Self, E>
+    >(&mut self, _name: &'static str, field: &'static str) -> Result<T, E> {
+        self.missing_field(field)
+    }
+
     /*
     /// Called when a `Deserialize` has decided to not consume this token.
     fn ignore_field(&mut self, _token: Token) -> Result<(), E> {
@@ -211,6 +275,171 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
         self.next().unwrap_or_else(|| Err(self.end_of_stream_error()))
     }
 
+    /// Reads a whole `()` without being handed its first token, which is
+    /// where `Deserialize::deserialize` starts. Like the other
+    /// `expect_next_*` methods, this reads the token and defers to the
+    /// matching `expect_*` method by default. Formats whose input doesn't
+    /// describe itself have no tokens to read, so they override these to
+    /// read the value as the type asks for it.
+    #[inline]
+    fn expect_next_null(&mut self) -> Result<(), E> {
+        let token = try!(self.expect_token());
+        self.expect_null(token)
+    }
+
+    #[inline]
+    fn expect_next_bool(&mut self) -> Result<bool, E> {
+        let token = try!(self.expect_token());
+        self.expect_bool(token)
+    }
+
+    #[inline]
+    fn expect_next_int(&mut self) -> Result<int, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_i8(&mut self) -> Result<i8, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_i16(&mut self) -> Result<i16, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_i32(&mut self) -> Result<i32, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_i64(&mut self) -> Result<i64, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_uint(&mut self) -> Result<uint, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_u8(&mut self) -> Result<u8, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_u16(&mut self) -> Result<u16, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_u32(&mut self) -> Result<u32, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_u64(&mut self) -> Result<u64, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_f32(&mut self) -> Result<f32, E> {
+        let token = try!(self.expect_token());
+        self.expect_f32(token)
+    }
+
+    #[inline]
+    fn expect_next_f64(&mut self) -> Result<f64, E> {
+        let token = try!(self.expect_token());
+        self.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_next_char(&mut self) -> Result<char, E> {
+        let token = try!(self.expect_token());
+        self.expect_char(token)
+    }
+
+    #[inline]
+    fn expect_next_string(&mut self) -> Result<string::String, E> {
+        let token = try!(self.expect_token());
+        self.expect_string(token)
+    }
+
+    #[inline]
+    fn expect_next_bytes(&mut self) -> Result<Vec<u8>, E> {
+        let token = try!(self.expect_token());
+        self.expect_bytes(token)
+    }
+
+    #[inline]
+    fn expect_next_option<
+        T: Deserialize<Self, E>
+    >(&mut self) -> Result<option::Option<T>, E> {
+        let token = try!(self.expect_token());
+        self.expect_option(token)
+    }
+
+    #[inline]
+    fn expect_next_tuple_start(&mut self) -> Result<uint, E> {
+        let token = try!(self.expect_token());
+        self.expect_tuple_start(token)
+    }
+
+    #[inline]
+    fn expect_next_struct_start(&mut self, name: &str) -> Result<(), E> {
+        let token = try!(self.expect_token());
+        self.expect_struct_start(token, name)
+    }
+
+    #[inline]
+    fn expect_next_enum_start(&mut self, name: &str, variants: &[&str]) -> Result<uint, E> {
+        let token = try!(self.expect_token());
+        self.expect_enum_start(token, name, variants)
+    }
+
+    #[inline]
+    fn expect_next_seq_start(&mut self) -> Result<uint, E> {
+        let token = try!(self.expect_token());
+        self.expect_seq_start(token)
+    }
+
+    #[inline]
+    fn expect_next_seq<
+        T: Deserialize<Self, E>,
+        C: FromIterator<T>
+    >(&mut self) -> Result<C, E> {
+        let len = try!(self.expect_next_seq_start());
+        collect_seq(self, len)
+    }
+
+    #[inline]
+    fn expect_next_map_start(&mut self) -> Result<uint, E> {
+        let token = try!(self.expect_token());
+        self.expect_map_start(token)
+    }
+
+    #[inline]
+    fn expect_next_map<
+        K: Deserialize<Self, E>,
+        V: Deserialize<Self, E>,
+        C: FromIterator<(K, V)>
+    >(&mut self) -> Result<C, E> {
+        let len = try!(self.expect_next_map_start());
+        collect_map(self, len)
+    }
+
     #[inline]
     fn expect_null(&mut self, token: Token) -> Result<(), E> {
         match token {
@@ -250,6 +479,11 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
         }
     }
 
+    #[inline]
+    fn expect_f32(&mut self, token: Token) -> Result<f32, E> {
+        self.expect_num(token)
+    }
+
     #[inline]
     fn expect_num<T: num::NumCast>(&mut self, token: Token) -> Result<T, E> {
         match token {
@@ -265,6 +499,7 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
             Token::U64(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
             Token::F32(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
             Token::F64(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
+            Token::RawNumber(ref x) => to_result!(raw_number_to_num(x.as_slice()), self.syntax_error(token.clone(), PRIMITIVE_TOKEN_KINDS)),
             token => Err(self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
         }
     }
@@ -284,6 +519,7 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
             Token::U64(x) => to_result!(num::from_u64(x), self.conversion_error(token)),
             Token::F32(x) => to_result!(num::from_f32(x), self.conversion_error(token)),
             Token::F64(x) => to_result!(num::from_f64(x), self.conversion_error(token)),
+            Token::RawNumber(ref x) => to_result!(raw_number_from_primitive(x.as_slice()), self.conversion_error(token.clone())),
             token => Err(self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
         }
     }
@@ -325,6 +561,35 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
         }
     }
 
+    /// Formats without a binary type write byte buffers as a sequence of
+    /// integers, so those are accepted as well as strings.
+    #[inline]
+    fn expect_bytes(&mut self, token: Token) -> Result<Vec<u8>, E> {
+        match token {
+            Token::Bytes(value) => Ok(value),
+            Token::Str(value) => Ok(value.as_bytes().to_vec()),
+            Token::String(value) => Ok(value.into_bytes()),
+            Token::TupleStart(_) | Token::SeqStart(_) => self.expect_seq::<u8, Vec<u8>>(token),
+            token => Err(self.syntax_error(token, BYTES_TOKEN_KINDS)),
+        }
+    }
+
+    /// Returns the next semantic tag of the value whose first token was just
+    /// read, such as a CBOR tag. Formats without tags return `None`, which
+    /// is the default.
+    #[inline]
+    fn expect_tag(&mut self) -> Result<Option<u64>, E> {
+        Ok(None)
+    }
+
+    /// Returns whether the string whose token was just read is a local
+    /// date-time, date or time. Formats that don't tell them apart from
+    /// other strings return `false`, which is the default.
+    #[inline]
+    fn expect_local_datetime(&mut self) -> Result<bool, E> {
+        Ok(false)
+    }
+
     #[inline]
     fn expect_option<
         T: Deserialize<Self, E>
@@ -418,6 +683,33 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
         }
     }
 
+    /// Like `expect_struct_field_or_end`, but errors out with
+    /// `unknown_field_error` on a field that is not in `fields`.
+    #[inline]
+    fn expect_known_struct_field_or_end(&mut self,
+                                        fields: &'static [&'static str]
+                                       ) -> Result<option::Option<uint>, E> {
+        let idx = match try!(self.expect_token()) {
+            Token::End => {
+                return Ok(None);
+            }
+            Token::Str(n) => {
+                fields.iter().position(|field| *field == n).ok_or(n.to_string())
+            }
+            Token::String(n) => {
+                fields.iter().position(|field| *field == n.as_slice()).ok_or(n)
+            }
+            token => {
+                return Err(self.syntax_error(token, STR_TOKEN_KINDS));
+            }
+        };
+
+        match idx {
+            Ok(idx) => Ok(Some(idx)),
+            Err(field) => Err(self.unknown_field_error(field.as_slice())),
+        }
+    }
+
     #[inline]
     fn expect_struct_value<
         T: Deserialize<Self, E>
@@ -467,6 +759,14 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
         Deserialize::deserialize(self)
     }
 
+    /// Called before the fields of a struct variant, which are written as a
+    /// single struct element of the enum.
+    #[inline]
+    fn expect_enum_struct_start(&mut self, name: &str) -> Result<(), E> {
+        let token = try!(self.expect_token());
+        self.expect_struct_start(token, name)
+    }
+
     #[inline]
     fn expect_enum_end(&mut self) -> Result<(), E> {
         match try!(self.expect_token()) {
@@ -514,22 +814,7 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
         C: FromIterator<T>
     >(&mut self, token: Token) -> Result<C, E> {
         let len = try!(self.expect_seq_start(token));
-        let mut err = None;
-
-        let collection: C = {
-            let d = SeqDeserializer {
-                d: self,
-                len: len,
-                err: &mut err,
-            };
-
-            d.collect()
-        };
-
-        match err {
-            Some(err) => Err(err),
-            None => Ok(collection),
-        }
+        collect_seq(self, len)
     }
 
     #[inline]
@@ -567,22 +852,62 @@ pub trait Deserializer<E>: Iterator<Item=Result<Token, E>> + Sized {
         C: FromIterator<(K, V)>
     >(&mut self, token: Token) -> Result<C, E> {
         let len = try!(self.expect_map_start(token));
-        let mut err = None;
+        collect_map(self, len)
+    }
+}
 
-        let collection: C = {
-            let d = MapDeserializer {
-                d: self,
-                len: len,
-                err: &mut err,
-            };
+//////////////////////////////////////////////////////////////////////////////
 
-            d.collect()
+/// Collects the elements of a sequence whose start was just read.
+#[inline]
+fn collect_seq<
+    D: Deserializer<E>,
+    E,
+    T: Deserialize<D, E>,
+    C: FromIterator<T>
+>(d: &mut D, len: uint) -> Result<C, E> {
+    let mut err = None;
+
+    let collection: C = {
+        let d = SeqDeserializer {
+            d: d,
+            len: len,
+            err: &mut err,
         };
 
-        match err {
-            Some(err) => Err(err),
-            None => Ok(collection),
-        }
+        d.collect()
+    };
+
+    match err {
+        Some(err) => Err(err),
+        None => Ok(collection),
+    }
+}
+
+/// Collects the entries of a map whose start was just read.
+#[inline]
+fn collect_map<
+    D: Deserializer<E>,
+    E,
+    K: Deserialize<D, E>,
+    V: Deserialize<D, E>,
+    C: FromIterator<(K, V)>
+>(d: &mut D, len: uint) -> Result<C, E> {
+    let mut err = None;
+
+    let collection: C = {
+        let d = MapDeserializer {
+            d: d,
+            len: len,
+            err: &mut err,
+        };
+
+        d.collect()
+    };
+
+    match err {
+        Some(err) => Err(err),
+        None => Ok(collection),
     }
 }
 
@@ -658,6 +983,31 @@ impl<
 
 //////////////////////////////////////////////////////////////////////////////
 
+/// A `Deserializer` that can lend out strings borrowed from its input for the
+/// lifetime `'a`, instead of allocating a `Token::String` for each of them.
+pub trait BorrowDeserializer<'a, E>: Deserializer<E> {
+    /// Deserialize the next value as a string, borrowing it from the input
+    /// unless it had to be unescaped.
+    #[inline]
+    fn expect_borrowed_str(&mut self) -> Result<CowString<'a>, E> {
+        let token = try!(self.expect_token());
+        self.expect_cow_str(token)
+    }
+
+    /// Convert an already parsed `token` into a string, borrowing it if
+    /// possible.
+    #[inline]
+    fn expect_cow_str(&mut self, token: Token) -> Result<CowString<'a>, E> {
+        match token {
+            Token::Str(value) => Ok(Cow::Borrowed(value)),
+            Token::String(value) => Ok(Cow::Owned(value)),
+            token => Err(self.syntax_error(token, STR_TOKEN_KINDS)),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
 pub trait Deserialize<D: Deserializer<E>, E>: Sized {
     #[inline]
     fn deserialize(d: &mut D) -> Result<Self, E> {
@@ -666,13 +1016,26 @@ pub trait Deserialize<D: Deserializer<E>, E>: Sized {
     }
 
     fn deserialize_token(d: &mut D, token: Token) -> Result<Self, E>;
+
+    /// Called when the field `field` of the structure `name` is missing.
+    /// Types like `Option` that have a natural value for being missing
+    /// return it, and everything else asks the deserializer.
+    #[inline]
+    fn deserialize_missing(d: &mut D, name: &'static str, field: &'static str) -> Result<Self, E> {
+        d.missing_struct_field(name, field)
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
 
 macro_rules! impl_deserialize {
-    ($ty:ty, $method:ident) => {
+    ($ty:ty, $next:ident, $method:ident) => {
         impl<D: Deserializer<E>, E> Deserialize<D, E> for $ty {
+            #[inline]
+            fn deserialize(d: &mut D) -> Result<$ty, E> {
+                d.$next()
+            }
+
             #[inline]
             fn deserialize_token(d: &mut D, token: Token) -> Result<$ty, E> {
                 d.$method(token)
@@ -681,22 +1044,51 @@ macro_rules! impl_deserialize {
     }
 }
 
-impl_deserialize!(bool, expect_bool);
-impl_deserialize!(int, expect_num);
-impl_deserialize!(i8, expect_num);
-impl_deserialize!(i16, expect_num);
-impl_deserialize!(i32, expect_num);
-impl_deserialize!(i64, expect_num);
-impl_deserialize!(uint, expect_num);
-impl_deserialize!(u8, expect_num);
-impl_deserialize!(u16, expect_num);
-impl_deserialize!(u32, expect_num);
-impl_deserialize!(u64, expect_num);
-impl_deserialize!(f32, expect_num);
-impl_deserialize!(f64, expect_num);
-impl_deserialize!(char, expect_char);
-impl_deserialize!(&'static str, expect_str);
-impl_deserialize!(string::String, expect_string);
+impl_deserialize!(bool, expect_next_bool, expect_bool);
+impl_deserialize!(int, expect_next_int, expect_num);
+impl_deserialize!(i8, expect_next_i8, expect_num);
+impl_deserialize!(i16, expect_next_i16, expect_num);
+impl_deserialize!(i32, expect_next_i32, expect_num);
+impl_deserialize!(i64, expect_next_i64, expect_num);
+impl_deserialize!(uint, expect_next_uint, expect_num);
+impl_deserialize!(u8, expect_next_u8, expect_num);
+impl_deserialize!(u16, expect_next_u16, expect_num);
+impl_deserialize!(u32, expect_next_u32, expect_num);
+impl_deserialize!(u64, expect_next_u64, expect_num);
+impl_deserialize!(f32, expect_next_f32, expect_f32);
+impl_deserialize!(f64, expect_next_f64, expect_num);
+impl_deserialize!(char, expect_next_char, expect_char);
+impl_deserialize!(string::String, expect_next_string, expect_string);
+
+impl<'a, D: BorrowDeserializer<'a, E>, E> Deserialize<D, E> for &'a str {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<&'a str, E> {
+        match try!(d.expect_borrowed_str()) {
+            Cow::Borrowed(value) => Ok(value),
+            Cow::Owned(value) => Err(d.conversion_error(Token::String(value))),
+        }
+    }
+
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<&'a str, E> {
+        match try!(d.expect_cow_str(token)) {
+            Cow::Borrowed(value) => Ok(value),
+            Cow::Owned(value) => Err(d.conversion_error(Token::String(value))),
+        }
+    }
+}
+
+impl<'a, D: BorrowDeserializer<'a, E>, E> Deserialize<D, E> for CowString<'a> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<CowString<'a>, E> {
+        d.expect_borrowed_str()
+    }
+
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<CowString<'a>, E> {
+        d.expect_cow_str(token)
+    }
+}
 
 //////////////////////////////////////////////////////////////////////////////
 
@@ -705,6 +1097,11 @@ impl<
     E,
     T: Deserialize<D, E>
 > Deserialize<D, E> for Box<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<Box<T>, E> {
+        Ok(Box::new(try!(Deserialize::deserialize(d))))
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<Box<T>, E> {
         Ok(Box::new(try!(Deserialize::deserialize_token(d, token))))
@@ -716,6 +1113,11 @@ impl<
     E,
     T: Deserialize<D, E>
 > Deserialize<D, E> for Rc<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<Rc<T>, E> {
+        Ok(Rc::new(try!(Deserialize::deserialize(d))))
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<Rc<T>, E> {
         Ok(Rc::new(try!(Deserialize::deserialize_token(d, token))))
@@ -727,6 +1129,11 @@ impl<
     E,
     T: Deserialize<D, E> + Send + Sync
 > Deserialize<D, E> for Arc<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<Arc<T>, E> {
+        Ok(Arc::new(try!(Deserialize::deserialize(d))))
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<Arc<T>, E> {
         Ok(Arc::new(try!(Deserialize::deserialize_token(d, token))))
@@ -740,10 +1147,103 @@ impl<
     E,
     T: Deserialize<D ,E>
 > Deserialize<D, E> for option::Option<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<option::Option<T>, E> {
+        d.expect_next_option()
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<option::Option<T>, E> {
         d.expect_option(token)
     }
+
+    #[inline]
+    fn deserialize_missing(_d: &mut D,
+                           _name: &'static str,
+                           _field: &'static str) -> Result<option::Option<T>, E> {
+        Ok(None)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A structure field that tells a missing value apart from a `null` one,
+/// which an `Option` field can't do. Derived `Serialize` impls leave out an
+/// `Absent` field.
+#[derive(Clone, PartialEq, Show)]
+pub enum Presence<T> {
+    /// The field was missing.
+    Absent,
+    /// The field was `null`.
+    Null,
+    /// The field had a value.
+    Value(T),
+}
+
+impl<T> Presence<T> {
+    /// Returns true if the field was missing.
+    #[inline]
+    pub fn is_absent(&self) -> bool {
+        match *self {
+            Presence::Absent => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the field was `null`.
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        match *self {
+            Presence::Null => true,
+            _ => false,
+        }
+    }
+
+    /// Converts the field into an `Option`, forgetting whether it was
+    /// missing or `null`.
+    #[inline]
+    pub fn into_option(self) -> option::Option<T> {
+        match self {
+            Presence::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Default for Presence<T> {
+    #[inline]
+    fn default() -> Presence<T> {
+        Presence::Absent
+    }
+}
+
+impl<
+    D: Deserializer<E>,
+    E,
+    T: Deserialize<D ,E>
+> Deserialize<D, E> for Presence<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<Presence<T>, E> {
+        match try!(d.expect_next_option()) {
+            Some(value) => Ok(Presence::Value(value)),
+            None => Ok(Presence::Null),
+        }
+    }
+
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Presence<T>, E> {
+        match try!(d.expect_option(token)) {
+            Some(value) => Ok(Presence::Value(value)),
+            None => Ok(Presence::Null),
+        }
+    }
+
+    #[inline]
+    fn deserialize_missing(_d: &mut D,
+                           _name: &'static str,
+                           _field: &'static str) -> Result<Presence<T>, E> {
+        Ok(Presence::Absent)
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -753,6 +1253,11 @@ impl<
     E,
     T: Deserialize<D ,E>
 > Deserialize<D, E> for Vec<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<Vec<T>, E> {
+        d.expect_next_seq()
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<Vec<T>, E> {
         d.expect_seq(token)
@@ -767,6 +1272,11 @@ impl<
     K: Deserialize<D, E> + Eq + Hash<Hasher>,
     V: Deserialize<D, E>
 > Deserialize<D, E> for HashMap<K, V> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<HashMap<K, V>, E> {
+        d.expect_next_map()
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<HashMap<K, V>, E> {
         d.expect_map(token)
@@ -779,6 +1289,11 @@ impl<
     K: Deserialize<D, E> + Ord,
     V: Deserialize<D, E>
 > Deserialize<D, E> for BTreeMap<K, V> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<BTreeMap<K, V>, E> {
+        d.expect_next_map()
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<BTreeMap<K, V>, E> {
         d.expect_map(token)
@@ -792,6 +1307,11 @@ impl<
     E,
     T: Deserialize<D, E> + Eq + Hash<Hasher>
 > Deserialize<D, E> for HashSet<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<HashSet<T>, E> {
+        d.expect_next_seq()
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<HashSet<T>, E> {
         d.expect_seq(token)
@@ -803,6 +1323,11 @@ impl<
     E,
     T: Deserialize<D, E> + Ord
 > Deserialize<D, E> for BTreeSet<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<BTreeSet<T>, E> {
+        d.expect_next_seq()
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<BTreeSet<T>, E> {
         d.expect_seq(token)
@@ -821,6 +1346,11 @@ macro_rules! impl_deserialize_tuple {
             D: Deserializer<E>,
             E
         > Deserialize<D, E> for () {
+            #[inline]
+            fn deserialize(d: &mut D) -> Result<(), E> {
+                d.expect_next_null()
+            }
+
             #[inline]
             fn deserialize_token(d: &mut D, token: Token) -> Result<(), E> {
                 d.expect_null(token)
@@ -833,6 +1363,21 @@ macro_rules! impl_deserialize_tuple {
             E,
             $($name: Deserialize<D, E>),*
         > Deserialize<D, E> for ($($name,)*) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn deserialize(d: &mut D) -> Result<($($name,)*), E> {
+                try!(d.expect_next_tuple_start());
+
+                let result = ($({
+                    let $name = try!(d.expect_tuple_elt());
+                    $name
+                },)*);
+
+                try!(d.expect_tuple_end());
+
+                Ok(result)
+            }
+
             #[inline]
             #[allow(non_snake_case)]
             fn deserialize_token(d: &mut D, token: Token) -> Result<($($name,)*), E> {
@@ -1071,6 +1616,267 @@ impl<D: Deserializer<E>, E> Deserialize<D, E> for GatherTokens {
     }
 }
 
+impl GatherTokens {
+    /// Remove the top level entry named `field` from gathered struct or map
+    /// tokens, returning the tokens of its value.
+    pub fn take_field(&mut self, field: &str) -> option::Option<Vec<Token>> {
+        match self.tokens.first() {
+            Some(&Token::StructStart(..)) | Some(&Token::MapStart(_)) => { }
+            _ => { return None; }
+        }
+
+        let mut idx = 1;
+
+        while idx < self.tokens.len() && self.tokens[idx] != Token::End {
+            let value = skip_value(self.tokens.as_slice(), idx);
+            let end = skip_value(self.tokens.as_slice(), value);
+
+            let found = match self.tokens[idx] {
+                Token::Str(key) => key == field,
+                Token::String(ref key) => key.as_slice() == field,
+                _ => false,
+            };
+
+            if found {
+                let tokens = mem::replace(&mut self.tokens, Vec::new());
+                let mut taken = Vec::with_capacity(end - value);
+
+                for (i, token) in tokens.into_iter().enumerate() {
+                    if i < idx || i >= end {
+                        self.tokens.push(token);
+                    } else if i >= value {
+                        taken.push(token);
+                    }
+                }
+
+                return Some(taken);
+            }
+
+            idx = end;
+        }
+
+        None
+    }
+}
+
+/// Returns the index right after the value that starts at `idx` in a well
+/// formed token stream.
+fn skip_value(tokens: &[Token], mut idx: uint) -> uint {
+    let mut depth = 0u;
+
+    loop {
+        match tokens[idx] {
+            Token::Option(true) => {
+                idx += 1;
+                continue;
+            }
+            Token::TupleStart(_)
+            | Token::StructStart(..)
+            | Token::EnumStart(..)
+            | Token::SeqStart(_)
+            | Token::MapStart(_) => { depth += 1; }
+            Token::End => { depth -= 1; }
+            _ => { }
+        }
+
+        idx += 1;
+
+        if depth == 0 {
+            return idx;
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A `Deserializer` that replays tokens gathered by `GatherTokens`, while
+/// still reporting errors through the deserializer they came from.
+///
+/// Since the tokens may come from a self describing format, a map is
+/// accepted where a struct or an enum is expected, a string is accepted as a
+/// unit variant, and `Null` is accepted as `None`.
+pub struct ReplayDeserializer<'a, D: 'a, E> {
+    d: &'a mut D,
+    tokens: vec::IntoIter<Token>,
+    // How many `End` tokens close each open enum: one for `EnumStart`, two
+    // for a `{"variant": [...]}` map and none for a bare variant name.
+    enum_stack: Vec<uint>,
+}
+
+impl<'a, D: Deserializer<E>, E> ReplayDeserializer<'a, D, E> {
+    #[inline]
+    pub fn new(d: &'a mut D, tokens: Vec<Token>) -> ReplayDeserializer<'a, D, E> {
+        ReplayDeserializer {
+            d: d,
+            tokens: tokens.into_iter(),
+            enum_stack: Vec::new(),
+        }
+    }
+}
+
+impl<'a, D: Deserializer<E>, E> Iterator for ReplayDeserializer<'a, D, E> {
+    type Item = Result<Token, E>;
+
+    #[inline]
+    fn next(&mut self) -> option::Option<Result<Token, E>> {
+        self.tokens.next().map(|token| Ok(token))
+    }
+}
+
+impl<'a, D: Deserializer<E>, E> Deserializer<E> for ReplayDeserializer<'a, D, E> {
+    fn end_of_stream_error(&mut self) -> E {
+        self.d.end_of_stream_error()
+    }
+
+    fn syntax_error(&mut self, token: Token, expected: &'static [TokenKind]) -> E {
+        self.d.syntax_error(token, expected)
+    }
+
+    fn unexpected_name_error(&mut self, token: Token) -> E {
+        self.d.unexpected_name_error(token)
+    }
+
+    fn conversion_error(&mut self, token: Token) -> E {
+        self.d.conversion_error(token)
+    }
+
+    fn unknown_field_error(&mut self, field: &str) -> E {
+        self.d.unknown_field_error(field)
+    }
+
+    #[inline]
+    fn missing_field<
+        T: Deserialize<ReplayDeserializer<'a, D, E>, E>
+    >(&mut self, _field: &'static str) -> Result<T, E> {
+        // The original deserializer can't produce a value for us, so treat
+        // the field as `null` like self describing formats do.
+        Deserialize::deserialize_token(self, Token::Null)
+    }
+
+    #[inline]
+    fn expect_option<
+        T: Deserialize<ReplayDeserializer<'a, D, E>, E>
+    >(&mut self, token: Token) -> Result<option::Option<T>, E> {
+        match token {
+            Token::Null | Token::Option(false) => Ok(None),
+            Token::Option(true) => {
+                let value: T = try!(Deserialize::deserialize(self));
+                Ok(Some(value))
+            }
+            token => {
+                let value: T = try!(Deserialize::deserialize_token(self, token));
+                Ok(Some(value))
+            }
+        }
+    }
+
+    #[inline]
+    fn expect_struct_start(&mut self, token: Token, _name: &str) -> Result<(), E> {
+        match token {
+            Token::StructStart(..) | Token::MapStart(_) => Ok(()),
+            token => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::StructStartKind,
+                    TokenKind::MapStartKind,
+                ];
+                Err(self.syntax_error(token, EXPECTED_TOKENS))
+            }
+        }
+    }
+
+    #[inline]
+    fn expect_enum_start(&mut self, token: Token, name: &str, variants: &[&str]) -> Result<uint, E> {
+        match token {
+            Token::EnumStart(n, v, _) => {
+                if name == n {
+                    match variants.iter().position(|variant| *variant == v) {
+                        Some(position) => {
+                            self.enum_stack.push(1);
+                            Ok(position)
+                        }
+                        None => Err(self.unexpected_name_error(token)),
+                    }
+                } else {
+                    Err(self.unexpected_name_error(token))
+                }
+            }
+            Token::MapStart(_) => {
+                let variant = match try!(self.expect_token()) {
+                    Token::Str(variant) => variant.to_string(),
+                    Token::String(variant) => variant,
+                    token => { return Err(self.syntax_error(token, STR_TOKEN_KINDS)); }
+                };
+
+                match try!(self.expect_token()) {
+                    Token::TupleStart(_) | Token::SeqStart(_) => { }
+                    token => {
+                        static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                            TokenKind::TupleStartKind,
+                            TokenKind::SeqStartKind,
+                        ];
+                        return Err(self.syntax_error(token, EXPECTED_TOKENS));
+                    }
+                }
+
+                match variants.iter().position(|v| *v == variant.as_slice()) {
+                    Some(position) => {
+                        self.enum_stack.push(2);
+                        Ok(position)
+                    }
+                    None => Err(self.unexpected_name_error(Token::String(variant))),
+                }
+            }
+            Token::Str(_) | Token::String(_) => {
+                let position = match token {
+                    Token::Str(variant) => variants.iter().position(|v| *v == variant),
+                    Token::String(ref variant) => {
+                        variants.iter().position(|v| *v == variant.as_slice())
+                    }
+                    _ => None,
+                };
+
+                match position {
+                    Some(position) => {
+                        self.enum_stack.push(0);
+                        Ok(position)
+                    }
+                    None => Err(self.unexpected_name_error(token)),
+                }
+            }
+            token => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::EnumStartKind,
+                    TokenKind::MapStartKind,
+                    TokenKind::StrKind,
+                    TokenKind::StringKind,
+                ];
+                Err(self.syntax_error(token, EXPECTED_TOKENS))
+            }
+        }
+    }
+
+    #[inline]
+    fn expect_enum_end(&mut self) -> Result<(), E> {
+        static EXPECTED_TOKENS: &'static [TokenKind] = &[
+            TokenKind::EndKind,
+        ];
+
+        let ends = self.enum_stack.pop().unwrap_or(1);
+
+        for _ in range(0, ends) {
+            match try!(self.expect_token()) {
+                Token::End => { }
+                token => { return Err(self.syntax_error(token, EXPECTED_TOKENS)); }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The gathered tokens own their strings, so only `Token::Str` can be lent out.
+impl<'a, 'b, D: Deserializer<E>, E> BorrowDeserializer<'b, E> for ReplayDeserializer<'a, D, E> { }
+
 //////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
@@ -1251,6 +2057,8 @@ mod tests {
         }
     }
 
+    impl<Iter: Iterator<Item=Token>> BorrowDeserializer<'static, Error> for TokenDeserializer<Iter> { }
+
     //////////////////////////////////////////////////////////////////////////////
 
     macro_rules! test_value {
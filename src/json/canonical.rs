@@ -0,0 +1,142 @@
+//! Canonical JSON output, following the JSON Canonicalization Scheme of
+//! RFC 8785.
+//!
+//! Equal values always produce byte-identical output, which makes it
+//! suitable for hashing and signing: object keys are sorted by their UTF-16
+//! code units, every number is rounded to an `f64` and written the way
+//! ECMAScript prints numbers, strings only escape what they must, and no
+//! whitespace is written.
+
+use std::io::{self, IoError, IoResult};
+use std::iter;
+use std::num::Float;
+
+use ser;
+
+use super::float;
+use super::value::{self, Value};
+
+/// Writes a `Value` in canonical form. NaN and infinite floats can't be
+/// represented and fail with an `InvalidInput` error.
+pub fn write_value<W: Writer>(wr: &mut W, value: &Value) -> IoResult<()> {
+    match *value {
+        Value::Null => wr.write_str("null"),
+        Value::Boolean(true) => wr.write_str("true"),
+        Value::Boolean(false) => wr.write_str("false"),
+        // Integers are numbers like any other, so ones past 2^53 lose
+        // precision just as they would in JavaScript.
+        Value::Integer(v) => write_f64(wr, v as f64),
+        Value::Unsigned(v) => write_f64(wr, v as f64),
+        Value::Floating(v) => write_f64(wr, v),
+        Value::RawNumber(ref v) => write_f64(wr, float::parse_f64(v.as_bytes())),
+        Value::String(ref v) => escape_str(wr, v.as_slice()),
+        Value::Array(ref v) => {
+            try!(wr.write_str("["));
+            for (i, elt) in v.iter().enumerate() {
+                if i != 0 {
+                    try!(wr.write_str(","));
+                }
+                try!(write_value(wr, elt));
+            }
+            wr.write_str("]")
+        }
+        Value::Object(ref v) => {
+            // A `BTreeMap` orders its keys by their UTF-8 bytes, which
+            // differs from the UTF-16 order for characters past U+FFFF.
+            let mut entries: Vec<(&String, &Value)> = v.iter().collect();
+            entries.sort_by(|&(a, _), &(b, _)| {
+                iter::order::cmp(a.as_slice().utf16_units(), b.as_slice().utf16_units())
+            });
+
+            try!(wr.write_str("{"));
+            for (i, &(key, value)) in entries.iter().enumerate() {
+                if i != 0 {
+                    try!(wr.write_str(","));
+                }
+                try!(escape_str(wr, key.as_slice()));
+                try!(wr.write_str(":"));
+                try!(write_value(wr, value));
+            }
+            wr.write_str("}")
+        }
+    }
+}
+
+fn write_f64<W: Writer>(wr: &mut W, v: f64) -> IoResult<()> {
+    if !v.is_finite() {
+        return Err(IoError {
+            kind: io::InvalidInput,
+            desc: "cannot serialize a non-finite float",
+            detail: Some(format!("{}", v)),
+        });
+    }
+
+    wr.write_str(float::fmt_f64_canonical(v).as_slice())
+}
+
+/// Unlike `ser::escape_str`, this writes every other control character as a
+/// lowercase `\u00xx` escape, as RFC 8785 requires.
+fn escape_str<W: Writer>(wr: &mut W, v: &str) -> IoResult<()> {
+    let bytes = v.as_bytes();
+
+    try!(wr.write_str("\""));
+
+    let mut start = 0;
+
+    for (i, byte) in bytes.iter().enumerate() {
+        let escaped = match *byte {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\x08' => "\\b",
+            b'\x0c' => "\\f",
+            b'\n' => "\\n",
+            b'\r' => "\\r",
+            b'\t' => "\\t",
+            b'\x00' ... b'\x1f' => "",
+            _ => { continue; }
+        };
+
+        if start < i {
+            try!(wr.write(bytes.slice(start, i)));
+        }
+
+        if escaped.is_empty() {
+            try!(write!(wr, "\\u{:04x}", *byte));
+        } else {
+            try!(wr.write_str(escaped));
+        }
+
+        start = i + 1;
+    }
+
+    if start != bytes.len() {
+        try!(wr.write(bytes.slice_from(start)));
+    }
+
+    wr.write_str("\"")
+}
+
+/// Encode the specified value into a canonical json `[u8]` writer.
+pub fn to_canonical_writer<
+    W: Writer,
+    T: ser::Serialize<value::Serializer, ()>
+>(mut writer: W, value: &T) -> IoResult<W> {
+    try!(write_value(&mut writer, &value::to_value(value)));
+    Ok(writer)
+}
+
+/// Encode the specified value into a canonical json `[u8]` buffer.
+pub fn to_canonical_vec<
+    T: ser::Serialize<value::Serializer, ()>
+>(value: &T) -> IoResult<Vec<u8>> {
+    to_canonical_writer(Vec::with_capacity(128), value)
+}
+
+/// Encode the specified value into a canonical json `String` buffer.
+pub fn to_canonical_string<
+    T: ser::Serialize<value::Serializer, ()>
+>(value: &T) -> IoResult<String> {
+    let buf = try!(to_canonical_vec(value));
+    // Only whole UTF-8 strings are ever written, so this can't fail.
+    Ok(String::from_utf8(buf).unwrap())
+}
@@ -1,11 +1,15 @@
+use std::borrow::Cow;
 use std::str;
-use std::num::Float;
+use std::string::CowString;
+use std::i64;
+use std::num::{Float, Int};
 use unicode::str::Utf16Item;
 use std::char;
 
-use de;
+use de::{self, BorrowDeserializer, Deserializer};
 
-use super::error::{Error, ErrorCode};
+use super::error::{Error, ErrorCode, Location};
+use super::float;
 
 #[derive(PartialEq, Show)]
 enum State {
@@ -25,6 +29,12 @@ enum State {
     ObjectValue,
 }
 
+// One step of the path to the value being parsed.
+enum PathSegment {
+    Index(uint),
+    Key(Vec<u8>),
+}
+
 /// A streaming JSON parser implemented as an iterator of JsonEvent, consuming
 /// an iterator of char.
 pub struct Parser<Iter> {
@@ -34,7 +44,22 @@ pub struct Parser<Iter> {
     col: uint,
     // A state machine is kept to make it possible to interupt and resume parsing.
     state_stack: Vec<State>,
+    // The array indices and object keys leading to the current value.
+    path: Vec<PathSegment>,
     buf: Vec<u8>,
+    // Set when the current enum was written as a bare variant name string.
+    unit_variant: bool,
+    // How many bytes have been read from `rdr`.
+    pos: uint,
+    // The byte range of the last parsed string, if it needed no unescaping.
+    str_span: Option<(uint, uint)>,
+    // The last float that was parsed and the digits it was parsed from.
+    last_float: Option<f64>,
+    float_digits: Vec<u8>,
+    // Emit numbers as `Token::RawNumber` instead of converting them.
+    arbitrary_precision: bool,
+    // Accept the `NaN`, `Infinity` and `-Infinity` extensions.
+    non_finite_floats: bool,
 }
 
 impl<Iter: Iterator<Item=u8>> Iterator for Parser<Iter> {
@@ -60,8 +85,20 @@ impl<Iter: Iterator<Item=u8>> Iterator for Parser<Iter> {
 
         match state {
             State::Value => Some(self.parse_value()),
-            State::ListStart => Some(self.parse_list_start()),
-            State::ListCommaOrEnd => Some(self.parse_list_comma_or_end()),
+            State::ListStart => {
+                match self.parse_list_start() {
+                    Ok(true) => Some(self.parse_value()),
+                    Ok(false) => Some(Ok(de::Token::End)),
+                    Err(err) => Some(Err(err)),
+                }
+            }
+            State::ListCommaOrEnd => {
+                match self.parse_list_comma_or_end() {
+                    Ok(true) => Some(self.parse_value()),
+                    Ok(false) => Some(Ok(de::Token::End)),
+                    Err(err) => Some(Err(err)),
+                }
+            }
             State::ObjectStart => {
                 match self.parse_object_start() {
                     Ok(Some(s)) => Some(Ok(de::Token::String(s.to_string()))),
@@ -77,7 +114,12 @@ impl<Iter: Iterator<Item=u8>> Iterator for Parser<Iter> {
                 }
             }
             //State::ObjectKey => Some(self.parse_object_key()),
-            State::ObjectValue => Some(self.parse_object_value()),
+            State::ObjectValue => {
+                match self.parse_object_value() {
+                    Ok(()) => Some(self.parse_value()),
+                    Err(err) => Some(Err(err)),
+                }
+            }
         }
     }
 }
@@ -92,12 +134,59 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
             line: 1,
             col: 0,
             state_stack: vec!(State::Value),
+            path: Vec::new(),
             buf: Vec::with_capacity(128),
+            unit_variant: false,
+            pos: 0,
+            str_span: None,
+            last_float: None,
+            float_digits: Vec::new(),
+            arbitrary_precision: false,
+            non_finite_floats: false,
         };
         p.bump();
         return p;
     }
 
+    /// Keeps the original digits of every number by emitting it as a
+    /// `Token::RawNumber`, so that it round-trips through a `Value` without
+    /// losing any precision.
+    #[inline]
+    pub fn arbitrary_precision(mut self, enabled: bool) -> Parser<Iter> {
+        self.arbitrary_precision = enabled;
+        self
+    }
+
+    /// Accepts the `NaN`, `Infinity` and `-Infinity` literals that a
+    /// `Serializer` writes with `NonFiniteFloats::Extension`.
+    #[inline]
+    pub fn allow_non_finite_floats(mut self, enabled: bool) -> Parser<Iter> {
+        self.non_finite_floats = enabled;
+        self
+    }
+
+    /// Makes sure the whole stream has been consumed.
+    #[inline]
+    pub fn end(&mut self) -> Result<(), Error> {
+        match self.next() {
+            Some(Ok(_token)) => Err(self.error(ErrorCode::TrailingCharacters)),
+            Some(Err(err)) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Lazily deserializes the array at the current position, one element at
+    /// a time, so that only a single element is ever held in memory. The
+    /// iterator stops at the end of the array, or after the first error.
+    #[inline]
+    pub fn array_iter<'a, T>(&'a mut self) -> ArrayIter<'a, T, Iter> {
+        ArrayIter {
+            parser: self,
+            started: false,
+            done: false,
+        }
+    }
+
     #[inline(always)]
     fn eof(&self) -> bool { self.ch.is_none() }
 
@@ -108,6 +197,10 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
     fn bump(&mut self) {
         self.ch = self.rdr.next();
 
+        if self.ch.is_some() {
+            self.pos += 1;
+        }
+
         if self.ch_is(b'\n') {
             self.line += 1;
             self.col = 1;
@@ -129,7 +222,24 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
 
     #[inline]
     fn error(&mut self, reason: ErrorCode) -> Error {
-        Error::SyntaxError(reason, self.line, self.col)
+        Error::SyntaxError(reason, self.line, self.col, self.location())
+    }
+
+    fn location(&self) -> Location {
+        // `pos` counts the current character as already read.
+        let offset = if self.eof() { self.pos } else { self.pos - 1 };
+        let mut location = Location::new(offset);
+
+        for segment in self.path.iter() {
+            match *segment {
+                PathSegment::Index(index) => location.push_index(index),
+                PathSegment::Key(ref key) => {
+                    location.push_key(str::from_utf8(key.as_slice()).unwrap());
+                }
+            }
+        }
+
+        location
     }
 
     #[inline]
@@ -153,6 +263,12 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
             b't' => self.parse_ident(b"rue", de::Token::Bool(true)),
             b'f' => self.parse_ident(b"alse", de::Token::Bool(false)),
             b'0' ... b'9' | b'-' => self.parse_number(),
+            b'N' if self.non_finite_floats => {
+                self.parse_ident(b"aN", de::Token::F64(Float::nan()))
+            }
+            b'I' if self.non_finite_floats => {
+                self.parse_ident(b"nfinity", de::Token::F64(Float::infinity()))
+            }
             b'"' => {
                 Ok(de::Token::String(try!(self.parse_string()).to_string()))
             }
@@ -184,39 +300,40 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
 
     #[inline]
     fn parse_number(&mut self) -> Result<de::Token, Error> {
-        let mut neg = 1;
+        self.buf.clear();
 
         if self.ch_is(b'-') {
+            self.buf.push(b'-');
             self.bump();
-            neg = -1;
-        }
-
-        let res = try!(self.parse_integer());
-
-        if self.ch_is(b'.') || self.ch_is(b'e') || self.ch_is(b'E') {
-            let neg = neg as f64;
-            let mut res = res as f64;
 
-            if self.ch_is(b'.') {
-                res = try!(self.parse_decimal(res));
+            if self.non_finite_floats && self.ch_is(b'I') {
+                return self.parse_ident(b"nfinity", de::Token::F64(Float::neg_infinity()));
             }
+        }
 
-            if self.ch_is(b'e') || self.ch_is(b'E') {
-                res = try!(self.parse_exponent(res));
-            }
+        let is_integer = try!(self.scan_number());
 
-            Ok(de::Token::F64(neg * res))
+        if self.arbitrary_precision {
+            let digits = str::from_utf8(self.buf.as_slice()).unwrap().to_string();
+            Ok(de::Token::RawNumber(digits))
+        } else if is_integer {
+            self.parse_integer()
         } else {
-            Ok(de::Token::I64(neg * res))
+            let x = float::parse_f64(self.buf.as_slice());
+            self.last_float = Some(x);
+            self.float_digits.clear();
+            self.float_digits.push_all(self.buf.as_slice());
+            Ok(de::Token::F64(x))
         }
     }
 
+    // Copies the digits of a number into `self.buf`, making sure it's well
+    // formed, and returns whether it is an integer.
     #[inline]
-    fn parse_integer(&mut self) -> Result<i64, Error> {
-        let mut res = 0;
-
+    fn scan_number(&mut self) -> Result<bool, Error> {
         match self.ch_or_null() {
             b'0' => {
+                self.buf.push(b'0');
                 self.bump();
 
                 // There can be only one leading '0'.
@@ -226,90 +343,105 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
                     }
                     _ => ()
                 }
-            },
-            b'1' ... b'9' => {
-                while !self.eof() {
-                    match self.ch_or_null() {
-                        c @ b'0' ... b'9' => {
-                            res *= 10;
-                            res += (c as i64) - (b'0' as i64);
-                            self.bump();
-                        }
-                        _ => break,
-                    }
-                }
             }
+            b'1' ... b'9' => self.scan_digits(),
             _ => { return Err(self.error(ErrorCode::InvalidNumber)); }
         }
 
-        Ok(res)
-    }
+        let mut is_integer = true;
 
-    #[inline]
-    fn parse_decimal(&mut self, res: f64) -> Result<f64, Error> {
-        self.bump();
+        if self.ch_is(b'.') {
+            is_integer = false;
+            self.buf.push(b'.');
+            self.bump();
 
-        // Make sure a digit follows the decimal place.
-        match self.ch_or_null() {
-            b'0' ... b'9' => (),
-             _ => { return Err(self.error(ErrorCode::InvalidNumber)); }
+            // Make sure a digit follows the decimal place.
+            try!(self.scan_some_digits());
         }
 
-        let mut res = res;
-        let mut dec = 1.0;
-        while !self.eof() {
+        if self.ch_is(b'e') || self.ch_is(b'E') {
+            is_integer = false;
+            let ch = self.ch_or_null();
+            self.buf.push(ch);
+            self.bump();
+
+            if self.ch_is(b'+') || self.ch_is(b'-') {
+                let ch = self.ch_or_null();
+                self.buf.push(ch);
+                self.bump();
+            }
+
+            // Make sure a digit follows the exponent place.
+            try!(self.scan_some_digits());
+        }
+
+        Ok(is_integer)
+    }
+
+    #[inline]
+    fn scan_digits(&mut self) {
+        loop {
             match self.ch_or_null() {
                 c @ b'0' ... b'9' => {
-                    dec /= 10.0;
-                    res += (((c as int) - (b'0' as int)) as f64) * dec;
+                    self.buf.push(c);
                     self.bump();
                 }
                 _ => break,
             }
         }
-
-        Ok(res)
     }
 
     #[inline]
-    fn parse_exponent(&mut self, mut res: f64) -> Result<f64, Error> {
-        self.bump();
-
-        let mut exp = 0u;
-        let mut neg_exp = false;
-
-        if self.ch_is(b'+') {
-            self.bump();
-        } else if self.ch_is(b'-') {
-            self.bump();
-            neg_exp = true;
-        }
-
-        // Make sure a digit follows the exponent place.
+    fn scan_some_digits(&mut self) -> Result<(), Error> {
         match self.ch_or_null() {
-            b'0' ... b'9' => (),
-            _ => { return Err(self.error(ErrorCode::InvalidNumber)); }
+            b'0' ... b'9' => {
+                self.scan_digits();
+                Ok(())
+            }
+            _ => Err(self.error(ErrorCode::InvalidNumber)),
         }
-        while !self.eof() {
-            match self.ch_or_null() {
-                c @ b'0' ... b'9' => {
-                    exp *= 10;
-                    exp += (c as uint) - (b'0' as uint);
+    }
 
-                    self.bump();
+    // Converts the integer in `self.buf`. Only positive integers that don't
+    // fit in an `i64` become a `U64`.
+    #[inline]
+    fn parse_integer(&mut self) -> Result<de::Token, Error> {
+        let neg = self.buf[0] == b'-';
+        let mut res = 0u64;
+        let mut overflow = false;
+
+        for &c in self.buf.iter().skip(if neg { 1 } else { 0 }) {
+            match res.checked_mul(10).and_then(|res| res.checked_add((c - b'0') as u64)) {
+                Some(x) => { res = x; }
+                None => {
+                    overflow = true;
+                    break;
                 }
-                _ => break
             }
         }
 
-        let exp: f64 = 10_f64.powi(exp as i32);
-        if neg_exp {
-            res /= exp;
+        let max = i64::MAX as u64;
+
+        let token = if overflow {
+            None
+        } else if !neg {
+            if res <= max {
+                Some(de::Token::I64(res as i64))
+            } else {
+                Some(de::Token::U64(res))
+            }
+        } else if res <= max {
+            Some(de::Token::I64(-(res as i64)))
+        } else if res == max + 1 {
+            Some(de::Token::I64(i64::MIN))
         } else {
-            res *= exp;
-        }
+            None
+        };
 
-        Ok(res)
+        match token {
+            Some(token) => Ok(token),
+            None => Err(self.error(ErrorCode::IntegerOverflow)),
+        }
     }
 
     #[inline]
@@ -344,7 +476,10 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
     fn parse_string(&mut self) -> Result<&str, Error> {
         self.buf.clear();
 
+        // The opening quote was the last byte read.
+        let start = self.pos;
         let mut escape = false;
+        let mut escaped = false;
 
         loop {
             let ch = match self.next_char() {
@@ -409,11 +544,18 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
             } else {
                 match ch {
                     b'"' => {
+                        self.str_span = if escaped {
+                            None
+                        } else {
+                            Some((start, self.pos - 1))
+                        };
+
                         self.bump();
                         return Ok(str::from_utf8(self.buf.as_slice()).unwrap());
                     }
                     b'\\' => {
                         escape = true;
+                        escaped = true;
                     }
                     ch => {
                         self.buf.push(ch);
@@ -423,30 +565,38 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
         }
     }
 
+    // Returns whether an element follows, or if the list has ended.
     #[inline]
-    fn parse_list_start(&mut self) -> Result<de::Token, Error> {
+    fn parse_list_start(&mut self) -> Result<bool, Error> {
         self.parse_whitespace();
 
         if self.ch_is(b']') {
             self.bump();
-            Ok(de::Token::End)
+            Ok(false)
         } else {
             self.state_stack.push(State::ListCommaOrEnd);
-            self.parse_value()
+            self.path.push(PathSegment::Index(0));
+            Ok(true)
         }
     }
 
+    // Returns whether an element follows, or if the list has ended.
     #[inline]
-    fn parse_list_comma_or_end(&mut self) -> Result<de::Token, Error> {
+    fn parse_list_comma_or_end(&mut self) -> Result<bool, Error> {
         self.parse_whitespace();
 
         if self.ch_is(b',') {
             self.bump();
             self.state_stack.push(State::ListCommaOrEnd);
-            self.parse_value()
+            match self.path.last_mut() {
+                Some(&mut PathSegment::Index(ref mut index)) => { *index += 1; }
+                _ => { }
+            }
+            Ok(true)
         } else if self.ch_is(b']') {
             self.bump();
-            Ok(de::Token::End)
+            self.path.pop();
+            Ok(false)
         } else if self.eof() {
             Err(self.error(ErrorCode::EOFWhileParsingList))
         } else {
@@ -462,7 +612,7 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
             self.bump();
             Ok(None)
         } else {
-            Ok(Some(try!(self.parse_object_key())))
+            Ok(Some(try!(self.parse_object_key(true))))
         }
     }
 
@@ -472,9 +622,10 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
 
         if self.ch_is(b',') {
             self.bump();
-            Ok(Some(try!(self.parse_object_key())))
+            Ok(Some(try!(self.parse_object_key(false))))
         } else if self.ch_is(b'}') {
             self.bump();
+            self.path.pop();
             Ok(None)
         } else if self.eof() {
             Err(self.error(ErrorCode::EOFWhileParsingObject))
@@ -484,7 +635,18 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
     }
 
     #[inline]
-    fn parse_object_key(&mut self) -> Result<&str, Error> {
+    fn parse_struct_field_or_end(&mut self) -> Result<Option<&str>, Error> {
+        match self.state_stack.pop() {
+            Some(State::ObjectStart) => self.parse_object_start(),
+            Some(State::ObjectCommaOrEnd) => self.parse_object_comma_or_end(),
+            _ => panic!("invalid internal state"),
+        }
+    }
+
+    // The first key of an object starts a new path segment, and the rest
+    // replace it.
+    #[inline]
+    fn parse_object_key(&mut self, first: bool) -> Result<&str, Error> {
         self.parse_whitespace();
 
         if self.eof() {
@@ -495,20 +657,34 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
             b'"' => {
                 self.state_stack.push(State::ObjectValue);
 
-                Ok(try!(self.parse_string()))
+                try!(self.parse_string());
+
+                if first {
+                    self.path.push(PathSegment::Key(self.buf.clone()));
+                } else {
+                    match self.path.last_mut() {
+                        Some(&mut PathSegment::Key(ref mut key)) => {
+                            key.clear();
+                            key.push_all(self.buf.as_slice());
+                        }
+                        _ => { }
+                    }
+                }
+
+                Ok(str::from_utf8(self.buf.as_slice()).unwrap())
             }
             _ => Err(self.error(ErrorCode::KeyMustBeAString)),
         }
     }
 
     #[inline]
-    fn parse_object_value(&mut self) -> Result<de::Token, Error> {
+    fn parse_object_value(&mut self) -> Result<(), Error> {
         self.parse_whitespace();
 
         if self.ch_is(b':') {
             self.bump();
             self.state_stack.push(State::ObjectCommaOrEnd);
-            self.parse_value()
+            Ok(())
         } else if self.eof() {
             Err(self.error(ErrorCode::EOFWhileParsingObject))
         } else {
@@ -519,19 +695,23 @@ impl<Iter: Iterator<Item=u8>> Parser<Iter> {
 
 impl<Iter: Iterator<Item=u8>> de::Deserializer<Error> for Parser<Iter> {
     fn end_of_stream_error(&mut self) -> Error {
-        Error::SyntaxError(ErrorCode::EOFWhileParsingValue, self.line, self.col)
+        self.error(ErrorCode::EOFWhileParsingValue)
     }
 
     fn syntax_error(&mut self, token: de::Token, expected: &'static [de::TokenKind]) -> Error {
-        Error::SyntaxError(ErrorCode::ExpectedTokens(token, expected), self.line, self.col)
+        self.error(ErrorCode::ExpectedTokens(token, expected))
     }
 
     fn unexpected_name_error(&mut self, token: de::Token) -> Error {
-        Error::SyntaxError(ErrorCode::UnexpectedName(token), self.line, self.col)
+        self.error(ErrorCode::UnexpectedName(token))
     }
 
     fn conversion_error(&mut self, token: de::Token) -> Error {
-        Error::SyntaxError(ErrorCode::ConversionError(token), self.line, self.col)
+        self.error(ErrorCode::ConversionError(token))
+    }
+
+    fn unknown_field_error(&mut self, field: &str) -> Error {
+        self.error(ErrorCode::UnknownField(field.to_string()))
     }
 
     #[inline]
@@ -543,6 +723,30 @@ impl<Iter: Iterator<Item=u8>> de::Deserializer<Error> for Parser<Iter> {
         de::Deserialize::deserialize_token(self, de::Token::Null)
     }
 
+    #[inline]
+    fn missing_struct_field<
+        T: de::Deserialize<Parser<Iter>, Error>
+    >(&mut self, name: &'static str, field: &'static str) -> Result<T, Error> {
+        Err(self.error(ErrorCode::MissingField(field, name)))
+    }
+
+    // Floats are parsed as an `f64`, so convert the digits again rather than
+    // rounding twice.
+    #[inline]
+    fn expect_f32(&mut self, token: de::Token) -> Result<f32, Error> {
+        match token {
+            de::Token::F64(x) => {
+                match self.last_float {
+                    Some(last) if last == x && last.is_negative() == x.is_negative() => {
+                        Ok(float::parse_f32(self.float_digits.as_slice()))
+                    }
+                    _ => self.expect_num(de::Token::F64(x)),
+                }
+            }
+            token => self.expect_num(token),
+        }
+    }
+
     // Special case treating options as a nullable value.
     #[inline]
     fn expect_option<
@@ -557,36 +761,72 @@ impl<Iter: Iterator<Item=u8>> de::Deserializer<Error> for Parser<Iter> {
         }
     }
 
-    // Special case treating enums as a `{"<variant-name>": [<fields>]}`.
+    // Special case treating enums as a `{"<variant-name>": [<fields>]}`, or
+    // as a bare `"<variant-name>"` string for unit variants.
     #[inline]
     fn expect_enum_start(&mut self,
                          token: de::Token,
                          _name: &str,
                          variants: &[&str]) -> Result<uint, Error> {
-        match token {
-            de::Token::MapStart(_) => { }
+        let variant = match token {
+            de::Token::String(variant) => {
+                self.unit_variant = true;
+                variant
+            }
+            de::Token::MapStart(_) => {
+                // Enums only have one field in them, which is the variant name.
+                let variant = match try!(self.expect_token()) {
+                    de::Token::String(variant) => variant,
+                    _ => { return Err(self.error(ErrorCode::ExpectedEnumVariantString)); }
+                };
+
+                // The variant's field is a list of the values.
+                match try!(self.expect_token()) {
+                    de::Token::SeqStart(_) => { }
+                    _ => { return Err(self.error(ErrorCode::ExpectedEnumToken)); }
+                }
+
+                self.unit_variant = false;
+                variant
+            }
             _ => { return Err(self.error(ErrorCode::ExpectedEnumMapStart)); }
         };
 
-        // Enums only have one field in them, which is the variant name.
-        let variant = match try!(self.expect_token()) {
-            de::Token::String(variant) => variant,
-            _ => { return Err(self.error(ErrorCode::ExpectedEnumVariantString)); }
-        };
+        match variants.iter().position(|v| *v == variant.as_slice()) {
+            Some(idx) => Ok(idx),
+            None => Err(self.error(ErrorCode::UnknownVariant(variant))),
+        }
+    }
 
-        // The variant's field is a list of the values.
-        match try!(self.expect_token()) {
-            de::Token::SeqStart(_) => { }
-            _ => { return Err(self.error(ErrorCode::ExpectedEnumToken)); }
+    #[inline]
+    fn expect_enum_elt<
+        T: de::Deserialize<Parser<Iter>, Error>
+    >(&mut self) -> Result<T, Error> {
+        // A bare variant name can't carry any fields.
+        if self.unit_variant {
+            return Err(self.error(ErrorCode::ExpectedEnumMapStart));
         }
 
-        match variants.iter().position(|v| *v == variant.as_slice()) {
-            Some(idx) => Ok(idx),
-            None => Err(self.error(ErrorCode::UnknownVariant)),
+        de::Deserialize::deserialize(self)
+    }
+
+    #[inline]
+    fn expect_enum_struct_start(&mut self, name: &str) -> Result<(), Error> {
+        if self.unit_variant {
+            return Err(self.error(ErrorCode::ExpectedEnumMapStart));
         }
+
+        let token = try!(self.expect_token());
+        self.expect_struct_start(token, name)
     }
 
     fn expect_enum_end(&mut self) -> Result<(), Error> {
+        // Unit variants written as a string have nothing left to consume.
+        if self.unit_variant {
+            self.unit_variant = false;
+            return Ok(());
+        }
+
         // There will be one `End` for the list, and one for the object.
         match try!(self.expect_token()) {
             de::Token::End => {
@@ -616,23 +856,236 @@ impl<Iter: Iterator<Item=u8>> de::Deserializer<Error> for Parser<Iter> {
     fn expect_struct_field_or_end(&mut self,
                                   fields: &'static [&'static str]
                                  ) -> Result<Option<Option<uint>>, Error> {
-        let result = match self.state_stack.pop() {
-            Some(State::ObjectStart) => {
-                try!(self.parse_object_start())
-            }
-            Some(State::ObjectCommaOrEnd) => {
-                try!(self.parse_object_comma_or_end())
-            }
-            _ => panic!("invalid internal state"),
-        };
-
-        let s = match result {
+        let s = match try!(self.parse_struct_field_or_end()) {
             Some(s) => s,
             None => { return Ok(None); }
         };
 
         Ok(Some(fields.iter().position(|field| *field == s.as_slice())))
     }
+
+    #[inline]
+    fn expect_known_struct_field_or_end(&mut self,
+                                        fields: &'static [&'static str]
+                                       ) -> Result<Option<uint>, Error> {
+        let field = match try!(self.parse_struct_field_or_end()) {
+            Some(s) => {
+                match fields.iter().position(|field| *field == s.as_slice()) {
+                    Some(idx) => { return Ok(Some(idx)); }
+                    None => s.to_string(),
+                }
+            }
+            None => { return Ok(None); }
+        };
+
+        Err(self.unknown_field_error(field.as_slice()))
+    }
+}
+
+/// The bytes of a `&str`, which lets a `Parser` lend out strings borrowed
+/// directly from the input.
+pub struct StrBytes<'a> {
+    s: &'a str,
+    bytes: str::Bytes<'a>,
+}
+
+impl<'a> StrBytes<'a> {
+    #[inline]
+    pub fn new(s: &'a str) -> StrBytes<'a> {
+        StrBytes {
+            s: s,
+            bytes: s.bytes(),
+        }
+    }
+}
+
+impl<'a> Iterator for StrBytes<'a> {
+    type Item = u8;
+
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        self.bytes.next()
+    }
+}
+
+static STR_TOKEN_KINDS: &'static [de::TokenKind] = &[
+    de::TokenKind::StrKind,
+    de::TokenKind::StringKind,
+];
+
+impl<'a> de::BorrowDeserializer<'a, Error> for Parser<StrBytes<'a>> {
+    // Strings read through here never allocate unless they contain escapes.
+    fn expect_borrowed_str(&mut self) -> Result<CowString<'a>, Error> {
+        let has_value = match self.state_stack.pop() {
+            Some(State::Value) => true,
+            Some(State::ListStart) => try!(self.parse_list_start()),
+            Some(State::ListCommaOrEnd) => try!(self.parse_list_comma_or_end()),
+            Some(State::ObjectValue) => {
+                try!(self.parse_object_value());
+                true
+            }
+            state => {
+                // Anything else, like an object key, goes through the tokens.
+                self.state_stack.extend(state.into_iter());
+                let token = try!(self.expect_token());
+                return self.expect_cow_str(token);
+            }
+        };
+
+        if !has_value {
+            return Err(self.syntax_error(de::Token::End, STR_TOKEN_KINDS));
+        }
+
+        self.parse_whitespace();
+
+        if !self.ch_is(b'"') {
+            let token = try!(self.parse_value());
+            return Err(self.syntax_error(token, STR_TOKEN_KINDS));
+        }
+
+        try!(self.parse_string());
+
+        match self.str_span {
+            Some((start, end)) => Ok(Cow::Borrowed(self.rdr.s.slice(start, end))),
+            None => Ok(Cow::Owned(str::from_utf8(self.buf.as_slice()).unwrap().to_string())),
+        }
+    }
+
+    #[inline]
+    fn expect_cow_str(&mut self, token: de::Token) -> Result<CowString<'a>, Error> {
+        match token {
+            de::Token::String(value) => {
+                // The token has already been allocated, but if it came
+                // straight from the input we can still hand out a borrow.
+                match self.str_span {
+                    Some((start, end)) if self.rdr.s.slice(start, end) == value.as_slice() => {
+                        Ok(Cow::Borrowed(self.rdr.s.slice(start, end)))
+                    }
+                    _ => Ok(Cow::Owned(value)),
+                }
+            }
+            token => Err(self.syntax_error(token, STR_TOKEN_KINDS)),
+        }
+    }
+}
+
+/// An iterator that decodes a sequence of json values, such as
+/// newline-delimited JSON or values simply written one after another.
+/// Iteration stops at the end of the input, or after the first error.
+pub struct StreamDeserializer<T, Iter> {
+    parser: Parser<Iter>,
+    offset: uint,
+    failed: bool,
+}
+
+impl<T, Iter: Iterator<Item=u8>> StreamDeserializer<T, Iter> {
+    /// Creates a stream deserializer that reads from the parser.
+    #[inline]
+    pub fn new(mut parser: Parser<Iter>) -> StreamDeserializer<T, Iter> {
+        // `next` starts each value itself, so the parser mustn't expect one
+        // already, or it would be left expecting a value after the last one.
+        parser.state_stack.clear();
+
+        StreamDeserializer {
+            parser: parser,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    /// The byte offset in the input at which the last value returned by
+    /// `next` started.
+    #[inline]
+    pub fn byte_offset(&self) -> uint {
+        self.offset
+    }
+
+    /// Unwrap the Parser from the StreamDeserializer.
+    pub fn unwrap(self) -> Parser<Iter> {
+        self.parser
+    }
+}
+
+impl<
+    T: de::Deserialize<Parser<Iter>, Error>,
+    Iter: Iterator<Item=u8>
+> Iterator for StreamDeserializer<T, Iter> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        if self.failed {
+            return None;
+        }
+
+        self.parser.parse_whitespace();
+
+        if self.parser.eof() {
+            return None;
+        }
+
+        // `pos` counts the current character as already read.
+        self.offset = self.parser.pos - 1;
+        self.parser.state_stack.push(State::Value);
+
+        match de::Deserialize::deserialize(&mut self.parser) {
+            Ok(value) => Some(Ok(value)),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// An iterator over the elements of a json array, created by
+/// `Parser::array_iter`.
+pub struct ArrayIter<'a, T, Iter: 'a> {
+    parser: &'a mut Parser<Iter>,
+    started: bool,
+    done: bool,
+}
+
+impl<
+    'a,
+    T: de::Deserialize<Parser<Iter>, Error>,
+    Iter: Iterator<Item=u8>
+> Iterator for ArrayIter<'a, T, Iter> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        if self.done {
+            return None;
+        }
+
+        if !self.started {
+            self.started = true;
+
+            let result = match self.parser.expect_token() {
+                Ok(token) => self.parser.expect_seq_start(token),
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(_) => { }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        match self.parser.expect_seq_elt_or_end() {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
 }
 
 /// Decodes a json value from an `Iterator<u8>`.
@@ -642,21 +1095,17 @@ pub fn from_iter<
 >(iter: Iter) -> Result<T, Error> {
     let mut parser = Parser::new(iter);
     let value = try!(de::Deserialize::deserialize(&mut parser));
-
-    // Make sure the whole stream has been consumed.
-    match parser.next() {
-        Some(Ok(_token)) => Err(parser.error(ErrorCode::TrailingCharacters)),
-        Some(Err(err)) => Err(err),
-        None => Ok(value),
-    }
+    try!(parser.end());
+    Ok(value)
 }
 
-/// Decodes a json value from a string
+/// Decodes a json value from a string. Any `&'a str` in the value borrows
+/// straight from `s`.
 pub fn from_str<
     'a,
-    T: de::Deserialize<Parser<str::Bytes<'a>>, Error>
+    T: de::Deserialize<Parser<StrBytes<'a>>, Error>
 >(s: &'a str) -> Result<T, Error> {
-    from_iter(s.bytes())
+    from_iter(StrBytes::new(s))
 }
 
 #[cfg(test)]
@@ -1,6 +1,7 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::str;
 
 use de::{Token, TokenKind};
 
@@ -25,18 +26,21 @@ pub enum ErrorCode {
     ExpectedSomeIdent,
     ExpectedSomeValue,
     ExpectedTokens(Token, &'static [TokenKind]),
+    IntegerOverflow,
     InvalidEscape,
     InvalidNumber,
     InvalidUnicodeCodePoint,
     KeyMustBeAString,
     LoneLeadingSurrogateInHexEscape,
-    MissingField(&'static str),
+    /// field, struct
+    MissingField(&'static str, &'static str),
     NotFourDigit,
     NotUtf8,
     TrailingCharacters,
     UnexpectedEndOfHexEscape,
     UnexpectedName(Token),
-    UnknownVariant,
+    UnknownField(String),
+    UnknownVariant(String),
     UnrecognizedHex,
 }
 
@@ -61,31 +65,93 @@ impl fmt::Show for ErrorCode {
             ErrorCode::ExpectedSomeIdent => "expected ident".fmt(f),
             ErrorCode::ExpectedSomeValue => "expected value".fmt(f),
             ErrorCode::ExpectedTokens(ref token, tokens) => write!(f, "expected {:?}, found {:?}", tokens, token),
+            ErrorCode::IntegerOverflow => "integer overflow".fmt(f),
             ErrorCode::InvalidEscape => "invalid escape".fmt(f),
             ErrorCode::InvalidNumber => "invalid number".fmt(f),
             ErrorCode::InvalidUnicodeCodePoint => "invalid unicode code point".fmt(f),
             ErrorCode::KeyMustBeAString => "key must be a string".fmt(f),
             ErrorCode::LoneLeadingSurrogateInHexEscape => "lone leading surrogate in hex escape".fmt(f),
-            ErrorCode::MissingField(ref field) => write!(f, "missing field \"{}\"", field),
+            ErrorCode::MissingField(field, name) => {
+                write!(f, "missing field `{}` in struct `{}`", field, name)
+            }
             ErrorCode::NotFourDigit => "invalid \\u escape (not four digits)".fmt(f),
             ErrorCode::NotUtf8 => "contents not utf-8".fmt(f),
             ErrorCode::TrailingCharacters => "trailing characters".fmt(f),
             ErrorCode::UnexpectedEndOfHexEscape => "unexpected end of hex escape".fmt(f),
             ErrorCode::UnexpectedName(ref name) => write!(f, "unexpected name {:?}", name),
-            ErrorCode::UnknownVariant => "unknown variant".fmt(f),
+            ErrorCode::UnknownField(ref field) => write!(f, "unknown field \"{}\"", field),
+            ErrorCode::UnknownVariant(ref variant) => write!(f, "unknown variant \"{}\"", variant),
             ErrorCode::UnrecognizedHex => "invalid \\u escape (unrecognized hex)".fmt(f),
         }
     }
 }
 
+/// Where in a JSON document an error happened.
+#[derive(Clone, PartialEq, Show)]
+pub struct Location {
+    /// The byte offset into the input. This is always 0 when deserializing
+    /// from a `Value`.
+    pub offset: uint,
+    /// The path to the value being parsed, like `.servers[3].port`.
+    pub path: String,
+}
+
+impl Location {
+    /// Creates a location at the root of the document.
+    pub fn new(offset: uint) -> Location {
+        Location {
+            offset: offset,
+            path: String::new(),
+        }
+    }
+
+    /// Steps into an array element.
+    pub fn push_index(&mut self, index: uint) {
+        if self.path.is_empty() {
+            self.path.push('.');
+        }
+        self.path.push_str(format!("[{}]", index).as_slice());
+    }
+
+    /// Steps into an object member. Keys that aren't plain identifiers are
+    /// written as quoted strings.
+    pub fn push_key(&mut self, key: &str) {
+        let ident = match key.chars().next() {
+            Some(c) => {
+                (c.is_alphabetic() || c == '_') &&
+                    key.chars().all(|c| c.is_alphanumeric() || c == '_')
+            }
+            None => false,
+        };
+
+        if ident {
+            self.path.push('.');
+            self.path.push_str(key);
+        } else {
+            if self.path.is_empty() {
+                self.path.push('.');
+            }
+            self.path.push('[');
+            let mut buf = Vec::new();
+            super::ser::escape_str(&mut buf, key).unwrap();
+            self.path.push_str(str::from_utf8(buf.as_slice()).unwrap());
+            self.path.push(']');
+        }
+    }
+}
+
+impl fmt::String for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "." } else { self.path.as_slice() };
+        write!(f, "{} (byte {})", path, self.offset)
+    }
+}
+
 #[derive(Clone, PartialEq, Show)]
 pub enum Error {
-    /// msg, line, col
-    SyntaxError(ErrorCode, uint, uint),
+    /// msg, line, col, and the path and byte offset of the failing value
+    SyntaxError(ErrorCode, uint, uint, Location),
     IoError(io::IoError),
-    ExpectedError(String, String),
-    MissingFieldError(String),
-    UnknownVariantError(String),
 }
 
 impl error::Error for Error {
@@ -93,27 +159,15 @@ impl error::Error for Error {
         match *self {
             Error::SyntaxError(..) => "syntax error",
             Error::IoError(ref error) => error.description(),
-            Error::ExpectedError(ref expected, _) => expected.as_slice(),
-            Error::MissingFieldError(_) => "missing field",
-            Error::UnknownVariantError(_) => "unknown variant",
         }
     }
 
     fn detail(&self) -> Option<String> {
         match *self {
-            Error::SyntaxError(ref code, line, col) => {
-                Some(format!("{:?} at line {:?} column {:?}", code, line, col))
+            Error::SyntaxError(ref code, line, col, ref location) => {
+                Some(format!("{:?} at line {:?} column {:?}, {}", code, line, col, location))
             }
             Error::IoError(ref error) => error.detail(),
-            Error::ExpectedError(ref expected, ref found) => {
-                Some(format!("expected {:?}, found {:?}", expected, found))
-            }
-            Error::MissingFieldError(ref field) => {
-                Some(format!("missing field {:?}", field))
-            }
-            Error::UnknownVariantError(ref variant) => {
-                Some(format!("unknown variant {:?}", variant))
-            }
         }
     }
 }
@@ -0,0 +1,636 @@
+//! Exact conversions between decimal numbers and floats.
+//!
+//! Parsing scales the decimal digits with big integers, so every literal is
+//! rounded to the closest float. Printing uses the free-format algorithm from
+//! Burger and Dybvig's "Printing Floating-Point Numbers Quickly and
+//! Accurately" to find the shortest digits that read back as the same float.
+
+use std::cmp::{self, Ordering};
+use std::iter::repeat;
+use std::mem;
+use std::num::{Float, Int};
+
+/// An arbitrary size unsigned integer, stored as little endian 32 bit limbs.
+#[derive(Clone)]
+struct Big {
+    limbs: Vec<u32>,
+}
+
+impl Big {
+    fn from_u64(v: u64) -> Big {
+        let mut big = Big { limbs: vec![v as u32, (v >> 32) as u32] };
+        big.trim();
+        big
+    }
+
+    fn trim(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn bit_len(&self) -> uint {
+        match self.limbs.last() {
+            Some(&top) => self.limbs.len() * 32 - top.leading_zeros(),
+            None => 0,
+        }
+    }
+
+    fn compare(&self, other: &Big) -> Ordering {
+        if self.limbs.len() != other.limbs.len() {
+            return self.limbs.len().cmp(&other.limbs.len());
+        }
+
+        for (a, b) in self.limbs.iter().rev().zip(other.limbs.iter().rev()) {
+            if a != b {
+                return a.cmp(b);
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    fn add(&mut self, other: &Big) {
+        let mut carry = 0u64;
+
+        for i in range(0, cmp::max(self.limbs.len(), other.limbs.len())) {
+            if i == self.limbs.len() {
+                self.limbs.push(0);
+            }
+
+            let b = if i < other.limbs.len() { other.limbs[i] as u64 } else { 0 };
+            let v = self.limbs[i] as u64 + b + carry;
+            self.limbs[i] = v as u32;
+            carry = v >> 32;
+        }
+
+        if carry > 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    /// Subtracts `other`, which must not be larger than `self`.
+    fn sub(&mut self, other: &Big) {
+        let mut borrow = 0i64;
+
+        for i in range(0, self.limbs.len()) {
+            let b = if i < other.limbs.len() { other.limbs[i] as i64 } else { 0 };
+            let mut v = self.limbs[i] as i64 - b - borrow;
+
+            if v < 0 {
+                v += 1 << 32;
+                borrow = 1;
+            } else {
+                borrow = 0;
+            }
+
+            self.limbs[i] = v as u32;
+        }
+
+        self.trim();
+    }
+
+    fn mul_small(&mut self, m: u32) {
+        let mut carry = 0u64;
+
+        for limb in self.limbs.iter_mut() {
+            let v = (*limb as u64) * (m as u64) + carry;
+            *limb = v as u32;
+            carry = v >> 32;
+        }
+
+        if carry > 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    fn add_small(&mut self, a: u32) {
+        let mut carry = a as u64;
+
+        for limb in self.limbs.iter_mut() {
+            if carry == 0 {
+                return;
+            }
+
+            let v = *limb as u64 + carry;
+            *limb = v as u32;
+            carry = v >> 32;
+        }
+
+        if carry > 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    fn mul_pow10(&mut self, mut n: uint) {
+        while n >= 9 {
+            self.mul_small(1_000_000_000);
+            n -= 9;
+        }
+
+        if n > 0 {
+            self.mul_small(10u32.pow(n));
+        }
+    }
+
+    fn mul_pow2(&mut self, n: uint) {
+        if self.is_zero() {
+            return;
+        }
+
+        let bits = n % 32;
+        if bits > 0 {
+            let mut carry = 0u32;
+
+            for limb in self.limbs.iter_mut() {
+                let v = *limb;
+                *limb = (v << bits) | carry;
+                carry = v >> (32 - bits);
+            }
+
+            if carry > 0 {
+                self.limbs.push(carry);
+            }
+        }
+
+        let words = n / 32;
+        if words > 0 {
+            let mut limbs: Vec<u32> = repeat(0).take(words).collect();
+            limbs.push_all(self.limbs.as_slice());
+            self.limbs = limbs;
+        }
+    }
+}
+
+/// The layout of a binary float type.
+struct Format {
+    // Bits in the significand, including the hidden bit.
+    mantissa_bits: uint,
+    // The exponent of the smallest subnormal, as in `q * 2^exp`.
+    min_exp: i64,
+    // The exponent of the largest finite values, as in `q * 2^exp`.
+    max_exp: i64,
+    infinity_bits: u64,
+    // Decimals below `10^min_10` round to zero, and those from `10^max_10`
+    // round to infinity.
+    min_10: i64,
+    max_10: i64,
+}
+
+static F64: Format = Format {
+    mantissa_bits: 53,
+    min_exp: -1074,
+    max_exp: 971,
+    infinity_bits: 0x7ff0_0000_0000_0000,
+    min_10: -324,
+    max_10: 309,
+};
+
+static F32: Format = Format {
+    mantissa_bits: 24,
+    min_exp: -149,
+    max_exp: 104,
+    infinity_bits: 0x7f80_0000,
+    min_10: -46,
+    max_10: 39,
+};
+
+/// Splits a JSON number into its sign, its significant digits, and the power
+/// of ten they are multiplied by.
+fn decompose(s: &[u8]) -> (bool, Vec<u8>, i64) {
+    let mut i = 0;
+    let neg = s.len() > 0 && s[0] == b'-';
+    if neg {
+        i += 1;
+    }
+
+    let mut digits = Vec::new();
+    let mut exp = 0i64;
+
+    while i < s.len() {
+        match s[i] {
+            b'0' if digits.is_empty() => { }
+            c @ b'0' ... b'9' => { digits.push(c - b'0'); }
+            _ => break,
+        }
+        i += 1;
+    }
+
+    if i < s.len() && s[i] == b'.' {
+        i += 1;
+
+        while i < s.len() {
+            match s[i] {
+                b'0' if digits.is_empty() => { }
+                c @ b'0' ... b'9' => { digits.push(c - b'0'); }
+                _ => break,
+            }
+            exp -= 1;
+            i += 1;
+        }
+    }
+
+    if i < s.len() && (s[i] == b'e' || s[i] == b'E') {
+        i += 1;
+
+        let mut neg_exp = false;
+        if i < s.len() && (s[i] == b'+' || s[i] == b'-') {
+            neg_exp = s[i] == b'-';
+            i += 1;
+        }
+
+        // Anything this large is already zero or infinity, so saturate
+        // rather than overflow.
+        let mut e = 0i64;
+        while i < s.len() {
+            match s[i] {
+                c @ b'0' ... b'9' => {
+                    if e < 1_000_000_000 {
+                        e = e * 10 + (c - b'0') as i64;
+                    }
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+
+        exp += if neg_exp { -e } else { e };
+    }
+
+    while digits.last() == Some(&0) {
+        digits.pop();
+        exp += 1;
+    }
+
+    (neg, digits, exp)
+}
+
+/// Rounds `digits * 10^exp` to the closest float of the given format,
+/// returning its bits without the sign.
+fn decimal_to_bits(digits: &[u8], exp: i64, format: &Format) -> u64 {
+    if digits.is_empty() {
+        return 0;
+    }
+
+    // The value lies in `[10^(magnitude - 1), 10^magnitude)`.
+    let magnitude = digits.len() as i64 + exp;
+    if magnitude <= format.min_10 {
+        return 0;
+    } else if magnitude > format.max_10 {
+        return format.infinity_bits;
+    }
+
+    let mut n = Big::from_u64(0);
+    for &digit in digits.iter() {
+        n.mul_small(10);
+        n.add_small(digit as u32);
+    }
+
+    let mut d = Big::from_u64(1);
+    if exp >= 0 {
+        n.mul_pow10(exp as uint);
+    } else {
+        d.mul_pow10((-exp) as uint);
+    }
+
+    // Pick `k` so that `n / (d * 2^k)` has one or two more bits than the
+    // significand, unless that would be below the subnormals.
+    let mantissa_bits = format.mantissa_bits;
+    let mut k = n.bit_len() as i64 - d.bit_len() as i64 - mantissa_bits as i64;
+    if k < format.min_exp {
+        k = format.min_exp;
+    }
+
+    if k >= 0 {
+        d.mul_pow2(k as uint);
+    } else {
+        n.mul_pow2((-k) as uint);
+    }
+
+    // Long division, leaving the remainder in `n`.
+    let mut q = 0u64;
+    for i in range(0, mantissa_bits + 1).rev() {
+        let mut t = d.clone();
+        t.mul_pow2(i);
+
+        if n.compare(&t) != Ordering::Less {
+            n.sub(&t);
+            q |= 1 << i;
+        }
+    }
+
+    // Round half to even.
+    let round_up = if q >= 1 << mantissa_bits {
+        let half = q & 1 == 1;
+        q >>= 1;
+        k += 1;
+        half && (!n.is_zero() || q & 1 == 1)
+    } else {
+        n.mul_pow2(1);
+        match n.compare(&d) {
+            Ordering::Less => false,
+            Ordering::Greater => true,
+            Ordering::Equal => q & 1 == 1,
+        }
+    };
+
+    if round_up {
+        q += 1;
+        if q == 1 << mantissa_bits {
+            q >>= 1;
+            k += 1;
+        }
+    }
+
+    let hidden_bit = 1 << (mantissa_bits - 1);
+
+    if k > format.max_exp {
+        format.infinity_bits
+    } else if q < hidden_bit {
+        // A subnormal, so `k` is the minimum exponent.
+        q
+    } else {
+        ((k - format.min_exp + 1) as u64) << (mantissa_bits - 1) | (q - hidden_bit)
+    }
+}
+
+// Powers of ten that are exactly representable as an `f64`.
+static POW10: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11,
+    1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+
+/// Parses a well formed JSON number into the closest `f64`.
+pub fn parse_f64(s: &[u8]) -> f64 {
+    let (neg, digits, exp) = decompose(s);
+
+    // Both the digits and the power of ten are exact, so a single
+    // multiplication or division rounds correctly.
+    let value = if digits.len() <= 15 && -22 <= exp && exp <= 22 {
+        let mut n = 0u64;
+        for &digit in digits.iter() {
+            n = n * 10 + digit as u64;
+        }
+
+        if exp >= 0 {
+            n as f64 * POW10[exp as uint]
+        } else {
+            n as f64 / POW10[(-exp) as uint]
+        }
+    } else {
+        let bits = decimal_to_bits(digits.as_slice(), exp, &F64);
+        unsafe { mem::transmute::<u64, f64>(bits) }
+    };
+
+    if neg { -value } else { value }
+}
+
+/// Parses a well formed JSON number into the closest `f32`.
+pub fn parse_f32(s: &[u8]) -> f32 {
+    let (neg, digits, exp) = decompose(s);
+    let bits = decimal_to_bits(digits.as_slice(), exp, &F32);
+    let value = unsafe { mem::transmute::<u32, f32>(bits as u32) };
+
+    if neg { -value } else { value }
+}
+
+/// Finds the shortest digits of a positive finite float `f * 2^e`, and the
+/// power of ten `k` such that the value is `0.digits * 10^k`.
+fn shortest_digits(f: u64, e: i64, lower_closer: bool) -> (Vec<u8>, i64) {
+    // With an even significand the boundaries round back to it as well.
+    let even = f & 1 == 0;
+
+    // The value is `r / s`, and the gaps to the midpoints with its
+    // neighbours are `m_plus / s` and `m_minus / s`.
+    let mut r = Big::from_u64(f);
+    let mut s = Big::from_u64(1);
+    let mut m_plus = Big::from_u64(1);
+    let mut m_minus = Big::from_u64(1);
+
+    if e >= 0 {
+        r.mul_pow2(e as uint + 1);
+        s.mul_pow2(1);
+        m_plus.mul_pow2(e as uint);
+        m_minus.mul_pow2(e as uint);
+    } else {
+        r.mul_pow2(1);
+        s.mul_pow2((1 - e) as uint);
+    }
+
+    // The gap below a power of two is half the size.
+    if lower_closer {
+        r.mul_pow2(1);
+        s.mul_pow2(1);
+        m_plus.mul_pow2(1);
+    }
+
+    // This estimate of `ceil(log10(value))` may be one too small.
+    let bits = 64 - f.leading_zeros() as i64;
+    let mut k = ((e + bits - 1) as f64 * 0.30102999566398114 - 1e-10).ceil() as i64;
+
+    if k >= 0 {
+        s.mul_pow10(k as uint);
+    } else {
+        r.mul_pow10((-k) as uint);
+        m_plus.mul_pow10((-k) as uint);
+        m_minus.mul_pow10((-k) as uint);
+    }
+
+    let mut high = r.clone();
+    high.add(&m_plus);
+    match high.compare(&s) {
+        Ordering::Greater => { k += 1; }
+        Ordering::Equal if even => { k += 1; }
+        _ => {
+            r.mul_small(10);
+            m_plus.mul_small(10);
+            m_minus.mul_small(10);
+        }
+    }
+
+    let mut digits = Vec::new();
+
+    loop {
+        let mut digit = 0u8;
+        while r.compare(&s) != Ordering::Less {
+            r.sub(&s);
+            digit += 1;
+        }
+
+        let low = match r.compare(&m_minus) {
+            Ordering::Less => true,
+            Ordering::Equal => even,
+            Ordering::Greater => false,
+        };
+
+        let mut high = r.clone();
+        high.add(&m_plus);
+        let high = match high.compare(&s) {
+            Ordering::Greater => true,
+            Ordering::Equal => even,
+            Ordering::Less => false,
+        };
+
+        if !low && !high {
+            digits.push(digit);
+            r.mul_small(10);
+            m_plus.mul_small(10);
+            m_minus.mul_small(10);
+            continue;
+        }
+
+        let round_up = if low && high {
+            r.mul_pow2(1);
+            r.compare(&s) != Ordering::Less
+        } else {
+            high
+        };
+
+        digits.push(if round_up { digit + 1 } else { digit });
+        return (digits, k);
+    }
+}
+
+fn push_digits(out: &mut String, digits: &[u8]) {
+    for &digit in digits.iter() {
+        out.push((b'0' + digit) as char);
+    }
+}
+
+/// Lays out digits `0.digits * 10^k` as a JSON number. Integers are written
+/// without an exponent as long as they safely fit in an `i64`.
+fn format_digits(neg: bool, digits: &[u8], k: i64) -> String {
+    let mut out = String::new();
+    let n = digits.len() as i64;
+
+    if neg {
+        out.push('-');
+    }
+
+    if 0 < k && k <= 17 {
+        if n <= k {
+            push_digits(&mut out, digits);
+            for _ in range(n, k) {
+                out.push('0');
+            }
+        } else {
+            push_digits(&mut out, digits.slice_to(k as uint));
+            out.push('.');
+            push_digits(&mut out, digits.slice_from(k as uint));
+        }
+    } else if -6 < k && k <= 0 {
+        out.push_str("0.");
+        for _ in range(k, 0) {
+            out.push('0');
+        }
+        push_digits(&mut out, digits);
+    } else {
+        push_digits(&mut out, digits.slice_to(1));
+        if n > 1 {
+            out.push('.');
+            push_digits(&mut out, digits.slice_from(1));
+        }
+        out.push_str(format!("e{}", k - 1).as_slice());
+    }
+
+    out
+}
+
+/// Finds the shortest digits of a finite, non-zero `f64`, ignoring its sign.
+fn f64_digits(bits: u64) -> (Vec<u8>, i64) {
+    let fraction = bits & ((1 << 52) - 1);
+    let biased = ((bits >> 52) & 0x7ff) as i64;
+
+    if biased == 0 {
+        shortest_digits(fraction, F64.min_exp, false)
+    } else {
+        shortest_digits(fraction | 1 << 52,
+                        biased + F64.min_exp - 1,
+                        fraction == 0 && biased > 1)
+    }
+}
+
+/// Formats a finite `f64` with the fewest digits that parse back to it.
+pub fn fmt_f64(v: f64) -> String {
+    let bits: u64 = unsafe { mem::transmute(v) };
+    let neg = bits >> 63 == 1;
+
+    if v == 0.0 {
+        // Keep the sign of negative zero by writing it as a float.
+        return if neg { "-0.0".to_string() } else { "0".to_string() };
+    }
+
+    let (digits, k) = f64_digits(bits);
+    format_digits(neg, digits.as_slice(), k)
+}
+
+/// Formats a finite `f64` the way ECMAScript's `Number.prototype.toString`
+/// does, which is the number format RFC 8785 requires for canonical JSON.
+pub fn fmt_f64_canonical(v: f64) -> String {
+    if v == 0.0 {
+        return "0".to_string();
+    }
+
+    let bits: u64 = unsafe { mem::transmute(v) };
+    let (digits, k) = f64_digits(bits);
+    let n = digits.len() as i64;
+
+    let mut out = String::new();
+
+    if v < 0.0 {
+        out.push('-');
+    }
+
+    if n <= k && k <= 21 {
+        push_digits(&mut out, digits.as_slice());
+        for _ in range(n, k) {
+            out.push('0');
+        }
+    } else if 0 < k && k <= 21 {
+        push_digits(&mut out, digits.slice_to(k as uint));
+        out.push('.');
+        push_digits(&mut out, digits.slice_from(k as uint));
+    } else if -6 < k && k <= 0 {
+        out.push_str("0.");
+        for _ in range(k, 0) {
+            out.push('0');
+        }
+        push_digits(&mut out, digits.as_slice());
+    } else {
+        push_digits(&mut out, digits.slice_to(1));
+        if n > 1 {
+            out.push('.');
+            push_digits(&mut out, digits.slice_from(1));
+        }
+        let sign = if k > 0 { "+" } else { "-" };
+        out.push_str(format!("e{}{}", sign, (k - 1).abs()).as_slice());
+    }
+
+    out
+}
+
+/// Formats a finite `f32` with the fewest digits that parse back to it.
+pub fn fmt_f32(v: f32) -> String {
+    let bits: u32 = unsafe { mem::transmute(v) };
+    let neg = bits >> 31 == 1;
+    let fraction = (bits & ((1 << 23) - 1)) as u64;
+    let biased = ((bits >> 23) & 0xff) as i64;
+
+    if biased == 0 && fraction == 0 {
+        return if neg { "-0.0".to_string() } else { "0".to_string() };
+    }
+
+    let (digits, k) = if biased == 0 {
+        shortest_digits(fraction, F32.min_exp, false)
+    } else {
+        shortest_digits(fraction | 1 << 23,
+                        biased + F32.min_exp - 1,
+                        fraction == 0 && biased > 1)
+    };
+
+    format_digits(neg, digits.as_slice(), k)
+}
@@ -170,6 +170,36 @@ fn main() {
 }
 ```
 
+If the type already implements `Serialize`, `to_value` builds the `Value`
+directly:
+
+```rust
+#![feature(plugin)]
+#[plugin]
+extern crate serde_macros;
+extern crate serde;
+
+use serde::json::{self, ToJson, Value};
+
+#[derive_serialize]
+pub struct MyStruct  {
+    attr1: u8,
+    attr2: String,
+}
+
+impl ToJson for MyStruct {
+    fn to_json( &self ) -> Value {
+        json::to_value(self)
+    }
+}
+
+fn main() {
+    let test = MyStruct {attr1: 1, attr2:"test".to_string()};
+    let json: Value = test.to_json();
+    let json_str: String = json.to_string();
+}
+```
+
 To deserialize a JSON string using `Deserialize` trait:
 
 ```rust
@@ -293,48 +323,67 @@ fn main() {
 */
 
 pub use self::builder::{ArrayBuilder, ObjectBuilder};
+pub use self::canonical::{to_canonical_writer, to_canonical_vec, to_canonical_string};
 pub use self::de::{
+    ArrayIter,
     Parser,
+    StrBytes,
+    StreamDeserializer,
     from_str,
 };
-pub use self::error::{Error, ErrorCode};
+pub use self::error::{Error, ErrorCode, Location};
 pub use self::ser::{
     Serializer,
     PrettySerializer,
+    PrettyFormatter,
+    NonFiniteFloats,
     to_writer,
+    to_array_writer,
     to_vec,
     to_string,
     to_pretty_writer,
     to_pretty_vec,
     to_pretty_string,
 };
-pub use self::value::{Value, ToJson, from_json};
+pub use self::value::{PointerError, Value, ValueIndex, ToJson, escape_pointer_token, from_json};
+pub use self::value::to_value;
 
 pub mod builder;
+pub mod canonical;
 pub mod de;
 pub mod ser;
 pub mod value;
 pub mod error;
+pub mod patch;
+
+mod float;
 
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
     use std::fmt::Show;
     use std::io;
-    use std::str;
-    use std::string;
+    use std::mem;
+    use std::num::Float;
+    use std::string::{self, CowString};
+    use std::sync::atomic::{AtomicUint, Ordering, ATOMIC_UINT_INIT};
     use std::collections::BTreeMap;
 
-    use de;
+    use de::{self, Presence};
     use ser::{Serialize, Serializer};
     use ser;
 
-    use super::{Error, Parser, ToJson, Value, value, from_str, from_json};
+    use super::{Error, ErrorCode, Location, NonFiniteFloats, Parser, PointerError};
+    use super::{PrettyFormatter, StrBytes, StreamDeserializer, ToJson, Value, value};
+    use super::{escape_pointer_token, from_str, from_json};
+    use super::patch::{self, Operation, PatchError};
 
     use super::error::Error::{
         SyntaxError,
     };
 
     use super::error::ErrorCode::{
+        ConversionError,
         EOFWhileParsingList,
         EOFWhileParsingObject,
         EOFWhileParsingString,
@@ -344,9 +393,12 @@ mod tests {
         ExpectedObjectCommaOrEnd,
         ExpectedSomeIdent,
         ExpectedSomeValue,
+        IntegerOverflow,
         InvalidNumber,
         KeyMustBeAString,
+        MissingField,
         TrailingCharacters,
+        UnknownField,
     };
 
     macro_rules! treemap {
@@ -368,13 +420,7 @@ mod tests {
     impl ToJson for Animal {
         fn to_json(&self) -> Value {
             match *self {
-                Animal::Dog => {
-                    Value::Object(
-                        treemap!(
-                            "Dog".to_string() => Value::Array(vec!())
-                        )
-                    )
-                }
+                Animal::Dog => Value::String("Dog".to_string()),
                 Animal::Frog(ref x0, ref x1) => {
                     Value::Object(
                         treemap!(
@@ -425,7 +471,11 @@ mod tests {
     }
 
     fn test_encode_ok<
-        T: PartialEq + Show + ToJson + ser::Serialize<super::Serializer<Vec<u8>>, io::IoError>
+        T: PartialEq
+         + Show
+         + ToJson
+         + ser::Serialize<super::Serializer<Vec<u8>>, io::IoError>
+         + ser::Serialize<value::Serializer, ()>
     >(errors: &[(T, &str)]) {
         for &(ref value, out) in errors.iter() {
             let out = out.to_string();
@@ -435,6 +485,9 @@ mod tests {
 
             let s = super::to_string(&value.to_json()).unwrap();
             assert_eq!(s, out);
+
+            let s = super::to_string(&super::to_value(value)).unwrap();
+            assert_eq!(s, out);
         }
     }
 
@@ -472,6 +525,38 @@ mod tests {
         test_pretty_encode_ok(tests);
     }
 
+    #[test]
+    fn test_non_finite_floats() {
+        let value = vec![1.5f64, Float::nan(), Float::infinity(), Float::neg_infinity()];
+        assert_eq!(super::to_string(&value).unwrap(), "[1.5,null,null,null]");
+
+        let mut serializer = super::Serializer::new(Vec::new())
+            .non_finite_floats(NonFiniteFloats::Error);
+        let err = value.serialize(&mut serializer).unwrap_err();
+        assert_eq!(err.kind, io::InvalidInput);
+
+        let mut serializer = super::Serializer::new(Vec::new())
+            .non_finite_floats(NonFiniteFloats::Extension);
+        value.serialize(&mut serializer).unwrap();
+        let s = string::String::from_utf8(serializer.unwrap()).unwrap();
+        assert_eq!(s, "[1.5,NaN,Infinity,-Infinity]");
+
+        let mut parser = Parser::new(s.as_slice().bytes()).allow_non_finite_floats(true);
+        let parsed: Vec<f64> = de::Deserialize::deserialize(&mut parser).unwrap();
+        parser.end().unwrap();
+        assert_eq!(parsed[0], 1.5);
+        assert!(parsed[1].is_nan());
+        assert_eq!(parsed[2], Float::infinity());
+        assert_eq!(parsed[3], Float::neg_infinity());
+
+        // The extensions are only accepted when asked for.
+        let result: Result<Vec<f64>, Error> = from_str(s.as_slice());
+        assert_eq!(
+            result.unwrap_err(),
+            SyntaxError(ExpectedSomeValue, 1, 6, Location { offset: 5, path: ".[1]".to_string() })
+        );
+    }
+
     #[test]
     fn test_write_f64() {
         let tests = &[
@@ -484,6 +569,25 @@ mod tests {
         test_pretty_encode_ok(tests);
     }
 
+    #[test]
+    fn test_to_value() {
+        assert_eq!(super::to_value(&Animal::Dog), Value::String("Dog".to_string()));
+        assert_eq!(super::to_value(&::std::u64::MAX), Value::Unsigned(::std::u64::MAX));
+        assert_eq!(super::to_value(&Some(5u)), Value::Integer(5));
+
+        let outer = Outer {
+            inner: vec![
+                Inner { a: (), b: 2, c: vec!["abc".to_string(), "xyz".to_string()] },
+            ],
+        };
+        assert_eq!(super::to_value(&outer), outer.to_json());
+
+        // Keys that are not strings are written out as their JSON text.
+        let map: BTreeMap<int, bool> = treemap!(1 => true, 2 => false);
+        assert_eq!(super::to_value(&map),
+                   from_str::<Value>(r#"{"1": true, "2": false}"#).unwrap());
+    }
+
     #[test]
     fn test_write_str() {
         let tests = &[
@@ -639,6 +743,102 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_pretty_formatter() {
+        fn to_string(value: &Value, formatter: PrettyFormatter) -> string::String {
+            let mut serializer = super::PrettySerializer::new(Vec::new()).formatter(formatter);
+            value.serialize(&mut serializer).unwrap();
+            string::String::from_utf8(serializer.unwrap()).unwrap()
+        }
+
+        let value = Value::Object(treemap!(
+            "a".to_string() => Value::Array(vec![Value::Integer(1), Value::Array(vec![])]),
+            "b".to_string() => Value::Object(treemap!())
+        ));
+
+        assert_eq!(to_string(&value, PrettyFormatter::new()), concat!(
+            "{\n",
+            "  \"a\": [\n",
+            "    1,\n",
+            "    []\n",
+            "  ],\n",
+            "  \"b\": {}\n",
+            "}"
+        ));
+
+        let formatter = PrettyFormatter {
+            indent: "\t".to_string(),
+            key_separator: " : ".to_string(),
+            newline: "\r\n".to_string(),
+            .. PrettyFormatter::new()
+        };
+        assert_eq!(to_string(&value, formatter), concat!(
+            "{\r\n",
+            "\t\"a\" : [\r\n",
+            "\t\t1,\r\n",
+            "\t\t[]\r\n",
+            "\t],\r\n",
+            "\t\"b\" : {}\r\n",
+            "}"
+        ));
+
+        let formatter = PrettyFormatter {
+            indent: "    ".to_string(),
+            compact_empty: false,
+            .. PrettyFormatter::new()
+        };
+        assert_eq!(to_string(&value, formatter), concat!(
+            "{\n",
+            "    \"a\": [\n",
+            "        1,\n",
+            "        [\n",
+            "        ]\n",
+            "    ],\n",
+            "    \"b\": {\n",
+            "    }\n",
+            "}"
+        ));
+    }
+
+    #[test]
+    fn test_write_canonical() {
+        use std::collections::HashMap;
+
+        let mut map = HashMap::new();
+        map.insert("b".to_string(), vec![1.0f64, 1e21, 1e-7, 0.000001, -0.0, 1.5e300]);
+        map.insert("a".to_string(), vec![]);
+        map.insert("\u{fb01}".to_string(), vec![]);
+        map.insert("\u{1f600}".to_string(), vec![]);
+        map.insert("\r\u{1f}".to_string(), vec![]);
+        assert_eq!(
+            super::to_canonical_string(&map).unwrap(),
+            concat!(
+                "{\"\\r\\u001f\":[],\"a\":[],",
+                "\"b\":[1,1e+21,1e-7,0.000001,0,1.5e+300],",
+                "\"\u{1f600}\":[],\"\u{fb01}\":[]}"
+            )
+        );
+
+        // Every number is written as the `f64` closest to it, even integers.
+        let value: Value = from_str(
+            "{\"z\": 10, \"y\": [1E2, -1.50, 18446744073709551615, 9007199254740993]}").unwrap();
+        assert_eq!(
+            super::to_canonical_string(&value).unwrap(),
+            "{\"y\":[100,-1.5,18446744073709552000,9007199254740992],\"z\":10}"
+        );
+
+        let mut parser = Parser::new("[1.0e0, 12345678901234567890123, 18446744073709551615]".bytes())
+            .arbitrary_precision(true);
+        let value: Value = de::Deserialize::deserialize(&mut parser).unwrap();
+        assert_eq!(
+            super::to_canonical_string(&value).unwrap(),
+            "[1,1.2345678901234568e+22,18446744073709552000]"
+        );
+
+        let err = super::to_canonical_string(&vec![Float::nan()]).unwrap_err();
+        assert_eq!(err.kind, io::InvalidInput);
+    }
+
     #[test]
     fn test_write_tuple() {
         test_encode_ok(&[
@@ -685,7 +885,7 @@ mod tests {
     #[test]
     fn test_write_enum() {
         test_encode_ok(&[
-            (Animal::Dog, "{\"Dog\":[]}"),
+            (Animal::Dog, "\"Dog\""),
             (Animal::Frog("Henry".to_string(), vec!()), "{\"Frog\":[\"Henry\",[]]}"),
             (Animal::Frog("Henry".to_string(), vec!(349)), "{\"Frog\":[\"Henry\",[349]]}"),
             (Animal::Frog("Henry".to_string(), vec!(349, 102)), "{\"Frog\":[\"Henry\",[349,102]]}"),
@@ -694,11 +894,7 @@ mod tests {
         test_pretty_encode_ok(&[
             (
                 Animal::Dog,
-                concat!(
-                    "{\n",
-                    "  \"Dog\": []\n",
-                    "}"
-                ),
+                "\"Dog\"",
             ),
             (
                 Animal::Frog("Henry".to_string(), vec!()),
@@ -776,17 +972,20 @@ mod tests {
     // FIXME (#5527): these could be merged once UFCS is finished.
     fn test_parse_err<
         'a,
-        T: Show + de::Deserialize<Parser<str::Bytes<'a>>, Error>
-    >(errors: &[(&'a str, Error)]) {
-        for &(s, ref err) in errors.iter() {
+        T: Show + de::Deserialize<Parser<StrBytes<'a>>, Error>
+    >(errors: &[(&'a str, ErrorCode, uint, uint)]) {
+        for &(s, ref code, line, col) in errors.iter() {
             let v: Result<T, Error> = from_str(s);
-            assert_eq!(v.unwrap_err(), *err);
+            match v.unwrap_err() {
+                SyntaxError(ref c, l, co, _) => assert_eq!((c, l, co), (code, line, col)),
+                err => panic!("unexpected error: {:?}", err),
+            }
         }
     }
 
     fn test_parse_ok<
         'a,
-        T: PartialEq + Show + ToJson + de::Deserialize<Parser<str::Bytes<'a>>, Error>
+        T: PartialEq + Show + ToJson + de::Deserialize<Parser<StrBytes<'a>>, Error>
     >(errors: &[(&'a str, T)]) {
         for &(s, ref value) in errors.iter() {
             let v: T = from_str(s).unwrap();
@@ -813,9 +1012,9 @@ mod tests {
     #[test]
     fn test_parse_null() {
         test_parse_err::<()>(&[
-            ("n", SyntaxError(ExpectedSomeIdent, 1, 2)),
-            ("nul", SyntaxError(ExpectedSomeIdent, 1, 4)),
-            ("nulla", SyntaxError(TrailingCharacters, 1, 5)),
+            ("n", ExpectedSomeIdent, 1, 2),
+            ("nul", ExpectedSomeIdent, 1, 4),
+            ("nulla", TrailingCharacters, 1, 5),
         ]);
 
         test_parse_ok(&[
@@ -833,12 +1032,12 @@ mod tests {
     #[test]
     fn test_parse_bool() {
         test_parse_err::<bool>(&[
-            ("t", SyntaxError(ExpectedSomeIdent, 1, 2)),
-            ("truz", SyntaxError(ExpectedSomeIdent, 1, 4)),
-            ("f", SyntaxError(ExpectedSomeIdent, 1, 2)),
-            ("faz", SyntaxError(ExpectedSomeIdent, 1, 3)),
-            ("truea", SyntaxError(TrailingCharacters, 1, 5)),
-            ("falsea", SyntaxError(TrailingCharacters, 1, 6)),
+            ("t", ExpectedSomeIdent, 1, 2),
+            ("truz", ExpectedSomeIdent, 1, 4),
+            ("f", ExpectedSomeIdent, 1, 2),
+            ("faz", ExpectedSomeIdent, 1, 3),
+            ("truea", TrailingCharacters, 1, 5),
+            ("falsea", TrailingCharacters, 1, 6),
         ]);
 
         test_parse_ok(&[
@@ -858,14 +1057,14 @@ mod tests {
     #[test]
     fn test_parse_number_errors() {
         test_parse_err::<f64>(&[
-            ("+", SyntaxError(ExpectedSomeValue, 1, 1)),
-            (".", SyntaxError(ExpectedSomeValue, 1, 1)),
-            ("-", SyntaxError(InvalidNumber, 1, 2)),
-            ("00", SyntaxError(InvalidNumber, 1, 2)),
-            ("1.", SyntaxError(InvalidNumber, 1, 3)),
-            ("1e", SyntaxError(InvalidNumber, 1, 3)),
-            ("1e+", SyntaxError(InvalidNumber, 1, 4)),
-            ("1a", SyntaxError(TrailingCharacters, 1, 2)),
+            ("+", ExpectedSomeValue, 1, 1),
+            (".", ExpectedSomeValue, 1, 1),
+            ("-", InvalidNumber, 1, 2),
+            ("00", InvalidNumber, 1, 2),
+            ("1.", InvalidNumber, 1, 3),
+            ("1e", InvalidNumber, 1, 3),
+            ("1e+", InvalidNumber, 1, 4),
+            ("1a", TrailingCharacters, 1, 2),
         ]);
     }
 
@@ -875,9 +1074,57 @@ mod tests {
             ("3", 3i64),
             ("-2", -2),
             ("-1234", -1234),
+            ("9223372036854775807", ::std::i64::MAX),
+            ("-9223372036854775808", ::std::i64::MIN),
+        ]);
+
+        test_parse_err::<i64>(&[
+            ("-9223372036854775809", IntegerOverflow, 1, 21),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_u64() {
+        test_parse_ok(&[
+            ("0", 0u64),
+            ("9223372036854775808", 9223372036854775808),
+            ("18446744073709551615", ::std::u64::MAX),
+        ]);
+
+        test_parse_err::<u64>(&[
+            ("18446744073709551616", IntegerOverflow, 1, 21),
         ]);
     }
 
+    #[test]
+    fn test_parse_arbitrary_precision() {
+        let s = "[12345678901234567890123, 0.10000000000000000000001, -1E400]";
+        let mut parser = Parser::new(s.bytes()).arbitrary_precision(true);
+        let value: Value = de::Deserialize::deserialize(&mut parser).unwrap();
+        parser.end().unwrap();
+
+        assert_eq!(value, Value::Array(vec![
+            Value::RawNumber("12345678901234567890123".to_string()),
+            Value::RawNumber("0.10000000000000000000001".to_string()),
+            Value::RawNumber("-1E400".to_string()),
+        ]));
+        assert_eq!(
+            value.to_string(),
+            "[12345678901234567890123,0.10000000000000000000001,-1E400]");
+
+        // Typed values still convert the digits.
+        let mut parser = Parser::new("[1, 18446744073709551615]".bytes())
+            .arbitrary_precision(true);
+        let value: Vec<u64> = de::Deserialize::deserialize(&mut parser).unwrap();
+        parser.end().unwrap();
+        assert_eq!(value, vec![1, ::std::u64::MAX]);
+
+        let mut parser = Parser::new("[2.5, -3]".bytes()).arbitrary_precision(true);
+        let value: Vec<f64> = de::Deserialize::deserialize(&mut parser).unwrap();
+        parser.end().unwrap();
+        assert_eq!(value, vec![2.5, -3.0]);
+    }
+
     #[test]
     fn test_parse_f64() {
         test_parse_ok(&[
@@ -891,6 +1138,92 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_parse_f64_correctly_rounded() {
+        test_parse_ok(&[
+            ("0.1", 0.1f64),
+            ("0.30000000000000004", 0.30000000000000004),
+            ("1e23", 1e23),
+            ("9007199254740993.0", 9007199254740992.0),
+            ("123456789012345678901234567890e-10", 12345678901234567890.123456789),
+            ("2.2250738585072011e-308", 2.2250738585072011e-308),
+            ("2.2250738585072014e-308", 2.2250738585072014e-308),
+            ("1.7976931348623157e308", ::std::f64::MAX),
+            ("4.9e-324", 4.9e-324),
+            ("2.4703282292062328e-324", 5e-324),
+            ("2.4703282292062327e-324", 0.0),
+            ("1e400", ::std::f64::INFINITY),
+        ]);
+
+        // Rounding to an `f64` first would land exactly halfway between two
+        // `f32`s, and then round the wrong way.
+        let value: f32 = from_str("1.00000017881393432617187499").unwrap();
+        assert_eq!(value, 1.0000001);
+
+        // Strings parsed after the float don't get in the way.
+        let value: (f32, String, f32) = from_str(
+            r#"[1.00000017881393432617187499, "1e1", -0.0]"#).unwrap();
+        assert_eq!(value.0, 1.0000001);
+        assert_eq!(value.1, "1e1".to_string());
+        assert!(value.2 == 0.0 && value.2.is_negative());
+    }
+
+    #[test]
+    fn test_write_shortest_float() {
+        test_encode_ok(&[
+            (0.1f64, "0.1"),
+            (0.3, "0.3"),
+            (100.0, "100"),
+            (123456.789, "123456.789"),
+            (1e-7, "1e-7"),
+            (1e23, "1e23"),
+            (5e-324, "5e-324"),
+            (::std::f64::MAX, "1.7976931348623157e308"),
+        ]);
+
+        assert_eq!(super::to_string(&0.1f32).unwrap(), "0.1");
+        assert_eq!(super::to_string(&16777216.0f32).unwrap(), "16777216");
+    }
+
+    // A xorshift generator, so the randomized tests are reproducible.
+    struct XorShift {
+        state: u64,
+    }
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            self.state
+        }
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        let mut rng = XorShift { state: 0x2545f4914f6cdd1d };
+
+        for _ in range(0u, 10000) {
+            let bits = rng.next();
+
+            let v: f64 = unsafe { mem::transmute(bits) };
+            if v.is_finite() {
+                let s = super::to_string(&v).unwrap();
+                let parsed: f64 = from_str(s.as_slice()).unwrap();
+                let parsed_bits: u64 = unsafe { mem::transmute(parsed) };
+                assert!(parsed_bits == bits, "{} did not round trip: {}", v, s);
+            }
+
+            let v: f32 = unsafe { mem::transmute((bits >> 32) as u32) };
+            if v.is_finite() {
+                let s = super::to_string(&v).unwrap();
+                let parsed: f32 = from_str(s.as_slice()).unwrap();
+                let parsed_bits: u32 = unsafe { mem::transmute(parsed) };
+                assert!(parsed_bits == (bits >> 32) as u32, "{} did not round trip: {}", v, s);
+            }
+        }
+    }
+
     #[test]
     fn test_json_deserialize_numbers() {
         test_json_deserialize_ok(&[
@@ -907,9 +1240,9 @@ mod tests {
     #[test]
     fn test_parse_string() {
         test_parse_err::<string::String>(&[
-            ("\"", SyntaxError(EOFWhileParsingString, 1, 2)),
-            ("\"lol", SyntaxError(EOFWhileParsingString, 1, 5)),
-            ("\"lol\"a", SyntaxError(TrailingCharacters, 1, 6)),
+            ("\"", EOFWhileParsingString, 1, 2),
+            ("\"lol", EOFWhileParsingString, 1, 5),
+            ("\"lol\"a", TrailingCharacters, 1, 6),
         ]);
 
         test_parse_ok(&[
@@ -943,13 +1276,13 @@ mod tests {
     #[test]
     fn test_parse_list() {
         test_parse_err::<Vec<f64>>(&[
-            ("[", SyntaxError(EOFWhileParsingValue, 1, 2)),
-            ("[ ", SyntaxError(EOFWhileParsingValue, 1, 3)),
-            ("[1", SyntaxError(EOFWhileParsingList,  1, 3)),
-            ("[1,", SyntaxError(EOFWhileParsingValue, 1, 4)),
-            ("[1,]", SyntaxError(ExpectedSomeValue, 1, 4)),
-            ("[1 2]", SyntaxError(ExpectedListCommaOrEnd, 1, 4)),
-            ("[]a", SyntaxError(TrailingCharacters, 1, 3)),
+            ("[", EOFWhileParsingValue, 1, 2),
+            ("[ ", EOFWhileParsingValue, 1, 3),
+            ("[1", EOFWhileParsingList,  1, 3),
+            ("[1,", EOFWhileParsingValue, 1, 4),
+            ("[1,]", ExpectedSomeValue, 1, 4),
+            ("[1 2]", ExpectedListCommaOrEnd, 1, 4),
+            ("[]a", TrailingCharacters, 1, 3),
         ]);
 
         test_parse_ok(&[
@@ -1003,18 +1336,18 @@ mod tests {
     #[test]
     fn test_parse_object() {
         test_parse_err::<BTreeMap<string::String, int>>(&[
-            ("{", SyntaxError(EOFWhileParsingString, 1, 2)),
-            ("{ ", SyntaxError(EOFWhileParsingString, 1, 3)),
-            ("{1", SyntaxError(KeyMustBeAString, 1, 2)),
-            ("{ \"a\"", SyntaxError(EOFWhileParsingObject, 1, 6)),
-            ("{\"a\"", SyntaxError(EOFWhileParsingObject, 1, 5)),
-            ("{\"a\" ", SyntaxError(EOFWhileParsingObject, 1, 6)),
-            ("{\"a\" 1", SyntaxError(ExpectedColon, 1, 6)),
-            ("{\"a\":", SyntaxError(EOFWhileParsingValue, 1, 6)),
-            ("{\"a\":1", SyntaxError(EOFWhileParsingObject, 1, 7)),
-            ("{\"a\":1 1", SyntaxError(ExpectedObjectCommaOrEnd, 1, 8)),
-            ("{\"a\":1,", SyntaxError(EOFWhileParsingString, 1, 8)),
-            ("{}a", SyntaxError(TrailingCharacters, 1, 3)),
+            ("{", EOFWhileParsingString, 1, 2),
+            ("{ ", EOFWhileParsingString, 1, 3),
+            ("{1", KeyMustBeAString, 1, 2),
+            ("{ \"a\"", EOFWhileParsingObject, 1, 6),
+            ("{\"a\"", EOFWhileParsingObject, 1, 5),
+            ("{\"a\" ", EOFWhileParsingObject, 1, 6),
+            ("{\"a\" 1", ExpectedColon, 1, 6),
+            ("{\"a\":", EOFWhileParsingValue, 1, 6),
+            ("{\"a\":1", EOFWhileParsingObject, 1, 7),
+            ("{\"a\":1 1", ExpectedObjectCommaOrEnd, 1, 8),
+            ("{\"a\":1,", EOFWhileParsingString, 1, 8),
+            ("{}a", TrailingCharacters, 1, 3),
         ]);
 
         test_parse_ok(&[
@@ -1117,6 +1450,218 @@ mod tests {
         assert_eq!(value, Foo { x: Some(5) });
     }
 
+    #[test]
+    fn test_parse_missing_field() {
+        #[derive(PartialEq, Show)]
+        #[derive_deserialize]
+        struct Foo {
+            x: string::String,
+            y: Option<int>,
+        }
+
+        let result: Result<Foo, Error> = from_str("{\"y\": 1}");
+        match result {
+            Err(SyntaxError(ref code @ MissingField("x", "Foo"), 1, 9, _)) => {
+                assert_eq!(format!("{:?}", code), "missing field `x` in struct `Foo`");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let value: Value = from_str("{\"y\": 1}").unwrap();
+        let result: Result<Foo, Error> = from_json(value);
+        match result {
+            Err(SyntaxError(MissingField("x", "Foo"), _, _, _)) => { }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let value: Foo = from_str("{\"x\": \"a\"}").unwrap();
+        assert_eq!(value, Foo { x: "a".to_string(), y: None });
+    }
+
+    #[test]
+    fn test_parse_presence() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        struct Foo {
+            x: Presence<int>,
+        }
+
+        let tests = &[
+            ("{}", Foo { x: Presence::Absent }, "{}"),
+            ("{\"x\": null}", Foo { x: Presence::Null }, "{\"x\":null}"),
+            ("{\"x\": 5}", Foo { x: Presence::Value(5) }, "{\"x\":5}"),
+        ];
+
+        for &(s, ref expected, json) in tests.iter() {
+            let value: Foo = from_str(s).unwrap();
+            assert_eq!(value, *expected);
+            assert_eq!(super::to_string(&value).unwrap(), json);
+
+            let value: Foo = from_json(from_str::<Value>(s).unwrap()).unwrap();
+            assert_eq!(value, *expected);
+        }
+
+        assert_eq!(Presence::Value(5i).into_option(), Some(5));
+        let null: Presence<int> = Presence::Null;
+        assert_eq!(null.into_option(), None);
+    }
+
+    fn default_y() -> int { 10 }
+
+    static SKIP_CALLS: AtomicUint = ATOMIC_UINT_INIT;
+
+    fn count_skip_calls(_: &int) -> bool {
+        SKIP_CALLS.fetch_add(1, Ordering::SeqCst);
+        false
+    }
+
+    #[test]
+    fn test_parse_default_and_skip() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        struct Foo {
+            #[serde(default)]
+            x: Vec<int>,
+            #[serde(default = "default_y")]
+            y: int,
+            #[serde(skip)]
+            z: int,
+        }
+
+        let value: Foo = from_str("{}").unwrap();
+        assert_eq!(value, Foo { x: vec![], y: 10, z: 0 });
+
+        let value: Foo = from_str("{\"x\": [1], \"y\": 2, \"z\": 3}").unwrap();
+        assert_eq!(value, Foo { x: vec![1], y: 2, z: 0 });
+
+        let value = Foo { x: vec![1], y: 2, z: 3 };
+        assert_eq!(super::to_string(&value).unwrap(), "{\"x\":[1],\"y\":2}".to_string());
+    }
+
+    #[test]
+    fn test_write_skip_serializing_if() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(skip_serializing_none)]
+        struct Foo {
+            a: Option<int>,
+            #[serde(skip_serializing_if = "Vec::is_empty", default)]
+            b: Vec<int>,
+            c: int,
+        }
+
+        let value = Foo { a: None, b: vec![], c: 1 };
+        assert_eq!(super::to_string(&value).unwrap(), "{\"c\":1}".to_string());
+        let parsed: Foo = from_str("{\"c\":1}").unwrap();
+        assert_eq!(parsed, value);
+
+        let value = Foo { a: Some(2), b: vec![3], c: 1 };
+        assert_eq!(
+            super::to_string(&value).unwrap(),
+            "{\"a\":2,\"b\":[3],\"c\":1}".to_string()
+        );
+
+        #[derive_serialize]
+        struct Counted {
+            #[serde(skip_serializing_if = "count_skip_calls")]
+            a: int,
+        }
+
+        // The predicate decides both the length and whether the field is
+        // written, so it must only be called once.
+        super::to_string(&Counted { a: 1 }).unwrap();
+        assert_eq!(SKIP_CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_parse_deny_unknown_fields() {
+        #[derive(PartialEq, Show)]
+        #[derive_deserialize]
+        #[serde(deny_unknown_fields)]
+        struct Foo {
+            x: int,
+        }
+
+        let value: Foo = from_str("{\"x\": 1}").unwrap();
+        assert_eq!(value, Foo { x: 1 });
+
+        let result: Result<Foo, Error> = from_str("{\"x\": 1, \"z\": 2}");
+        match result {
+            Err(SyntaxError(UnknownField(ref field), _, _, _)) => {
+                assert_eq!(field.as_slice(), "z");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let value: Value = from_str("{\"x\": 1, \"z\": 2}").unwrap();
+        let result: Result<Foo, Error> = from_json(value);
+        match result {
+            Err(SyntaxError(UnknownField(ref field), _, _, _)) => {
+                assert_eq!(field.as_slice(), "z");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_borrowed_str() {
+        #[derive(PartialEq, Show)]
+        #[derive_deserialize]
+        struct Line<'a> {
+            level: &'a str,
+            host: Option<&'a str>,
+            message: CowString<'a>,
+        }
+
+        let s = "{\"level\": \"info\", \"host\": \"a\", \"message\": \"hi \\\"there\\\"\"}";
+        let line: Line = from_str(s).unwrap();
+        assert_eq!(line.level, "info");
+        assert_eq!(line.level.as_ptr(), s.slice_from(11).as_ptr());
+        assert_eq!(line.host, Some("a"));
+
+        // Escaped strings need to be unescaped into an owned string.
+        match line.message {
+            Cow::Owned(ref message) => assert_eq!(message.as_slice(), "hi \"there\""),
+            ref message => panic!("unexpected message: {:?}", message),
+        }
+
+        let s = "[\"abc\", \"a\\nb\"]";
+        let value: Vec<CowString> = from_str(s).unwrap();
+        match value[0] {
+            Cow::Borrowed(v) => assert_eq!(v.as_ptr(), s.slice_from(2).as_ptr()),
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+        match value[1] {
+            Cow::Owned(ref v) => assert_eq!(v.as_slice(), "a\nb"),
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+
+        let result: Result<&str, Error> = from_str("\"a\\nb\"");
+        match result {
+            Err(SyntaxError(ConversionError(_), _, _, _)) => { }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // A `Value` owns its strings, so they can only be copied out of it.
+        #[derive(PartialEq, Show)]
+        #[derive_deserialize]
+        struct Note<'a> {
+            text: CowString<'a>,
+            tags: Vec<string::String>,
+        }
+
+        let value: Value = from_str("{\"text\": \"hi\", \"tags\": [\"a\"]}").unwrap();
+        let note: Note = from_json(value).unwrap();
+        match note.text {
+            Cow::Owned(ref text) => assert_eq!(text.as_slice(), "hi"),
+            ref text => panic!("unexpected text: {:?}", text),
+        }
+        assert_eq!(note.tags, vec!["a".to_string()]);
+    }
+
     #[test]
     fn test_json_deserialize_option() {
         test_json_deserialize_ok(&[
@@ -1128,7 +1673,7 @@ mod tests {
     #[test]
     fn test_parse_enum() {
         test_parse_ok(&[
-            ("{\"Dog\": []}", Animal::Dog),
+            ("\"Dog\"", Animal::Dog),
             (
                 "{\"Frog\": [\"Henry\", []]}",
                 Animal::Frog("Henry".to_string(), vec!()),
@@ -1147,7 +1692,7 @@ mod tests {
             (
                 concat!(
                     "{",
-                    "  \"a\": {\"Dog\": []},",
+                    "  \"a\": \"Dog\",",
                     "  \"b\": {\"Frog\":[\"Henry\", []]}",
                     "}"
                 ),
@@ -1159,6 +1704,40 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_parse_unit_variant_as_object() {
+        // Unit variants used to be written as objects, keep accepting them.
+        let value: Animal = from_str("{\"Dog\": []}").unwrap();
+        assert_eq!(value, Animal::Dog);
+
+        let value: Value = from_str("{\"Dog\": []}").unwrap();
+        let value: Animal = from_json(value).unwrap();
+        assert_eq!(value, Animal::Dog);
+
+        let result: Result<Animal, Error> = from_str("\"Frog\"");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_discriminant_enum() {
+        #[derive(Copy, PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(discriminant)]
+        enum Method {
+            Get,
+            Post = 5,
+        }
+
+        test_round_trip_ok(&[
+            (Method::Get, "0"),
+            (Method::Post, "5"),
+        ]);
+
+        let result: Result<Method, Error> = from_str("1");
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_json_deserialize_enum() {
         test_json_deserialize_ok(&[
@@ -1169,13 +1748,435 @@ mod tests {
         ]);
     }
 
+    fn test_round_trip_ok<
+        'a,
+        T: PartialEq + Show
+            + ser::Serialize<super::Serializer<Vec<u8>>, io::IoError>
+            + de::Deserialize<Parser<StrBytes<'a>>, Error>
+    >(tests: &[(T, &'a str)]) {
+        for &(ref value, out) in tests.iter() {
+            let s = super::to_string(value).unwrap();
+            assert_eq!(s, out.to_string());
+
+            let v: T = from_str(out).unwrap();
+            assert_eq!(v, *value);
+        }
+    }
+
+    #[test]
+    fn test_tuple_structs() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        struct Unit;
+
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        struct Meters(f64);
+
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        struct Point(int, int);
+
+        test_round_trip_ok(&[(Unit, "null")]);
+        test_round_trip_ok(&[(Meters(1.5), "1.5")]);
+        test_round_trip_ok(&[(Point(1, 2), "[1,2]")]);
+
+        let result: Result<Point, Error> = from_str("[1]");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_struct_variants() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        enum Shape {
+            Point,
+            Circle(int),
+            Rect { width: int, height: int },
+        }
+
+        test_round_trip_ok(&[
+            (Shape::Point, "\"Point\""),
+            (Shape::Circle(1), "{\"Circle\":[1]}"),
+            (
+                Shape::Rect { width: 2, height: 3 },
+                "{\"Rect\":[{\"width\":2,\"height\":3}]}",
+            ),
+        ]);
+
+        let result: Result<Shape, Error> = from_str("\"Rect\"");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_internally_tagged_enum() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(tag = "type")]
+        enum Message {
+            Ping,
+            Data { id: int, body: string::String },
+        }
+
+        test_round_trip_ok(&[
+            (Message::Ping, "{\"type\":\"Ping\"}"),
+            (
+                Message::Data { id: 1, body: "hi".to_string() },
+                "{\"type\":\"Data\",\"id\":1,\"body\":\"hi\"}",
+            ),
+        ]);
+
+        // The tag doesn't need to come first.
+        let value: Message = from_str("{\"id\": 1, \"body\": \"hi\", \"type\": \"Data\"}").unwrap();
+        assert_eq!(value, Message::Data { id: 1, body: "hi".to_string() });
+
+        let value: Value = from_str("{\"type\": \"Ping\", \"extra\": [1, 2]}").unwrap();
+        let value: Message = from_json(value).unwrap();
+        assert_eq!(value, Message::Ping);
+
+        let result: Result<Message, Error> = from_str("{\"type\": \"Pong\"}");
+        assert!(result.is_err());
+
+        let result: Result<Message, Error> = from_str("{\"id\": 1, \"body\": \"hi\"}");
+        match result {
+            Err(SyntaxError(MissingField("type", "Message"), _, _, _)) => { }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_adjacently_tagged_enum() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(tag = "t", content = "c")]
+        enum Shape {
+            Empty,
+            Circle(f64),
+            Line(int, int),
+            Rect { w: int, h: int },
+            Label(Option<string::String>),
+        }
+
+        test_round_trip_ok(&[
+            (Shape::Empty, "{\"t\":\"Empty\"}"),
+            (Shape::Circle(1.5), "{\"t\":\"Circle\",\"c\":1.5}"),
+            (Shape::Line(1, 2), "{\"t\":\"Line\",\"c\":[1,2]}"),
+            (Shape::Rect { w: 3, h: 4 }, "{\"t\":\"Rect\",\"c\":{\"w\":3,\"h\":4}}"),
+        ]);
+
+        let value: Shape = from_str("{\"c\": [1, 2], \"t\": \"Line\"}").unwrap();
+        assert_eq!(value, Shape::Line(1, 2));
+
+        // Only a newtype variant of an `Option` can leave out its content.
+        let value: Shape = from_str("{\"t\": \"Label\"}").unwrap();
+        assert_eq!(value, Shape::Label(None));
+
+        let result: Result<Shape, Error> = from_str("{\"t\": \"Line\"}");
+        match result {
+            Err(SyntaxError(MissingField("c", "Shape"), _, _, _)) => { }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let result: Result<Shape, Error> = from_str("{\"t\": \"Circle\"}");
+        match result {
+            Err(SyntaxError(MissingField("c", "Shape"), _, _, _)) => { }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let result: Result<Shape, Error> = from_str("{\"c\": 1.5}");
+        match result {
+            Err(SyntaxError(MissingField("t", "Shape"), _, _, _)) => { }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_untagged_enum() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(untagged)]
+        enum Untagged {
+            Nothing,
+            Number(int),
+            Pair(int, string::String),
+            Named { name: string::String },
+        }
+
+        test_round_trip_ok(&[
+            (Untagged::Nothing, "null"),
+            (Untagged::Number(5), "5"),
+            (Untagged::Pair(1, "a".to_string()), "[1,\"a\"]"),
+            (Untagged::Named { name: "b".to_string() }, "{\"name\":\"b\"}"),
+        ]);
+
+        let result: Result<Untagged, Error> = from_str("true");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_struct_variant_field_attrs() {
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(deny_unknown_fields)]
+        enum External {
+            Item {
+                #[serial_name = "type"]
+                kind: string::String,
+                #[serde(skip)]
+                cache: int,
+                #[serde(skip_serializing_if = "Vec::is_empty", default)]
+                tags: Vec<int>,
+            },
+        }
+
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(tag = "t", deny_unknown_fields)]
+        enum Internal {
+            Item {
+                #[serial_name = "type"]
+                kind: string::String,
+                #[serde(skip)]
+                cache: int,
+                #[serde(skip_serializing_if = "Vec::is_empty", default)]
+                tags: Vec<int>,
+            },
+        }
+
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(tag = "t", content = "c", deny_unknown_fields)]
+        enum Adjacent {
+            Item {
+                #[serial_name = "type"]
+                kind: string::String,
+                #[serde(skip)]
+                cache: int,
+                #[serde(skip_serializing_if = "Vec::is_empty", default)]
+                tags: Vec<int>,
+            },
+        }
+
+        #[derive(PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(untagged, deny_unknown_fields)]
+        enum Untagged {
+            Item {
+                #[serial_name = "type"]
+                kind: string::String,
+                #[serde(skip)]
+                cache: int,
+                #[serde(skip_serializing_if = "Vec::is_empty", default)]
+                tags: Vec<int>,
+            },
+        }
+
+        test_round_trip_ok(&[
+            (
+                External::Item { kind: "a".to_string(), cache: 0, tags: vec![] },
+                "{\"Item\":[{\"type\":\"a\"}]}",
+            ),
+            (
+                External::Item { kind: "a".to_string(), cache: 0, tags: vec![1] },
+                "{\"Item\":[{\"type\":\"a\",\"tags\":[1]}]}",
+            ),
+        ]);
+
+        test_round_trip_ok(&[
+            (
+                Internal::Item { kind: "a".to_string(), cache: 0, tags: vec![] },
+                "{\"t\":\"Item\",\"type\":\"a\"}",
+            ),
+            (
+                Internal::Item { kind: "a".to_string(), cache: 0, tags: vec![1] },
+                "{\"t\":\"Item\",\"type\":\"a\",\"tags\":[1]}",
+            ),
+        ]);
+
+        test_round_trip_ok(&[
+            (
+                Adjacent::Item { kind: "a".to_string(), cache: 0, tags: vec![] },
+                "{\"t\":\"Item\",\"c\":{\"type\":\"a\"}}",
+            ),
+        ]);
+
+        test_round_trip_ok(&[
+            (
+                Untagged::Item { kind: "a".to_string(), cache: 0, tags: vec![1] },
+                "{\"type\":\"a\",\"tags\":[1]}",
+            ),
+        ]);
+
+        // Skipped fields are never written, whatever their value.
+        let value = Untagged::Item { kind: "a".to_string(), cache: 5, tags: vec![] };
+        assert_eq!(super::to_string(&value).unwrap(), "{\"type\":\"a\"}".to_string());
+    }
+
+    #[test]
+    fn test_error_location() {
+        #[derive(PartialEq, Show)]
+        #[derive_deserialize]
+        struct Server {
+            port: u16,
+        }
+
+        #[derive(PartialEq, Show)]
+        #[derive_deserialize]
+        struct Config {
+            servers: Vec<Server>,
+        }
+
+        let s = "{\"servers\": [{\"port\": 1}, {\"port\": \"x\"}]}";
+        let result: Result<Config, Error> = from_str(s);
+        match result {
+            Err(SyntaxError(_, 1, _, ref location)) => {
+                assert_eq!(location.path, ".servers[1].port");
+                assert_eq!(location.offset, s.find("\"x\"").unwrap() + 3);
+                assert_eq!(
+                    format!("{}", location),
+                    format!(".servers[1].port (byte {})", location.offset)
+                );
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // The same error found while deserializing a `Value` has no offset.
+        let value: Value = from_str(s).unwrap();
+        let result: Result<Config, Error> = from_json(value);
+        match result {
+            Err(SyntaxError(_, _, _, ref location)) => {
+                assert_eq!(*location, Location { offset: 0, path: ".servers[1].port".to_string() });
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // Malformed enums in a `Value` are reported where they are, too.
+        let tests = [
+            ("[{\"Frog\": 1}]", ErrorCode::ExpectedEnumToken),
+            ("[{}]", ErrorCode::ExpectedEnumVariantString),
+            ("[{\"Dog\": [], \"Frog\": []}]", ErrorCode::ExpectedEnumEndToken),
+            ("[1]", ErrorCode::ExpectedEnumMapStart),
+            ("[\"Cat\"]", ErrorCode::UnknownVariant("Cat".to_string())),
+        ];
+        for &(s, ref code) in tests.iter() {
+            let value: Value = from_str(s).unwrap();
+            let result: Result<Vec<Animal>, Error> = from_json(value);
+            match result {
+                Err(SyntaxError(ref err, _, _, ref location)) => {
+                    assert_eq!(err, code);
+                    assert_eq!(*location, Location { offset: 0, path: ".[0]".to_string() });
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        let result: Result<Value, Error> = from_str("{\"a b\": [true, nul]}");
+        match result {
+            Err(SyntaxError(_, _, _, ref location)) => {
+                assert_eq!(location.path, ".[\"a b\"][1]");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let result: Result<Vec<int>, Error> = from_str("[1, 2]x");
+        match result {
+            Err(SyntaxError(TrailingCharacters, 1, 7, ref location)) => {
+                assert_eq!(*location, Location::new(6));
+                assert_eq!(format!("{}", location), ". (byte 6)");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
     #[test]
     fn test_multiline_errors() {
         test_parse_err::<BTreeMap<string::String, string::String>>(&[
-            ("{\n  \"foo\":\n \"bar\"", SyntaxError(EOFWhileParsingObject, 3u, 8u)),
+            ("{\n  \"foo\":\n \"bar\"", EOFWhileParsingObject, 3u, 8u),
         ]);
     }
 
+    #[test]
+    fn test_array_iter() {
+        let mut parser = Parser::new("[{\"a\": null, \"b\": 2, \"c\": []}, {\"a\": null, \"b\": 4, \"c\": [\"x\"]}]".bytes());
+        {
+            let mut iter = parser.array_iter::<Inner>();
+            assert_eq!(iter.next(), Some(Ok(Inner { a: (), b: 2, c: vec![] })));
+            assert_eq!(iter.next(), Some(Ok(Inner { a: (), b: 4, c: vec!["x".to_string()] })));
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next(), None);
+        }
+        parser.end().unwrap();
+
+        let mut parser = Parser::new("[]".bytes());
+        assert_eq!(parser.array_iter::<int>().next(), None);
+        parser.end().unwrap();
+
+        let mut parser = Parser::new("[1, true, 3]".bytes());
+        let values: Vec<Result<int, Error>> = parser.array_iter().collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0], Ok(1));
+        assert!(values[1].is_err());
+
+        let mut parser = Parser::new("{}".bytes());
+        let values: Vec<Result<int, Error>> = parser.array_iter().collect();
+        assert_eq!(values.len(), 1);
+        assert!(values[0].is_err());
+    }
+
+    #[test]
+    fn test_to_array_writer() {
+        let writer = super::to_array_writer(Vec::new(), range(0i, 3).map(|i| i * 2)).unwrap();
+        assert_eq!(string::String::from_utf8(writer).unwrap(), "[0,2,4]");
+
+        let writer = super::to_array_writer(Vec::new(), Vec::<int>::new().into_iter()).unwrap();
+        assert_eq!(string::String::from_utf8(writer).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_stream_deserializer() {
+        let parser = Parser::new("{\"a\":1} {\"a\":2}\n\n[3]\n".bytes());
+        let mut stream: StreamDeserializer<Value, _> = StreamDeserializer::new(parser);
+
+        assert_eq!(stream.next(), Some(Ok(Value::Object(treemap!("a".to_string() => Value::Integer(1))))));
+        assert_eq!(stream.byte_offset(), 0);
+        assert_eq!(stream.next(), Some(Ok(Value::Object(treemap!("a".to_string() => Value::Integer(2))))));
+        assert_eq!(stream.byte_offset(), 8);
+        assert_eq!(stream.next(), Some(Ok(Value::Array(vec![Value::Integer(3)]))));
+        assert_eq!(stream.byte_offset(), 17);
+        assert_eq!(stream.next(), None);
+
+        // The parser is left at the end of the input once the stream is.
+        let mut parser = stream.unwrap();
+        assert_eq!(parser.end(), Ok(()));
+
+        let parser = Parser::new("1\n[1,]\n3".bytes());
+        let mut stream: StreamDeserializer<Value, _> = StreamDeserializer::new(parser);
+
+        assert_eq!(stream.next(), Some(Ok(Value::Integer(1))));
+        assert_eq!(
+            stream.next(),
+            Some(Err(SyntaxError(ExpectedSomeValue, 2, 5, Location { offset: 5, path: ".[1]".to_string() })))
+        );
+        assert_eq!(stream.byte_offset(), 2);
+        assert_eq!(stream.next(), None);
+
+        let parser = Parser::new("  ".bytes());
+        let mut stream: StreamDeserializer<Value, _> = StreamDeserializer::new(parser);
+        assert_eq!(stream.next(), None);
+        assert_eq!(stream.unwrap().end(), Ok(()));
+    }
+
     /*
     #[derive(Decodable)]
     struct DecodeStruct {
@@ -1249,6 +2250,261 @@ mod tests {
         assert!(found_str.is_some() && found_str.unwrap().as_string().unwrap() == "cheese");
     }
 
+    #[test]
+    fn test_pointer() {
+        let json_value: Value = from_str(r#"{
+            "foo": ["bar", "baz"],
+            "": 0,
+            "a/b": 1,
+            "c%d": 2,
+            "e^f": 3,
+            "g|h": 4,
+            "i\\j": 5,
+            "k\"l": 6,
+            " ": 7,
+            "m~n": 8
+        }"#).unwrap();
+
+        assert_eq!(json_value.pointer(""), Some(&json_value));
+        assert_eq!(json_value.pointer("/foo"),
+                   Some(&Value::Array(vec![Value::String("bar".to_string()),
+                                           Value::String("baz".to_string())])));
+        assert_eq!(json_value.pointer("/foo/0"), Some(&Value::String("bar".to_string())));
+        assert_eq!(json_value.pointer("/"), Some(&Value::Integer(0)));
+        assert_eq!(json_value.pointer("/a~1b"), Some(&Value::Integer(1)));
+        assert_eq!(json_value.pointer("/c%d"), Some(&Value::Integer(2)));
+        assert_eq!(json_value.pointer("/e^f"), Some(&Value::Integer(3)));
+        assert_eq!(json_value.pointer("/g|h"), Some(&Value::Integer(4)));
+        assert_eq!(json_value.pointer("/i\\j"), Some(&Value::Integer(5)));
+        assert_eq!(json_value.pointer("/k\"l"), Some(&Value::Integer(6)));
+        assert_eq!(json_value.pointer("/ "), Some(&Value::Integer(7)));
+        assert_eq!(json_value.pointer("/m~0n"), Some(&Value::Integer(8)));
+
+        assert_eq!(json_value.pointer("foo"), None);
+        assert_eq!(json_value.pointer("/foo/2"), None);
+        assert_eq!(json_value.pointer("/foo/01"), None);
+        assert_eq!(json_value.pointer("/foo/-"), None);
+        assert_eq!(json_value.pointer("/foo/0/bar"), None);
+        assert_eq!(json_value.pointer("/m~2n"), None);
+        assert_eq!(json_value.pointer("/missing"), None);
+
+        assert_eq!(escape_pointer_token("m~n"), "m~0n".to_string());
+        assert_eq!(escape_pointer_token("a/b"), "a~1b".to_string());
+        assert_eq!(escape_pointer_token("~1"), "~01".to_string());
+    }
+
+    #[test]
+    fn test_pointer_mut() {
+        let mut json_value: Value = from_str(r#"{"a": [{"b": 1}]}"#).unwrap();
+
+        *json_value.pointer_mut("/a/0/b").unwrap() = Value::Integer(2);
+        assert_eq!(json_value.pointer("/a/0/b"), Some(&Value::Integer(2)));
+        assert!(json_value.pointer_mut("/a/1").is_none());
+    }
+
+    #[test]
+    fn test_insert_pointer() {
+        let mut json_value: Value = from_str(r#"{"a": [1, 3], "b": {}}"#).unwrap();
+
+        assert_eq!(json_value.insert_pointer("/a/1", Value::Integer(2)), Ok(None));
+        assert_eq!(json_value.insert_pointer("/a/-", Value::Integer(4)), Ok(None));
+        assert_eq!(json_value.insert_pointer("/a/4", Value::Integer(5)), Ok(None));
+        assert_eq!(json_value.insert_pointer("/b/c", Value::Null), Ok(None));
+        assert_eq!(json_value.insert_pointer("/b/c", Value::Boolean(true)),
+                   Ok(Some(Value::Null)));
+        assert_eq!(json_value,
+                   from_str::<Value>(r#"{"a": [1, 2, 3, 4, 5], "b": {"c": true}}"#).unwrap());
+
+        assert_eq!(json_value.insert_pointer("/a/7", Value::Null),
+                   Err(PointerError::InvalidIndex));
+        assert_eq!(json_value.insert_pointer("/a/x", Value::Null),
+                   Err(PointerError::InvalidIndex));
+        assert_eq!(json_value.insert_pointer("/x/y", Value::Null),
+                   Err(PointerError::NotFound));
+        assert_eq!(json_value.insert_pointer("/b/c/d", Value::Null),
+                   Err(PointerError::NotFound));
+        assert_eq!(json_value.insert_pointer("a", Value::Null),
+                   Err(PointerError::InvalidPointer));
+
+        let old = json_value.clone();
+        assert_eq!(json_value.insert_pointer("", Value::Null), Ok(Some(old)));
+        assert_eq!(json_value, Value::Null);
+    }
+
+    #[test]
+    fn test_remove_pointer() {
+        let mut json_value: Value = from_str(r#"{"a": [1, 2, 3], "b/c": {}}"#).unwrap();
+
+        assert_eq!(json_value.remove_pointer("/a/1"), Ok(Value::Integer(2)));
+        assert_eq!(json_value.remove_pointer("/b~1c"), Ok(Value::Object(BTreeMap::new())));
+        assert_eq!(json_value, from_str::<Value>(r#"{"a": [1, 3]}"#).unwrap());
+
+        assert_eq!(json_value.remove_pointer("/a/2"), Err(PointerError::InvalidIndex));
+        assert_eq!(json_value.remove_pointer("/a/-"), Err(PointerError::InvalidIndex));
+        assert_eq!(json_value.remove_pointer("/b"), Err(PointerError::NotFound));
+        assert_eq!(json_value.remove_pointer("/a/0/b"), Err(PointerError::NotFound));
+        assert_eq!(json_value.remove_pointer("a"), Err(PointerError::InvalidPointer));
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let mut json_value: Value = from_str(r#"{"a": {"b": [1, 2]}, "c": "d"}"#).unwrap();
+
+        let patch = vec![
+            Operation::Test { path: "/c".to_string(), value: Value::String("d".to_string()) },
+            Operation::Add { path: "/a/b/-".to_string(), value: Value::Integer(3) },
+            Operation::Remove { path: "/a/b/0".to_string() },
+            Operation::Replace { path: "/c".to_string(), value: Value::Boolean(true) },
+            Operation::Copy { from: "/a/b".to_string(), path: "/e".to_string() },
+            Operation::Move { from: "/c".to_string(), path: "/a/f".to_string() },
+        ];
+
+        assert_eq!(json_value.apply_patch(patch.as_slice()), Ok(()));
+        assert_eq!(json_value,
+                   from_str::<Value>(r#"{"a": {"b": [2, 3], "f": true}, "e": [2, 3]}"#).unwrap());
+    }
+
+    #[test]
+    fn test_apply_patch_errors() {
+        let original: Value = from_str(r#"{"a": {"b": 1}}"#).unwrap();
+        let mut json_value = original.clone();
+
+        // A failing operation leaves the value untouched.
+        let patch = vec![
+            Operation::Remove { path: "/a/b".to_string() },
+            Operation::Test { path: "/a/b".to_string(), value: Value::Integer(1) },
+        ];
+        assert_eq!(json_value.apply_patch(patch.as_slice()), Err(PatchError::TestFailed(1)));
+        assert_eq!(json_value, original);
+
+        let patch = vec![
+            Operation::Replace { path: "/x".to_string(), value: Value::Null },
+        ];
+        assert_eq!(json_value.apply_patch(patch.as_slice()),
+                   Err(PatchError::InvalidPointer(0, PointerError::NotFound)));
+
+        let patch = vec![
+            Operation::Replace { path: "a".to_string(), value: Value::Null },
+        ];
+        assert_eq!(json_value.apply_patch(patch.as_slice()),
+                   Err(PatchError::InvalidPointer(0, PointerError::InvalidPointer)));
+
+        let patch = vec![
+            Operation::Copy { from: "/a/~2".to_string(), path: "/c".to_string() },
+        ];
+        assert_eq!(json_value.apply_patch(patch.as_slice()),
+                   Err(PatchError::InvalidPointer(0, PointerError::InvalidPointer)));
+
+        let patch = vec![
+            Operation::Move { from: "/a".to_string(), path: "/a/c".to_string() },
+        ];
+        assert_eq!(json_value.apply_patch(patch.as_slice()), Err(PatchError::MoveIntoChild(0)));
+        assert_eq!(json_value, original);
+    }
+
+    #[test]
+    fn test_apply_merge_patch() {
+        let tests = [
+            (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+            (r#"{"a":"b","b":"c"}"#, r#"{"a":null}"#, r#"{"b":"c"}"#),
+            (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, r#"{"a":["b"]}"#),
+            (r#"{"a":{"b":"c"}}"#, r#"{"a":{"b":"d","c":null}}"#, r#"{"a":{"b":"d"}}"#),
+            (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+            (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+            (r#"{"a":"b"}"#, r#"["c"]"#, r#"["c"]"#),
+            (r#"{"a":"foo"}"#, r#"null"#, r#"null"#),
+            (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+            (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"a":1,"e":null}"#),
+            (r#"[1,2]"#, r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+            (r#"{}"#, r#"{"a":{"bb":{"ccc":null}}}"#, r#"{"a":{"bb":{}}}"#),
+        ];
+
+        for &(target, patch, expected) in tests.iter() {
+            let mut target: Value = from_str(target).unwrap();
+            let patch: Value = from_str(patch).unwrap();
+            let expected: Value = from_str(expected).unwrap();
+
+            target.apply_merge_patch(&patch);
+            assert_eq!(target, expected);
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let tests = [
+            (r#"{"a": 1}"#, r#"{"a": 1}"#),
+            (r#"{"a": 1, "b/c": 2}"#, r#"{"a": 2, "d~e": 3}"#),
+            (r#"[1, 2, 3, 4]"#, r#"[1, 5]"#),
+            (r#"[1]"#, r#"[2, [3], {"a": 4}]"#),
+            (r#"{"a": [1, {"b": 2}]}"#, r#"{"a": [1, {"b": 3, "c": 4}]}"#),
+            (r#"{"a": 1}"#, r#"[1]"#),
+            (r#"null"#, r#"1"#),
+        ];
+
+        for &(from, to) in tests.iter() {
+            let from: Value = from_str(from).unwrap();
+            let to: Value = from_str(to).unwrap();
+
+            let mut json_value = from.clone();
+            assert_eq!(json_value.apply_patch(patch::diff(&from, &to).as_slice()), Ok(()));
+            assert_eq!(json_value, to);
+        }
+
+        let from: Value = from_str(r#"{"a": [1, 2]}"#).unwrap();
+        let to: Value = from_str(r#"{"a": [1]}"#).unwrap();
+        assert_eq!(patch::diff(&from, &to),
+                   vec![Operation::Remove { path: "/a/1".to_string() }]);
+        assert_eq!(patch::diff(&from, &from), vec![]);
+    }
+
+    #[test]
+    fn test_patch_serialization() {
+        let patch = vec![
+            Operation::Add { path: "/a".to_string(), value: Value::Integer(1) },
+            Operation::Remove { path: "/b".to_string() },
+            Operation::Replace { path: "/c".to_string(), value: Value::Null },
+            Operation::Move { from: "/d".to_string(), path: "/e".to_string() },
+            Operation::Copy { from: "/f".to_string(), path: "/g".to_string() },
+            Operation::Test { path: "/h".to_string(), value: Value::Boolean(false) },
+        ];
+
+        let s = super::to_string(&patch).unwrap();
+        assert_eq!(s, concat!(
+            r#"[{"op":"add","path":"/a","value":1},"#,
+            r#"{"op":"remove","path":"/b"},"#,
+            r#"{"op":"replace","path":"/c","value":null},"#,
+            r#"{"op":"move","path":"/e","from":"/d"},"#,
+            r#"{"op":"copy","path":"/g","from":"/f"},"#,
+            r#"{"op":"test","path":"/h","value":false}]"#).to_string());
+
+        let v: Vec<Operation> = from_str(s.as_slice()).unwrap();
+        assert_eq!(v, patch);
+
+        let v: Vec<Operation> = from_json(from_str::<Value>(s.as_slice()).unwrap()).unwrap();
+        assert_eq!(v, patch);
+
+        let v: Vec<Operation> = from_str(
+            r#"[{"from": "/a", "extra": [1], "path": "/b", "op": "copy"}]"#).unwrap();
+        assert_eq!(v, vec![Operation::Copy { from: "/a".to_string(), path: "/b".to_string() }]);
+
+        assert!(from_str::<Operation>(r#"{"op": "frob", "path": "/a"}"#).is_err());
+        assert!(from_str::<Operation>(r#"{"op": "move", "path": "/a"}"#).is_err());
+        assert!(from_str::<Operation>(r#"{"path": "/a"}"#).is_err());
+
+        // `value` has to be there, even though it could be read as `null`.
+        for s in [
+            r#"{"op": "add", "path": "/a"}"#,
+            r#"{"op": "replace", "path": "/a"}"#,
+            r#"{"op": "test", "path": "/a"}"#,
+        ].iter() {
+            assert!(from_str::<Operation>(*s).is_err());
+            assert!(from_json::<Operation>(from_str(*s).unwrap()).is_err());
+        }
+    }
+
     #[test]
     fn test_search(){
         let json_value: Value = from_str("{\"dog\":{\"cat\": {\"mouse\" : \"cheese\"}}}").unwrap();
@@ -1271,6 +2527,16 @@ mod tests {
         assert_eq!(json_object, Some(&map));
     }
 
+    #[test]
+    fn test_as_object_mut() {
+        let mut json_value: Value = from_str(r#"{"a": 1}"#).unwrap();
+        json_value.as_object_mut().unwrap().insert("b".to_string(), Value::Integer(2));
+        assert_eq!(json_value, from_str::<Value>(r#"{"a": 1, "b": 2}"#).unwrap());
+
+        let mut json_value: Value = from_str("[]").unwrap();
+        assert!(json_value.as_object_mut().is_none());
+    }
+
     #[test]
     fn test_is_array() {
         let json_value: Value = from_str("[1, 2, 3]").unwrap();
@@ -1291,6 +2557,94 @@ mod tests {
         assert!(json_value.is_string());
     }
 
+    #[test]
+    fn test_as_array_mut() {
+        let mut json_value: Value = from_str("[1]").unwrap();
+        json_value.as_array_mut().unwrap().push(Value::Integer(2));
+        assert_eq!(json_value, from_str::<Value>("[1, 2]").unwrap());
+
+        let mut json_value: Value = from_str("{}").unwrap();
+        assert!(json_value.as_array_mut().is_none());
+    }
+
+    #[test]
+    fn test_as_string_mut() {
+        let mut json_value: Value = from_str("\"dog\"").unwrap();
+        json_value.as_string_mut().unwrap().push_str("s");
+        assert_eq!(json_value.as_string(), Some("dogs"));
+    }
+
+    #[test]
+    fn test_get() {
+        let mut json_value: Value = from_str(r#"{"a": [1, {"b": 2}]}"#).unwrap();
+
+        assert_eq!(json_value.get("a").and_then(|a| a.get(0u)), Some(&Value::Integer(1)));
+        assert_eq!(json_value.get(&"a".to_string()).map(|a| a.is_array()), Some(true));
+        assert_eq!(json_value.get("b"), None);
+        assert_eq!(json_value.get(0u), None);
+        assert_eq!(json_value["a"].get(2u), None);
+
+        *json_value.get_mut("a").unwrap().get_mut(0u).unwrap() = Value::Null;
+        json_value.find_mut(&"a".to_string()).unwrap().as_array_mut().unwrap().pop();
+        assert_eq!(json_value, from_str::<Value>(r#"{"a": [null]}"#).unwrap());
+    }
+
+    #[test]
+    fn test_index() {
+        let mut json_value: Value = from_str(r#"{"a": [1, {"b": 2}]}"#).unwrap();
+
+        assert_eq!(json_value["a"][0u], Value::Integer(1));
+        assert_eq!(json_value["a"][1u]["b"], Value::Integer(2));
+
+        json_value["a"][1u]["b"] = Value::Boolean(true);
+        json_value["a"][0u] = Value::String("one".to_string());
+        assert_eq!(json_value, from_str::<Value>(r#"{"a": ["one", {"b": true}]}"#).unwrap());
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_index_missing_key() {
+        let json_value: Value = from_str(r#"{"a": 1}"#).unwrap();
+        json_value["b"];
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_index_out_of_bounds() {
+        let json_value: Value = from_str("[1]").unwrap();
+        json_value[1u];
+    }
+
+    #[test]
+    fn test_take() {
+        let mut json_value: Value = from_str(r#"{"a": [1, 2]}"#).unwrap();
+        let array = json_value["a"].take();
+        assert_eq!(array, from_str::<Value>("[1, 2]").unwrap());
+        assert_eq!(json_value, from_str::<Value>(r#"{"a": null}"#).unwrap());
+    }
+
+    #[test]
+    fn test_entry() {
+        use std::collections::btree_map::Entry;
+
+        let mut json_value: Value = from_str(r#"{"a": 1}"#).unwrap();
+
+        match json_value.entry("a".to_string()).unwrap() {
+            Entry::Occupied(mut entry) => { *entry.get_mut() = Value::Integer(2); }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+
+        match json_value.entry("b".to_string()).unwrap() {
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+            Entry::Vacant(entry) => { entry.insert(Value::Integer(3)); }
+        }
+
+        assert_eq!(json_value, from_str::<Value>(r#"{"a": 2, "b": 3}"#).unwrap());
+
+        let mut json_value: Value = from_str("[]").unwrap();
+        assert!(json_value.entry("a".to_string()).is_none());
+    }
+
     #[test]
     fn test_as_string() {
         let json_value: Value = from_str("\"dog\"").unwrap();
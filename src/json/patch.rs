@@ -0,0 +1,294 @@
+//! JSON Patch (RFC 6902) and JSON Merge Patch (RFC 7396) support for `Value`.
+
+use std::collections::BTreeMap;
+use std::error::FromError;
+
+use de::{self, Token};
+use ser;
+
+use super::value::{PointerError, Value, escape_pointer_token, parse_pointer};
+
+/// A single JSON Patch operation. A patch document is a sequence of these,
+/// and serializes to the `{"op": "add", "path": ..., ...}` form of RFC 6902.
+#[derive(Clone, PartialEq, Show)]
+pub enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// The reasons a JSON Patch could not be applied. Each carries the index of
+/// the operation that failed.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum PatchError {
+    /// The operation's `path` or `from` does not refer to a usable location.
+    InvalidPointer(uint, PointerError),
+    /// A `test` operation found a different value.
+    TestFailed(uint),
+    /// A `move` operation tried to move a value into one of its children.
+    MoveIntoChild(uint),
+}
+
+impl Value {
+    /// Applies a JSON Patch to the value. The patch is applied atomically: if
+    /// any operation fails, the value is left unchanged.
+    pub fn apply_patch(&mut self, patch: &[Operation]) -> Result<(), PatchError> {
+        let mut doc = self.clone();
+        for (index, operation) in patch.iter().enumerate() {
+            try!(doc.apply_operation(operation).map_err(|err| {
+                match err {
+                    OperationError::Pointer(err) => PatchError::InvalidPointer(index, err),
+                    OperationError::TestFailed => PatchError::TestFailed(index),
+                    OperationError::MoveIntoChild => PatchError::MoveIntoChild(index),
+                }
+            }));
+        }
+        *self = doc;
+        Ok(())
+    }
+
+    fn apply_operation(&mut self, operation: &Operation) -> Result<(), OperationError> {
+        match *operation {
+            Operation::Add { ref path, ref value } => {
+                try!(self.insert_pointer(path.as_slice(), value.clone()));
+            }
+            Operation::Remove { ref path } => {
+                try!(self.remove_pointer(path.as_slice()));
+            }
+            Operation::Replace { ref path, ref value } => {
+                match self.pointer_mut(path.as_slice()) {
+                    Some(target) => { *target = value.clone(); }
+                    None => { return Err(missing_target(path.as_slice())); }
+                }
+            }
+            Operation::Move { ref from, ref path } => {
+                if path.as_slice().starts_with(format!("{}/", from).as_slice()) {
+                    return Err(OperationError::MoveIntoChild);
+                }
+
+                let value = try!(self.remove_pointer(from.as_slice()));
+                try!(self.insert_pointer(path.as_slice(), value));
+            }
+            Operation::Copy { ref from, ref path } => {
+                let value = match self.pointer(from.as_slice()) {
+                    Some(value) => value.clone(),
+                    None => { return Err(missing_target(from.as_slice())); }
+                };
+                try!(self.insert_pointer(path.as_slice(), value));
+            }
+            Operation::Test { ref path, ref value } => {
+                if self.pointer(path.as_slice()) != Some(value) {
+                    return Err(OperationError::TestFailed);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a JSON Merge Patch to the value. Objects in the patch are
+    /// merged recursively, `null` members remove keys, and anything else
+    /// replaces the target outright.
+    pub fn apply_merge_patch(&mut self, patch: &Value) {
+        let patch = match *patch {
+            Value::Object(ref patch) => patch,
+            _ => {
+                *self = patch.clone();
+                return;
+            }
+        };
+
+        if !self.is_object() {
+            *self = Value::Object(BTreeMap::new());
+        }
+
+        let map = match *self {
+            Value::Object(ref mut map) => map,
+            _ => unreachable!(),
+        };
+
+        for (key, value) in patch.iter() {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                let mut target = map.remove(key).unwrap_or(Value::Null);
+                target.apply_merge_patch(value);
+                map.insert(key.clone(), target);
+            }
+        }
+    }
+}
+
+enum OperationError {
+    Pointer(PointerError),
+    TestFailed,
+    MoveIntoChild,
+}
+
+impl FromError<PointerError> for OperationError {
+    fn from_error(err: PointerError) -> OperationError {
+        OperationError::Pointer(err)
+    }
+}
+
+/// The error for a pointer that `Value::pointer` could not resolve.
+fn missing_target(pointer: &str) -> OperationError {
+    match parse_pointer(pointer) {
+        Some(_) => OperationError::Pointer(PointerError::NotFound),
+        None => OperationError::Pointer(PointerError::InvalidPointer),
+    }
+}
+
+/// Computes a JSON Patch that turns `from` into `to`.
+pub fn diff(from: &Value, to: &Value) -> Vec<Operation> {
+    let mut patch = Vec::new();
+    diff_into(&mut patch, "", from, to);
+    patch
+}
+
+fn diff_into(patch: &mut Vec<Operation>, path: &str, from: &Value, to: &Value) {
+    match (from, to) {
+        (&Value::Object(ref from), &Value::Object(ref to)) => {
+            for (key, value) in from.iter() {
+                let path = format!("{}/{}", path, escape_pointer_token(key.as_slice()));
+                match to.get(key) {
+                    Some(to) => diff_into(patch, path.as_slice(), value, to),
+                    None => patch.push(Operation::Remove { path: path }),
+                }
+            }
+
+            for (key, value) in to.iter() {
+                if !from.contains_key(key) {
+                    patch.push(Operation::Add {
+                        path: format!("{}/{}", path, escape_pointer_token(key.as_slice())),
+                        value: value.clone(),
+                    });
+                }
+            }
+        }
+        (&Value::Array(ref from), &Value::Array(ref to)) => {
+            for (index, (from, to)) in from.iter().zip(to.iter()).enumerate() {
+                diff_into(patch, format!("{}/{}", path, index).as_slice(), from, to);
+            }
+
+            // Remove from the back so the remaining indices stay valid.
+            for index in range(to.len(), from.len()).rev() {
+                patch.push(Operation::Remove { path: format!("{}/{}", path, index) });
+            }
+
+            for index in range(from.len(), to.len()) {
+                patch.push(Operation::Add {
+                    path: format!("{}/{}", path, index),
+                    value: to[index].clone(),
+                });
+            }
+        }
+        _ => {
+            if from != to {
+                patch.push(Operation::Replace {
+                    path: path.to_string(),
+                    value: to.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for Operation {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        let (op, path, from, value) = match *self {
+            Operation::Add { ref path, ref value } => ("add", path, None, Some(value)),
+            Operation::Remove { ref path } => ("remove", path, None, None),
+            Operation::Replace { ref path, ref value } => ("replace", path, None, Some(value)),
+            Operation::Move { ref from, ref path } => ("move", path, Some(from), None),
+            Operation::Copy { ref from, ref path } => ("copy", path, Some(from), None),
+            Operation::Test { ref path, ref value } => ("test", path, None, Some(value)),
+        };
+
+        let len = 2 + from.is_some() as uint + value.is_some() as uint;
+        try!(s.serialize_struct_start("Operation", len));
+        try!(s.serialize_struct_elt("op", &op));
+        try!(s.serialize_struct_elt("path", path));
+
+        match from {
+            Some(from) => { try!(s.serialize_struct_elt("from", from)); }
+            None => { }
+        }
+
+        match value {
+            Some(value) => { try!(s.serialize_struct_elt("value", value)); }
+            None => { }
+        }
+
+        s.serialize_struct_end()
+    }
+}
+
+impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for Operation {
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Operation, E> {
+        try!(d.expect_struct_start(token, "Operation"));
+
+        static FIELDS: &'static [&'static str] = &["op", "path", "from", "value"];
+
+        let mut op = None;
+        let mut path = None;
+        let mut from = None;
+        let mut value = None;
+
+        loop {
+            match try!(d.expect_struct_field_or_end(FIELDS)) {
+                Some(Some(0)) => { op = Some(try!(d.expect_struct_value())); }
+                Some(Some(1)) => { path = Some(try!(d.expect_struct_value())); }
+                Some(Some(2)) => { from = Some(try!(d.expect_struct_value())); }
+                Some(Some(3)) => { value = Some(try!(d.expect_struct_value())); }
+                Some(Some(_)) => unreachable!(),
+                Some(None) => {
+                    let _: de::IgnoreTokens = try!(de::Deserialize::deserialize(d));
+                }
+                None => { break; }
+            }
+        }
+
+        let op: String = try!(field(d, op, "op"));
+        let path = try!(field(d, path, "path"));
+
+        match op.as_slice() {
+            "add" => {
+                Ok(Operation::Add { path: path, value: try!(field(d, value, "value")) })
+            }
+            "remove" => {
+                Ok(Operation::Remove { path: path })
+            }
+            "replace" => {
+                Ok(Operation::Replace { path: path, value: try!(field(d, value, "value")) })
+            }
+            "move" => {
+                Ok(Operation::Move { from: try!(field(d, from, "from")), path: path })
+            }
+            "copy" => {
+                Ok(Operation::Copy { from: try!(field(d, from, "from")), path: path })
+            }
+            "test" => {
+                Ok(Operation::Test { path: path, value: try!(field(d, value, "value")) })
+            }
+            _ => Err(d.unexpected_name_error(Token::String(op))),
+        }
+    }
+}
+
+fn field<
+    D: de::Deserializer<E>,
+    E,
+    T: de::Deserialize<D, E>
+>(d: &mut D, value: Option<T>, name: &'static str) -> Result<T, E> {
+    // RFC 6902 requires every member the operation uses, even when its type
+    // would allow it to be missing, like `value`.
+    match value {
+        Some(value) => Ok(value),
+        None => d.missing_struct_field("Operation", name),
+    }
+}
@@ -1,12 +1,12 @@
-use std::f32;
-use std::f64;
 use std::num::{Float, FpCategory};
-use std::io::{IoError, IoResult};
+use std::io::{self, IoError, IoResult};
 use std::string::FromUtf8Error;
 
 use ser::Serialize;
 use ser;
 
+use super::float;
+
 fn escape_bytes<W: Writer>(wr: &mut W, bytes: &[u8]) -> IoResult<()> {
     try!(wr.write_str("\""));
 
@@ -50,33 +50,51 @@ fn escape_char<W: Writer>(wr: &mut W, v: char) -> IoResult<()> {
     escape_bytes(wr, buf)
 }
 
-fn fmt_f32_or_null<W: Writer>(wr: &mut W, v: f32) -> IoResult<()> {
-    match v.classify() {
-        FpCategory::Nan | FpCategory::Infinite => wr.write_str("null"),
-        _ => wr.write_str(f32::to_str_digits(v, 6).as_slice()),
-    }
+/// How to write the NaN and infinite floats, which JSON can't represent.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum NonFiniteFloats {
+    /// Fail with an `InvalidInput` error.
+    Error,
+    /// Write them as `null`, which loses the value.
+    Null,
+    /// Write the `NaN`, `Infinity` and `-Infinity` extensions, which a
+    /// `Parser` accepts with `allow_non_finite_floats`.
+    Extension,
 }
 
-fn fmt_f64_or_null<W: Writer>(wr: &mut W, v: f64) -> IoResult<()> {
-    match v.classify() {
-        FpCategory::Nan | FpCategory::Infinite => wr.write_str("null"),
-        _ => wr.write_str(f64::to_str_digits(v, 6).as_slice()),
+fn fmt_non_finite<W: Writer>(wr: &mut W, v: f64, non_finite: NonFiniteFloats) -> IoResult<()> {
+    match non_finite {
+        NonFiniteFloats::Error => {
+            Err(IoError {
+                kind: io::InvalidInput,
+                desc: "cannot serialize a non-finite float",
+                detail: Some(format!("{}", v)),
+            })
+        }
+        NonFiniteFloats::Null => wr.write_str("null"),
+        NonFiniteFloats::Extension => {
+            if v.is_nan() {
+                wr.write_str("NaN")
+            } else if v > 0.0 {
+                wr.write_str("Infinity")
+            } else {
+                wr.write_str("-Infinity")
+            }
+        }
     }
 }
 
-fn spaces<W: Writer>(wr: &mut W, mut n: uint) -> IoResult<()> {
-    const LEN: uint = 16;
-    const BUF: &'static [u8; LEN] = &[b' '; LEN];
-
-    while n >= LEN {
-        try!(wr.write(BUF));
-        n -= LEN;
+fn fmt_f32<W: Writer>(wr: &mut W, v: f32, non_finite: NonFiniteFloats) -> IoResult<()> {
+    match v.classify() {
+        FpCategory::Nan | FpCategory::Infinite => fmt_non_finite(wr, v as f64, non_finite),
+        _ => wr.write_str(float::fmt_f32(v).as_slice()),
     }
+}
 
-    if n > 0 {
-        wr.write(BUF.slice_to(n))
-    } else {
-        Ok(())
+fn fmt_f64<W: Writer>(wr: &mut W, v: f64, non_finite: NonFiniteFloats) -> IoResult<()> {
+    match v.classify() {
+        FpCategory::Nan | FpCategory::Infinite => fmt_non_finite(wr, v, non_finite),
+        _ => wr.write_str(float::fmt_f64(v).as_slice()),
     }
 }
 
@@ -94,6 +112,9 @@ enum SerializerState {
 pub struct Serializer<W> {
     wr: W,
     first: bool,
+    // Set while writing a unit variant as a bare string.
+    unit_variant: bool,
+    non_finite: NonFiniteFloats,
 }
 
 impl<W: Writer> Serializer<W> {
@@ -103,9 +124,19 @@ impl<W: Writer> Serializer<W> {
         Serializer {
             wr: wr,
             first: true,
+            unit_variant: false,
+            non_finite: NonFiniteFloats::Null,
         }
     }
 
+    /// Sets how NaN and infinite floats are written. They are written as
+    /// `null` by default.
+    #[inline]
+    pub fn non_finite_floats(mut self, non_finite: NonFiniteFloats) -> Serializer<W> {
+        self.non_finite = non_finite;
+        self
+    }
+
     /// Unwrap the Writer from the Serializer.
     pub fn unwrap(self) -> W {
         self.wr
@@ -179,12 +210,17 @@ impl<W: Writer> ser::Serializer<IoError> for Serializer<W> {
 
     #[inline]
     fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
-        fmt_f32_or_null(&mut self.wr, v)
+        fmt_f32(&mut self.wr, v, self.non_finite)
     }
 
     #[inline]
     fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
-        fmt_f64_or_null(&mut self.wr, v)
+        fmt_f64(&mut self.wr, v, self.non_finite)
+    }
+
+    #[inline]
+    fn serialize_raw_number(&mut self, v: &str) -> IoResult<()> {
+        self.wr.write_str(v)
     }
 
     #[inline]
@@ -246,7 +282,13 @@ impl<W: Writer> ser::Serializer<IoError> for Serializer<W> {
     }
 
     #[inline]
-    fn serialize_enum_start(&mut self, _name: &str, variant: &str, _len: uint) -> IoResult<()> {
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, len: uint) -> IoResult<()> {
+        // Unit variants are written as just their name.
+        if len == 0 {
+            self.unit_variant = true;
+            return self.serialize_str(variant);
+        }
+
         self.first = true;
         try!(self.wr.write_str("{"));
         try!(self.serialize_str(variant));
@@ -267,6 +309,11 @@ impl<W: Writer> ser::Serializer<IoError> for Serializer<W> {
 
     #[inline]
     fn serialize_enum_end(&mut self) -> IoResult<()> {
+        if self.unit_variant {
+            self.unit_variant = false;
+            return Ok(());
+        }
+
         self.wr.write_str("]}")
     }
 
@@ -325,12 +372,45 @@ impl<W: Writer> ser::Serializer<IoError> for Serializer<W> {
     }
 }
 
+/// Controls the layout of the JSON written by a `PrettySerializer`.
+#[derive(Clone, PartialEq, Show)]
+pub struct PrettyFormatter {
+    /// Written once per level of nesting. Two spaces by default.
+    pub indent: String,
+    /// Written between the elements of an array or object. `","` by default.
+    pub item_separator: String,
+    /// Written between an object key and its value. `": "` by default.
+    pub key_separator: String,
+    /// Written at the end of each line. `"\n"` by default.
+    pub newline: String,
+    /// Whether empty arrays and objects are written as `[]` and `{}`, rather
+    /// than being broken over two lines. True by default.
+    pub compact_empty: bool,
+}
+
+impl PrettyFormatter {
+    /// Creates the default formatter.
+    pub fn new() -> PrettyFormatter {
+        PrettyFormatter {
+            indent: "  ".to_string(),
+            item_separator: ",".to_string(),
+            key_separator: ": ".to_string(),
+            newline: "\n".to_string(),
+            compact_empty: true,
+        }
+    }
+}
+
 /// Another serializer for JSON, but prints out human-readable JSON instead of
 /// compact data
 pub struct PrettySerializer<W> {
     wr: W,
-    indent: uint,
+    formatter: PrettyFormatter,
+    level: uint,
     first: bool,
+    // Set while writing a unit variant as a bare string.
+    unit_variant: bool,
+    non_finite: NonFiniteFloats,
 }
 
 impl<W: Writer> PrettySerializer<W> {
@@ -338,35 +418,69 @@ impl<W: Writer> PrettySerializer<W> {
     pub fn new(wr: W) -> PrettySerializer<W> {
         PrettySerializer {
             wr: wr,
-            indent: 0,
+            formatter: PrettyFormatter::new(),
+            level: 0,
             first: true,
+            unit_variant: false,
+            non_finite: NonFiniteFloats::Null,
         }
     }
 
+    /// Sets how NaN and infinite floats are written. They are written as
+    /// `null` by default.
+    #[inline]
+    pub fn non_finite_floats(mut self, non_finite: NonFiniteFloats) -> PrettySerializer<W> {
+        self.non_finite = non_finite;
+        self
+    }
+
+    /// Sets the indentation, separators and line endings to write.
+    #[inline]
+    pub fn formatter(mut self, formatter: PrettyFormatter) -> PrettySerializer<W> {
+        self.formatter = formatter;
+        self
+    }
+
     /// Unwrap the Writer from the Serializer.
     pub fn unwrap(self) -> W {
         self.wr
     }
 
+    #[inline]
+    fn serialize_newline(&mut self) -> IoResult<()> {
+        try!(self.wr.write_str(self.formatter.newline.as_slice()));
+
+        for _ in range(0, self.level) {
+            try!(self.wr.write_str(self.formatter.indent.as_slice()));
+        }
+
+        Ok(())
+    }
+
     #[inline]
     fn serialize_sep(&mut self) -> IoResult<()> {
         if self.first {
             self.first = false;
-            self.indent += 2;
-            try!(self.wr.write_str("\n"));
+            self.level += 1;
         } else {
-            try!(self.wr.write_str(",\n"));
+            try!(self.wr.write_str(self.formatter.item_separator.as_slice()));
         }
 
-        spaces(&mut self.wr, self.indent)
+        self.serialize_newline()
+    }
+
+    #[inline]
+    fn serialize_key_sep(&mut self) -> IoResult<()> {
+        self.wr.write_str(self.formatter.key_separator.as_slice())
     }
 
     #[inline]
     fn serialize_end(&mut self, s: &str) -> IoResult<()> {
         if !self.first {
-            try!(self.wr.write_str("\n"));
-            self.indent -= 2;
-            try!(spaces(&mut self.wr, self.indent));
+            self.level -= 1;
+            try!(self.serialize_newline());
+        } else if !self.formatter.compact_empty {
+            try!(self.serialize_newline());
         }
 
         self.first = false;
@@ -442,12 +556,17 @@ impl<W: Writer> ser::Serializer<IoError> for PrettySerializer<W> {
 
     #[inline]
     fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
-        fmt_f32_or_null(&mut self.wr, v)
+        fmt_f32(&mut self.wr, v, self.non_finite)
     }
 
     #[inline]
     fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
-        fmt_f64_or_null(&mut self.wr, v)
+        fmt_f64(&mut self.wr, v, self.non_finite)
+    }
+
+    #[inline]
+    fn serialize_raw_number(&mut self, v: &str) -> IoResult<()> {
+        self.wr.write_str(v)
     }
 
     #[inline]
@@ -491,7 +610,7 @@ impl<W: Writer> ser::Serializer<IoError> for PrettySerializer<W> {
     >(&mut self, name: &str, value: &T) -> IoResult<()> {
         try!(self.serialize_sep());
         try!(self.serialize_str(name));
-        try!(self.wr.write_str(": "));
+        try!(self.serialize_key_sep());
         value.serialize(self)
     }
 
@@ -501,13 +620,20 @@ impl<W: Writer> ser::Serializer<IoError> for PrettySerializer<W> {
     }
 
     #[inline]
-    fn serialize_enum_start(&mut self, _name: &str, variant: &str, _len: uint) -> IoResult<()> {
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, len: uint) -> IoResult<()> {
+        // Unit variants are written as just their name.
+        if len == 0 {
+            self.unit_variant = true;
+            return self.serialize_str(variant);
+        }
+
         self.first = true;
         try!(self.wr.write_str("{"));
         try!(self.serialize_sep());
         try!(self.serialize_str(variant));
+        try!(self.serialize_key_sep());
         self.first = true;
-        self.wr.write_str(": [")
+        self.wr.write_str("[")
     }
 
     #[inline]
@@ -520,6 +646,11 @@ impl<W: Writer> ser::Serializer<IoError> for PrettySerializer<W> {
 
     #[inline]
     fn serialize_enum_end(&mut self) -> IoResult<()> {
+        if self.unit_variant {
+            self.unit_variant = false;
+            return Ok(());
+        }
+
         try!(self.serialize_tuple_end());
         self.serialize_struct_end()
     }
@@ -566,7 +697,7 @@ impl<W: Writer> ser::Serializer<IoError> for PrettySerializer<W> {
         for (key, value) in iter {
             try!(self.serialize_sep());
             try!(key.serialize(self));
-            try!(self.wr.write_str(": "));
+            try!(self.serialize_key_sep());
             try!(value.serialize(self));
         }
 
@@ -585,6 +716,19 @@ pub fn to_writer<
     Ok(serializer.unwrap())
 }
 
+/// Encode the elements of an iterator into a json array, one at a time,
+/// without first collecting them.
+#[inline]
+pub fn to_array_writer<
+    W: Writer,
+    T: Serialize<Serializer<W>, IoError>,
+    Iter: Iterator<Item=T>
+>(writer: W, iter: Iter) -> IoResult<W> {
+    let mut serializer = Serializer::new(writer);
+    try!(ser::Serializer::serialize_seq(&mut serializer, iter));
+    Ok(serializer.unwrap())
+}
+
 /// Encode the specified struct into a json `[u8]` buffer.
 #[inline]
 pub fn to_vec<
@@ -1,5 +1,8 @@
 use std::collections::{HashMap, BTreeMap, btree_map};
 use std::fmt;
+use std::i64;
+use std::mem;
+use std::ops;
 use std::io::{ByRefWriter, IoResult};
 use std::io;
 use std::str;
@@ -10,8 +13,8 @@ use de::{self, Token, TokenKind};
 use ser::Serialize;
 use ser;
 
-use super::ser::{Serializer, PrettySerializer};
-use super::error::{Error, ErrorCode};
+use super::ser::PrettySerializer;
+use super::error::{Error, ErrorCode, Location};
 
 /// Represents a JSON value
 #[derive(Clone, PartialEq, PartialOrd)]
@@ -20,6 +23,10 @@ pub enum Value {
     Boolean(bool),
     Integer(i64),
     Floating(f64),
+    // An integer too large to fit in an `i64`.
+    Unsigned(u64),
+    // A number kept as its original digits by `Parser::arbitrary_precision`.
+    RawNumber(String),
     String(String),
     Array(Vec<Value>),
     Object(BTreeMap<String, Value>),
@@ -28,7 +35,7 @@ pub enum Value {
 impl Value {
     /// Serializes a json value into an io::writer.  Uses a single line.
     pub fn to_writer<W: Writer>(&self, wr: W) -> IoResult<()> {
-        let mut serializer = Serializer::new(wr);
+        let mut serializer = super::ser::Serializer::new(wr);
         self.serialize(&mut serializer)
     }
 
@@ -55,6 +62,40 @@ impl Value {
         }
     }
 
+    /// If the Json value is an Object, returns a mutable reference to the value
+    /// associated with the provided key. Otherwise, returns None.
+    pub fn find_mut<'a>(&'a mut self, key: &String) -> Option<&'a mut Value> {
+        match *self {
+            Value::Object(ref mut map) => map.get_mut(key),
+            _ => None
+        }
+    }
+
+    /// Looks up a key in an Object or an index in an Array. Returns None if
+    /// the Json value is neither, or if the key or index does not exist.
+    pub fn get<'a, I: ValueIndex>(&'a self, index: I) -> Option<&'a Value> {
+        index.index_into(self)
+    }
+
+    /// Like `get`, but returns a mutable reference to the value.
+    pub fn get_mut<'a, I: ValueIndex>(&'a mut self, index: I) -> Option<&'a mut Value> {
+        index.index_into_mut(self)
+    }
+
+    /// If the Json value is an Object, returns the map entry for `key` so it
+    /// can be inspected and modified in place. Returns None otherwise.
+    pub fn entry<'a>(&'a mut self, key: String) -> Option<btree_map::Entry<'a, String, Value>> {
+        match *self {
+            Value::Object(ref mut map) => Some(map.entry(key)),
+            _ => None
+        }
+    }
+
+    /// Takes the value out, leaving a Null in its place.
+    pub fn take(&mut self) -> Value {
+        mem::replace(self, Value::Null)
+    }
+
     /// Attempts to get a nested Json Object for each key in `keys`.
     /// If any key is found not to exist, find_path will return None.
     /// Otherwise, it will return the Json value associated with the final key.
@@ -93,6 +134,138 @@ impl Value {
         }
     }
 
+    /// Looks up a value by a JSON Pointer (RFC 6901), such as `/a/0/b`. The
+    /// empty pointer refers to the whole value. Returns None if the pointer is
+    /// malformed or does not refer to an existing value.
+    pub fn pointer<'a>(&'a self, pointer: &str) -> Option<&'a Value> {
+        match parse_pointer(pointer) {
+            Some(tokens) => self.walk(tokens.as_slice()),
+            None => None,
+        }
+    }
+
+    /// Like `pointer`, but returns a mutable reference to the value.
+    pub fn pointer_mut<'a>(&'a mut self, pointer: &str) -> Option<&'a mut Value> {
+        match parse_pointer(pointer) {
+            Some(tokens) => self.walk_mut(tokens.as_slice()),
+            None => None,
+        }
+    }
+
+    /// Inserts `value` at a JSON Pointer, like the JSON Patch `add` operation.
+    /// The parent of the pointer must already exist. Inserting into an array
+    /// shifts the following elements along, and the index `-` appends to the
+    /// array. Returns the value that was replaced, if any.
+    pub fn insert_pointer(&mut self,
+                          pointer: &str,
+                          value: Value) -> Result<Option<Value>, PointerError> {
+        let mut tokens = match parse_pointer(pointer) {
+            Some(tokens) => tokens,
+            None => { return Err(PointerError::InvalidPointer); }
+        };
+
+        let last = match tokens.pop() {
+            Some(last) => last,
+            None => { return Ok(Some(mem::replace(self, value))); }
+        };
+
+        match self.walk_mut(tokens.as_slice()) {
+            Some(&mut Value::Object(ref mut map)) => Ok(map.insert(last, value)),
+            Some(&mut Value::Array(ref mut array)) => {
+                if last.as_slice() == "-" {
+                    array.push(value);
+                    return Ok(None);
+                }
+
+                match parse_index(last.as_slice()) {
+                    Some(index) if index <= array.len() => {
+                        array.insert(index, value);
+                        Ok(None)
+                    }
+                    _ => Err(PointerError::InvalidIndex),
+                }
+            }
+            _ => Err(PointerError::NotFound),
+        }
+    }
+
+    /// Removes and returns the value at a JSON Pointer, like the JSON Patch
+    /// `remove` operation. Removing the whole value leaves `Null` behind.
+    pub fn remove_pointer(&mut self, pointer: &str) -> Result<Value, PointerError> {
+        let mut tokens = match parse_pointer(pointer) {
+            Some(tokens) => tokens,
+            None => { return Err(PointerError::InvalidPointer); }
+        };
+
+        let last = match tokens.pop() {
+            Some(last) => last,
+            None => { return Ok(mem::replace(self, Value::Null)); }
+        };
+
+        match self.walk_mut(tokens.as_slice()) {
+            Some(&mut Value::Object(ref mut map)) => {
+                map.remove(&last).ok_or(PointerError::NotFound)
+            }
+            Some(&mut Value::Array(ref mut array)) => {
+                match parse_index(last.as_slice()) {
+                    Some(index) if index < array.len() => Ok(array.remove(index)),
+                    _ => Err(PointerError::InvalidIndex),
+                }
+            }
+            _ => Err(PointerError::NotFound),
+        }
+    }
+
+    fn walk<'a>(&'a self, tokens: &[String]) -> Option<&'a Value> {
+        let mut target = self;
+        for token in tokens.iter() {
+            target = match *target {
+                Value::Object(ref map) => {
+                    match map.get(token) {
+                        Some(value) => value,
+                        None => { return None; }
+                    }
+                }
+                Value::Array(ref array) => {
+                    match parse_index(token.as_slice()) {
+                        Some(index) if index < array.len() => &array[index],
+                        _ => { return None; }
+                    }
+                }
+                _ => { return None; }
+            };
+        }
+        Some(target)
+    }
+
+    fn walk_mut<'a>(&'a mut self, tokens: &[String]) -> Option<&'a mut Value> {
+        let mut target = self;
+        for token in tokens.iter() {
+            let current = target;
+            target = match *current {
+                Value::Object(ref mut map) => {
+                    match map.get_mut(token) {
+                        Some(value) => value,
+                        None => { return None; }
+                    }
+                }
+                Value::Array(ref mut array) => {
+                    match parse_index(token.as_slice()) {
+                        Some(index) => {
+                            match array.get_mut(index) {
+                                Some(value) => value,
+                                None => { return None; }
+                            }
+                        }
+                        None => { return None; }
+                    }
+                }
+                _ => { return None; }
+            };
+        }
+        Some(target)
+    }
+
     /// Returns true if the Json value is an Object. Returns false otherwise.
     pub fn is_object<'a>(&'a self) -> bool {
         self.as_object().is_some()
@@ -107,6 +280,15 @@ impl Value {
         }
     }
 
+    /// If the Json value is an Object, returns the associated mutable TreeMap.
+    /// Returns None otherwise.
+    pub fn as_object_mut<'a>(&'a mut self) -> Option<&'a mut BTreeMap<String, Value>> {
+        match *self {
+            Value::Object(ref mut map) => Some(map),
+            _ => None
+        }
+    }
+
     /// Returns true if the Json value is a Array. Returns false otherwise.
     pub fn is_array<'a>(&'a self) -> bool {
         self.as_array().is_some()
@@ -121,6 +303,15 @@ impl Value {
         }
     }
 
+    /// If the Json value is a Array, returns the associated mutable vector.
+    /// Returns None otherwise.
+    pub fn as_array_mut<'a>(&'a mut self) -> Option<&'a mut Vec<Value>> {
+        match *self {
+            Value::Array(ref mut array) => Some(array),
+            _ => None
+        }
+    }
+
     /// Returns true if the Json value is a String. Returns false otherwise.
     pub fn is_string<'a>(&'a self) -> bool {
         self.as_string().is_some()
@@ -135,10 +326,22 @@ impl Value {
         }
     }
 
-    /// Returns true if the Json value is a i64 or f64. Returns false otherwise.
+    /// If the Json value is a String, returns the associated mutable String.
+    /// Returns None otherwise.
+    pub fn as_string_mut<'a>(&'a mut self) -> Option<&'a mut String> {
+        match *self {
+            Value::String(ref mut s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Returns true if the Json value is a number. Returns false otherwise.
     pub fn is_number(&self) -> bool {
         match *self {
-            Value::Integer(_) | Value::Floating(_) => true,
+            Value::Integer(_)
+            | Value::Floating(_)
+            | Value::Unsigned(_)
+            | Value::RawNumber(_) => true,
             _ => false,
         }
     }
@@ -151,6 +354,15 @@ impl Value {
         }
     }
 
+    /// Returns true if the Json value is a u64. Returns false otherwise.
+    pub fn is_u64(&self) -> bool {
+        match *self {
+            Value::Integer(n) => n >= 0,
+            Value::Unsigned(_) => true,
+            _ => false,
+        }
+    }
+
     /// Returns true if the Json value is a f64. Returns false otherwise.
     pub fn is_f64(&self) -> bool {
         match *self {
@@ -165,6 +377,19 @@ impl Value {
         match *self {
             Value::Integer(n) => Some(n),
             Value::Floating(n) => Some(n as i64),
+            Value::RawNumber(ref n) => n.parse(),
+            _ => None
+        }
+    }
+
+    /// If the Json value is a u64, returns the associated u64.
+    /// Returns None otherwise.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Integer(n) if n >= 0 => Some(n as u64),
+            Value::Floating(n) if n >= 0.0 => Some(n as u64),
+            Value::Unsigned(n) => Some(n),
+            Value::RawNumber(ref n) => n.parse(),
             _ => None
         }
     }
@@ -175,6 +400,8 @@ impl Value {
         match *self {
             Value::Integer(n) => Some(n as f64),
             Value::Floating(n) => Some(n),
+            Value::Unsigned(n) => Some(n as f64),
+            Value::RawNumber(ref n) => n.parse(),
             _ => None
         }
     }
@@ -208,6 +435,182 @@ impl Value {
     }
 }
 
+/// The reasons a JSON Pointer could not be used to modify a `Value`.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum PointerError {
+    /// The pointer is not empty and does not start with `/`, or contains a
+    /// `~` that is not followed by `0` or `1`.
+    InvalidPointer,
+    /// The pointer's parent, or the object key being removed, does not exist.
+    NotFound,
+    /// An array index is not a number or is out of bounds.
+    InvalidIndex,
+}
+
+/// Escapes an object key so it can be used as one token of a JSON Pointer.
+pub fn escape_pointer_token(token: &str) -> String {
+    token.replace("~", "~0").replace("/", "~1")
+}
+
+/// Splits a JSON Pointer into its unescaped tokens. Returns None if the
+/// pointer is malformed.
+pub fn parse_pointer(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+
+    if !pointer.starts_with("/") {
+        return None;
+    }
+
+    let mut tokens = Vec::new();
+    for token in pointer.slice_from(1).split('/') {
+        match unescape_pointer_token(token) {
+            Some(token) => { tokens.push(token); }
+            None => { return None; }
+        }
+    }
+    Some(tokens)
+}
+
+fn unescape_pointer_token(token: &str) -> Option<String> {
+    let mut result = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    loop {
+        match chars.next() {
+            Some('~') => {
+                match chars.next() {
+                    Some('0') => { result.push('~'); }
+                    Some('1') => { result.push('/'); }
+                    _ => { return None; }
+                }
+            }
+            Some(ch) => { result.push(ch); }
+            None => { return Some(result); }
+        }
+    }
+}
+
+fn parse_index(token: &str) -> Option<uint> {
+    // RFC 6901 only allows plain decimal indices without leading zeros.
+    if token.len() > 1 && token.starts_with("0") {
+        return None;
+    }
+
+    if !token.chars().all(|ch| ch >= '0' && ch <= '9') {
+        return None;
+    }
+
+    token.parse()
+}
+
+/// A type that can be used to look up a value in a Json Object or Array, with
+/// `Value::get`, `Value::get_mut` or indexing.
+pub trait ValueIndex {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value>;
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value>;
+}
+
+impl ValueIndex for uint {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        match *value {
+            Value::Array(ref array) => array.get(*self),
+            _ => None
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        match *value {
+            Value::Array(ref mut array) => array.get_mut(*self),
+            _ => None
+        }
+    }
+}
+
+impl ValueIndex for str {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        match *value {
+            Value::Object(ref map) => map.get(self),
+            _ => None
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        match *value {
+            Value::Object(ref mut map) => map.get_mut(self),
+            _ => None
+        }
+    }
+}
+
+impl ValueIndex for String {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        self.as_slice().index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        self.as_slice().index_into_mut(value)
+    }
+}
+
+impl<'a, T: ?Sized + ValueIndex> ValueIndex for &'a T {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        (**self).index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        (**self).index_into_mut(value)
+    }
+}
+
+impl<'a> ops::Index<&'a str> for Value {
+    type Output = Value;
+
+    /// Returns the value associated with `key`. Panics if the Json value is
+    /// not an Object or does not contain the key.
+    fn index<'b>(&'b self, key: &&'a str) -> &'b Value {
+        match self.get(*key) {
+            Some(value) => value,
+            None => panic!("no key \"{}\" in Json value", key),
+        }
+    }
+}
+
+impl<'a> ops::IndexMut<&'a str> for Value {
+    type Output = Value;
+
+    fn index_mut<'b>(&'b mut self, key: &&'a str) -> &'b mut Value {
+        match self.get_mut(*key) {
+            Some(value) => value,
+            None => panic!("no key \"{}\" in Json value", key),
+        }
+    }
+}
+
+impl ops::Index<uint> for Value {
+    type Output = Value;
+
+    /// Returns the element at `index`. Panics if the Json value is not an
+    /// Array or the index is out of bounds.
+    fn index<'a>(&'a self, index: &uint) -> &'a Value {
+        match self.get(*index) {
+            Some(value) => value,
+            None => panic!("no index {} in Json value", index),
+        }
+    }
+}
+
+impl ops::IndexMut<uint> for Value {
+    type Output = Value;
+
+    fn index_mut<'a>(&'a mut self, index: &uint) -> &'a mut Value {
+        match self.get_mut(*index) {
+            Some(value) => value,
+            None => panic!("no index {} in Json value", index),
+        }
+    }
+}
+
 impl ToString for Value {
     fn to_string(&self) -> String {
         let mut wr = Vec::new();
@@ -250,6 +653,12 @@ impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for Value {
             Value::Floating(v) => {
                 v.serialize(s)
             }
+            Value::Unsigned(v) => {
+                v.serialize(s)
+            }
+            Value::RawNumber(ref v) => {
+                s.serialize_raw_number(v.as_slice())
+            }
             Value::String(ref v) => {
                 v.serialize(s)
             }
@@ -263,6 +672,15 @@ impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for Value {
     }
 }
 
+// Integers are only kept as an `Unsigned` when they don't fit in an `i64`.
+fn u64_to_value(x: u64) -> Value {
+    if x > i64::MAX as u64 {
+        Value::Unsigned(x)
+    } else {
+        Value::Integer(x as i64)
+    }
+}
+
 impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for Value {
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<Value, E> {
@@ -274,16 +692,20 @@ impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for Value {
             Token::I16(x) => Ok(Value::Integer(x as i64)),
             Token::I32(x) => Ok(Value::Integer(x as i64)),
             Token::I64(x) => Ok(Value::Integer(x)),
-            Token::Uint(x) => Ok(Value::Integer(x as i64)),
+            Token::Uint(x) => Ok(u64_to_value(x as u64)),
             Token::U8(x) => Ok(Value::Integer(x as i64)),
             Token::U16(x) => Ok(Value::Integer(x as i64)),
             Token::U32(x) => Ok(Value::Integer(x as i64)),
-            Token::U64(x) => Ok(Value::Integer(x as i64)),
+            Token::U64(x) => Ok(u64_to_value(x)),
             Token::F32(x) => Ok(Value::Floating(x as f64)),
             Token::F64(x) => Ok(Value::Floating(x)),
+            Token::RawNumber(x) => Ok(Value::RawNumber(x)),
             Token::Char(x) => Ok(Value::String(x.to_string())),
             Token::Str(x) => Ok(Value::String(x.to_string())),
             Token::String(x) => Ok(Value::String(x)),
+            Token::Bytes(x) => {
+                Ok(Value::Array(x.into_iter().map(|b| Value::Integer(b as i64)).collect()))
+            }
             Token::Option(false) => Ok(Value::Null),
             Token::Option(true) => de::Deserialize::deserialize(d),
             Token::TupleStart(_) | Token::SeqStart(_) => {
@@ -311,10 +733,234 @@ impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for Value {
     }
 }
 
+enum SerializerState {
+    Value(Value),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+    Variant(String, Vec<Value>),
+}
+
+/// Create a `Value` tree out of any `Serialize` type.
+pub struct Serializer {
+    state: Vec<SerializerState>,
+}
+
+impl Serializer {
+    /// Creates a new serializer instance.
+    pub fn new() -> Serializer {
+        Serializer {
+            state: Vec::with_capacity(4),
+        }
+    }
+
+    /// Returns the value that was serialized.
+    pub fn unwrap(mut self) -> Value {
+        match self.state.pop() {
+            Some(SerializerState::Value(value)) => value,
+            _ => panic!("expected a complete value"),
+        }
+    }
+
+    fn serialize_value<
+        T: ser::Serialize<Serializer, ()>
+    >(&mut self, value: &T) -> Value {
+        value.serialize(self).unwrap();
+        match self.state.pop() {
+            Some(SerializerState::Value(value)) => value,
+            _ => panic!("expected a complete value"),
+        }
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), ()> {
+        self.state.push(SerializerState::Value(value));
+        Ok(())
+    }
+}
+
+impl ser::Serializer<()> for Serializer {
+    #[inline]
+    fn serialize_null(&mut self) -> Result<(), ()> {
+        self.push(Value::Null)
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> Result<(), ()> {
+        self.push(Value::Boolean(v))
+    }
+
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> Result<(), ()> {
+        self.push(Value::Integer(v))
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> Result<(), ()> {
+        self.push(u64_to_value(v))
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> Result<(), ()> {
+        self.push(Value::Floating(v))
+    }
+
+    #[inline]
+    fn serialize_raw_number(&mut self, v: &str) -> Result<(), ()> {
+        self.push(Value::RawNumber(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> Result<(), ()> {
+        self.push(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> Result<(), ()> {
+        self.push(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, len: uint) -> Result<(), ()> {
+        self.state.push(SerializerState::Array(Vec::with_capacity(len)));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: ser::Serialize<Serializer, ()>
+    >(&mut self, v: &T) -> Result<(), ()> {
+        let value = self.serialize_value(v);
+        match self.state.last_mut() {
+            Some(&mut SerializerState::Array(ref mut array)) => { array.push(value); }
+            _ => panic!("expected an array"),
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> Result<(), ()> {
+        match self.state.pop() {
+            Some(SerializerState::Array(array)) => self.push(Value::Array(array)),
+            _ => panic!("expected an array"),
+        }
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, _len: uint) -> Result<(), ()> {
+        self.state.push(SerializerState::Object(BTreeMap::new()));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: ser::Serialize<Serializer, ()>
+    >(&mut self, name: &str, v: &T) -> Result<(), ()> {
+        let value = self.serialize_value(v);
+        match self.state.last_mut() {
+            Some(&mut SerializerState::Object(ref mut object)) => {
+                object.insert(name.to_string(), value);
+            }
+            _ => panic!("expected an object"),
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> Result<(), ()> {
+        match self.state.pop() {
+            Some(SerializerState::Object(object)) => self.push(Value::Object(object)),
+            _ => panic!("expected an object"),
+        }
+    }
+
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, len: uint) -> Result<(), ()> {
+        self.state.push(SerializerState::Variant(variant.to_string(), Vec::with_capacity(len)));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: ser::Serialize<Serializer, ()>
+    >(&mut self, v: &T) -> Result<(), ()> {
+        let value = self.serialize_value(v);
+        match self.state.last_mut() {
+            Some(&mut SerializerState::Variant(_, ref mut fields)) => { fields.push(value); }
+            _ => panic!("expected an enum variant"),
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> Result<(), ()> {
+        match self.state.pop() {
+            // Unit variants are written as just their name, like the JSON
+            // serializer does.
+            Some(SerializerState::Variant(variant, fields)) => {
+                if fields.is_empty() {
+                    self.push(Value::String(variant))
+                } else {
+                    let mut object = BTreeMap::new();
+                    object.insert(variant, Value::Array(fields));
+                    self.push(Value::Object(object))
+                }
+            }
+            _ => panic!("expected an enum variant"),
+        }
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: ser::Serialize<Serializer, ()>
+    >(&mut self, v: &Option<T>) -> Result<(), ()> {
+        match *v {
+            Some(ref v) => v.serialize(self),
+            None => self.serialize_null(),
+        }
+    }
+
+    #[inline]
+    fn serialize_seq<
+        T: ser::Serialize<Serializer, ()>,
+        Iter: Iterator<Item=T>
+    >(&mut self, iter: Iter) -> Result<(), ()> {
+        let array: Vec<Value> = iter.map(|elt| self.serialize_value(&elt)).collect();
+        self.push(Value::Array(array))
+    }
+
+    #[inline]
+    fn serialize_map<
+        K: ser::Serialize<Serializer, ()>,
+        V: ser::Serialize<Serializer, ()>,
+        Iter: Iterator<Item=(K, V)>
+    >(&mut self, iter: Iter) -> Result<(), ()> {
+        let mut object = BTreeMap::new();
+        for (key, value) in iter {
+            // JSON object keys must be strings, so other keys are written out
+            // as their JSON text.
+            let key = match self.serialize_value(&key) {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            let value = self.serialize_value(&value);
+            object.insert(key, value);
+        }
+        self.push(Value::Object(object))
+    }
+}
+
+/// Serializes any `Serialize` type into a `Value`. This makes it easy to
+/// implement `ToJson` for a type that already implements `Serialize`.
+pub fn to_value<T: ser::Serialize<Serializer, ()>>(value: &T) -> Value {
+    let mut serializer = Serializer::new();
+    serializer.serialize_value(value)
+}
+
 enum State {
     Value(Value),
-    Array(vec::IntoIter<Value>),
-    Object(btree_map::IntoIter<String, Value>),
+    // The elements left, and how many have been taken so far.
+    Array(vec::IntoIter<Value>, uint),
+    // The members left, and the key of the last one taken.
+    Object(btree_map::IntoIter<String, Value>, Option<String>),
     End,
 }
 
@@ -329,6 +975,24 @@ impl Deserializer {
             stack: vec!(State::Value(json)),
         }
     }
+
+    fn error(&self, code: ErrorCode) -> Error {
+        Error::SyntaxError(code, 0, 0, self.location())
+    }
+
+    fn location(&self) -> Location {
+        let mut location = Location::new(0);
+
+        for state in self.stack.iter() {
+            match *state {
+                State::Array(_, index) if index > 0 => location.push_index(index - 1),
+                State::Object(_, Some(ref key)) => location.push_key(key.as_slice()),
+                _ => { }
+            }
+        }
+
+        location
+    }
 }
 
 impl Iterator for Deserializer {
@@ -344,25 +1008,27 @@ impl Iterator for Deserializer {
                         Value::Boolean(x) => Token::Bool(x),
                         Value::Integer(x) => Token::I64(x),
                         Value::Floating(x) => Token::F64(x),
+                        Value::Unsigned(x) => Token::U64(x),
+                        Value::RawNumber(x) => Token::RawNumber(x),
                         Value::String(x) => Token::String(x),
                         Value::Array(x) => {
                             let len = x.len();
-                            self.stack.push(State::Array(x.into_iter()));
+                            self.stack.push(State::Array(x.into_iter(), 0));
                             Token::SeqStart(len)
                         }
                         Value::Object(x) => {
                             let len = x.len();
-                            self.stack.push(State::Object(x.into_iter()));
+                            self.stack.push(State::Object(x.into_iter(), None));
                             Token::MapStart(len)
                         }
                     };
 
                     return Some(Ok(token));
                 }
-                Some(State::Array(mut iter)) => {
+                Some(State::Array(mut iter, index)) => {
                     match iter.next() {
                         Some(value) => {
-                            self.stack.push(State::Array(iter));
+                            self.stack.push(State::Array(iter, index + 1));
                             self.stack.push(State::Value(value));
                             // loop around.
                         }
@@ -371,10 +1037,10 @@ impl Iterator for Deserializer {
                         }
                     }
                 }
-                Some(State::Object(mut iter)) => {
+                Some(State::Object(mut iter, _)) => {
                     match iter.next() {
                         Some((key, value)) => {
-                            self.stack.push(State::Object(iter));
+                            self.stack.push(State::Object(iter, Some(key.clone())));
                             self.stack.push(State::Value(value));
                             return Some(Ok(Token::String(key)));
                         }
@@ -394,21 +1060,25 @@ impl Iterator for Deserializer {
 
 impl de::Deserializer<Error> for Deserializer {
     fn end_of_stream_error(&mut self) -> Error {
-        Error::SyntaxError(ErrorCode::EOFWhileParsingValue, 0, 0)
+        self.error(ErrorCode::EOFWhileParsingValue)
     }
 
     fn syntax_error(&mut self,
                     token: Token,
                     expected: &'static [TokenKind]) -> Error {
-        Error::SyntaxError(ErrorCode::ExpectedTokens(token, expected), 0, 0)
+        self.error(ErrorCode::ExpectedTokens(token, expected))
     }
 
     fn unexpected_name_error(&mut self, token: Token) -> Error {
-        Error::SyntaxError(ErrorCode::UnexpectedName(token), 0, 0)
+        self.error(ErrorCode::UnexpectedName(token))
     }
 
     fn conversion_error(&mut self, token: Token) -> Error {
-        Error::SyntaxError(ErrorCode::ConversionError(token), 0, 0)
+        self.error(ErrorCode::ConversionError(token))
+    }
+
+    fn unknown_field_error(&mut self, field: &str) -> Error {
+        self.error(ErrorCode::UnknownField(field.to_string()))
     }
 
     #[inline]
@@ -420,6 +1090,13 @@ impl de::Deserializer<Error> for Deserializer {
         de::Deserialize::deserialize_token(self, Token::Null)
     }
 
+    #[inline]
+    fn missing_struct_field<
+        T: de::Deserialize<Deserializer, Error>
+    >(&mut self, name: &'static str, field: &'static str) -> Result<T, Error> {
+        Err(self.error(ErrorCode::MissingField(field, name)))
+    }
+
     // Special case treating options as a nullable value.
     #[inline]
     fn expect_option<
@@ -441,6 +1118,11 @@ impl de::Deserializer<Error> for Deserializer {
                          _name: &str,
                          variants: &[&str]) -> Result<uint, Error> {
         let variant = match token {
+            Token::String(variant) => {
+                // Unit variants have no fields, so there is just the end left.
+                self.stack.push(State::End);
+                variant
+            }
             Token::MapStart(_) => {
                 let state = match self.stack.pop() {
                     Some(state) => state,
@@ -448,36 +1130,20 @@ impl de::Deserializer<Error> for Deserializer {
                 };
 
                 let mut iter = match state {
-                    State::Object(iter) => iter,
+                    State::Object(iter, _) => iter,
                     _ => { panic!("state machine error, expected an object"); }
                 };
 
+                // Enums only have one field in them, which is the variant
+                // name, holding a list of the values.
                 let (variant, fields) = match iter.next() {
                     Some((variant, Value::Array(fields))) => (variant, fields),
-                    Some((key, value)) => {
-                        return Err(
-                            Error::ExpectedError(
-                                "Array".to_string(),
-                                format!("{:?} => {:?}", key, value)
-                            )
-                        );
-                    }
-                    None => {
-                        return Err(Error::MissingFieldError("<variant-name>".to_string()));
-                    }
+                    Some(_) => { return Err(self.error(ErrorCode::ExpectedEnumToken)); }
+                    None => { return Err(self.error(ErrorCode::ExpectedEnumVariantString)); }
                 };
 
-                // Error out if there are other fields in the enum.
-                match iter.next() {
-                    Some((key, value)) => {
-                        return Err(
-                            Error::ExpectedError(
-                                "None".to_string(),
-                                format!("{:?} => {:?}", key, value)
-                            )
-                        );
-                    }
-                    None => { }
+                if iter.next().is_some() {
+                    return Err(self.error(ErrorCode::ExpectedEnumEndToken));
                 }
 
                 self.stack.push(State::End);
@@ -488,19 +1154,12 @@ impl de::Deserializer<Error> for Deserializer {
 
                 variant
             }
-            token => {
-                return Err(
-                    Error::ExpectedError(
-                        "String or Object".to_string(),
-                        format!("{:?}", token)
-                    )
-                );
-            }
+            _ => { return Err(self.error(ErrorCode::ExpectedEnumMapStart)); }
         };
 
         match variants.iter().position(|v| *v == variant.as_slice()) {
             Some(idx) => Ok(idx),
-            None => Err(Error::UnknownVariantError(variant)),
+            None => Err(self.error(ErrorCode::UnknownVariant(variant))),
         }
     }
 
@@ -518,6 +1177,10 @@ impl de::Deserializer<Error> for Deserializer {
     }
 }
 
+// A `Value` owns its strings, so they can't be borrowed for any longer than
+// the deserializer lives.
+impl<'a> de::BorrowDeserializer<'a, Error> for Deserializer { }
+
 /// Decodes a json value from a `Value`.
 pub fn from_json<
     T: de::Deserialize<Deserializer, Error>
@@ -557,7 +1220,7 @@ impl ToJson for i64 {
 }
 
 impl ToJson for uint {
-    fn to_json(&self) -> Value { Value::Integer(*self as i64) }
+    fn to_json(&self) -> Value { u64_to_value(*self as u64) }
 }
 
 impl ToJson for u8 {
@@ -573,7 +1236,7 @@ impl ToJson for u32 {
 }
 
 impl ToJson for u64 {
-    fn to_json(&self) -> Value { Value::Integer(*self as i64) }
+    fn to_json(&self) -> Value { u64_to_value(*self) }
 }
 
 impl ToJson for f32 {
@@ -22,6 +22,13 @@ pub use ser::{Serializer, Serialize};
 pub mod de;
 pub mod ser;
 pub mod json;
+pub mod bin;
+pub mod bytes;
+pub mod msgpack;
+pub mod cbor;
+pub mod toml;
+
+mod binary;
 
 // an inner module so we can use serde_macros.
 mod serde {
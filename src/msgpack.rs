@@ -0,0 +1,1218 @@
+//! MessagePack serialization.
+//!
+//! Strings are written as `str`, byte buffers wrapped in `bytes::Bytes` or
+//! `bytes::ByteBuf` as `bin`, and an `Ext` as an extension type. `None` is
+//! written as `nil` and `Some(v)` as just `v`.
+//!
+//! Structs are written as maps from their field names to their values by
+//! default, which any reader can make sense of, or as arrays of their values
+//! in order with `StructEncoding::Array`, which is more compact. Either one
+//! can be read back, but structs that leave out fields with
+//! `skip_serializing_if` or `skip_serializing_none` fail to serialize as
+//! arrays, since their values would end up in the wrong fields. Enums are written the same way as in JSON: a unit
+//! variant as its name, and any other variant as a map from its name to an
+//! array of its fields.
+
+use std::cmp;
+use std::error;
+use std::io::{self, IoError, IoResult};
+use std::mem;
+use std::{u8, u16, u32, i8, i16, i32};
+
+use bytes::ByteBuf;
+use binary::{self, MAX_PREALLOCATION};
+use de::{self, Deserialize, Token, TokenKind};
+use ser::{self, Serialize};
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, PartialEq, Show)]
+pub enum Error {
+    IoError(IoError),
+    /// The input ended in the middle of a value.
+    EndOfStream,
+    /// A marker byte that MessagePack doesn't use.
+    InvalidMarker(u8),
+    /// A string wasn't valid UTF-8.
+    InvalidUtf8,
+    /// There was more input after the value.
+    TrailingBytes,
+    /// A variant with fields was written as just its name.
+    MissingVariantFields,
+    SyntaxError(Token, &'static [TokenKind]),
+    UnexpectedName(Token),
+    ConversionError(Token),
+    /// field, struct
+    MissingField(&'static str, &'static str),
+    UnknownField(String),
+    UnknownVariant(String),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::IoError(ref error) => error.description(),
+            Error::EndOfStream => "end of stream",
+            Error::InvalidMarker(_) => "invalid marker",
+            Error::InvalidUtf8 => "string is not utf-8",
+            Error::TrailingBytes => "trailing bytes",
+            Error::MissingVariantFields => "missing variant fields",
+            Error::SyntaxError(..) => "syntax error",
+            Error::UnexpectedName(_) => "unexpected name",
+            Error::ConversionError(_) => "conversion error",
+            Error::MissingField(..) => "missing field",
+            Error::UnknownField(_) => "unknown field",
+            Error::UnknownVariant(_) => "unknown variant",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match *self {
+            Error::IoError(ref error) => error.detail(),
+            Error::InvalidMarker(v) => Some(format!("invalid marker 0x{:x}", v)),
+            Error::SyntaxError(ref token, tokens) => {
+                Some(format!("expected {:?}, found {:?}", tokens, token))
+            }
+            Error::UnexpectedName(ref token) => Some(format!("unexpected name {:?}", token)),
+            Error::ConversionError(ref token) => Some(format!("failed to convert {:?}", token)),
+            Error::MissingField(field, name) => {
+                Some(format!("missing field `{}` in struct `{}`", field, name))
+            }
+            Error::UnknownField(ref field) => Some(format!("unknown field {:?}", field)),
+            Error::UnknownVariant(ref variant) => Some(format!("unknown variant {:?}", variant)),
+            _ => None,
+        }
+    }
+}
+
+impl error::FromError<IoError> for Error {
+    fn from_error(error: IoError) -> Error {
+        if error.kind == io::EndOfFile {
+            Error::EndOfStream
+        } else {
+            Error::IoError(error)
+        }
+    }
+}
+
+impl binary::EnumError for Error {
+    fn unknown_variant(variant: String) -> Error {
+        Error::UnknownVariant(variant)
+    }
+
+    fn missing_variant_fields() -> Error {
+        Error::MissingVariantFields
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A MessagePack extension type, which is application specific data tagged
+/// with a type number. Negative types are reserved by MessagePack itself.
+#[derive(Clone, PartialEq, Show)]
+pub struct Ext {
+    pub typ: i8,
+    pub data: Vec<u8>,
+}
+
+impl<S: ser::Serializer<E>, E> Serialize<S, E> for Ext {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        s.serialize_ext(self.typ, self.data.as_slice())
+    }
+}
+
+// Extension types are read as a `(typ, data)` tuple, which is how formats
+// other than MessagePack write them.
+impl<D: de::Deserializer<E>, E> Deserialize<D, E> for Ext {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<Ext, E> {
+        let (typ, data): (i8, ByteBuf) = try!(Deserialize::deserialize(d));
+        Ok(Ext {
+            typ: typ,
+            data: data.unwrap(),
+        })
+    }
+
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Ext, E> {
+        let (typ, data): (i8, ByteBuf) = try!(Deserialize::deserialize_token(d, token));
+        Ok(Ext {
+            typ: typ,
+            data: data.unwrap(),
+        })
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// How structs are written.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum StructEncoding {
+    /// A map from field names to values.
+    Map,
+    /// An array of the values in the order the fields are declared.
+    Array,
+}
+
+/// The markers of a family of length prefixed types, from the shortest to
+/// the longest.
+struct LenMarkers {
+    // A marker that holds the length itself, and the longest it can hold.
+    fix: Option<(u8, uint)>,
+    len8: Option<u8>,
+    len16: u8,
+    len32: u8,
+}
+
+static STR_MARKERS: LenMarkers = LenMarkers {
+    fix: Some((0xa0, 31)),
+    len8: Some(0xd9),
+    len16: 0xda,
+    len32: 0xdb,
+};
+
+static BIN_MARKERS: LenMarkers = LenMarkers {
+    fix: None,
+    len8: Some(0xc4),
+    len16: 0xc5,
+    len32: 0xc6,
+};
+
+static EXT_MARKERS: LenMarkers = LenMarkers {
+    fix: None,
+    len8: Some(0xc7),
+    len16: 0xc8,
+    len32: 0xc9,
+};
+
+static ARRAY_MARKERS: LenMarkers = LenMarkers {
+    fix: Some((0x90, 15)),
+    len8: None,
+    len16: 0xdc,
+    len32: 0xdd,
+};
+
+static MAP_MARKERS: LenMarkers = LenMarkers {
+    fix: Some((0x80, 15)),
+    len8: None,
+    len16: 0xde,
+    len32: 0xdf,
+};
+
+/// A structure for serializing values into MessagePack.
+pub struct Serializer<W> {
+    wr: W,
+    struct_encoding: StructEncoding,
+    // Sequences and maps whose length isn't known up front are written here
+    // first, so that their length can go before them.
+    buffers: Vec<Vec<u8>>,
+}
+
+impl<W: Writer> Serializer<W> {
+    /// Creates a new MessagePack serializer whose output will be written to
+    /// the writer specified.
+    #[inline]
+    pub fn new(wr: W) -> Serializer<W> {
+        Serializer {
+            wr: wr,
+            struct_encoding: StructEncoding::Map,
+            buffers: Vec::new(),
+        }
+    }
+
+    /// Sets how structs are written. They are written as maps by default.
+    #[inline]
+    pub fn struct_encoding(mut self, struct_encoding: StructEncoding) -> Serializer<W> {
+        self.struct_encoding = struct_encoding;
+        self
+    }
+
+    /// Unwrap the Writer from the Serializer.
+    #[inline]
+    pub fn unwrap(self) -> W {
+        self.wr
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> IoResult<()> {
+        match self.buffers.last_mut() {
+            Some(buf) => {
+                buf.push_all(bytes);
+                Ok(())
+            }
+            None => self.wr.write(bytes),
+        }
+    }
+
+    /// Writes `marker` followed by the low `size` bytes of `v`, big endian.
+    fn write_marker(&mut self, marker: u8, v: u64, size: uint) -> IoResult<()> {
+        let mut buf = [0u8; 9];
+        buf[0] = marker;
+
+        for i in range(0, size) {
+            buf[i + 1] = (v >> ((size - i - 1) * 8)) as u8;
+        }
+
+        self.write_bytes(buf.slice_to(size + 1))
+    }
+
+    fn write_len(&mut self, len: uint, markers: &LenMarkers) -> IoResult<()> {
+        match markers.fix {
+            Some((marker, max)) if len <= max => {
+                return self.write_bytes(&[marker | len as u8]);
+            }
+            _ => { }
+        }
+
+        match markers.len8 {
+            Some(marker) if len <= u8::MAX as uint => {
+                return self.write_marker(marker, len as u64, 1);
+            }
+            _ => { }
+        }
+
+        if len <= u16::MAX as uint {
+            self.write_marker(markers.len16, len as u64, 2)
+        } else if len as u64 <= u32::MAX as u64 {
+            self.write_marker(markers.len32, len as u64, 4)
+        } else {
+            Err(IoError {
+                kind: io::InvalidInput,
+                desc: "length is too long for MessagePack",
+                detail: Some(format!("{}", len)),
+            })
+        }
+    }
+}
+
+impl<W: Writer> ser::Serializer<IoError> for Serializer<W> {
+    #[inline]
+    fn serialize_null(&mut self) -> IoResult<()> {
+        self.write_bytes(&[0xc0])
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> IoResult<()> {
+        self.write_bytes(&[if v { 0xc3 } else { 0xc2 }])
+    }
+
+    // Integers are written in the fewest bytes that hold them.
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> IoResult<()> {
+        if v >= 0 {
+            self.serialize_u64(v as u64)
+        } else if v >= -32 {
+            self.write_bytes(&[v as u8])
+        } else if v >= i8::MIN as i64 {
+            self.write_marker(0xd0, v as u64, 1)
+        } else if v >= i16::MIN as i64 {
+            self.write_marker(0xd1, v as u64, 2)
+        } else if v >= i32::MIN as i64 {
+            self.write_marker(0xd2, v as u64, 4)
+        } else {
+            self.write_marker(0xd3, v as u64, 8)
+        }
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> IoResult<()> {
+        if v < 0x80 {
+            self.write_bytes(&[v as u8])
+        } else if v <= u8::MAX as u64 {
+            self.write_marker(0xcc, v, 1)
+        } else if v <= u16::MAX as u64 {
+            self.write_marker(0xcd, v, 2)
+        } else if v <= u32::MAX as u64 {
+            self.write_marker(0xce, v, 4)
+        } else {
+            self.write_marker(0xcf, v, 8)
+        }
+    }
+
+    #[inline]
+    fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
+        let bits: u32 = unsafe { mem::transmute(v) };
+        self.write_marker(0xca, bits as u64, 4)
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
+        let bits: u64 = unsafe { mem::transmute(v) };
+        self.write_marker(0xcb, bits, 8)
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> IoResult<()> {
+        self.serialize_str(v.to_string().as_slice())
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> IoResult<()> {
+        try!(self.write_len(v.len(), &STR_MARKERS));
+        self.write_bytes(v.as_bytes())
+    }
+
+    #[inline]
+    fn serialize_bytes(&mut self, v: &[u8]) -> IoResult<()> {
+        try!(self.write_len(v.len(), &BIN_MARKERS));
+        self.write_bytes(v)
+    }
+
+    #[inline]
+    fn serialize_ext(&mut self, typ: i8, data: &[u8]) -> IoResult<()> {
+        let fixext = match data.len() {
+            1 => Some(0xd4),
+            2 => Some(0xd5),
+            4 => Some(0xd6),
+            8 => Some(0xd7),
+            16 => Some(0xd8),
+            _ => None,
+        };
+
+        match fixext {
+            Some(marker) => {
+                try!(self.write_bytes(&[marker, typ as u8]));
+            }
+            None => {
+                try!(self.write_len(data.len(), &EXT_MARKERS));
+                try!(self.write_bytes(&[typ as u8]));
+            }
+        }
+
+        self.write_bytes(data)
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, len: uint) -> IoResult<()> {
+        self.write_len(len, &ARRAY_MARKERS)
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, len: uint) -> IoResult<()> {
+        match self.struct_encoding {
+            StructEncoding::Map => self.write_len(len, &MAP_MARKERS),
+            StructEncoding::Array => self.write_len(len, &ARRAY_MARKERS),
+        }
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, name: &str, value: &T) -> IoResult<()> {
+        if self.struct_encoding == StructEncoding::Map {
+            try!(ser::Serializer::serialize_str(self, name));
+        }
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_struct_skipped_elt(&mut self, _name: &str) -> IoResult<()> {
+        match self.struct_encoding {
+            StructEncoding::Map => Ok(()),
+            StructEncoding::Array => {
+                Err(IoError {
+                    kind: io::InvalidInput,
+                    desc: "can't skip a field of a struct written as an array",
+                    detail: None,
+                })
+            }
+        }
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, len: uint) -> IoResult<()> {
+        // Unit variants are written as just their name.
+        if len == 0 {
+            return ser::Serializer::serialize_str(self, variant);
+        }
+
+        try!(self.write_len(1, &MAP_MARKERS));
+        try!(ser::Serializer::serialize_str(self, variant));
+        self.write_len(len, &ARRAY_MARKERS)
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: Serialize<Serializer<W>, IoError>
+    >(&mut self, v: &Option<T>) -> IoResult<()> {
+        match *v {
+            Some(ref v) => v.serialize(self),
+            None => ser::Serializer::serialize_null(self),
+        }
+    }
+
+    #[inline]
+    fn serialize_seq<
+        T: Serialize<Serializer<W>, IoError>,
+        Iter: Iterator<Item=T>
+    >(&mut self, mut iter: Iter) -> IoResult<()> {
+        match iter.size_hint() {
+            (lo, Some(hi)) if lo == hi => {
+                try!(self.write_len(lo, &ARRAY_MARKERS));
+                for elt in iter {
+                    try!(elt.serialize(self));
+                }
+                Ok(())
+            }
+            _ => {
+                self.buffers.push(Vec::new());
+                let mut len = 0;
+                for elt in iter {
+                    try!(elt.serialize(self));
+                    len += 1;
+                }
+                let buf = self.buffers.pop().unwrap();
+
+                try!(self.write_len(len, &ARRAY_MARKERS));
+                self.write_bytes(buf.as_slice())
+            }
+        }
+    }
+
+    #[inline]
+    fn serialize_map<
+        K: Serialize<Serializer<W>, IoError>,
+        V: Serialize<Serializer<W>, IoError>,
+        Iter: Iterator<Item=(K, V)>
+    >(&mut self, mut iter: Iter) -> IoResult<()> {
+        match iter.size_hint() {
+            (lo, Some(hi)) if lo == hi => {
+                try!(self.write_len(lo, &MAP_MARKERS));
+                for (key, value) in iter {
+                    try!(key.serialize(self));
+                    try!(value.serialize(self));
+                }
+                Ok(())
+            }
+            _ => {
+                self.buffers.push(Vec::new());
+                let mut len = 0;
+                for (key, value) in iter {
+                    try!(key.serialize(self));
+                    try!(value.serialize(self));
+                    len += 1;
+                }
+                let buf = self.buffers.pop().unwrap();
+
+                try!(self.write_len(len, &MAP_MARKERS));
+                self.write_bytes(buf.as_slice())
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+static STR_TOKEN_KINDS: &'static [TokenKind] = &[
+    TokenKind::StrKind,
+    TokenKind::StringKind,
+];
+
+/// A structure that deserializes MessagePack into Rust values.
+pub struct Deserializer<R> {
+    rdr: R,
+    // The number of values left in each array and map being read. Map keys
+    // and values are counted separately.
+    remaining: Vec<uint>,
+    // Tokens that were read ahead, in reverse order.
+    pending: Vec<Token>,
+    // Set once the top level value has been started.
+    started: bool,
+    // Set while reading a unit variant written as a bare string.
+    unit_variant: bool,
+    // For each struct being read, the index of its next field if it was
+    // written as an array, or `None` if it was written as a map.
+    structs: Vec<Option<uint>>,
+}
+
+impl<R: Reader> Iterator for Deserializer<R> {
+    type Item = Result<Token, Error>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<Token, Error>> {
+        match self.pending.pop() {
+            Some(token) => { return Some(Ok(token)); }
+            None => { }
+        }
+
+        match self.remaining.last().map(|len| *len) {
+            Some(0) => {
+                self.remaining.pop();
+                return Some(Ok(Token::End));
+            }
+            Some(len) => {
+                let i = self.remaining.len() - 1;
+                self.remaining[i] = len - 1;
+            }
+            None if self.started => {
+                return None;
+            }
+            None => {
+                self.started = true;
+            }
+        }
+
+        Some(self.parse_value())
+    }
+}
+
+impl<R: Reader> Deserializer<R> {
+    /// Creates the MessagePack deserializer.
+    #[inline]
+    pub fn new(rdr: R) -> Deserializer<R> {
+        Deserializer {
+            rdr: rdr,
+            remaining: Vec::new(),
+            pending: Vec::new(),
+            started: false,
+            unit_variant: false,
+            structs: Vec::new(),
+        }
+    }
+
+    /// Unwrap the Reader from the Deserializer.
+    #[inline]
+    pub fn unwrap(self) -> R {
+        self.rdr
+    }
+
+    /// Makes sure there is no input left after the value.
+    #[inline]
+    pub fn end(&mut self) -> Result<(), Error> {
+        if try!(binary::at_end(&mut self.rdr)) {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes)
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Token, Error> {
+        let marker = try!(self.rdr.read_u8());
+
+        match marker {
+            0x00 ... 0x7f => Ok(Token::U8(marker)),
+            0x80 ... 0x8f => Ok(self.parse_map((marker & 0x0f) as uint)),
+            0x90 ... 0x9f => Ok(self.parse_array((marker & 0x0f) as uint)),
+            0xa0 ... 0xbf => self.parse_str((marker & 0x1f) as uint),
+            0xc0 => Ok(Token::Null),
+            0xc2 => Ok(Token::Bool(false)),
+            0xc3 => Ok(Token::Bool(true)),
+            0xc4 => {
+                let len = try!(self.read_len(1));
+                self.parse_bin(len)
+            }
+            0xc5 => {
+                let len = try!(self.read_len(2));
+                self.parse_bin(len)
+            }
+            0xc6 => {
+                let len = try!(self.read_len(4));
+                self.parse_bin(len)
+            }
+            0xc7 => {
+                let len = try!(self.read_len(1));
+                self.parse_ext(len)
+            }
+            0xc8 => {
+                let len = try!(self.read_len(2));
+                self.parse_ext(len)
+            }
+            0xc9 => {
+                let len = try!(self.read_len(4));
+                self.parse_ext(len)
+            }
+            0xca => Ok(Token::F32(try!(self.rdr.read_be_f32()))),
+            0xcb => Ok(Token::F64(try!(self.rdr.read_be_f64()))),
+            0xcc => Ok(Token::U8(try!(self.rdr.read_u8()))),
+            0xcd => Ok(Token::U16(try!(self.rdr.read_be_u16()))),
+            0xce => Ok(Token::U32(try!(self.rdr.read_be_u32()))),
+            0xcf => Ok(Token::U64(try!(self.rdr.read_be_u64()))),
+            0xd0 => Ok(Token::I8(try!(self.rdr.read_i8()))),
+            0xd1 => Ok(Token::I16(try!(self.rdr.read_be_i16()))),
+            0xd2 => Ok(Token::I32(try!(self.rdr.read_be_i32()))),
+            0xd3 => Ok(Token::I64(try!(self.rdr.read_be_i64()))),
+            0xd4 => self.parse_ext(1),
+            0xd5 => self.parse_ext(2),
+            0xd6 => self.parse_ext(4),
+            0xd7 => self.parse_ext(8),
+            0xd8 => self.parse_ext(16),
+            0xd9 => {
+                let len = try!(self.read_len(1));
+                self.parse_str(len)
+            }
+            0xda => {
+                let len = try!(self.read_len(2));
+                self.parse_str(len)
+            }
+            0xdb => {
+                let len = try!(self.read_len(4));
+                self.parse_str(len)
+            }
+            0xdc => {
+                let len = try!(self.read_len(2));
+                Ok(self.parse_array(len))
+            }
+            0xdd => {
+                let len = try!(self.read_len(4));
+                Ok(self.parse_array(len))
+            }
+            0xde => {
+                let len = try!(self.read_len(2));
+                Ok(self.parse_map(len))
+            }
+            0xdf => {
+                let len = try!(self.read_len(4));
+                Ok(self.parse_map(len))
+            }
+            0xe0 ... 0xff => Ok(Token::I8(marker as i8)),
+            _ => Err(Error::InvalidMarker(marker)),
+        }
+    }
+
+    fn read_len(&mut self, size: uint) -> Result<uint, Error> {
+        match size {
+            1 => Ok(try!(self.rdr.read_u8()) as uint),
+            2 => Ok(try!(self.rdr.read_be_u16()) as uint),
+            _ => Ok(try!(self.rdr.read_be_u32()) as uint),
+        }
+    }
+
+    // The lengths in the tokens are only used to reserve space, so they are
+    // capped like `read_bytes`.
+    fn parse_array(&mut self, len: uint) -> Token {
+        self.remaining.push(len);
+        Token::SeqStart(cmp::min(len, MAX_PREALLOCATION))
+    }
+
+    fn parse_map(&mut self, len: uint) -> Token {
+        self.remaining.push(len * 2);
+        Token::MapStart(cmp::min(len, MAX_PREALLOCATION))
+    }
+
+    fn parse_str(&mut self, len: uint) -> Result<Token, Error> {
+        let bytes = try!(binary::read_bytes(&mut self.rdr, len));
+        match String::from_utf8(bytes) {
+            Ok(v) => Ok(Token::String(v)),
+            Err(_) => Err(Error::InvalidUtf8),
+        }
+    }
+
+    fn parse_bin(&mut self, len: uint) -> Result<Token, Error> {
+        Ok(Token::Bytes(try!(binary::read_bytes(&mut self.rdr, len))))
+    }
+
+    fn parse_ext(&mut self, len: uint) -> Result<Token, Error> {
+        let typ = try!(self.rdr.read_i8());
+        let data = try!(binary::read_bytes(&mut self.rdr, len));
+
+        self.pending.push(Token::End);
+        self.pending.push(Token::Bytes(data));
+        self.pending.push(Token::I8(typ));
+
+        Ok(Token::TupleStart(2))
+    }
+
+    /// Returns the index of the next field of the struct being read, or the
+    /// name of a field that isn't in `fields`, or `None` at the end of the
+    /// struct.
+    fn next_struct_field(&mut self,
+                         fields: &'static [&'static str]
+                        ) -> Result<Option<Result<uint, String>>, Error> {
+        match self.structs.last().map(|s| *s) {
+            Some(Some(idx)) => {
+                // A struct written as an array ends with its values.
+                if self.remaining.last() == Some(&0) {
+                    try!(de::Deserializer::expect_token(self));
+                    self.structs.pop();
+                    return Ok(None);
+                }
+
+                let i = self.structs.len() - 1;
+                self.structs[i] = Some(idx + 1);
+
+                if idx < fields.len() {
+                    Ok(Some(Ok(idx)))
+                } else {
+                    Ok(Some(Err(idx.to_string())))
+                }
+            }
+            _ => {
+                match try!(de::Deserializer::expect_token(self)) {
+                    Token::End => {
+                        self.structs.pop();
+                        Ok(None)
+                    }
+                    Token::String(n) => {
+                        match fields.iter().position(|field| *field == n.as_slice()) {
+                            Some(idx) => Ok(Some(Ok(idx))),
+                            None => Ok(Some(Err(n))),
+                        }
+                    }
+                    token => {
+                        Err(de::Deserializer::syntax_error(self, token, STR_TOKEN_KINDS))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R: Reader> de::Deserializer<Error> for Deserializer<R> {
+    fn end_of_stream_error(&mut self) -> Error {
+        Error::EndOfStream
+    }
+
+    fn syntax_error(&mut self, token: Token, expected: &'static [TokenKind]) -> Error {
+        Error::SyntaxError(token, expected)
+    }
+
+    fn unexpected_name_error(&mut self, token: Token) -> Error {
+        Error::UnexpectedName(token)
+    }
+
+    fn conversion_error(&mut self, token: Token) -> Error {
+        Error::ConversionError(token)
+    }
+
+    fn unknown_field_error(&mut self, field: &str) -> Error {
+        Error::UnknownField(field.to_string())
+    }
+
+    #[inline]
+    fn missing_field<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self, _field: &'static str) -> Result<T, Error> {
+        // A missing value is read like a `nil` one.
+        Deserialize::deserialize_token(self, Token::Null)
+    }
+
+    #[inline]
+    fn missing_struct_field<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self, name: &'static str, field: &'static str) -> Result<T, Error> {
+        Err(Error::MissingField(field, name))
+    }
+
+    #[inline]
+    fn expect_option<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self, token: Token) -> Result<Option<T>, Error> {
+        match token {
+            Token::Null => Ok(None),
+            token => {
+                let value = try!(Deserialize::deserialize_token(self, token));
+                Ok(Some(value))
+            }
+        }
+    }
+
+    #[inline]
+    fn expect_struct_start(&mut self, token: Token, _name: &str) -> Result<(), Error> {
+        match token {
+            Token::MapStart(_) => {
+                self.structs.push(None);
+                Ok(())
+            }
+            Token::SeqStart(_) => {
+                self.structs.push(Some(0));
+                Ok(())
+            }
+            _ => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::MapStartKind,
+                    TokenKind::SeqStartKind,
+                ];
+                Err(self.syntax_error(token, EXPECTED_TOKENS))
+            }
+        }
+    }
+
+    // Values past the end of a struct written as an array, such as ones
+    // written by a newer version of it, are skipped.
+    #[inline]
+    fn expect_struct_field_or_end(&mut self,
+                                  fields: &'static [&'static str]
+                                 ) -> Result<Option<Option<uint>>, Error> {
+        match try!(self.next_struct_field(fields)) {
+            Some(idx) => Ok(Some(idx.ok())),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn expect_known_struct_field_or_end(&mut self,
+                                        fields: &'static [&'static str]
+                                       ) -> Result<Option<uint>, Error> {
+        match try!(self.next_struct_field(fields)) {
+            Some(Ok(idx)) => Ok(Some(idx)),
+            Some(Err(field)) => Err(self.unknown_field_error(field.as_slice())),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn expect_enum_start(&mut self,
+                         token: Token,
+                         _name: &str,
+                         variants: &[&str]) -> Result<uint, Error> {
+        let (idx, unit_variant) = try!(binary::expect_enum_start(self, token, variants));
+        self.unit_variant = unit_variant;
+        Ok(idx)
+    }
+
+    #[inline]
+    fn expect_enum_elt<
+        T: Deserialize<Deserializer<R>, Error>
+    >(&mut self) -> Result<T, Error> {
+        let unit_variant = self.unit_variant;
+        binary::expect_enum_elt(self, unit_variant)
+    }
+
+    #[inline]
+    fn expect_enum_struct_start(&mut self, name: &str) -> Result<(), Error> {
+        let unit_variant = self.unit_variant;
+        binary::expect_enum_struct_start(self, unit_variant, name)
+    }
+
+    fn expect_enum_end(&mut self) -> Result<(), Error> {
+        let unit_variant = mem::replace(&mut self.unit_variant, false);
+        binary::expect_enum_end(self, unit_variant)
+    }
+}
+
+// Strings are always copied out of the reader.
+impl<'a, R: Reader> de::BorrowDeserializer<'a, Error> for Deserializer<R> { }
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// Encode the specified value into a MessagePack `[u8]` writer.
+#[inline]
+pub fn to_writer<
+    W: Writer,
+    T: Serialize<Serializer<W>, IoError>
+>(writer: W, value: &T) -> IoResult<W> {
+    let mut serializer = Serializer::new(writer);
+    try!(value.serialize(&mut serializer));
+    Ok(serializer.unwrap())
+}
+
+/// Encode the specified value into a MessagePack `[u8]` buffer.
+#[inline]
+pub fn to_vec<
+    T: Serialize<Serializer<Vec<u8>>, IoError>
+>(value: &T) -> Vec<u8> {
+    // We are writing to a Vec, which doesn't fail. So we can ignore
+    // the error.
+    to_writer(Vec::with_capacity(128), value).unwrap()
+}
+
+/// Decodes a MessagePack value from a `Reader`, which must hold nothing
+/// after it.
+#[inline]
+pub fn from_reader<
+    R: Reader,
+    T: Deserialize<Deserializer<R>, Error>
+>(rdr: R) -> Result<T, Error> {
+    let mut deserializer = Deserializer::new(rdr);
+    let value = try!(Deserialize::deserialize(&mut deserializer));
+    try!(deserializer.end());
+    Ok(value)
+}
+
+/// Decodes a MessagePack value from a `[u8]` buffer.
+#[inline]
+pub fn from_slice<
+    'a,
+    T: Deserialize<Deserializer<io::BufReader<'a>>, Error>
+>(v: &'a [u8]) -> Result<T, Error> {
+    from_reader(io::BufReader::new(v))
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fmt::Show;
+    use std::io::{IoError, MemReader};
+
+    use bytes::ByteBuf;
+    use de::{Deserialize, Token};
+    use json::Value;
+    use ser::Serialize;
+
+    use super::{Deserializer, Error, Ext, Serializer, StructEncoding};
+    use super::{from_reader, from_slice, to_vec};
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Point {
+        x: int,
+        y: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Point3 {
+        x: int,
+        y: Option<String>,
+        z: Option<f64>,
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    enum Animal {
+        Dog,
+        Frog(String, int),
+        Cat { age: uint, name: String },
+    }
+
+    #[derive(Clone, PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Record {
+        id: u64,
+        points: Vec<Point>,
+        animals: BTreeMap<String, Animal>,
+        data: ByteBuf,
+        ext: Ext,
+        ratio: f32,
+        tag: Option<char>,
+    }
+
+    fn to_vec_array<
+        T: Serialize<Serializer<Vec<u8>>, IoError>
+    >(value: &T) -> Vec<u8> {
+        let mut serializer = Serializer::new(Vec::new())
+            .struct_encoding(StructEncoding::Array);
+        value.serialize(&mut serializer).unwrap();
+        serializer.unwrap()
+    }
+
+    fn test_encode<
+        T: PartialEq + Show + Serialize<Serializer<Vec<u8>>, IoError>
+                     + Deserialize<Deserializer<MemReader>, Error>
+    >(value: T, bytes: &[u8]) {
+        let v = to_vec(&value);
+        assert_eq!(v.as_slice(), bytes);
+
+        let v: T = from_reader(MemReader::new(v)).unwrap();
+        assert_eq!(v, value);
+    }
+
+    #[test]
+    fn test_write_primitives() {
+        test_encode((), &[0xc0]);
+        test_encode(true, &[0xc3]);
+        test_encode(false, &[0xc2]);
+
+        test_encode(0u8, &[0x00]);
+        test_encode(127i, &[0x7f]);
+        test_encode(128i, &[0xcc, 0x80]);
+        test_encode(256u16, &[0xcd, 0x01, 0x00]);
+        test_encode(65536u32, &[0xce, 0x00, 0x01, 0x00, 0x00]);
+        test_encode(1u64 << 32, &[0xcf, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+        test_encode(-1i8, &[0xff]);
+        test_encode(-32i, &[0xe0]);
+        test_encode(-33i, &[0xd0, 0xdf]);
+        test_encode(-129i16, &[0xd1, 0xff, 0x7f]);
+        test_encode(-32769i32, &[0xd2, 0xff, 0xff, 0x7f, 0xff]);
+        test_encode(-(1i64 << 32), &[0xd3, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00]);
+
+        test_encode(1.5f32, &[0xca, 0x3f, 0xc0, 0x00, 0x00]);
+        test_encode(1.5f64, &[0xcb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        test_encode('x', &[0xa1, b'x']);
+        test_encode("abc".to_string(), &[0xa3, b'a', b'b', b'c']);
+
+        let long: String = range(0u, 32).map(|_| 'a').collect();
+        let mut bytes = vec![0xd9, 32];
+        bytes.push_all(long.as_bytes());
+        test_encode(long, bytes.as_slice());
+
+        test_encode(None::<int>, &[0xc0]);
+        test_encode(Some(1i), &[0x01]);
+    }
+
+    #[test]
+    fn test_write_bytes() {
+        test_encode(ByteBuf(vec![1, 2, 3]), &[0xc4, 0x03, 0x01, 0x02, 0x03]);
+        test_encode(vec![1u8, 2, 3], &[0x93, 0x01, 0x02, 0x03]);
+
+        test_encode(Ext { typ: 5, data: vec![1, 2] }, &[0xd5, 0x05, 0x01, 0x02]);
+        test_encode(Ext { typ: -1, data: vec![1, 2, 3] }, &[0xc7, 0x03, 0xff, 0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn test_write_struct() {
+        let point = Point { x: 1, y: Some("a".to_string()) };
+
+        test_encode(point.clone(), &[0x82, 0xa1, b'x', 0x01, 0xa1, b'y', 0xa1, b'a']);
+
+        let bytes = to_vec_array(&point);
+        assert_eq!(bytes, vec![0x92, 0x01, 0xa1, b'a']);
+        assert_eq!(from_slice(bytes.as_slice()), Ok(point));
+    }
+
+    #[test]
+    fn test_write_skipped_field() {
+        #[derive(Clone, PartialEq, Show)]
+        #[derive_serialize]
+        #[derive_deserialize]
+        #[serde(skip_serializing_none)]
+        struct Sparse {
+            a: Option<int>,
+            b: int,
+        }
+
+        // Maps can leave a field out, but an array would shift `b` into `a`.
+        let value = Sparse { a: None, b: 2 };
+        test_encode(value.clone(), &[0x81, 0xa1, b'b', 0x02]);
+
+        let mut serializer = Serializer::new(Vec::new())
+            .struct_encoding(StructEncoding::Array);
+        assert!(value.serialize(&mut serializer).is_err());
+
+        let value = Sparse { a: Some(1), b: 2 };
+        assert_eq!(to_vec_array(&value), vec![0x92, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_write_enum() {
+        test_encode(Animal::Dog, &[0xa3, b'D', b'o', b'g']);
+        test_encode(Animal::Frog("Henry".to_string(), 349), &[
+            0x81,
+                0xa4, b'F', b'r', b'o', b'g',
+                0x92,
+                    0xa5, b'H', b'e', b'n', b'r', b'y',
+                    0xcd, 0x01, 0x5d,
+        ]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut animals = BTreeMap::new();
+        animals.insert("dog".to_string(), Animal::Dog);
+        animals.insert("cat".to_string(), Animal::Cat { age: 3, name: "Tom".to_string() });
+
+        let record = Record {
+            id: 1 << 40,
+            points: vec![
+                Point { x: -1000, y: None },
+                Point { x: 7, y: Some("\u{1f600}".to_string()) },
+            ],
+            animals: animals,
+            data: ByteBuf(range(0u, 300).map(|i| i as u8).collect()),
+            ext: Ext { typ: 12, data: range(0u8, 20).collect() },
+            ratio: 0.25,
+            tag: Some('t'),
+        };
+
+        let v: Record = from_slice(to_vec(&record).as_slice()).unwrap();
+        assert_eq!(v, record);
+
+        let v: Record = from_slice(to_vec_array(&record).as_slice()).unwrap();
+        assert_eq!(v, record);
+    }
+
+    #[test]
+    fn test_read_positional_struct() {
+        // Fields missing from the end of an array are read as missing.
+        let bytes = to_vec_array(&Point { x: 1, y: None });
+        let v: Point3 = from_slice(bytes.as_slice()).unwrap();
+        assert_eq!(v, Point3 { x: 1, y: None, z: None });
+
+        // And extra values are skipped.
+        let bytes = to_vec_array(&Point3 { x: 1, y: Some("a".to_string()), z: Some(0.5) });
+        let v: Point = from_slice(bytes.as_slice()).unwrap();
+        assert_eq!(v, Point { x: 1, y: Some("a".to_string()) });
+
+        let v: Result<Point, Error> = from_slice(&[0x90]);
+        assert_eq!(v, Err(Error::MissingField("x", "Point")));
+    }
+
+    #[test]
+    fn test_read_bin_and_str() {
+        let v: Result<String, Error> = from_slice(&[0xc4, 0x01, b'a']);
+        match v {
+            Err(Error::SyntaxError(Token::Bytes(ref bytes), _)) if *bytes == vec![b'a'] => { }
+            v => panic!("unexpected result {:?}", v),
+        }
+
+        // Byte buffers can be read from any of them.
+        assert_eq!(from_slice(&[0xc4, 0x01, b'a']), Ok(ByteBuf(vec![b'a'])));
+        assert_eq!(from_slice(&[0xa1, b'a']), Ok(ByteBuf(vec![b'a'])));
+        assert_eq!(from_slice(&[0x91, 0x61]), Ok(ByteBuf(vec![b'a'])));
+    }
+
+    #[test]
+    fn test_read_value() {
+        let bytes = to_vec(&Point { x: 1, y: None });
+        let v: Value = from_slice(bytes.as_slice()).unwrap();
+
+        let mut object = BTreeMap::new();
+        object.insert("x".to_string(), Value::Integer(1));
+        object.insert("y".to_string(), Value::Null);
+        assert_eq!(v, Value::Object(object));
+
+        let bytes = to_vec(&Ext { typ: 1, data: vec![2] });
+        let v: Value = from_slice(bytes.as_slice()).unwrap();
+        assert_eq!(v, Value::Array(vec![
+            Value::Integer(1),
+            Value::Array(vec![Value::Integer(2)]),
+        ]));
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let v: Result<String, Error> = from_slice(&[0xa3, b'a']);
+        assert_eq!(v, Err(Error::EndOfStream));
+
+        let v: Result<int, Error> = from_slice(&[0xc1]);
+        assert_eq!(v, Err(Error::InvalidMarker(0xc1)));
+
+        let v: Result<int, Error> = from_slice(&[0x01, 0x02]);
+        assert_eq!(v, Err(Error::TrailingBytes));
+
+        let v: Result<String, Error> = from_slice(&[0xa1, 0xff]);
+        assert_eq!(v, Err(Error::InvalidUtf8));
+
+        let v: Result<Animal, Error> = from_slice(&[0xa3, b'C', b'o', b'w']);
+        assert_eq!(v, Err(Error::UnknownVariant("Cow".to_string())));
+
+        let v: Result<Animal, Error> = from_slice(&[0xa4, b'F', b'r', b'o', b'g']);
+        assert_eq!(v, Err(Error::MissingVariantFields));
+    }
+}
@@ -14,6 +14,9 @@ use std::hash::Hash;
 use std::rc::Rc;
 use std::sync::Arc;
 
+use bytes::Bytes;
+use de::Presence;
+
 //////////////////////////////////////////////////////////////////////////////
 
 pub trait Serializer<E> {
@@ -74,10 +77,64 @@ pub trait Serializer<E> {
 
     fn serialize_f64(&mut self, v: f64) -> Result<(), E>;
 
+    /// Serializes a number given by its decimal digits, such as one that was
+    /// parsed with arbitrary precision. By default it's converted into the
+    /// closest primitive, preferring integers so they stay exact.
+    #[inline]
+    fn serialize_raw_number(&mut self, v: &str) -> Result<(), E> {
+        match v.parse::<i64>() {
+            Some(v) => self.serialize_i64(v),
+            None => {
+                match v.parse::<u64>() {
+                    Some(v) => self.serialize_u64(v),
+                    None => {
+                        match v.parse::<f64>() {
+                            Some(v) => self.serialize_f64(v),
+                            None => self.serialize_str(v),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     fn serialize_char(&mut self, v: char) -> Result<(), E>;
 
     fn serialize_str(&mut self, v: &str) -> Result<(), E>;
 
+    /// Serializes a byte buffer. Formats without a binary type write it as
+    /// a sequence of integers, which is the default.
+    #[inline]
+    fn serialize_bytes(&mut self, v: &[u8]) -> Result<(), E> {
+        self.serialize_seq(v.iter())
+    }
+
+    /// Serializes data tagged with a type number, such as a MessagePack
+    /// extension type. Other formats write it as a `(typ, data)` tuple,
+    /// which is the default.
+    #[inline]
+    fn serialize_ext(&mut self, typ: i8, data: &[u8]) -> Result<(), E> {
+        try!(self.serialize_tuple_start(2));
+        try!(self.serialize_tuple_elt(&typ));
+        try!(self.serialize_tuple_elt(&Bytes(data)));
+        self.serialize_tuple_end()
+    }
+
+    /// Tags the value serialized next with a semantic tag, such as a CBOR
+    /// tag. Formats without tags leave it out, which is the default.
+    #[inline]
+    fn serialize_tag(&mut self, _tag: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Marks the string serialized next as a local date-time, date or time,
+    /// which have no semantic tag. Formats that don't tell them apart from
+    /// other strings ignore it, which is the default.
+    #[inline]
+    fn serialize_local_datetime(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
     fn serialize_tuple_start(&mut self, len: uint) -> Result<(), E>;
     fn serialize_tuple_elt<
         T: Serialize<Self, E>
@@ -88,9 +145,36 @@ pub trait Serializer<E> {
     fn serialize_struct_elt<
         T: Serialize<Self, E>
     >(&mut self, name: &str, v: &T) -> Result<(), E>;
+
+    /// Called in place of `serialize_struct_elt` for a field that is left
+    /// out, such as with `skip_serializing_if`. Formats that write fields
+    /// by position can't tell which one is missing, so they can fail here.
+    /// It does nothing by default.
+    #[inline]
+    fn serialize_struct_skipped_elt(&mut self, _name: &str) -> Result<(), E> {
+        Ok(())
+    }
+
     fn serialize_struct_end(&mut self) -> Result<(), E>;
 
+    /// Starts writing the variant `variant` of the enum `name`. Formats that
+    /// write variants by index, like `bin`, can't write an enum from its name
+    /// alone and fail here, so `Serialize` impls should call
+    /// `serialize_enum_variant_start` instead, as derived ones do.
     fn serialize_enum_start(&mut self, name: &str, variant: &str, len: uint) -> Result<(), E>;
+
+    /// Like `serialize_enum_start`, but also given the position of the
+    /// variant in its enum, for formats that write variants by index rather
+    /// than by name. The index is ignored by default.
+    #[inline]
+    fn serialize_enum_variant_start(&mut self,
+                                    name: &str,
+                                    _variant_index: uint,
+                                    variant: &str,
+                                    len: uint) -> Result<(), E> {
+        self.serialize_enum_start(name, variant, len)
+    }
+
     fn serialize_enum_elt<
         T: Serialize<Self, E>
     >(&mut self, v: &T) -> Result<(), E>;
@@ -208,6 +292,21 @@ impl<
     }
 }
 
+impl<
+    S: Serializer<E>,
+    E,
+    T: Serialize<S, E>
+> Serialize<S, E> for Presence<T> {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        // Written like an `Option`, since that's how it's read back.
+        match *self {
+            Presence::Value(ref value) => s.serialize_option(&Some(value)),
+            Presence::Absent | Presence::Null => s.serialize_option::<&T>(&None),
+        }
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 
 impl<
@@ -316,6 +415,52 @@ impl_serialize_tuple! { T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, }
 
 //////////////////////////////////////////////////////////////////////////////
 
+/// Serializes a list of named values as a struct. This lets derived
+/// serializers nest the fields of an enum variant inside another struct.
+/// Fields without a value are skipped.
+pub struct StructFields<'a, S: 'a, E: 'a> {
+    name: &'static str,
+    fields: &'a [(&'static str, Option<&'a (Serialize<S, E> + 'a)>)],
+}
+
+impl<'a, S, E> StructFields<'a, S, E> {
+    #[inline]
+    pub fn new(name: &'static str,
+               fields: &'a [(&'static str, Option<&'a (Serialize<S, E> + 'a)>)]
+              ) -> StructFields<'a, S, E> {
+        StructFields {
+            name: name,
+            fields: fields,
+        }
+    }
+}
+
+impl<'a, S: Serializer<E>, E> Serialize<S, E> for StructFields<'a, S, E> {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        let len = self.fields.iter().filter(|&&(_, value)| value.is_some()).count();
+        try!(s.serialize_struct_start(self.name, len));
+        for &(name, value) in self.fields.iter() {
+            match value {
+                Some(value) => { try!(s.serialize_struct_elt(name, &Field(value))); }
+                None => { try!(s.serialize_struct_skipped_elt(name)); }
+            }
+        }
+        s.serialize_struct_end()
+    }
+}
+
+struct Field<'a, S: 'a, E: 'a>(&'a (Serialize<S, E> + 'a));
+
+impl<'a, S: Serializer<E>, E> Serialize<S, E> for Field<'a, S, E> {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        self.0.serialize(s)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
 #[cfg(test)]
 mod tests {
     use std::collections::{HashMap, BTreeMap};
@@ -0,0 +1,833 @@
+use std::char;
+use std::collections::{BTreeMap, HashSet};
+use std::f64;
+use std::i64;
+
+use de;
+use json;
+
+use super::error::{Error, ErrorCode};
+use super::value::{self, Datetime, Table, Value};
+
+/// A parser for TOML documents, which reads them into a `Table`.
+///
+/// Besides the syntax, it checks that no key or table is defined twice,
+/// that inline tables and arrays aren't extended by later headers, and that
+/// tables defined with dotted keys aren't reopened with a header.
+pub struct Parser<'a> {
+    input: &'a str,
+    pos: uint,
+    root: Table,
+    // The path to the table that key/value pairs go into. Arrays of tables
+    // are followed by the index of the element in them.
+    current: Vec<String>,
+    // Tables defined by a `[header]`.
+    headers: HashSet<Vec<String>>,
+    // Tables defined by dotted keys.
+    dotted: HashSet<Vec<String>>,
+    // Inline tables, which can't be added to.
+    inline: HashSet<Vec<String>>,
+    // Arrays defined by `[[headers]]`.
+    table_arrays: HashSet<Vec<String>>,
+}
+
+enum Found {
+    Missing,
+    Table,
+    TableArray(uint),
+    Other,
+}
+
+/// Returns the table at `path`, which must only go through tables and
+/// arrays of tables.
+fn table_mut<'a>(table: &'a mut Table, path: &[String]) -> &'a mut Table {
+    if path.is_empty() {
+        return table;
+    }
+
+    match table.get_mut(&path[0]) {
+        Some(&mut Value::Table(ref mut table)) => table_mut(table, path.slice_from(1)),
+        Some(&mut Value::Array(ref mut array)) => {
+            let index: uint = path[1].parse().unwrap();
+            match array[index] {
+                Value::Table(ref mut table) => table_mut(table, path.slice_from(2)),
+                _ => panic!("expected an array of tables"),
+            }
+        }
+        _ => panic!("expected a table"),
+    }
+}
+
+/// Formats the keys of a path for an error message.
+fn path_name(path: &[String]) -> String {
+    let keys: Vec<&str> = path.iter().map(|key| key.as_slice()).collect();
+    keys.connect(".")
+}
+
+fn is_bare_key_char(c: u8) -> bool {
+    match c {
+        b'A' ... b'Z' | b'a' ... b'z' | b'0' ... b'9' | b'_' | b'-' => true,
+        _ => false,
+    }
+}
+
+/// Checks that `s` is digits of the radix, with underscores only between
+/// them.
+fn valid_digits(s: &str, radix: uint) -> bool {
+    let b = s.as_bytes();
+    if b.is_empty() || b[0] == b'_' || b[b.len() - 1] == b'_' {
+        return false;
+    }
+
+    let mut last = b'0';
+    for &c in b.iter() {
+        if c == b'_' {
+            if last == b'_' {
+                return false;
+            }
+        } else if (c as char).to_digit(radix).is_none() {
+            return false;
+        }
+        last = c;
+    }
+
+    true
+}
+
+impl<'a> Parser<'a> {
+    /// Creates the TOML parser.
+    pub fn new(input: &'a str) -> Parser<'a> {
+        Parser {
+            input: input,
+            pos: 0,
+            root: BTreeMap::new(),
+            current: Vec::new(),
+            headers: HashSet::new(),
+            dotted: HashSet::new(),
+            inline: HashSet::new(),
+            table_arrays: HashSet::new(),
+        }
+    }
+
+    /// Parses the whole document.
+    pub fn parse(mut self) -> Result<Table, Error> {
+        loop {
+            try!(self.skip_blank());
+
+            match self.peek() {
+                None => { break; }
+                Some(b'[') => { try!(self.parse_header()); }
+                Some(_) => { try!(self.parse_key_value()); }
+            }
+
+            try!(self.expect_newline());
+        }
+
+        Ok(self.root)
+    }
+
+    fn error(&self, code: ErrorCode) -> Error {
+        let mut line = 1;
+        let mut col = 1;
+        for c in self.input.slice_to(self.pos).chars() {
+            if c == '\n' {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+        }
+
+        Error::SyntaxError(code, line, col)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: uint) -> Option<u8> {
+        self.input.as_bytes().get(self.pos + offset).map(|c| *c)
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        if self.input.slice_from(self.pos).starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_newline(&mut self) -> bool {
+        self.eat(b'\n') || self.eat_str("\r\n")
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.eat(b' ') || self.eat(b'\t') { }
+    }
+
+    fn skip_comment(&mut self) -> Result<(), Error> {
+        if !self.eat(b'#') {
+            return Ok(());
+        }
+
+        loop {
+            match self.peek() {
+                None | Some(b'\n') => { return Ok(()); }
+                Some(b'\r') if self.peek_at(1) == Some(b'\n') => { return Ok(()); }
+                Some(c) if (c < 0x20 && c != b'\t') || c == 0x7f => {
+                    return Err(self.error(ErrorCode::ControlCharacter));
+                }
+                Some(_) => { self.pos += 1; }
+            }
+        }
+    }
+
+    /// Skips whitespace, comments and newlines.
+    fn skip_blank(&mut self) -> Result<(), Error> {
+        loop {
+            self.skip_whitespace();
+            try!(self.skip_comment());
+            if !self.eat_newline() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn expect_newline(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        try!(self.skip_comment());
+
+        if self.peek().is_none() || self.eat_newline() {
+            Ok(())
+        } else {
+            Err(self.error(ErrorCode::ExpectedNewline))
+        }
+    }
+
+    //////////////////////////////////////////////////////////////////////////
+
+    fn parse_header(&mut self) -> Result<(), Error> {
+        self.eat(b'[');
+        let array = self.eat(b'[');
+
+        self.skip_whitespace();
+        let keys = try!(self.parse_key());
+        self.skip_whitespace();
+
+        if !self.eat(b']') || (array && !self.eat(b']')) {
+            return Err(self.error(ErrorCode::ExpectedTableHeaderEnd));
+        }
+
+        let mut path = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let last = i == keys.len() - 1;
+            let parent = path.clone();
+            path.push(key.clone());
+
+            let found = match table_mut(&mut self.root, parent.as_slice()).get(key) {
+                None => Found::Missing,
+                Some(&Value::Table(_)) => Found::Table,
+                Some(&Value::Array(ref array)) if self.table_arrays.contains(&path) => {
+                    Found::TableArray(array.len())
+                }
+                Some(_) => Found::Other,
+            };
+
+            match found {
+                Found::Missing if last && array => {
+                    let table = table_mut(&mut self.root, parent.as_slice());
+                    table.insert(key.clone(), Value::Array(vec![Value::Table(BTreeMap::new())]));
+                    self.table_arrays.insert(path.clone());
+                    path.push("0".to_string());
+                }
+                Found::Missing => {
+                    let table = table_mut(&mut self.root, parent.as_slice());
+                    table.insert(key.clone(), Value::Table(BTreeMap::new()));
+                }
+                Found::TableArray(len) if last && array => {
+                    match table_mut(&mut self.root, parent.as_slice()).get_mut(key) {
+                        Some(&mut Value::Array(ref mut array)) => {
+                            array.push(Value::Table(BTreeMap::new()));
+                        }
+                        _ => unreachable!(),
+                    }
+                    path.push(len.to_string());
+                }
+                // Headers go into the last table of an array of tables.
+                Found::TableArray(len) if !last => {
+                    path.push((len - 1).to_string());
+                }
+                Found::Table if !array && !self.inline.contains(&path) => {
+                    if last && (self.headers.contains(&path) || self.dotted.contains(&path)) {
+                        return Err(self.error(ErrorCode::DuplicateTable(path_name(keys.as_slice()))));
+                    }
+                }
+                Found::Table if !last && !self.inline.contains(&path) => { }
+                _ => {
+                    return Err(self.error(ErrorCode::DuplicateTable(path_name(keys.as_slice()))));
+                }
+            }
+        }
+
+        if !array {
+            self.headers.insert(path.clone());
+        }
+        self.current = path;
+
+        Ok(())
+    }
+
+    /// Parses a possibly dotted key.
+    fn parse_key(&mut self) -> Result<Vec<String>, Error> {
+        let mut keys = vec![try!(self.parse_simple_key())];
+
+        loop {
+            self.skip_whitespace();
+            if !self.eat(b'.') {
+                return Ok(keys);
+            }
+            self.skip_whitespace();
+            keys.push(try!(self.parse_simple_key()));
+        }
+    }
+
+    fn parse_simple_key(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(b'"') => {
+                self.pos += 1;
+                self.parse_basic_string()
+            }
+            Some(b'\'') => {
+                self.pos += 1;
+                self.parse_literal_string()
+            }
+            _ => {
+                let start = self.pos;
+                while self.peek().map_or(false, is_bare_key_char) {
+                    self.pos += 1;
+                }
+
+                if self.pos == start {
+                    Err(self.error(ErrorCode::ExpectedKey))
+                } else {
+                    Ok(self.input.slice(start, self.pos).to_string())
+                }
+            }
+        }
+    }
+
+    fn parse_key_value(&mut self) -> Result<(), Error> {
+        let (keys, value) = try!(self.parse_entry());
+
+        let mut path = self.current.clone();
+        let last = keys.len() - 1;
+
+        // Dotted keys define tables, which can't be ones defined elsewhere.
+        for key in keys.slice_to(last).iter() {
+            let parent = path.clone();
+            path.push(key.clone());
+
+            let found = match table_mut(&mut self.root, parent.as_slice()).get(key) {
+                None => Found::Missing,
+                Some(&Value::Table(_)) => Found::Table,
+                Some(_) => Found::Other,
+            };
+
+            match found {
+                Found::Missing => {
+                    let table = table_mut(&mut self.root, parent.as_slice());
+                    table.insert(key.clone(), Value::Table(BTreeMap::new()));
+                    self.dotted.insert(path.clone());
+                }
+                Found::Table if !self.headers.contains(&path) &&
+                                !self.inline.contains(&path) => { }
+                _ => {
+                    return Err(self.error(ErrorCode::DuplicateKey(path_name(keys.as_slice()))));
+                }
+            }
+        }
+
+        let key = keys[last].clone();
+        let is_table = match value {
+            Value::Table(_) => true,
+            _ => false,
+        };
+
+        {
+            let table = table_mut(&mut self.root, path.as_slice());
+            if table.contains_key(&key) {
+                return Err(self.error(ErrorCode::DuplicateKey(path_name(keys.as_slice()))));
+            }
+            table.insert(key.clone(), value);
+        }
+
+        if is_table {
+            path.push(key);
+            self.inline.insert(path);
+        }
+
+        Ok(())
+    }
+
+    /// Parses a `key = value` pair.
+    fn parse_entry(&mut self) -> Result<(Vec<String>, Value), Error> {
+        let keys = try!(self.parse_key());
+
+        self.skip_whitespace();
+        if !self.eat(b'=') {
+            return Err(self.error(ErrorCode::ExpectedEquals));
+        }
+        self.skip_whitespace();
+
+        let value = try!(self.parse_value());
+        Ok((keys, value))
+    }
+
+    //////////////////////////////////////////////////////////////////////////
+
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        match self.peek() {
+            Some(b'"') => {
+                if self.eat_str("\"\"\"") {
+                    Ok(Value::String(try!(self.parse_multiline_string(b'"'))))
+                } else {
+                    self.pos += 1;
+                    Ok(Value::String(try!(self.parse_basic_string())))
+                }
+            }
+            Some(b'\'') => {
+                if self.eat_str("'''") {
+                    Ok(Value::String(try!(self.parse_multiline_string(b'\''))))
+                } else {
+                    self.pos += 1;
+                    Ok(Value::String(try!(self.parse_literal_string())))
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                self.parse_array()
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                self.parse_inline_table()
+            }
+            Some(b't') if self.eat_str("true") => Ok(Value::Boolean(true)),
+            Some(b'f') if self.eat_str("false") => Ok(Value::Boolean(false)),
+            Some(c) if (c >= b'0' && c <= b'9') || c == b'+' || c == b'-' ||
+                       c == b'i' || c == b'n' => {
+                self.parse_number_or_datetime()
+            }
+            Some(_) => Err(self.error(ErrorCode::ExpectedSomeValue)),
+            None => Err(self.error(ErrorCode::EOFWhileParsingValue)),
+        }
+    }
+
+    /// Parses the escape after a backslash in a basic string.
+    fn parse_escape(&mut self, buf: &mut String) -> Result<(), Error> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => { return Err(self.error(ErrorCode::EOFWhileParsingString)); }
+        };
+        self.pos += 1;
+
+        match c {
+            b'b' => buf.push('\x08'),
+            b't' => buf.push('\t'),
+            b'n' => buf.push('\n'),
+            b'f' => buf.push('\x0c'),
+            b'r' => buf.push('\r'),
+            b'"' => buf.push('"'),
+            b'\\' => buf.push('\\'),
+            b'u' | b'U' => {
+                let len = if c == b'u' { 4 } else { 8 };
+                let end = self.pos + len;
+                let hex = end <= self.input.len() &&
+                          self.input.as_bytes().slice(self.pos, end).iter().all(|c| {
+                              (*c as char).to_digit(16).is_some()
+                          });
+                let c = if hex {
+                    let digits = self.input.slice(self.pos, end);
+                    char::from_u32(digits.chars().fold(0, |v, c| {
+                        v * 16 + c.to_digit(16).unwrap() as u32
+                    }))
+                } else {
+                    None
+                };
+
+                match c {
+                    Some(c) => buf.push(c),
+                    None => {
+                        self.pos -= 1;
+                        return Err(self.error(ErrorCode::InvalidEscape));
+                    }
+                }
+                self.pos = end;
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.error(ErrorCode::InvalidEscape));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies the characters up to the next one that ends a run of
+    /// unescaped characters in a string.
+    fn parse_string_run(&mut self, buf: &mut String, multiline: bool) -> Result<(), Error> {
+        let start = self.pos;
+
+        loop {
+            match self.peek() {
+                Some(b'\\') | Some(b'"') | Some(b'\'') | None => { break; }
+                Some(b'\n') if !multiline => { break; }
+                Some(b'\r') if multiline && self.peek_at(1) == Some(b'\n') => { self.pos += 2; }
+                Some(b'\n') | Some(b'\t') => { self.pos += 1; }
+                Some(c) if c < 0x20 || c == 0x7f => {
+                    return Err(self.error(ErrorCode::ControlCharacter));
+                }
+                Some(_) => { self.pos += 1; }
+            }
+        }
+
+        buf.push_str(self.input.slice(start, self.pos));
+        Ok(())
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, Error> {
+        let mut buf = String::new();
+
+        loop {
+            try!(self.parse_string_run(&mut buf, false));
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(buf);
+                }
+                Some(b'\'') => {
+                    self.pos += 1;
+                    buf.push('\'');
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    try!(self.parse_escape(&mut buf));
+                }
+                _ => { return Err(self.error(ErrorCode::EOFWhileParsingString)); }
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, Error> {
+        let mut buf = String::new();
+
+        loop {
+            try!(self.parse_string_run(&mut buf, false));
+
+            match self.peek() {
+                Some(b'\'') => {
+                    self.pos += 1;
+                    return Ok(buf);
+                }
+                Some(c) if c == b'"' || c == b'\\' => {
+                    self.pos += 1;
+                    buf.push(c as char);
+                }
+                _ => { return Err(self.error(ErrorCode::EOFWhileParsingString)); }
+            }
+        }
+    }
+
+    /// Parses a multi-line string after its opening quotes, which are
+    /// `quote` three times. Only basic strings have escapes.
+    fn parse_multiline_string(&mut self, quote: u8) -> Result<String, Error> {
+        let mut buf = String::new();
+
+        // A newline right after the opening quotes is trimmed.
+        self.eat_newline();
+
+        loop {
+            try!(self.parse_string_run(&mut buf, true));
+
+            match self.peek() {
+                Some(c) if c == quote => {
+                    // Up to two quotes can come right before the closing
+                    // ones.
+                    let mut quotes = 0;
+                    while self.peek() == Some(quote) {
+                        self.pos += 1;
+                        quotes += 1;
+                    }
+
+                    if quotes >= 3 {
+                        if quotes > 5 {
+                            return Err(self.error(ErrorCode::EOFWhileParsingString));
+                        }
+                        for _ in range(3, quotes) {
+                            buf.push(quote as char);
+                        }
+                        return Ok(buf);
+                    }
+
+                    for _ in range(0, quotes) {
+                        buf.push(quote as char);
+                    }
+                }
+                Some(b'\\') if quote == b'"' => {
+                    self.pos += 1;
+
+                    // A backslash at the end of a line trims the whitespace
+                    // and newlines after it.
+                    let start = self.pos;
+                    self.skip_whitespace();
+                    if self.eat_newline() {
+                        loop {
+                            self.skip_whitespace();
+                            if !self.eat_newline() {
+                                break;
+                            }
+                        }
+                    } else {
+                        self.pos = start;
+                        try!(self.parse_escape(&mut buf));
+                    }
+                }
+                Some(c) if c == b'"' || c == b'\'' || c == b'\\' => {
+                    self.pos += 1;
+                    buf.push(c as char);
+                }
+                _ => { return Err(self.error(ErrorCode::EOFWhileParsingString)); }
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, Error> {
+        let mut array = Vec::new();
+
+        loop {
+            try!(self.skip_blank());
+            if self.eat(b']') {
+                return Ok(Value::Array(array));
+            }
+
+            array.push(try!(self.parse_value()));
+
+            try!(self.skip_blank());
+            if self.eat(b']') {
+                return Ok(Value::Array(array));
+            }
+            if !self.eat(b',') {
+                return Err(self.error(ErrorCode::ExpectedArrayCommaOrEnd));
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, Error> {
+        let mut table = BTreeMap::new();
+
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Ok(Value::Table(table));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let (keys, value) = try!(self.parse_entry());
+
+            if !insert_dotted(&mut table, keys.as_slice(), value) {
+                return Err(self.error(ErrorCode::DuplicateKey(path_name(keys.as_slice()))));
+            }
+
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Ok(Value::Table(table));
+            }
+            if !self.eat(b',') {
+                return Err(self.error(ErrorCode::ExpectedInlineTableCommaOrEnd));
+            }
+        }
+    }
+
+    fn parse_number_or_datetime(&mut self) -> Result<Value, Error> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| {
+            is_bare_key_char(c) || c == b'+' || c == b'.' || c == b':'
+        }) {
+            self.pos += 1;
+        }
+
+        // Dates and times can be separated by a space.
+        if self.pos - start == 10 && self.peek() == Some(b' ') &&
+           self.peek_at(3) == Some(b':') {
+            self.pos += 1;
+            while self.peek().map_or(false, |c| {
+                is_bare_key_char(c) || c == b'+' || c == b'.' || c == b':'
+            }) {
+                self.pos += 1;
+            }
+        }
+
+        let s = self.input.slice(start, self.pos);
+        let b = s.as_bytes();
+
+        let datetime = (b.len() >= 5 && b[4] == b'-' && valid_digits(s.slice_to(4), 10)) ||
+                       (b.len() >= 3 && b[2] == b':');
+        if datetime {
+            return match Datetime::parse(s) {
+                Some(datetime) => Ok(Value::Datetime(datetime)),
+                None => {
+                    self.pos = start;
+                    Err(self.error(ErrorCode::InvalidDatetime))
+                }
+            };
+        }
+
+        match parse_number(s) {
+            Ok(value) => Ok(value),
+            Err(code) => {
+                self.pos = start;
+                Err(self.error(code))
+            }
+        }
+    }
+}
+
+/// Inserts `value` at the dotted `keys` in an inline table, returning false
+/// if something is already there.
+fn insert_dotted(table: &mut Table, keys: &[String], value: Value) -> bool {
+    if keys.len() == 1 {
+        if table.contains_key(&keys[0]) {
+            return false;
+        }
+        table.insert(keys[0].clone(), value);
+        return true;
+    }
+
+    if !table.contains_key(&keys[0]) {
+        table.insert(keys[0].clone(), Value::Table(BTreeMap::new()));
+    }
+
+    match table.get_mut(&keys[0]) {
+        Some(&mut Value::Table(ref mut table)) => insert_dotted(table, keys.slice_from(1), value),
+        _ => false,
+    }
+}
+
+fn parse_number(s: &str) -> Result<Value, ErrorCode> {
+    let (negative, unsigned) = match s.as_bytes().get(0) {
+        Some(&b'+') => (false, s.slice_from(1)),
+        Some(&b'-') => (true, s.slice_from(1)),
+        _ => (false, s),
+    };
+
+    match unsigned {
+        "inf" => {
+            return Ok(Value::Float(if negative { f64::NEG_INFINITY } else { f64::INFINITY }));
+        }
+        "nan" => {
+            return Ok(Value::Float(f64::NAN));
+        }
+        _ => { }
+    }
+
+    // Integers in other radixes can't have a sign.
+    let radix = if unsigned.starts_with("0x") {
+        16
+    } else if unsigned.starts_with("0o") {
+        8
+    } else if unsigned.starts_with("0b") {
+        2
+    } else {
+        10
+    };
+
+    if radix != 10 {
+        let digits = unsigned.slice_from(2);
+        if s.len() != unsigned.len() || !valid_digits(digits, radix) {
+            return Err(ErrorCode::InvalidNumber);
+        }
+        return parse_integer(digits, radix, false);
+    }
+
+    let int_end = unsigned.find(|c: char| c == '.' || c == 'e' || c == 'E').unwrap_or(unsigned.len());
+    let int = unsigned.slice_to(int_end);
+
+    // Leading zeros aren't allowed.
+    if !valid_digits(int, 10) || (int.len() > 1 && int.starts_with("0")) {
+        return Err(ErrorCode::InvalidNumber);
+    }
+
+    if int_end == unsigned.len() {
+        return parse_integer(int, 10, negative);
+    }
+
+    let mut rest = unsigned.slice_from(int_end);
+    if rest.starts_with(".") {
+        let frac_end = rest.find(|c: char| c == 'e' || c == 'E').unwrap_or(rest.len());
+        if !valid_digits(rest.slice(1, frac_end), 10) {
+            return Err(ErrorCode::InvalidNumber);
+        }
+        rest = rest.slice_from(frac_end);
+    }
+    if !rest.is_empty() {
+        let exp = rest.slice_from(1).trim_left_matches(|c: char| c == '+' || c == '-');
+        if rest.len() - exp.len() > 2 || !valid_digits(exp, 10) {
+            return Err(ErrorCode::InvalidNumber);
+        }
+    }
+
+    // JSON has the same float syntax once the underscores and any leading
+    // plus are gone.
+    let mut number: String = unsigned.chars().filter(|c| *c != '_').collect();
+    if negative {
+        number.insert(0, '-');
+    }
+
+    match json::from_str::<f64>(number.as_slice()) {
+        Ok(v) => Ok(Value::Float(v)),
+        Err(_) => Err(ErrorCode::InvalidNumber),
+    }
+}
+
+fn parse_integer(digits: &str, radix: uint, negative: bool) -> Result<Value, ErrorCode> {
+    // Negative numbers go one further than positive ones.
+    let max = if negative { i64::MAX as u64 + 1 } else { i64::MAX as u64 };
+
+    let mut v = 0u64;
+    for c in digits.chars().filter(|c| *c != '_') {
+        let digit = c.to_digit(radix).unwrap() as u64;
+        if v > (max - digit) / radix as u64 {
+            return Err(ErrorCode::IntegerOverflow);
+        }
+        v = v * radix as u64 + digit;
+    }
+
+    if negative && v > 0 {
+        Ok(Value::Integer(-((v - 1) as i64) - 1))
+    } else {
+        Ok(Value::Integer(v as i64))
+    }
+}
+
+/// Parses a TOML document into a `Table`.
+pub fn parse(s: &str) -> Result<Table, Error> {
+    Parser::new(s).parse()
+}
+
+/// Decodes a TOML value from a `&str`.
+pub fn from_str<
+    T: de::Deserialize<value::Deserializer, Error>
+>(s: &str) -> Result<T, Error> {
+    let table = try!(parse(s));
+    value::from_value(Value::Table(table))
+}
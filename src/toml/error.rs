@@ -0,0 +1,95 @@
+use std::error;
+use std::fmt;
+
+use de::{Token, TokenKind};
+
+/// The errors that can arise while parsing a TOML document, or reading it
+/// into a value.
+#[derive(Clone, PartialEq)]
+pub enum ErrorCode {
+    ControlCharacter,
+    ConversionError(Token),
+    DuplicateKey(String),
+    DuplicateTable(String),
+    EOFWhileParsingString,
+    EOFWhileParsingValue,
+    ExpectedArrayCommaOrEnd,
+    ExpectedEquals,
+    ExpectedInlineTableCommaOrEnd,
+    ExpectedKey,
+    ExpectedNewline,
+    ExpectedSomeValue,
+    ExpectedTableHeaderEnd,
+    ExpectedTokens(Token, &'static [TokenKind]),
+    IntegerOverflow,
+    InvalidDatetime,
+    InvalidEscape,
+    InvalidNumber,
+    /// field, struct
+    MissingField(&'static str, &'static str),
+    UnexpectedName(Token),
+    UnknownField(String),
+    UnknownVariant(String),
+}
+
+impl fmt::Show for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorCode::ControlCharacter => "control character in string or comment".fmt(f),
+            ErrorCode::ConversionError(ref token) => write!(f, "failed to convert {:?}", token),
+            ErrorCode::DuplicateKey(ref key) => write!(f, "duplicate key `{}`", key),
+            ErrorCode::DuplicateTable(ref table) => write!(f, "duplicate table `{}`", table),
+            ErrorCode::EOFWhileParsingString => "EOF While parsing string".fmt(f),
+            ErrorCode::EOFWhileParsingValue => "EOF While parsing value".fmt(f),
+            ErrorCode::ExpectedArrayCommaOrEnd => "expected `,` or `]`".fmt(f),
+            ErrorCode::ExpectedEquals => "expected `=`".fmt(f),
+            ErrorCode::ExpectedInlineTableCommaOrEnd => "expected `,` or `}`".fmt(f),
+            ErrorCode::ExpectedKey => "expected key".fmt(f),
+            ErrorCode::ExpectedNewline => "expected newline".fmt(f),
+            ErrorCode::ExpectedSomeValue => "expected value".fmt(f),
+            ErrorCode::ExpectedTableHeaderEnd => "expected `]`".fmt(f),
+            ErrorCode::ExpectedTokens(ref token, tokens) => write!(f, "expected {:?}, found {:?}", tokens, token),
+            ErrorCode::IntegerOverflow => "integer overflow".fmt(f),
+            ErrorCode::InvalidDatetime => "invalid datetime".fmt(f),
+            ErrorCode::InvalidEscape => "invalid escape".fmt(f),
+            ErrorCode::InvalidNumber => "invalid number".fmt(f),
+            ErrorCode::MissingField(field, name) => {
+                write!(f, "missing field `{}` in struct `{}`", field, name)
+            }
+            ErrorCode::UnexpectedName(ref name) => write!(f, "unexpected name {:?}", name),
+            ErrorCode::UnknownField(ref field) => write!(f, "unknown field \"{}\"", field),
+            ErrorCode::UnknownVariant(ref variant) => write!(f, "unknown variant \"{}\"", variant),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Show)]
+pub enum Error {
+    /// A syntax error in the document at a line and column, both counting
+    /// from 1.
+    SyntaxError(ErrorCode, uint, uint),
+    /// An error reading the parsed document into a value, at the path to
+    /// the value, like `servers[3].port`.
+    DeserializeError(ErrorCode, String),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::SyntaxError(..) => "syntax error",
+            Error::DeserializeError(..) => "deserialize error",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match *self {
+            Error::SyntaxError(ref code, line, col) => {
+                Some(format!("{:?} at line {} column {}", code, line, col))
+            }
+            Error::DeserializeError(ref code, ref path) => {
+                let path = if path.is_empty() { "the document" } else { path.as_slice() };
+                Some(format!("{:?} at {}", code, path))
+            }
+        }
+    }
+}
@@ -0,0 +1,535 @@
+/*!
+TOML parsing and serialization
+
+TOML is a configuration file format made of key/value pairs grouped under
+`[table]` headers, with `[[array]]` headers for arrays of tables:
+
+```toml
+title = "Example"
+
+[database]
+server = "192.168.1.1"
+ports = [8001, 8002]
+
+[[servers]]
+name = "alpha"
+
+[[servers]]
+name = "beta"
+```
+
+A document is parsed into a `Value::Table`, which is then deserialized like
+a JSON object: tables are read as maps or structs, arrays as sequences, and
+enums the same way as in JSON, with a unit variant as its name and any other
+variant as a table from its name to an array of its fields. `None` fields
+are left out of tables, since TOML has no null.
+
+Datetimes are kept as a `Datetime`, which reads and writes as a string in
+other formats. In TOML it's written bare, as `1979-05-27T07:32:00Z`.
+
+When writing, a table's key/value pairs are written before its sub-tables,
+since a key after a header belongs to that header's table. The value being
+written must be a table, like a struct or a map with string keys.
+
+```rust
+#![feature(plugin)]
+#[plugin]
+extern crate serde_macros;
+extern crate serde;
+
+use serde::toml;
+
+#[derive_serialize]
+#[derive_deserialize]
+struct Database {
+    server: String,
+    ports: Vec<u16>,
+}
+
+#[derive_serialize]
+#[derive_deserialize]
+struct Config {
+    title: String,
+    database: Database,
+}
+
+fn main() {
+    let config: Config = toml::from_str("
+        title = \"Example\"
+
+        [database]
+        server = \"192.168.1.1\"
+        ports = [8001, 8002]
+    ").unwrap();
+
+    let s = toml::to_string(&config).unwrap();
+}
+```
+
+*/
+
+pub use self::de::{Parser, from_str, parse};
+pub use self::error::{Error, ErrorCode};
+pub use self::ser::{Serializer, to_writer, to_vec, to_string};
+pub use self::value::{Datetime, Table, Value, from_value, to_value};
+
+pub mod de;
+pub mod ser;
+pub mod value;
+pub mod error;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::f64;
+    use std::fmt::Show;
+    use std::io::IoError;
+    use std::num::Float;
+
+    use cbor::{self, Tagged};
+    use de::Deserialize;
+    use json;
+    use ser::Serialize;
+
+    use super::{Datetime, Error, ErrorCode, Value};
+    use super::{from_str, from_value, parse, to_string, to_value, value};
+
+    macro_rules! treemap {
+        ($($k:expr => $v:expr),*) => ({
+            let mut _m = ::std::collections::BTreeMap::new();
+            $(_m.insert($k.to_string(), $v);)*
+            _m
+        })
+    }
+
+    #[derive(PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    enum Animal {
+        Dog,
+        Frog(String, Vec<int>)
+    }
+
+    #[derive(PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Server {
+        name: String,
+        port: u16,
+        tags: Vec<String>,
+    }
+
+    #[derive(PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Owner {
+        name: String,
+        dob: Datetime,
+    }
+
+    #[derive(PartialEq, Show)]
+    #[derive_serialize]
+    #[derive_deserialize]
+    struct Config {
+        title: String,
+        debug: Option<bool>,
+        ratio: f64,
+        pet: Animal,
+        owner: Owner,
+        servers: Vec<Server>,
+    }
+
+    fn test_parse_ok(tests: &[(&str, Value)]) {
+        for &(s, ref value) in tests.iter() {
+            let table = treemap!("a" => value.clone());
+            assert_eq!(parse(format!("a = {}", s).as_slice()), Ok(table));
+        }
+    }
+
+    fn test_parse_err(tests: &[(&str, Error)]) {
+        for &(s, ref err) in tests.iter() {
+            assert_eq!(parse(s), Err(err.clone()));
+        }
+    }
+
+    fn test_round_trip<
+        T: PartialEq + Show + Serialize<value::Serializer, IoError>
+                     + Deserialize<value::Deserializer, Error>
+    >(value: T, expected: &str) {
+        let s = to_string(&value).unwrap();
+        assert_eq!(s.as_slice(), expected);
+
+        let v: T = from_str(s.as_slice()).unwrap();
+        assert_eq!(v, value);
+    }
+
+    #[test]
+    fn test_parse_strings() {
+        test_parse_ok(&[
+            ("\"\"", Value::String("".to_string())),
+            ("\"a \\\"b\\\" \\t\\u00e9\\U0001F600\"", Value::String("a \"b\" \t\u{e9}\u{1F600}".to_string())),
+            ("'C:\\temp\\'", Value::String("C:\\temp\\".to_string())),
+            ("\"\"\"\nfirst\nsecond\"\"\"", Value::String("first\nsecond".to_string())),
+            ("\"\"\"one \\\n    two\"\"\"", Value::String("one two".to_string())),
+            ("\"\"\"a \"\"quote\"\"\"\"\"", Value::String("a \"\"quote\"\"".to_string())),
+            ("'''\n\\n is kept'''", Value::String("\\n is kept".to_string())),
+        ]);
+
+        test_parse_err(&[
+            ("a = \"abc", Error::SyntaxError(ErrorCode::EOFWhileParsingString, 1, 9)),
+            ("a = \"a\nb\"", Error::SyntaxError(ErrorCode::EOFWhileParsingString, 1, 7)),
+            ("a = \"\\x41\"", Error::SyntaxError(ErrorCode::InvalidEscape, 1, 7)),
+            ("a = \"\\uD800\"", Error::SyntaxError(ErrorCode::InvalidEscape, 1, 7)),
+            ("a = \"\\u12\"", Error::SyntaxError(ErrorCode::InvalidEscape, 1, 7)),
+            ("a = \"\x01\"", Error::SyntaxError(ErrorCode::ControlCharacter, 1, 6)),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        test_parse_ok(&[
+            ("0", Value::Integer(0)),
+            ("+99", Value::Integer(99)),
+            ("-17", Value::Integer(-17)),
+            ("1_000_000", Value::Integer(1000000)),
+            ("0xdead_BEEF", Value::Integer(0xdeadbeef)),
+            ("0o755", Value::Integer(0o755)),
+            ("0b1101", Value::Integer(13)),
+            ("9223372036854775807", Value::Integer(::std::i64::MAX)),
+            ("-9223372036854775808", Value::Integer(::std::i64::MIN)),
+            ("3.1415", Value::Float(3.1415)),
+            ("-0.01", Value::Float(-0.01)),
+            ("5e+22", Value::Float(5e22)),
+            ("6.626e-34", Value::Float(6.626e-34)),
+            ("224_617.445_991", Value::Float(224617.445991)),
+            ("inf", Value::Float(f64::INFINITY)),
+            ("-inf", Value::Float(f64::NEG_INFINITY)),
+        ]);
+
+        match parse("a = nan").unwrap().get("a") {
+            Some(&Value::Float(v)) => assert!(v.is_nan()),
+            ref v => panic!("expected nan, found {:?}", v),
+        }
+
+        test_parse_err(&[
+            ("a = 01", Error::SyntaxError(ErrorCode::InvalidNumber, 1, 5)),
+            ("a = 1__0", Error::SyntaxError(ErrorCode::InvalidNumber, 1, 5)),
+            ("a = _1", Error::SyntaxError(ErrorCode::ExpectedSomeValue, 1, 5)),
+            ("a = 1.", Error::SyntaxError(ErrorCode::InvalidNumber, 1, 5)),
+            ("a = .5", Error::SyntaxError(ErrorCode::ExpectedSomeValue, 1, 5)),
+            ("a = -0x1", Error::SyntaxError(ErrorCode::InvalidNumber, 1, 5)),
+            ("a = 9223372036854775808", Error::SyntaxError(ErrorCode::IntegerOverflow, 1, 5)),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_datetimes() {
+        for s in [
+            "1979-05-27T07:32:00Z",
+            "1979-05-27T00:32:00.999999-07:00",
+            "1979-05-27 07:32:00",
+            "1979-05-27",
+            "07:32:00",
+            "2000-02-29",
+        ].iter() {
+            let datetime = Datetime::parse(*s).unwrap();
+            test_parse_ok(&[(*s, Value::Datetime(datetime))]);
+        }
+
+        test_parse_err(&[
+            ("a = 1979-13-27", Error::SyntaxError(ErrorCode::InvalidDatetime, 1, 5)),
+            ("a = 1900-02-29", Error::SyntaxError(ErrorCode::InvalidDatetime, 1, 5)),
+            ("a = 07:32:00Z", Error::SyntaxError(ErrorCode::InvalidDatetime, 1, 5)),
+            ("a = 1979-05-27T25:00:00", Error::SyntaxError(ErrorCode::InvalidDatetime, 1, 5)),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_arrays_and_inline_tables() {
+        test_parse_ok(&[
+            ("[]", Value::Array(vec![])),
+            ("[1, \"two\", [3.0]]", Value::Array(vec![
+                Value::Integer(1),
+                Value::String("two".to_string()),
+                Value::Array(vec![Value::Float(3.0)]),
+            ])),
+            ("[\n  1, # one\n  2,\n]", Value::Array(vec![Value::Integer(1), Value::Integer(2)])),
+            ("{}", Value::Table(BTreeMap::new())),
+            ("{ x = 1, y.z = true }", Value::Table(treemap!(
+                "x" => Value::Integer(1),
+                "y" => Value::Table(treemap!("z" => Value::Boolean(true)))
+            ))),
+        ]);
+
+        test_parse_err(&[
+            ("a = [1 2]", Error::SyntaxError(ErrorCode::ExpectedArrayCommaOrEnd, 1, 8)),
+            ("a = { x = 1, }", Error::SyntaxError(ErrorCode::ExpectedKey, 1, 14)),
+            ("a = { x = 1\n}", Error::SyntaxError(ErrorCode::ExpectedInlineTableCommaOrEnd, 1, 12)),
+            ("a = { x = 1, x = 2 }", Error::SyntaxError(ErrorCode::DuplicateKey("x".to_string()), 1, 19)),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_tables() {
+        let table = parse("
+            # a comment
+            title = \"TOML\" # after a value
+            site.\"google.com\" = true
+
+            [a.b]
+            c = 1
+
+            [a]
+            d = 2
+
+            [[fruit]]
+            name = \"apple\"
+            [fruit.physical]
+            color = \"red\"
+
+            [[fruit]]
+            name = \"banana\"
+        ").unwrap();
+
+        assert_eq!(table, treemap!(
+            "title" => Value::String("TOML".to_string()),
+            "site" => Value::Table(treemap!("google.com" => Value::Boolean(true))),
+            "a" => Value::Table(treemap!(
+                "b" => Value::Table(treemap!("c" => Value::Integer(1))),
+                "d" => Value::Integer(2)
+            )),
+            "fruit" => Value::Array(vec![
+                Value::Table(treemap!(
+                    "name" => Value::String("apple".to_string()),
+                    "physical" => Value::Table(treemap!("color" => Value::String("red".to_string())))
+                )),
+                Value::Table(treemap!("name" => Value::String("banana".to_string())))
+            ])
+        ));
+
+        let value = Value::Table(table);
+        assert_eq!(value.lookup("a.b.c").and_then(|v| v.as_i64()), Some(1));
+        assert_eq!(value.lookup("fruit").and_then(|v| v.as_array()).map(|v| v.len()), Some(2));
+        assert_eq!(value.lookup("a.b.d"), None);
+    }
+
+    #[test]
+    fn test_parse_table_errors() {
+        test_parse_err(&[
+            ("a = 1\na = 2", Error::SyntaxError(ErrorCode::DuplicateKey("a".to_string()), 2, 6)),
+            ("[a]\n[a]", Error::SyntaxError(ErrorCode::DuplicateTable("a".to_string()), 2, 4)),
+            ("[a]\nb = 1\n[a.b]", Error::SyntaxError(ErrorCode::DuplicateTable("a.b".to_string()), 3, 6)),
+            ("a.b = 1\n[a]", Error::SyntaxError(ErrorCode::DuplicateTable("a".to_string()), 2, 4)),
+            ("[a.b]\n[a]\nb.c = 1", Error::SyntaxError(ErrorCode::DuplicateKey("b.c".to_string()), 3, 8)),
+            ("a = {}\n[a]", Error::SyntaxError(ErrorCode::DuplicateTable("a".to_string()), 2, 4)),
+            ("a = []\n[[a]]", Error::SyntaxError(ErrorCode::DuplicateTable("a".to_string()), 2, 6)),
+            ("[[a]]\n[a]", Error::SyntaxError(ErrorCode::DuplicateTable("a".to_string()), 2, 4)),
+            ("[a\nb = 1", Error::SyntaxError(ErrorCode::ExpectedTableHeaderEnd, 1, 3)),
+            ("a = 1 b = 2", Error::SyntaxError(ErrorCode::ExpectedNewline, 1, 7)),
+            ("a 1", Error::SyntaxError(ErrorCode::ExpectedEquals, 1, 3)),
+            ("= 1", Error::SyntaxError(ErrorCode::ExpectedKey, 1, 1)),
+            ("a =", Error::SyntaxError(ErrorCode::EOFWhileParsingValue, 1, 4)),
+        ]);
+    }
+
+    #[test]
+    fn test_deserialize_config() {
+        let config: Config = from_str("
+            title = \"Example\"
+            ratio = 0.5
+            pet = { Frog = [\"Henry\", [349, 102]] }
+
+            [owner]
+            name = \"Tom\"
+            dob = 1979-05-27T07:32:00-08:00
+
+            [[servers]]
+            name = \"alpha\"
+            port = 8001
+            tags = []
+
+            [[servers]]
+            name = \"beta\"
+            port = 8002
+            tags = [\"backup\"]
+        ").unwrap();
+
+        assert_eq!(config, Config {
+            title: "Example".to_string(),
+            debug: None,
+            ratio: 0.5,
+            pet: Animal::Frog("Henry".to_string(), vec![349, 102]),
+            owner: Owner {
+                name: "Tom".to_string(),
+                dob: Datetime::parse("1979-05-27T07:32:00-08:00").unwrap(),
+            },
+            servers: vec![
+                Server { name: "alpha".to_string(), port: 8001, tags: vec![] },
+                Server { name: "beta".to_string(), port: 8002, tags: vec!["backup".to_string()] },
+            ],
+        });
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let err = from_str::<Config>("
+            title = \"Example\"
+            ratio = 0.5
+            pet = \"Dog\"
+            [owner]
+            name = \"Tom\"
+            dob = 1979-05-27
+            [[servers]]
+            name = \"alpha\"
+            port = 80000
+            tags = []
+        ").unwrap_err();
+        match err {
+            Error::DeserializeError(_, ref path) => assert_eq!(path.as_slice(), "servers[0].port"),
+            ref err => panic!("expected a deserialize error, found {:?}", err),
+        }
+
+        let err = from_str::<Owner>("name = \"Tom\"").unwrap_err();
+        assert_eq!(err, Error::DeserializeError(ErrorCode::MissingField("dob", "Owner"), "".to_string()));
+
+        let err = from_str::<Owner>("name = \"Tom\"\ndob = \"today\"").unwrap_err();
+        match err {
+            Error::DeserializeError(ErrorCode::ConversionError(_), ref path) => {
+                assert_eq!(path.as_slice(), "dob");
+            }
+            ref err => panic!("expected a conversion error, found {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_serialize_orders_scalars_before_tables() {
+        let config = Config {
+            title: "Example".to_string(),
+            debug: Some(true),
+            ratio: 2.0,
+            pet: Animal::Dog,
+            owner: Owner {
+                name: "Tom \"T\"".to_string(),
+                dob: Datetime::parse("1979-05-27").unwrap(),
+            },
+            servers: vec![
+                Server { name: "alpha".to_string(), port: 8001, tags: vec!["a".to_string(), "b".to_string()] },
+                Server { name: "beta".to_string(), port: 8002, tags: vec![] },
+            ],
+        };
+
+        test_round_trip(config, "\
+debug = true
+pet = \"Dog\"
+ratio = 2.0
+title = \"Example\"
+
+[owner]
+dob = 1979-05-27
+name = \"Tom \\\"T\\\"\"
+
+[[servers]]
+name = \"alpha\"
+port = 8001
+tags = [\"a\", \"b\"]
+
+[[servers]]
+name = \"beta\"
+port = 8002
+tags = []
+");
+    }
+
+    #[test]
+    fn test_serialize_tables() {
+        let table = treemap!(
+            "a" => treemap!(
+                "b" => treemap!("c" => 1i),
+                "d e" => BTreeMap::new()
+            )
+        );
+        test_round_trip(table, "\
+[a.b]
+c = 1
+
+[a.\"d e\"]
+");
+
+        let nested = treemap!(
+            "points" => vec![vec![1i, 2], vec![3]],
+            "empty" => vec![]
+        );
+        test_round_trip(nested, "empty = []\npoints = [[1, 2], [3]]\n");
+
+        let floats = treemap!(
+            "a" => f64::INFINITY,
+            "b" => 1e300,
+            "c" => -0.25
+        );
+        test_round_trip(floats, "a = inf\nb = 1e300\nc = -0.25\n");
+
+        let strings = treemap!("s" => "tab\tbell\x07".to_string());
+        test_round_trip(strings, "s = \"tab\\tbell\\u0007\"\n");
+    }
+
+    #[test]
+    fn test_serialize_errors() {
+        assert!(to_string(&1i).is_err());
+        assert!(to_string(&vec![1i]).is_err());
+        assert!(to_string(&treemap!("a" => vec![None, Some(1i)])).is_err());
+        assert!(to_string(&treemap!("a" => ::std::u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let value = Value::Table(treemap!(
+            "when" => Value::Datetime(Datetime::parse("07:32:00").unwrap()),
+            "list" => Value::Array(vec![Value::Boolean(false)])
+        ));
+
+        assert_eq!(to_value(&value).unwrap(), value);
+        assert_eq!(from_value::<Value>(value.clone()).unwrap(), value);
+        assert_eq!(from_str::<Value>(to_string(&value).unwrap().as_slice()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_datetime_in_json() {
+        let owner = Owner {
+            name: "Tom".to_string(),
+            dob: Datetime::parse("1979-05-27T07:32:00Z").unwrap(),
+        };
+
+        let s = json::to_string(&owner).unwrap();
+        assert_eq!(s.as_slice(), "{\"name\":\"Tom\",\"dob\":\"1979-05-27T07:32:00Z\"}");
+        assert_eq!(json::from_str::<Owner>(s.as_slice()).unwrap(), owner);
+    }
+
+    #[test]
+    fn test_datetime_in_cbor() {
+        // Only offset date-times are CBOR date/time strings.
+        let datetime = Datetime::parse("1979-05-27T07:32:00Z").unwrap();
+        let v = cbor::to_vec(&datetime);
+        assert_eq!(v[0], 0xc0);
+        assert_eq!(cbor::from_slice::<Datetime>(v.as_slice()).unwrap(), datetime);
+
+        for s in ["1979-05-27T07:32:00", "1979-05-27", "07:32:00.5"].iter() {
+            let datetime = Datetime::parse(*s).unwrap();
+            let v = cbor::to_vec(&datetime);
+            assert_eq!(v, cbor::to_vec(&(*s).to_string()));
+            assert_eq!(cbor::from_slice::<Datetime>(v.as_slice()).unwrap(), datetime);
+        }
+    }
+
+    #[test]
+    fn test_tag_on_compound_value() {
+        let tagged = Tagged::new(value::DATETIME_TAG, vec!["1979-05-27".to_string()]);
+        assert_eq!(to_value(&tagged).unwrap(),
+                   Value::Array(vec![Value::String("1979-05-27".to_string())]));
+
+        let tagged = Tagged::new(value::DATETIME_TAG, ("07:32:00".to_string(), 1i));
+        assert_eq!(to_value(&tagged).unwrap(), Value::Array(vec![
+            Value::String("07:32:00".to_string()),
+            Value::Integer(1),
+        ]));
+    }
+}
@@ -0,0 +1,274 @@
+use std::io::{self, IoError, IoResult};
+use std::num::Float;
+
+use json;
+use ser;
+
+use super::value::{self, Table, Value};
+
+/// Returns whether `key` can be written without quotes.
+pub fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|c| {
+        match c {
+            b'A' ... b'Z' | b'a' ... b'z' | b'0' ... b'9' | b'_' | b'-' => true,
+            _ => false,
+        }
+    })
+}
+
+/// Writes `v` as a basic string, escaping the characters TOML doesn't
+/// allow in one.
+pub fn escape_str<W: Writer>(wr: &mut W, v: &str) -> IoResult<()> {
+    try!(wr.write_str("\""));
+
+    let bytes = v.as_bytes();
+    let mut start = 0;
+
+    for (i, byte) in bytes.iter().enumerate() {
+        let escaped = match *byte {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\x08' => "\\b",
+            b'\x0c' => "\\f",
+            b'\n' => "\\n",
+            b'\r' => "\\r",
+            b'\t' => "\\t",
+            c if c < 0x20 || c == 0x7f => "",
+            _ => { continue; }
+        };
+
+        if start < i {
+            try!(wr.write(bytes.slice(start, i)));
+        }
+
+        if escaped.is_empty() {
+            try!(write!(wr, "\\u{:04X}", *byte));
+        } else {
+            try!(wr.write_str(escaped));
+        }
+
+        start = i + 1;
+    }
+
+    if start != bytes.len() {
+        try!(wr.write(bytes.slice_from(start)));
+    }
+
+    wr.write_str("\"")
+}
+
+fn write_key<W: Writer>(wr: &mut W, key: &str) -> IoResult<()> {
+    if is_bare_key(key) {
+        wr.write_str(key)
+    } else {
+        escape_str(wr, key)
+    }
+}
+
+fn write_header<W: Writer>(wr: &mut W, path: &[String], array: bool) -> IoResult<()> {
+    try!(wr.write_str(if array { "[[" } else { "[" }));
+    for (i, key) in path.iter().enumerate() {
+        if i > 0 {
+            try!(wr.write_str("."));
+        }
+        try!(write_key(wr, key.as_slice()));
+    }
+    wr.write_str(if array { "]]\n" } else { "]\n" })
+}
+
+fn write_float<W: Writer>(wr: &mut W, v: f64) -> IoResult<()> {
+    if v.is_nan() {
+        return wr.write_str("nan");
+    }
+    if v.is_infinite() {
+        return wr.write_str(if v > 0.0 { "inf" } else { "-inf" });
+    }
+
+    // JSON writes the shortest float that reads back the same, but TOML
+    // needs a fraction or exponent to tell it from an integer.
+    let s = json::to_string(&v).unwrap();
+    try!(wr.write_str(s.as_slice()));
+    if !s.contains_char('.') && !s.contains_char('e') {
+        try!(wr.write_str(".0"));
+    }
+    Ok(())
+}
+
+/// Writes a value on the line of its key.
+fn write_inline<W: Writer>(wr: &mut W, value: &Value) -> IoResult<()> {
+    match *value {
+        Value::String(ref v) => escape_str(wr, v.as_slice()),
+        Value::Integer(v) => write!(wr, "{}", v),
+        Value::Float(v) => write_float(wr, v),
+        Value::Boolean(v) => write!(wr, "{}", v),
+        Value::Datetime(ref v) => wr.write_str(v.as_str()),
+        Value::Array(ref array) => {
+            try!(wr.write_str("["));
+            for (i, value) in array.iter().enumerate() {
+                if i > 0 {
+                    try!(wr.write_str(", "));
+                }
+                try!(write_inline(wr, value));
+            }
+            wr.write_str("]")
+        }
+        Value::Table(ref table) => {
+            if table.is_empty() {
+                return wr.write_str("{}");
+            }
+
+            try!(wr.write_str("{ "));
+            for (i, (key, value)) in table.iter().enumerate() {
+                if i > 0 {
+                    try!(wr.write_str(", "));
+                }
+                try!(write_key(wr, key.as_slice()));
+                try!(wr.write_str(" = "));
+                try!(write_inline(wr, value));
+            }
+            wr.write_str(" }")
+        }
+    }
+}
+
+fn is_table(value: &Value) -> bool {
+    match *value {
+        Value::Table(_) => true,
+        _ => false,
+    }
+}
+
+/// Arrays of tables are written as `[[header]]` sections, and any other
+/// array inline.
+fn is_table_array(value: &Value) -> bool {
+    match *value {
+        Value::Array(ref array) => !array.is_empty() && array.iter().all(is_table),
+        _ => false,
+    }
+}
+
+/// Writes TOML documents.
+///
+/// A table's key/value pairs have to come before its sub-tables, so those
+/// are written first, then the sub-tables, then the arrays of tables, each
+/// under its own header.
+pub struct Serializer<W> {
+    writer: W,
+    path: Vec<String>,
+    // Whether anything has been written yet, so headers after it get a
+    // blank line before them.
+    started: bool,
+}
+
+impl<W: Writer> Serializer<W> {
+    /// Creates a new TOML serializer.
+    pub fn new(writer: W) -> Serializer<W> {
+        Serializer {
+            writer: writer,
+            path: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Unwrap the `Writer` from the `Serializer`.
+    pub fn unwrap(self) -> W {
+        self.writer
+    }
+
+    /// Writes a document, which must be a table.
+    pub fn serialize(&mut self, value: &Value) -> IoResult<()> {
+        match *value {
+            Value::Table(ref table) => self.serialize_table(table),
+            _ => {
+                Err(IoError {
+                    kind: io::InvalidInput,
+                    desc: "a TOML document must be a table",
+                    detail: None,
+                })
+            }
+        }
+    }
+
+    fn serialize_header(&mut self, array: bool) -> IoResult<()> {
+        if self.started {
+            try!(self.writer.write_str("\n"));
+        }
+        self.started = true;
+        write_header(&mut self.writer, self.path.as_slice(), array)
+    }
+
+    fn serialize_table(&mut self, table: &Table) -> IoResult<()> {
+        for (key, value) in table.iter() {
+            if !is_table(value) && !is_table_array(value) {
+                try!(write_key(&mut self.writer, key.as_slice()));
+                try!(self.writer.write_str(" = "));
+                try!(write_inline(&mut self.writer, value));
+                try!(self.writer.write_str("\n"));
+                self.started = true;
+            }
+        }
+
+        for (key, value) in table.iter() {
+            match *value {
+                Value::Table(ref table) => {
+                    self.path.push(key.clone());
+
+                    // Tables that only hold other tables get their headers
+                    // from those.
+                    let implicit = !table.is_empty() && table.values().all(|value| {
+                        is_table(value) || is_table_array(value)
+                    });
+                    if !implicit {
+                        try!(self.serialize_header(false));
+                    }
+                    try!(self.serialize_table(table));
+
+                    self.path.pop();
+                }
+                _ => { }
+            }
+        }
+
+        for (key, value) in table.iter() {
+            if is_table_array(value) {
+                self.path.push(key.clone());
+
+                for table in value.as_array().unwrap().iter() {
+                    try!(self.serialize_header(true));
+                    try!(self.serialize_table(table.as_table().unwrap()));
+                }
+
+                self.path.pop();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Encode the specified value into a TOML `[u8]` writer. It must serialize
+/// to a table, like a struct or a map with string keys.
+pub fn to_writer<
+    W: Writer,
+    T: ser::Serialize<value::Serializer, IoError>
+>(writer: W, value: &T) -> IoResult<W> {
+    let value = try!(value::to_value(value));
+    let mut serializer = Serializer::new(writer);
+    try!(serializer.serialize(&value));
+    Ok(serializer.unwrap())
+}
+
+/// Encode the specified value into a TOML `[u8]` buffer.
+pub fn to_vec<
+    T: ser::Serialize<value::Serializer, IoError>
+>(value: &T) -> IoResult<Vec<u8>> {
+    to_writer(Vec::with_capacity(128), value)
+}
+
+/// Encode the specified value into a TOML `String` buffer.
+pub fn to_string<
+    T: ser::Serialize<value::Serializer, IoError>
+>(value: &T) -> IoResult<String> {
+    let buf = try!(to_vec(value));
+    Ok(String::from_utf8(buf).ok().expect("TOML output is UTF-8"))
+}
@@ -0,0 +1,952 @@
+use std::collections::{BTreeMap, btree_map};
+use std::fmt;
+use std::i64;
+use std::io::{self, IoError, IoResult};
+use std::mem;
+use std::vec;
+
+use de::{self, Token, TokenKind};
+use ser::{self, Serialize};
+
+use super::error::{Error, ErrorCode};
+use super::ser::is_bare_key;
+
+/// The semantic tag of date and time strings, which is the same as CBOR's.
+/// Serializers that can tell datetimes apart from other strings, like the
+/// TOML and CBOR ones, write an offset date-time `Datetime` as one, and the
+/// TOML deserializer tags offset date-times with it. Local ones have no tag,
+/// and are marked with `serialize_local_datetime` and
+/// `expect_local_datetime` instead.
+pub static DATETIME_TAG: u64 = 0;
+
+/// A TOML table.
+pub type Table = BTreeMap<String, Value>;
+
+/// Represents a TOML value
+#[derive(Clone, PartialEq, Show)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Datetime(Datetime),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    /// If the `Value` is a Table, returns the associated BTreeMap.
+    /// Returns None otherwise.
+    pub fn as_table<'a>(&'a self) -> Option<&'a Table> {
+        match *self {
+            Value::Table(ref table) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// If the `Value` is an Array, returns the associated vector.
+    /// Returns None otherwise.
+    pub fn as_array<'a>(&'a self) -> Option<&'a Vec<Value>> {
+        match *self {
+            Value::Array(ref array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// If the `Value` is a String, returns the associated str.
+    /// Returns None otherwise.
+    pub fn as_str<'a>(&'a self) -> Option<&'a str> {
+        match *self {
+            Value::String(ref s) => Some(s.as_slice()),
+            _ => None,
+        }
+    }
+
+    /// If the `Value` is an Integer, returns the associated i64.
+    /// Returns None otherwise.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// If the `Value` is a Float, returns the associated f64.
+    /// Returns None otherwise.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// If the `Value` is a Boolean, returns the associated bool.
+    /// Returns None otherwise.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// If the `Value` is a Datetime, returns the associated Datetime.
+    /// Returns None otherwise.
+    pub fn as_datetime<'a>(&'a self) -> Option<&'a Datetime> {
+        match *self {
+            Value::Datetime(ref datetime) => Some(datetime),
+            _ => None,
+        }
+    }
+
+    /// Looks up a value by a dotted path of keys, like `servers.alpha.ip`.
+    pub fn lookup<'a>(&'a self, path: &str) -> Option<&'a Value> {
+        let mut target = self;
+        for key in path.split('.') {
+            match target.as_table().and_then(|table| table.get(key)) {
+                Some(value) => { target = value; }
+                None => { return None; }
+            }
+        }
+        Some(target)
+    }
+}
+
+impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for Value {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        match *self {
+            Value::String(ref v) => v.serialize(s),
+            Value::Integer(v) => v.serialize(s),
+            Value::Float(v) => v.serialize(s),
+            Value::Boolean(v) => v.serialize(s),
+            Value::Datetime(ref v) => v.serialize(s),
+            Value::Array(ref v) => v.serialize(s),
+            Value::Table(ref v) => v.serialize(s),
+        }
+    }
+}
+
+impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for Value {
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Value, E> {
+        match token {
+            Token::Bool(x) => Ok(Value::Boolean(x)),
+            Token::Int(x) => Ok(Value::Integer(x as i64)),
+            Token::I8(x) => Ok(Value::Integer(x as i64)),
+            Token::I16(x) => Ok(Value::Integer(x as i64)),
+            Token::I32(x) => Ok(Value::Integer(x as i64)),
+            Token::I64(x) => Ok(Value::Integer(x)),
+            Token::U8(x) => Ok(Value::Integer(x as i64)),
+            Token::U16(x) => Ok(Value::Integer(x as i64)),
+            Token::U32(x) => Ok(Value::Integer(x as i64)),
+            Token::Uint(x) if x as u64 <= i64::MAX as u64 => Ok(Value::Integer(x as i64)),
+            Token::U64(x) if x <= i64::MAX as u64 => Ok(Value::Integer(x as i64)),
+            Token::F32(x) => Ok(Value::Float(x as f64)),
+            Token::F64(x) => Ok(Value::Float(x)),
+            Token::Char(x) => Ok(Value::String(x.to_string())),
+            Token::Str(_) | Token::String(_) => {
+                let tag = try!(d.expect_tag());
+                let local = try!(d.expect_local_datetime());
+                let v = try!(d.expect_string(token));
+
+                if tag == Some(DATETIME_TAG) || local {
+                    match Datetime::parse(v.as_slice()) {
+                        Some(datetime) => { return Ok(Value::Datetime(datetime)); }
+                        None => { }
+                    }
+                }
+
+                Ok(Value::String(v))
+            }
+            Token::Bytes(x) => {
+                Ok(Value::Array(x.into_iter().map(|b| Value::Integer(b as i64)).collect()))
+            }
+            Token::Option(true) => de::Deserialize::deserialize(d),
+            Token::TupleStart(_) | Token::SeqStart(_) => {
+                let array = try!(de::Deserialize::deserialize_token(d, token));
+                Ok(Value::Array(array))
+            }
+            Token::StructStart(_, _) | Token::MapStart(_) => {
+                let table = try!(de::Deserialize::deserialize_token(d, token));
+                Ok(Value::Table(table))
+            }
+            Token::EnumStart(_, name, len) => {
+                let token = Token::SeqStart(len);
+                let fields: Vec<Value> = try!(de::Deserialize::deserialize_token(d, token));
+                let mut table = BTreeMap::new();
+                table.insert(name.to_string(), Value::Array(fields));
+                Ok(Value::Table(table))
+            }
+            Token::Uint(_) | Token::U64(_) => Err(d.conversion_error(token)),
+            // TOML has no null.
+            token => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::BoolKind,
+                    TokenKind::I64Kind,
+                    TokenKind::F64Kind,
+                    TokenKind::StringKind,
+                    TokenKind::SeqStartKind,
+                    TokenKind::MapStartKind,
+                ];
+                Err(d.syntax_error(token, EXPECTED_TOKENS))
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A TOML datetime, which is an offset or local date-time, a local date, or
+/// a local time, kept as it was written. Other formats write it as a string.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub struct Datetime {
+    s: String,
+}
+
+impl Datetime {
+    /// Parses a datetime in the RFC 3339 based format TOML uses, like
+    /// `1979-05-27T07:32:00-08:00`, `1979-05-27 07:32:00`, `1979-05-27` or
+    /// `07:32:00.999`.
+    pub fn parse(s: &str) -> Option<Datetime> {
+        let b = s.as_bytes();
+        let mut pos = 0;
+
+        let date = b.len() >= 5 && b[4] == b'-';
+        if date {
+            if !parse_date(b) {
+                return None;
+            }
+            pos = 10;
+
+            if pos == b.len() {
+                return Some(Datetime { s: s.to_string() });
+            }
+
+            match b[pos] {
+                b'T' | b't' | b' ' => { pos += 1; }
+                _ => { return None; }
+            }
+        }
+
+        if !parse_time(b, &mut pos) {
+            return None;
+        }
+
+        // Only date-times can have an offset.
+        if date && pos < b.len() {
+            match b[pos] {
+                b'Z' | b'z' => { pos += 1; }
+                b'+' | b'-' => {
+                    match (digits(b, pos + 1, 2), digits(b, pos + 4, 2)) {
+                        (Some(hour), Some(minute)) if b[pos + 3] == b':' &&
+                                                      hour < 24 && minute < 60 => {
+                            pos += 6;
+                        }
+                        _ => { return None; }
+                    }
+                }
+                _ => { return None; }
+            }
+        }
+
+        if pos == b.len() {
+            Some(Datetime { s: s.to_string() })
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.s.as_slice()
+    }
+
+    /// Whether this is a date-time with an offset, which is the only kind an
+    /// RFC 3339 date/time string can be.
+    fn has_offset(&self) -> bool {
+        let b = self.s.as_bytes();
+        let len = b.len();
+        len > 10 && b[4] == b'-' && match b[len - 1] {
+            b'Z' | b'z' => true,
+            _ => b[len - 6] == b'+' || b[len - 6] == b'-',
+        }
+    }
+
+    /// Parses a string that was deserialized as a datetime.
+    fn from_string<D: de::Deserializer<E>, E>(d: &mut D, s: String) -> Result<Datetime, E> {
+        match Datetime::parse(s.as_slice()) {
+            Some(datetime) => Ok(datetime),
+            None => Err(d.conversion_error(Token::String(s))),
+        }
+    }
+}
+
+impl fmt::String for Datetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::String::fmt(&self.s, f)
+    }
+}
+
+/// Reads `n` decimal digits at `pos`.
+fn digits(b: &[u8], pos: uint, n: uint) -> Option<u32> {
+    if pos + n > b.len() {
+        return None;
+    }
+
+    let mut v = 0;
+    for &c in b.slice(pos, pos + n).iter() {
+        match c {
+            b'0' ... b'9' => { v = v * 10 + (c - b'0') as u32; }
+            _ => { return None; }
+        }
+    }
+
+    Some(v)
+}
+
+fn parse_date(b: &[u8]) -> bool {
+    match (digits(b, 0, 4), digits(b, 5, 2), digits(b, 8, 2)) {
+        (Some(year), Some(month), Some(day)) if b[7] == b'-' => {
+            let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+            let days = match month {
+                1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+                4 | 6 | 9 | 11 => 30,
+                2 if leap => 29,
+                2 => 28,
+                _ => { return false; }
+            };
+            day >= 1 && day <= days
+        }
+        _ => false,
+    }
+}
+
+fn parse_time(b: &[u8], pos: &mut uint) -> bool {
+    let start = *pos;
+    match (digits(b, start, 2), digits(b, start + 3, 2), digits(b, start + 6, 2)) {
+        (Some(hour), Some(minute), Some(second)) => {
+            // Leap seconds are allowed.
+            if b[start + 2] != b':' || b[start + 5] != b':' ||
+               hour > 23 || minute > 59 || second > 60 {
+                return false;
+            }
+        }
+        _ => { return false; }
+    }
+    *pos += 8;
+
+    if *pos < b.len() && b[*pos] == b'.' {
+        *pos += 1;
+        let frac = *pos;
+        while *pos < b.len() && b[*pos] >= b'0' && b[*pos] <= b'9' {
+            *pos += 1;
+        }
+        if *pos == frac {
+            return false;
+        }
+    }
+
+    true
+}
+
+impl<S: ser::Serializer<E>, E> ser::Serialize<S, E> for Datetime {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        if self.has_offset() {
+            try!(s.serialize_tag(DATETIME_TAG));
+        } else {
+            try!(s.serialize_local_datetime());
+        }
+        s.serialize_str(self.s.as_slice())
+    }
+}
+
+impl<D: de::Deserializer<E>, E> de::Deserialize<D, E> for Datetime {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<Datetime, E> {
+        let s = try!(d.expect_next_string());
+        Datetime::from_string(d, s)
+    }
+
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Datetime, E> {
+        let s = try!(d.expect_string(token));
+        Datetime::from_string(d, s)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+enum SerializerState {
+    Value(Value),
+    // A `None`, which TOML leaves out.
+    Missing,
+    Array(Vec<Value>),
+    Table(Table),
+    Variant(String, Vec<Value>),
+}
+
+fn invalid_input(desc: &'static str) -> IoError {
+    IoError {
+        kind: io::InvalidInput,
+        desc: desc,
+        detail: None,
+    }
+}
+
+/// Create a `Value` tree out of any `Serialize` type.
+pub struct Serializer {
+    state: Vec<SerializerState>,
+    // Set when the next string is a datetime.
+    datetime: bool,
+}
+
+impl Serializer {
+    /// Creates a new serializer instance.
+    pub fn new() -> Serializer {
+        Serializer {
+            state: Vec::with_capacity(4),
+            datetime: false,
+        }
+    }
+
+    /// Returns the value that was serialized, or `None` if it was a `None`.
+    pub fn unwrap(mut self) -> Option<Value> {
+        match self.state.pop() {
+            Some(SerializerState::Value(value)) => Some(value),
+            Some(SerializerState::Missing) => None,
+            _ => panic!("expected a complete value"),
+        }
+    }
+
+    fn serialize_value<
+        T: ser::Serialize<Serializer, IoError>
+    >(&mut self, value: &T) -> IoResult<Option<Value>> {
+        try!(value.serialize(self));
+        match self.state.pop() {
+            Some(SerializerState::Value(value)) => Ok(Some(value)),
+            Some(SerializerState::Missing) => Ok(None),
+            _ => panic!("expected a complete value"),
+        }
+    }
+
+    fn serialize_elt<
+        T: ser::Serialize<Serializer, IoError>
+    >(&mut self, value: &T) -> IoResult<Value> {
+        match try!(self.serialize_value(value)) {
+            Some(value) => Ok(value),
+            None => Err(invalid_input("TOML arrays can't hold a None")),
+        }
+    }
+
+    fn push(&mut self, value: Value) -> IoResult<()> {
+        self.datetime = false;
+        self.state.push(SerializerState::Value(value));
+        Ok(())
+    }
+}
+
+impl ser::Serializer<IoError> for Serializer {
+    #[inline]
+    fn serialize_null(&mut self) -> IoResult<()> {
+        self.datetime = false;
+        self.state.push(SerializerState::Missing);
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> IoResult<()> {
+        self.push(Value::Boolean(v))
+    }
+
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> IoResult<()> {
+        self.push(Value::Integer(v))
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> IoResult<()> {
+        if v > i64::MAX as u64 {
+            return Err(invalid_input("TOML integers must fit in an i64"));
+        }
+        self.push(Value::Integer(v as i64))
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
+        self.push(Value::Float(v))
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> IoResult<()> {
+        self.push(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> IoResult<()> {
+        if self.datetime {
+            match Datetime::parse(v) {
+                Some(datetime) => { return self.push(Value::Datetime(datetime)); }
+                None => { }
+            }
+        }
+
+        self.push(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_tag(&mut self, tag: u64) -> IoResult<()> {
+        self.datetime = tag == DATETIME_TAG;
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_local_datetime(&mut self) -> IoResult<()> {
+        self.datetime = true;
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, len: uint) -> IoResult<()> {
+        self.datetime = false;
+        self.state.push(SerializerState::Array(Vec::with_capacity(len)));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: ser::Serialize<Serializer, IoError>
+    >(&mut self, v: &T) -> IoResult<()> {
+        let value = try!(self.serialize_elt(v));
+        match self.state.last_mut() {
+            Some(&mut SerializerState::Array(ref mut array)) => { array.push(value); }
+            _ => panic!("expected an array"),
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> IoResult<()> {
+        match self.state.pop() {
+            Some(SerializerState::Array(array)) => self.push(Value::Array(array)),
+            _ => panic!("expected an array"),
+        }
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, _len: uint) -> IoResult<()> {
+        self.datetime = false;
+        self.state.push(SerializerState::Table(BTreeMap::new()));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: ser::Serialize<Serializer, IoError>
+    >(&mut self, name: &str, v: &T) -> IoResult<()> {
+        let value = match try!(self.serialize_value(v)) {
+            Some(value) => value,
+            None => { return Ok(()); }
+        };
+        match self.state.last_mut() {
+            Some(&mut SerializerState::Table(ref mut table)) => {
+                table.insert(name.to_string(), value);
+            }
+            _ => panic!("expected a table"),
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> IoResult<()> {
+        match self.state.pop() {
+            Some(SerializerState::Table(table)) => self.push(Value::Table(table)),
+            _ => panic!("expected a table"),
+        }
+    }
+
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, len: uint) -> IoResult<()> {
+        self.datetime = false;
+        self.state.push(SerializerState::Variant(variant.to_string(), Vec::with_capacity(len)));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: ser::Serialize<Serializer, IoError>
+    >(&mut self, v: &T) -> IoResult<()> {
+        let value = try!(self.serialize_elt(v));
+        match self.state.last_mut() {
+            Some(&mut SerializerState::Variant(_, ref mut fields)) => { fields.push(value); }
+            _ => panic!("expected an enum variant"),
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> IoResult<()> {
+        match self.state.pop() {
+            // Unit variants are written as just their name, like the JSON
+            // serializer does.
+            Some(SerializerState::Variant(variant, fields)) => {
+                if fields.is_empty() {
+                    self.push(Value::String(variant))
+                } else {
+                    let mut table = BTreeMap::new();
+                    table.insert(variant, Value::Array(fields));
+                    self.push(Value::Table(table))
+                }
+            }
+            _ => panic!("expected an enum variant"),
+        }
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: ser::Serialize<Serializer, IoError>
+    >(&mut self, v: &Option<T>) -> IoResult<()> {
+        match *v {
+            Some(ref v) => v.serialize(self),
+            None => ser::Serializer::serialize_null(self),
+        }
+    }
+
+    #[inline]
+    fn serialize_seq<
+        T: ser::Serialize<Serializer, IoError>,
+        Iter: Iterator<Item=T>
+    >(&mut self, iter: Iter) -> IoResult<()> {
+        self.datetime = false;
+        let mut array = Vec::with_capacity(iter.size_hint().0);
+        for elt in iter {
+            array.push(try!(self.serialize_elt(&elt)));
+        }
+        self.push(Value::Array(array))
+    }
+
+    #[inline]
+    fn serialize_map<
+        K: ser::Serialize<Serializer, IoError>,
+        V: ser::Serialize<Serializer, IoError>,
+        Iter: Iterator<Item=(K, V)>
+    >(&mut self, iter: Iter) -> IoResult<()> {
+        self.datetime = false;
+        let mut table = BTreeMap::new();
+        for (key, value) in iter {
+            let key = match try!(self.serialize_value(&key)) {
+                Some(Value::String(key)) => key,
+                _ => { return Err(invalid_input("TOML keys must be strings")); }
+            };
+            match try!(self.serialize_value(&value)) {
+                Some(value) => { table.insert(key, value); }
+                None => { }
+            }
+        }
+        self.push(Value::Table(table))
+    }
+}
+
+/// Serializes any `Serialize` type into a `Value`. `None`s are left out of
+/// tables, and fail anywhere else with an `InvalidInput` error, as do
+/// integers that don't fit in an `i64` and map keys that aren't strings.
+pub fn to_value<T: ser::Serialize<Serializer, IoError>>(value: &T) -> IoResult<Value> {
+    let mut serializer = Serializer::new();
+    match try!(serializer.serialize_value(value)) {
+        Some(value) => Ok(value),
+        None => Err(invalid_input("a TOML value can't be a None")),
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+enum State {
+    Value(Value),
+    // The elements left, and how many have been taken so far.
+    Array(vec::IntoIter<Value>, uint),
+    // The entries left, and the key of the last one taken.
+    Table(btree_map::IntoIter<String, Value>, Option<String>),
+    End,
+}
+
+/// A structure that deserializes a TOML `Value` into Rust values.
+pub struct Deserializer {
+    stack: Vec<State>,
+    // The tag of the last token, if it was an offset date-time.
+    tag: Option<u64>,
+    // Set when the last token was a local datetime.
+    local_datetime: bool,
+}
+
+impl Deserializer {
+    /// Creates a new deserializer instance for deserializing the specified TOML value.
+    pub fn new(value: Value) -> Deserializer {
+        Deserializer {
+            stack: vec!(State::Value(value)),
+            tag: None,
+            local_datetime: false,
+        }
+    }
+
+    fn error(&self, code: ErrorCode) -> Error {
+        Error::DeserializeError(code, self.path())
+    }
+
+    /// The path to the value being read, like `servers[3].port`.
+    fn path(&self) -> String {
+        let mut path = String::new();
+
+        for state in self.stack.iter() {
+            match *state {
+                State::Array(_, index) if index > 0 => {
+                    path.push_str(format!("[{}]", index - 1).as_slice());
+                }
+                State::Table(_, Some(ref key)) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    if is_bare_key(key.as_slice()) {
+                        path.push_str(key.as_slice());
+                    } else {
+                        path.push_str(format!("{:?}", key).as_slice());
+                    }
+                }
+                _ => { }
+            }
+        }
+
+        path
+    }
+}
+
+impl Iterator for Deserializer {
+    type Item = Result<Token, Error>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<Token, Error>> {
+        self.tag = None;
+        self.local_datetime = false;
+
+        loop {
+            match self.stack.pop() {
+                Some(State::Value(value)) => {
+                    let token = match value {
+                        Value::String(x) => Token::String(x),
+                        Value::Integer(x) => Token::I64(x),
+                        Value::Float(x) => Token::F64(x),
+                        Value::Boolean(x) => Token::Bool(x),
+                        Value::Datetime(x) => {
+                            if x.has_offset() {
+                                self.tag = Some(DATETIME_TAG);
+                            } else {
+                                self.local_datetime = true;
+                            }
+                            Token::String(x.s)
+                        }
+                        Value::Array(x) => {
+                            let len = x.len();
+                            self.stack.push(State::Array(x.into_iter(), 0));
+                            Token::SeqStart(len)
+                        }
+                        Value::Table(x) => {
+                            let len = x.len();
+                            self.stack.push(State::Table(x.into_iter(), None));
+                            Token::MapStart(len)
+                        }
+                    };
+
+                    return Some(Ok(token));
+                }
+                Some(State::Array(mut iter, index)) => {
+                    match iter.next() {
+                        Some(value) => {
+                            self.stack.push(State::Array(iter, index + 1));
+                            self.stack.push(State::Value(value));
+                            // loop around.
+                        }
+                        None => {
+                            return Some(Ok(Token::End));
+                        }
+                    }
+                }
+                Some(State::Table(mut iter, _)) => {
+                    match iter.next() {
+                        Some((key, value)) => {
+                            self.stack.push(State::Table(iter, Some(key.clone())));
+                            self.stack.push(State::Value(value));
+                            return Some(Ok(Token::String(key)));
+                        }
+                        None => {
+                            return Some(Ok(Token::End));
+                        }
+                    }
+                }
+                Some(State::End) => {
+                    return Some(Ok(Token::End));
+                }
+                None => { return None; }
+            }
+        }
+    }
+}
+
+impl de::Deserializer<Error> for Deserializer {
+    fn end_of_stream_error(&mut self) -> Error {
+        self.error(ErrorCode::EOFWhileParsingValue)
+    }
+
+    fn syntax_error(&mut self,
+                    token: Token,
+                    expected: &'static [TokenKind]) -> Error {
+        self.error(ErrorCode::ExpectedTokens(token, expected))
+    }
+
+    fn unexpected_name_error(&mut self, token: Token) -> Error {
+        self.error(ErrorCode::UnexpectedName(token))
+    }
+
+    fn conversion_error(&mut self, token: Token) -> Error {
+        self.error(ErrorCode::ConversionError(token))
+    }
+
+    fn unknown_field_error(&mut self, field: &str) -> Error {
+        self.error(ErrorCode::UnknownField(field.to_string()))
+    }
+
+    #[inline]
+    fn missing_field<
+        T: de::Deserialize<Deserializer, Error>
+    >(&mut self, _field: &'static str) -> Result<T, Error> {
+        // TOML has no null, so a missing value is the only way to leave
+        // out an optional one.
+        de::Deserialize::deserialize_token(self, Token::Null)
+    }
+
+    #[inline]
+    fn missing_struct_field<
+        T: de::Deserialize<Deserializer, Error>
+    >(&mut self, name: &'static str, field: &'static str) -> Result<T, Error> {
+        Err(self.error(ErrorCode::MissingField(field, name)))
+    }
+
+    #[inline]
+    fn expect_tag(&mut self) -> Result<Option<u64>, Error> {
+        Ok(self.tag.take())
+    }
+
+    #[inline]
+    fn expect_local_datetime(&mut self) -> Result<bool, Error> {
+        Ok(mem::replace(&mut self.local_datetime, false))
+    }
+
+    #[inline]
+    fn expect_option<
+        U: de::Deserialize<Deserializer, Error>
+    >(&mut self, token: Token) -> Result<Option<U>, Error> {
+        match token {
+            Token::Null => Ok(None),
+            token => {
+                let value: U = try!(de::Deserialize::deserialize_token(self, token));
+                Ok(Some(value))
+            }
+        }
+    }
+
+    // Enums are read as a String, or a table from the variant name to an
+    // array of its fields.
+    #[inline]
+    fn expect_enum_start(&mut self,
+                         token: Token,
+                         _name: &str,
+                         variants: &[&str]) -> Result<uint, Error> {
+        let variant = match token {
+            Token::String(variant) => {
+                // Unit variants have no fields, so there is just the end left.
+                self.stack.push(State::End);
+                variant
+            }
+            Token::MapStart(_) => {
+                let mut iter = match self.stack.pop() {
+                    Some(State::Table(iter, _)) => iter,
+                    _ => { panic!("state machine error, expected a table"); }
+                };
+
+                let (variant, fields) = match iter.next() {
+                    Some((variant, Value::Array(fields))) => (variant, fields),
+                    Some((_, value)) => {
+                        static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                            TokenKind::SeqStartKind,
+                        ];
+                        let token = match value {
+                            Value::Table(_) => Token::MapStart(0),
+                            Value::String(x) => Token::String(x),
+                            Value::Datetime(x) => Token::String(x.s),
+                            Value::Integer(x) => Token::I64(x),
+                            Value::Float(x) => Token::F64(x),
+                            Value::Boolean(x) => Token::Bool(x),
+                            Value::Array(_) => unreachable!(),
+                        };
+                        return Err(self.syntax_error(token, EXPECTED_TOKENS));
+                    }
+                    None => {
+                        static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                            TokenKind::StringKind,
+                        ];
+                        return Err(self.syntax_error(Token::End, EXPECTED_TOKENS));
+                    }
+                };
+
+                // Error out if there are other entries in the table.
+                match iter.next() {
+                    Some((key, _)) => {
+                        static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                            TokenKind::EndKind,
+                        ];
+                        return Err(self.syntax_error(Token::String(key), EXPECTED_TOKENS));
+                    }
+                    None => { }
+                }
+
+                self.stack.push(State::End);
+
+                for field in fields.into_iter().rev() {
+                    self.stack.push(State::Value(field));
+                }
+
+                variant
+            }
+            token => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::StringKind,
+                    TokenKind::MapStartKind,
+                ];
+                return Err(self.syntax_error(token, EXPECTED_TOKENS));
+            }
+        };
+
+        match variants.iter().position(|v| *v == variant.as_slice()) {
+            Some(idx) => Ok(idx),
+            None => Err(self.error(ErrorCode::UnknownVariant(variant))),
+        }
+    }
+
+    #[inline]
+    fn expect_struct_start(&mut self, token: Token, _name: &str) -> Result<(), Error> {
+        match token {
+            Token::MapStart(_) => Ok(()),
+            _ => {
+                static EXPECTED_TOKENS: &'static [TokenKind] = &[
+                    TokenKind::MapStartKind
+                ];
+                Err(self.syntax_error(token, EXPECTED_TOKENS))
+            }
+        }
+    }
+}
+
+/// Decodes a TOML value from a `Value`.
+pub fn from_value<
+    T: de::Deserialize<Deserializer, Error>
+>(value: Value) -> Result<T, Error> {
+    let mut d = Deserializer::new(value);
+    de::Deserialize::deserialize(&mut d)
+}